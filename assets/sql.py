@@ -1,6 +1,46 @@
+import argparse
+import re
+
 import daft
-import sys
 
-sql_query = sys.argv[1]
+# Daft's SQL has no DDL, so `CREATE TABLE <name> AS <query>` is run here by
+# registering the query's result as a table in the current session.
+CREATE_TABLE = re.compile(
+    r"\s*CREATE\s+(?:TEMP(?:ORARY)?\s+)?TABLE\s+(\w+)\s+AS\s+(.*?)\s*;?\s*",
+    re.IGNORECASE | re.DOTALL,
+)
+
+
+def run_setup_statement(statement):
+    create_table = CREATE_TABLE.fullmatch(statement)
+    if create_table is not None:
+        name, query = create_table.groups()
+        daft.create_temp_table(name, daft.sql(query).collect())
+        return
+    result = daft.sql(statement)
+    if result is not None:
+        result.collect()
+
+
+parser = argparse.ArgumentParser()
+parser.add_argument("--limit", type=int)
+parser.add_argument("--max-column-width", type=int)
+parser.add_argument("--explain", action="store_true")
+parser.add_argument("--setup", action="append", default=[])
+parser.add_argument("sql_query")
+args = parser.parse_args()
+
+show_kwargs = {}
+if args.limit is not None:
+    show_kwargs["n"] = args.limit
+if args.max_column_width is not None:
+    show_kwargs["max_width"] = args.max_column_width
+
 daft.context.set_runner_ray()
-daft.sql(sql_query).show()
+for setup_statement in args.setup:
+    run_setup_statement(setup_statement)
+df = daft.sql(args.sql_query)
+if args.explain:
+    df.explain(show_all=True)
+else:
+    df.show(**show_kwargs)
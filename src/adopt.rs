@@ -0,0 +1,101 @@
+use std::path::Path;
+
+use aws_sdk_ec2::types::InstanceStateName;
+use toml_edit::DocumentMut;
+
+use crate::{edit, AwsInstance, NodeType};
+
+fn is_live(instance: &AwsInstance) -> bool {
+    matches!(
+        instance.state,
+        Some(InstanceStateName::Running | InstanceStateName::Pending)
+    )
+}
+
+/// Returns the running head node among the given instances of a Ray cluster.
+pub fn head<'a>(instances: &[&'a AwsInstance]) -> Option<&'a AwsInstance> {
+    instances.iter().copied().find(|instance| {
+        instance.node_type == NodeType::Head && instance.state == Some(InstanceStateName::Running)
+    })
+}
+
+/// Reconstructs a provisioned configuration file for the running Ray cluster
+/// with the given name (in the given region) which the given instances make
+/// up, by filling in the given provisioned template, so that the launcher can
+/// operate on it (e.g., with `ssh`, `connect`, `job submit` and `kill`).
+///
+/// Only what the instances tell about the cluster is filled in; the AMI, IAM
+/// instance profile and setup of its nodes cannot be recovered, so the
+/// configuration is not meant for spinning the cluster up again as it was.
+/// Returns the configuration file, along with warnings about anything which had
+/// to be guessed.
+pub fn adopt(
+    template: &str,
+    name: &str,
+    region: &str,
+    instances: &[&AwsInstance],
+    ssh_user: Option<&str>,
+    ssh_private_key: Option<&Path>,
+) -> anyhow::Result<(String, Vec<String>)> {
+    let Some(head) = head(instances) else {
+        anyhow::bail!("No head node of a Ray cluster named {name} is running in {region}");
+    };
+    let mut warnings = vec![];
+    let mut contents = template.to_string();
+    let mut set = |key: &str, value: &str| -> anyhow::Result<()> {
+        contents = edit::set(&contents, key, value)?;
+        Ok(())
+    };
+    set("setup.name", name)?;
+    set("setup.provisioned.region", region)?;
+
+    let workers = instances
+        .iter()
+        .filter(|instance| instance.node_type == NodeType::Worker && is_live(instance))
+        .count();
+    set("setup.provisioned.number-of-workers", &workers.to_string())?;
+    warnings.push(format!(
+        "Set `number-of-workers` to the {workers} workers which are running now, since the cluster's maximum cannot be recovered"
+    ));
+    if let Some(instance_type) = &head.instance_type {
+        set("setup.provisioned.instance-type", instance_type.as_ref())?;
+    };
+
+    match ssh_user {
+        Some(ssh_user) => set("setup.provisioned.ssh-user", ssh_user)?,
+        None => warnings.push(
+            "Assumed the SSH user `ubuntu`; pass `--ssh-user` if the nodes' AMI has another"
+                .to_string(),
+        ),
+    };
+    match (ssh_private_key, &head.key_pair_name) {
+        (Some(ssh_private_key), _) => set(
+            "setup.provisioned.ssh-private-key",
+            &ssh_private_key.to_string_lossy(),
+        )?,
+        (None, Some(key_pair_name)) => {
+            set(
+                "setup.provisioned.ssh-private-key",
+                &format!("~/.ssh/{key_pair_name}"),
+            )?;
+            warnings.push(format!(
+                "Assumed the SSH private key `~/.ssh/{key_pair_name}`, after the nodes' key pair; pass `--ssh-private-key` if it is elsewhere"
+            ));
+        }
+        (None, None) => anyhow::bail!(
+            "The head node of {name} has no key pair to find its SSH private key by; pass `--ssh-private-key`"
+        ),
+    };
+    warnings.push(
+        "The AMI and setup of the nodes cannot be recovered, so `image-id` is the template's; review it before spinning the cluster up again".to_string(),
+    );
+
+    // The template's example job and placeholder IAM instance profile do not
+    // describe the cluster.
+    let mut document = contents.parse::<DocumentMut>()?;
+    document.remove("job");
+    if let Some(provisioned) = document["setup"]["provisioned"].as_table_mut() {
+        provisioned.remove("iam-instance-profile-name");
+    };
+    Ok((document.to_string(), warnings))
+}
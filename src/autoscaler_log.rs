@@ -0,0 +1,102 @@
+use serde::Serialize;
+
+use crate::StrRef;
+
+/// The logs of the Ray autoscaler on the head node: `monitor.log` has its own
+/// logging, and `monitor.out` the summaries of what it decided.
+pub const LOG_PATHS: [&str; 2] = [
+    "/tmp/ray/session_latest/logs/monitor.log",
+    "/tmp/ray/session_latest/logs/monitor.out",
+];
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventKind {
+    /// The autoscaler asked for new nodes.
+    NodeLaunched,
+    /// The cloud provider did not provide the nodes which were asked for.
+    NodeFailedToLaunch,
+    /// The autoscaler terminated nodes (e.g., because they were idle).
+    ScaleDown,
+    /// No node type can satisfy a resource request, so no node is launched
+    /// for it.
+    Infeasible,
+}
+
+impl EventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::NodeLaunched => "node launched",
+            Self::NodeFailedToLaunch => "node failed to launch",
+            Self::ScaleDown => "scale down",
+            Self::Infeasible => "infeasible",
+        }
+    }
+}
+
+/// An event of the autoscaler, as recognised from a line of its logs.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub kind: EventKind,
+    /// The time at the start of the line (e.g., `2024-05-01 10:00:00,123`),
+    /// if it has one.
+    pub time: Option<StrRef>,
+    pub message: StrRef,
+}
+
+/// Returns the event which the given line of the autoscaler's logs records,
+/// if any.
+pub fn parse_line(line: &str) -> Option<Event> {
+    const MARKERS: [(&str, EventKind); 8] = [
+        ("Failed to launch", EventKind::NodeFailedToLaunch),
+        ("Could not launch", EventKind::NodeFailedToLaunch),
+        ("Adding ", EventKind::NodeLaunched),
+        ("Launched ", EventKind::NodeLaunched),
+        ("Removing ", EventKind::ScaleDown),
+        ("Terminating the node", EventKind::ScaleDown),
+        (
+            "No available node types can fulfill resource request",
+            EventKind::Infeasible,
+        ),
+        ("is infeasible", EventKind::Infeasible),
+    ];
+    let line = line.trim();
+    let kind = MARKERS
+        .iter()
+        .find(|(marker, _)| line.contains(marker))
+        .map(|(_, kind)| *kind)?;
+    // Lines of `monitor.log` start with e.g. `2024-05-01 10:00:00,123\tINFO
+    // autoscaler.py:123 -- `, which is left out of the message.
+    let has_time = line.len() >= 23
+        && line.as_bytes()[4] == b'-'
+        && line.as_bytes()[10] == b' '
+        && line.is_char_boundary(23);
+    let time = has_time.then(|| StrRef::from(&line[..23]));
+    let message = line.split_once(" -- ").map_or(line, |(_, message)| message);
+    Some(Event {
+        kind,
+        time,
+        message: message.trim().into(),
+    })
+}
+
+/// Formats the given event as a line of `provisioned events`.
+pub fn format_event(event: &Event) -> String {
+    format!(
+        "{} [{}] {}",
+        event.time.as_deref().unwrap_or("-"),
+        event.kind.as_str(),
+        event.message
+    )
+}
+
+/// The command which prints the last given number of lines of the
+/// autoscaler's logs on the head node (and then, if following, the lines
+/// which are added to them).
+pub fn tail_command(lines: usize, follow: bool) -> String {
+    format!(
+        "tail -q -n {lines}{} {}",
+        if follow { " -F" } else { "" },
+        LOG_PATHS.join(" ")
+    )
+}
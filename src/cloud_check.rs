@@ -0,0 +1,210 @@
+use std::error::Error;
+
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_ec2::{
+    error::ProvideErrorMetadata,
+    types::{Filter, LocationType},
+};
+use tokio::process::Command;
+
+use crate::{exit_code, AwsConfig};
+
+/// The region from which the regions are listed: the given one may not exist,
+/// and so may have no endpoint to ask.
+const REGIONS_QUERY_REGION: &str = "us-east-1";
+
+/// Returns the response to the given request to AWS, or `None` if it failed
+/// with one of the given error codes, by which AWS says that what was asked
+/// for does not exist; any other failure is returned as it is.
+fn unless_not_found<T, E>(
+    result: Result<T, E>,
+    not_found_codes: &[&str],
+) -> anyhow::Result<Option<T>>
+where
+    E: ProvideErrorMetadata + Error + Send + Sync + 'static,
+{
+    match result {
+        Ok(response) => Ok(Some(response)),
+        Err(error)
+            if error
+                .code()
+                .is_some_and(|code| not_found_codes.contains(&code)) =>
+        {
+            Ok(None)
+        }
+        Err(error) => exit_code::classify_aws(Err(error)),
+    }
+}
+
+/// Returns the base64-encoded key of the given OpenSSH public key (e.g.,
+/// `ssh-rsa AAAA... comment`), ignoring its type and comment.
+pub fn public_key_material(public_key: &str) -> Option<&str> {
+    public_key.split_whitespace().nth(1)
+}
+
+/// Derives the OpenSSH public key of the given private key file.
+async fn derive_public_key(aws_config: &AwsConfig) -> anyhow::Result<String> {
+    let output = Command::new("ssh-keygen")
+        .arg("-y")
+        .arg("-f")
+        .arg(aws_config.ssh_private_key.as_ref())
+        .output()
+        .await?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to derive the public key of {:?}: {}",
+            aws_config.ssh_private_key,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    };
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Validates the given AWS configuration against the account it is used with,
+/// returning a description of each problem found.
+///
+/// This checks that the region exists, that the instance type is offered in it,
+/// that the AMI exists and supports the instance type's architecture, that the
+/// IAM instance profile exists, and that the key pair matches the private key.
+pub async fn check(aws_config: &AwsConfig) -> anyhow::Result<Vec<String>> {
+    let mut problems = vec![];
+    let region = aws_config.region().as_ref();
+
+    let sdk_config = aws_config::defaults(BehaviorVersion::latest())
+        .region(Region::new(region.to_string()))
+        .load()
+        .await;
+    let ec2 = aws_sdk_ec2::Client::new(&sdk_config);
+
+    let regions_sdk_config = aws_config::defaults(BehaviorVersion::latest())
+        .region(Region::new(REGIONS_QUERY_REGION))
+        .load()
+        .await;
+    let regions = exit_code::classify_aws(
+        aws_sdk_ec2::Client::new(&regions_sdk_config)
+            .describe_regions()
+            .all_regions(true)
+            .send()
+            .await,
+    )?;
+    let region_exists = regions
+        .regions()
+        .iter()
+        .any(|candidate| candidate.region_name() == Some(region));
+    if !region_exists {
+        // None of the other resources can be looked up in a region which does
+        // not exist.
+        problems.push(format!("The region {region} does not exist"));
+        return Ok(problems);
+    };
+
+    let instance_type = aws_config.instance_type.as_ref();
+    let offerings = exit_code::classify_aws(
+        ec2.describe_instance_type_offerings()
+            .location_type(LocationType::Region)
+            .filters(
+                Filter::builder()
+                    .name("instance-type")
+                    .values(instance_type)
+                    .build(),
+            )
+            .send()
+            .await,
+    )?;
+    if offerings.instance_type_offerings().is_empty() {
+        problems.push(format!(
+            "The instance type {instance_type} is not offered in the region {region}"
+        ));
+    };
+
+    let image_id = aws_config.image_id.as_ref();
+    let images = unless_not_found(
+        ec2.describe_images().image_ids(image_id).send().await,
+        &[
+            "InvalidAMIID.NotFound",
+            "InvalidAMIID.Malformed",
+            "InvalidAMIID.Unavailable",
+        ],
+    )?;
+    let image = images.and_then(|images| images.images.unwrap_or_default().into_iter().next());
+    match image {
+        None => problems.push(format!(
+            "The AMI {image_id} does not exist in the region {region}"
+        )),
+        Some(image) => {
+            let image_architecture = image
+                .architecture()
+                .map(|architecture| architecture.as_str().to_string());
+            let instance_types = unless_not_found(
+                ec2.describe_instance_types()
+                    .instance_types(instance_type.into())
+                    .send()
+                    .await,
+                &["InvalidInstanceType"],
+            )?;
+            let instance_architectures = instance_types
+                .iter()
+                .flat_map(|instance_types| instance_types.instance_types())
+                .filter_map(|instance_type| instance_type.processor_info())
+                .flat_map(|processor_info| processor_info.supported_architectures())
+                .map(|architecture| architecture.as_str().to_string())
+                .collect::<Vec<_>>();
+            if let Some(image_architecture) = image_architecture {
+                if !instance_architectures.is_empty()
+                    && !instance_architectures.contains(&image_architecture)
+                {
+                    problems.push(format!(
+                        "The AMI {image_id} is built for {image_architecture}, but the instance type {instance_type} supports only {}",
+                        instance_architectures.join(", ")
+                    ));
+                };
+            };
+        }
+    };
+
+    if let Some(instance_profile_name) = &aws_config.iam_instance_profile_name {
+        let iam = aws_sdk_iam::Client::new(&sdk_config);
+        let instance_profile = unless_not_found(
+            iam.get_instance_profile()
+                .instance_profile_name(instance_profile_name.as_ref())
+                .send()
+                .await,
+            &["NoSuchEntity"],
+        )?;
+        if instance_profile.is_none() {
+            problems.push(format!(
+                "The IAM instance profile {instance_profile_name} does not exist"
+            ));
+        };
+    };
+
+    let key_name = aws_config.key_name()?;
+    let key_pairs = unless_not_found(
+        ec2.describe_key_pairs()
+            .key_names(key_name.as_ref())
+            .include_public_key(true)
+            .send()
+            .await,
+        &["InvalidKeyPair.NotFound"],
+    )?;
+    let key_pair_public_key = key_pairs
+        .iter()
+        .flat_map(|key_pairs| key_pairs.key_pairs())
+        .find_map(|key_pair| key_pair.public_key());
+    match key_pair_public_key {
+        None => problems.push(format!(
+            "The key pair {key_name} does not exist in the region {region}"
+        )),
+        Some(key_pair_public_key) => {
+            let public_key = derive_public_key(aws_config).await?;
+            if public_key_material(key_pair_public_key) != public_key_material(&public_key) {
+                problems.push(format!(
+                    "The key pair {key_name} does not match the private key {:?}",
+                    aws_config.ssh_private_key
+                ));
+            };
+        }
+    };
+
+    Ok(problems)
+}
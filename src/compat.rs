@@ -0,0 +1,178 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use versions::Versioning;
+
+/// The JSON API of the Python Package Index.
+const PYPI_URL: &str = "https://pypi.org/pypi";
+
+/// The name under which Daft is published to PyPI.
+const DAFT_PACKAGE: &str = "getdaft";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Deserialize)]
+struct PypiRelease {
+    info: PypiInfo,
+}
+
+#[derive(Debug, Deserialize)]
+struct PypiInfo {
+    version: String,
+    #[serde(default)]
+    requires_dist: Option<Vec<String>>,
+}
+
+/// Returns the version specifiers (e.g., `>=2.0.0,<2.38.0`) with which the
+/// given requirements of a package (as published to PyPI, e.g., `ray[data,
+/// client]>=2.0.0; extra == "ray"`) require the given package, ignoring the
+/// requirements which only apply on Windows (as the cluster's nodes run
+/// Linux).
+pub fn specifiers_of<'a>(requires_dist: &'a [String], package: &str) -> Vec<&'a str> {
+    requires_dist
+        .iter()
+        .filter_map(|requirement| {
+            let (requirement, marker) = requirement
+                .split_once(';')
+                .unwrap_or((requirement.as_str(), ""));
+            if marker.contains("platform_system == \"Windows\"")
+                || marker.contains("sys_platform == \"win32\"")
+            {
+                return None;
+            };
+            let name_end = requirement
+                .find(|c: char| !(c.is_ascii_alphanumeric() || "-_.".contains(c)))
+                .unwrap_or(requirement.len());
+            let (name, rest) = requirement.split_at(name_end);
+            if !name.eq_ignore_ascii_case(package) {
+                return None;
+            };
+            let rest = rest.trim_start();
+            let rest = match rest.strip_prefix('[') {
+                Some(rest) => rest.split_once(']').map_or("", |(_, rest)| rest),
+                None => rest,
+            };
+            Some(
+                rest.trim()
+                    .trim_start_matches('(')
+                    .trim_end_matches(')')
+                    .trim(),
+            )
+        })
+        .collect()
+}
+
+/// Returns the numeric components of the release of the given version (e.g.,
+/// `[2, 34, 0]` for `2.34.0rc1`), padded with zeros to at least three.
+fn release(version: &str) -> Option<Vec<u64>> {
+    let mut components = version
+        .split('.')
+        .map_while(|component| {
+            let digits = component
+                .find(|c: char| !c.is_ascii_digit())
+                .map_or(component, |end| &component[..end]);
+            digits.parse().ok()
+        })
+        .collect::<Vec<u64>>();
+    if components.is_empty() {
+        return None;
+    };
+    while components.len() < 3 {
+        components.push(0);
+    }
+    Some(components)
+}
+
+/// Returns whether the given version satisfies every one of the given
+/// comma-separated version specifiers (e.g., `>=2.0.0,!=2.5.*`), or `None` if
+/// the specifiers cannot be understood.
+pub fn satisfies(version: &Versioning, specifiers: &str) -> Option<bool> {
+    let version = release(&version.to_string())?;
+    for specifier in specifiers.split(',').map(str::trim) {
+        if specifier.is_empty() {
+            continue;
+        };
+        let (operator, bound) = ["~=", "==", "!=", ">=", "<=", ">", "<"]
+            .into_iter()
+            .find_map(|operator| {
+                specifier
+                    .strip_prefix(operator)
+                    .map(|bound| (operator, bound.trim()))
+            })?;
+        let satisfied = match bound.strip_suffix(".*") {
+            Some(prefix) => {
+                let prefix = prefix
+                    .split('.')
+                    .map(|component| component.parse().ok())
+                    .collect::<Option<Vec<u64>>>()?;
+                let matches = version.starts_with(&prefix);
+                match operator {
+                    "==" => matches,
+                    "!=" => !matches,
+                    _ => return None,
+                }
+            }
+            None => {
+                let bound_components = bound.split('.').count();
+                let bound = release(bound)?;
+                match operator {
+                    "==" => version == bound,
+                    "!=" => version != bound,
+                    ">=" => version >= bound,
+                    "<=" => version <= bound,
+                    ">" => version > bound,
+                    "<" => version < bound,
+                    // A compatible release: at least the bound, with the same
+                    // components but for the last one given.
+                    _ => {
+                        version >= bound
+                            && version.starts_with(&bound[..bound_components.max(2) - 1])
+                    }
+                }
+            }
+        };
+        if !satisfied {
+            return Some(false);
+        };
+    }
+    Some(true)
+}
+
+/// Returns the problems with installing the given version of Daft (or the
+/// latest, if none is given) alongside the given version of Ray, as found
+/// from the requirements which Daft publishes to PyPI.
+pub async fn check(
+    daft_version: Option<&Versioning>,
+    ray_version: &Versioning,
+) -> anyhow::Result<Vec<String>> {
+    let url = match daft_version {
+        Some(daft_version) => format!("{PYPI_URL}/{DAFT_PACKAGE}/{daft_version}/json"),
+        None => format!("{PYPI_URL}/{DAFT_PACKAGE}/json"),
+    };
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?;
+    let response = client.get(&url).send().await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(vec![format!(
+            "Daft {} is not published to PyPI; set `setup.daft-version` to a released version",
+            daft_version.map_or("(latest)".to_string(), ToString::to_string)
+        )]);
+    };
+    let release = response
+        .error_for_status()?
+        .json::<PypiRelease>()
+        .await?
+        .info;
+    let requires_dist = release.requires_dist.unwrap_or_default();
+    Ok(specifiers_of(&requires_dist, "ray")
+        .into_iter()
+        .filter(|specifiers| satisfies(ray_version, specifiers) == Some(false))
+        .map(|specifiers| {
+            format!(
+                "Daft {} requires Ray {specifiers}, but `setup.ray-version` is {ray_version}; change `setup.ray-version` or pin `setup.daft-version` to a release which supports it",
+                release.version
+            )
+        })
+        .collect())
+}
@@ -0,0 +1,88 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use clap_complete::{engine::CompletionCandidate, env::Shells};
+
+/// The environment variable with which the shell asks the launcher for
+/// completions.
+pub const COMPLETE_VAR: &str = "COMPLETE";
+
+/// The configuration file whose jobs are completed.
+const CONFIG_FILE_NAME: &str = ".daft.toml";
+
+/// The shells for which completions can be generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+impl CompletionShell {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Bash => "bash",
+            Self::Zsh => "zsh",
+            Self::Fish => "fish",
+            Self::Powershell => "powershell",
+        }
+    }
+}
+
+/// Writes the script which registers the launcher's completions with the given
+/// shell; the script calls back into the launcher (at its current path) for the
+/// completions themselves.
+pub fn write_registration(shell: CompletionShell, buf: &mut dyn Write) -> anyhow::Result<()> {
+    let shells = Shells::builtins();
+    let completer = shells
+        .completer(shell.name())
+        .expect("every completion shell is built in");
+    let bin = env!("CARGO_PKG_NAME");
+    let exe = std::env::current_exe()?;
+    completer.write_registration(COMPLETE_VAR, bin, bin, &exe.to_string_lossy(), buf)?;
+    Ok(())
+}
+
+/// Returns the configuration file in the given directory or its closest
+/// ancestor, if there is one.
+pub fn nearest_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(CONFIG_FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// Returns the names of the jobs in the given configuration file, or nothing if
+/// it is not valid.
+pub fn job_names(contents: &str) -> Vec<String> {
+    let Ok(table) = toml::from_str::<toml::Table>(contents) else {
+        return vec![];
+    };
+    table
+        .get("job")
+        .and_then(toml::Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|job| job.get("name")?.as_str())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Completes the names of the jobs in the configuration file nearest to the
+/// current directory.
+pub fn job_candidates() -> Vec<CompletionCandidate> {
+    let Some(contents) = std::env::current_dir()
+        .ok()
+        .and_then(|dir| nearest_config(&dir))
+        .and_then(|path| std::fs::read_to_string(path).ok())
+    else {
+        return vec![];
+    };
+    job_names(&contents)
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
@@ -0,0 +1,454 @@
+use std::{
+    collections::BTreeMap,
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use tokio::{fs, io::AsyncReadExt};
+use toml::{Table, Value};
+
+use crate::{
+    s3,
+    secrets::{self, Secrets},
+};
+
+/// The key with which a configuration file names the (base) configuration file
+/// which it extends.
+const EXTENDS_KEY: &str = "extends";
+
+/// The key of the table of named profiles, each of which holds overrides for
+/// the rest of the configuration.
+const PROFILE_KEY: &str = "profile";
+
+/// The key with which the user-level defaults file names the profile to use
+/// when none is given (and the configuration defines it).
+const DEFAULT_PROFILE_KEY: &str = "default-profile";
+
+/// The key of the table of variables, which the rest of the configuration can
+/// refer to as `{{name}}`.
+const VARS_KEY: &str = "vars";
+
+/// The path with which the configuration file is read from the standard input.
+pub const STDIN_PATH: &str = "-";
+
+/// The schemes of the URLs from which configuration files can be fetched.
+const REMOTE_SCHEMES: &[&str] = &["s3://", "http://", "https://"];
+
+/// The path of the user-level defaults file, relative to the home directory.
+const USER_DEFAULTS_PATH: &str = ".config/daft-launcher/config.toml";
+
+/// The format of a configuration file, as given by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl Format {
+    /// Returns the format of the given configuration file; files without a
+    /// recognized extension are assumed to be TOML.
+    fn of(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("yaml" | "yml") => Self::Yaml,
+            Some("json") => Self::Json,
+            _ => Self::Toml,
+        }
+    }
+
+    fn parse<T: DeserializeOwned>(self, contents: &str) -> anyhow::Result<T> {
+        let result = match self {
+            Self::Toml => toml::from_str(contents).map_err(|error| error.to_string()),
+            Self::Yaml => serde_yaml::from_str(contents).map_err(|error| error.to_string()),
+            Self::Json => serde_json::from_str(contents).map_err(|error| error.to_string()),
+        };
+        result.map_err(|message| anyhow::anyhow!(explain_unknown_key(message, contents)))
+    }
+}
+
+/// Returns the edit distance between the given strings.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut distances = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut previous_diagonal = distances[0];
+        distances[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous_diagonal + usize::from(a != *b);
+            previous_diagonal = distances[j + 1];
+            distances[j + 1] = substitution.min(distances[j] + 1).min(distances[j + 1] + 1);
+        }
+    }
+    distances[b.len()]
+}
+
+/// Returns the candidate which is closest to the given key, if any is close
+/// enough to be what was meant.
+pub fn closest<'a>(key: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let threshold = (key.chars().count() / 3).max(2);
+    candidates
+        .iter()
+        .map(|candidate| (levenshtein(key, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Returns the (1-based) line and column at which the given key is set in the
+/// given configuration file, if it can be found.
+fn locate(contents: &str, key: &str) -> Option<(usize, usize)> {
+    contents.lines().enumerate().find_map(|(index, line)| {
+        let trimmed = line.trim_start();
+        let rest = trimmed
+            .strip_prefix(key)
+            .or_else(|| trimmed.strip_prefix(&format!("\"{key}\"")))?
+            .trim_start();
+        (rest.starts_with('=') || rest.starts_with(':'))
+            .then(|| (index + 1, line.len() - trimmed.len() + 1))
+    })
+}
+
+/// Adds a suggestion of the closest valid key (and where the invalid one is
+/// set, if the error does not say already) to an error about an unknown key in
+/// the given configuration file; other errors are returned as they are.
+pub fn explain_unknown_key(message: String, contents: &str) -> String {
+    let unknown =
+        Regex::new(r"unknown (?:field|variant) `([^`]*)`, (?:expected|there are)([^\n]*)")
+            .expect("static regex is valid");
+    let Some(captures) = unknown.captures(&message) else {
+        return message;
+    };
+    let key = captures[1].to_string();
+    let candidates = Regex::new(r"`([^`]*)`")
+        .expect("static regex is valid")
+        .captures_iter(&captures[2])
+        .map(|candidate| candidate.get(1).expect("the group always matches").as_str())
+        .collect::<Vec<_>>();
+    let mut message = message.trim_end().to_string();
+    if let Some(suggestion) = closest(&key, &candidates) {
+        message.push_str(&format!("; did you mean `{suggestion}`?"));
+    };
+    if !message.contains("line") {
+        if let Some((line, column)) = locate(contents, &key) {
+            message.push_str(&format!(" (at line {line}, column {column})"));
+        };
+    };
+    message
+}
+
+/// Returns the URL of the given configuration file, if it is fetched from an
+/// `s3://`, `http://` or `https://` URL rather than read from a local file.
+pub fn remote_url(path: &Path) -> Option<&str> {
+    path.to_str()
+        .filter(|path| REMOTE_SCHEMES.iter().any(|scheme| path.starts_with(scheme)))
+}
+
+/// Returns whether the given configuration file is a local file (rather than
+/// the standard input or a URL), which can be written to.
+pub fn is_local(path: &Path) -> bool {
+    path != Path::new(STDIN_PATH) && remote_url(path).is_none()
+}
+
+async fn fetch(url: &str) -> anyhow::Result<String> {
+    let contents = if url.starts_with("s3://") {
+        s3::download(url).await?
+    } else {
+        reqwest::get(url)
+            .await
+            .and_then(reqwest::Response::error_for_status)?
+            .bytes()
+            .await?
+            .to_vec()
+    };
+    String::from_utf8(contents)
+        .map_err(|_| anyhow::anyhow!("The configuration file {url:?} is not valid UTF-8"))
+}
+
+/// Reads the contents of the given configuration file, which may also be the
+/// standard input (`-`) or an `s3://`, `http://` or `https://` URL.
+pub async fn read_to_string(path: &Path) -> anyhow::Result<String> {
+    if path == Path::new(STDIN_PATH) {
+        let mut contents = String::new();
+        tokio::io::stdin().read_to_string(&mut contents).await?;
+        return Ok(contents);
+    };
+    if let Some(url) = remote_url(path) {
+        return fetch(url).await.map_err(|error| {
+            anyhow::anyhow!("The configuration file {url:?} could not be fetched: {error}")
+        });
+    };
+    let contents = fs::read_to_string(path).await.map_err(|error| {
+        if let ErrorKind::NotFound = error.kind() {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("The file {path:?} does not exist"),
+            )
+        } else {
+            error
+        }
+    })?;
+    Ok(contents)
+}
+
+/// Reads the user-level defaults file, if there is one.
+async fn read_user_defaults() -> anyhow::Result<Option<Table>> {
+    let Some(home) = crate::home_dir() else {
+        return Ok(None);
+    };
+    let path = home.join(USER_DEFAULTS_PATH);
+    if !path.exists() {
+        return Ok(None);
+    };
+    let defaults = toml::from_str(&read_to_string(&path).await?)
+        .map_err(|error| anyhow::anyhow!("The defaults file {path:?} is not valid: {error}"))?;
+    Ok(Some(defaults))
+}
+
+/// Reads the configuration file at the given path, which may be written in
+/// TOML, YAML (`.yaml` or `.yml`) or JSON (`.json`). The path may also be `-`,
+/// to read (TOML) from the standard input, or an `s3://`, `http://` or
+/// `https://` URL, to fetch the file from there.
+///
+/// If the file `extends` another configuration file (given relative to the
+/// file itself, or to the current directory when reading from the standard
+/// input), the file is deep-merged over that one first: tables are
+/// merged key by key, and any other values (including arrays, such as the list
+/// of jobs) in the extending file replace those in the base file.
+///
+/// If a profile is given, its overrides are then deep-merged over the
+/// resulting configuration in the same way. Then, any references to the
+/// variables in the `[vars]` table (e.g., `{{env}}`) are replaced with their
+/// values. Finally, any references to secrets (e.g.,
+/// `{ secret = "ssm:/team/daft/ssh-user" }`) are resolved (or redacted, as
+/// given).
+///
+/// The user-level defaults file (`~/.config/daft-launcher/config.toml`), if
+/// there is one, fills in any values which are missing from the sections which
+/// the configuration has, and may name a `default-profile` to use when no
+/// profile is given.
+pub async fn read<T: DeserializeOwned>(
+    path: &Path,
+    profile: Option<&str>,
+    secrets: Secrets,
+) -> anyhow::Result<T> {
+    read_with_defaults(path, profile, read_user_defaults().await?, secrets).await
+}
+
+/// Reads the configuration file at the given path, as [`read`] does, but with
+/// the given user-level defaults.
+pub async fn read_with_defaults<T: DeserializeOwned>(
+    path: &Path,
+    profile: Option<&str>,
+    defaults: Option<Table>,
+    secrets: Secrets,
+) -> anyhow::Result<T> {
+    let format = Format::of(path);
+    let contents = read_to_string(path).await?;
+    let mut table = format.parse::<Table>(&contents)?;
+    if profile.is_none()
+        && defaults.is_none()
+        && !table.contains_key(EXTENDS_KEY)
+        && !table.contains_key(PROFILE_KEY)
+        && !table.contains_key(VARS_KEY)
+        && secrets::references(&table).is_empty()
+    {
+        // Deserializing from the contents directly gives better errors.
+        return format.parse(&contents);
+    };
+
+    // The chain of configuration files, from the given one to its last base.
+    let mut visited = vec![if is_local(path) {
+        path.canonicalize()?
+    } else {
+        path.to_path_buf()
+    }];
+    let mut tables = vec![];
+    while let Some(extends) = table.remove(EXTENDS_KEY) {
+        let Value::String(extends) = extends else {
+            anyhow::bail!("The `{EXTENDS_KEY}` key must be a path to a configuration file");
+        };
+        let current = visited.last().expect("at least one file was visited");
+        let base_path = resolve_extends(current, &extends)?;
+        if visited.contains(&base_path) {
+            anyhow::bail!("The configuration file {base_path:?} extends itself");
+        };
+        tables.push(table);
+        table = Format::of(&base_path).parse::<Table>(&read_to_string(&base_path).await?)?;
+        visited.push(base_path);
+    }
+
+    let mut merged = tables.into_iter().rev().fold(table, |mut base, overrides| {
+        merge(&mut base, overrides);
+        base
+    });
+    let mut default_profile = None;
+    if let Some(mut defaults) = defaults {
+        default_profile = match defaults.remove(DEFAULT_PROFILE_KEY) {
+            Some(Value::String(default_profile)) => Some(default_profile),
+            Some(_) => anyhow::bail!("The `{DEFAULT_PROFILE_KEY}` default must be a string"),
+            None => None,
+        };
+        merge_under(&mut merged, defaults);
+    };
+    let profile = profile.or_else(|| {
+        let default_profile = default_profile.as_deref()?;
+        merged
+            .get(PROFILE_KEY)
+            .and_then(|profiles| profiles.get(default_profile))
+            .map(|_| default_profile)
+    });
+    apply_profile(&mut merged, profile)?;
+    apply_vars(&mut merged)?;
+    match secrets {
+        Secrets::Resolve => secrets::resolve(&mut merged).await?,
+        Secrets::Redact => secrets::redact_all(&mut merged),
+    };
+    Value::Table(merged)
+        .try_into()
+        .map_err(|error: toml::de::Error| {
+            anyhow::anyhow!(explain_unknown_key(error.to_string(), &contents))
+        })
+}
+
+/// Returns the path (or URL) of the base configuration file which the given
+/// configuration file `extends`.
+fn resolve_extends(current: &Path, extends: &str) -> anyhow::Result<PathBuf> {
+    if remote_url(Path::new(extends)).is_some() {
+        return Ok(PathBuf::from(extends));
+    };
+    if let Some(url) = remote_url(current) {
+        // Relative to the URL's "directory", as a link in a web page would be.
+        let (dir, _) = url.rsplit_once('/').expect("URLs contain a `/`");
+        return Ok(PathBuf::from(format!("{dir}/{extends}")));
+    };
+    let base_path = match current.parent() {
+        Some(dir) if current != Path::new(STDIN_PATH) => dir.join(extends),
+        _ => PathBuf::from(extends),
+    };
+    base_path.canonicalize().map_err(|error| {
+        anyhow::anyhow!(
+            "The configuration file {base_path:?} which {current:?} extends could not be read: {error}"
+        )
+    })
+}
+
+/// Removes the profiles from the given configuration, and deep-merges the
+/// overrides of the given profile (if any) into it.
+fn apply_profile(table: &mut Table, profile: Option<&str>) -> anyhow::Result<()> {
+    let profiles = match table.remove(PROFILE_KEY) {
+        Some(Value::Table(profiles)) => profiles,
+        Some(_) => anyhow::bail!("The `{PROFILE_KEY}` key must be a table of profiles"),
+        None => Table::new(),
+    };
+    let Some(profile) = profile else {
+        return Ok(());
+    };
+    match profiles.get(profile) {
+        Some(Value::Table(overrides)) => {
+            merge(table, overrides.clone());
+            Ok(())
+        }
+        Some(_) => anyhow::bail!("The profile {profile} must be a table of overrides"),
+        None => {
+            let available = profiles.keys().map(String::as_str).collect::<Vec<_>>();
+            anyhow::bail!(
+                "The profile {profile} does not exist; the available profiles are: [{}]",
+                available.join(", ")
+            )
+        }
+    }
+}
+
+/// Removes the variables from the given configuration, and replaces every
+/// reference to one (e.g., `{{env}}`) in its strings with the variable's value.
+pub fn apply_vars(table: &mut Table) -> anyhow::Result<()> {
+    let vars = match table.remove(VARS_KEY) {
+        Some(Value::Table(vars)) => vars,
+        Some(_) => anyhow::bail!("The `{VARS_KEY}` key must be a table of variables"),
+        None => return Ok(()),
+    };
+    let vars = vars
+        .into_iter()
+        .map(|(name, value)| {
+            let value = match value {
+                Value::String(value) => value,
+                Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => value.to_string(),
+                _ => anyhow::bail!("The variable {name} must be a string, number or boolean"),
+            };
+            Ok((name, value))
+        })
+        .collect::<anyhow::Result<BTreeMap<_, _>>>()?;
+    let reference =
+        Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_-]*)\s*\}\}").expect("static regex is valid");
+    table
+        .iter_mut()
+        .try_for_each(|(_, value)| substitute_vars(value, &vars, &reference))
+}
+
+fn substitute_vars(
+    value: &mut Value,
+    vars: &BTreeMap<String, String>,
+    reference: &Regex,
+) -> anyhow::Result<()> {
+    match value {
+        Value::String(string) => {
+            let mut missing = None;
+            let substituted =
+                reference.replace_all(string, |captures: &regex::Captures| {
+                    match vars.get(&captures[1]) {
+                        Some(value) => value.clone(),
+                        None => {
+                            missing.get_or_insert_with(|| captures[1].to_string());
+                            captures[0].to_string()
+                        }
+                    }
+                });
+            if let Some(missing) = missing {
+                let available = vars.keys().map(String::as_str).collect::<Vec<_>>();
+                anyhow::bail!(
+                    "The variable {missing} is not defined; the available variables are: [{}]",
+                    available.join(", ")
+                );
+            };
+            *string = substituted.into_owned();
+        }
+        Value::Table(table) => table
+            .iter_mut()
+            .try_for_each(|(_, value)| substitute_vars(value, vars, reference))?,
+        Value::Array(array) => array
+            .iter_mut()
+            .try_for_each(|value| substitute_vars(value, vars, reference))?,
+        _ => (),
+    };
+    Ok(())
+}
+
+/// Fills in the values which are missing from the given table (or from the
+/// tables within it) with the given defaults; tables which are missing are not
+/// filled in.
+fn merge_under(table: &mut Table, defaults: Table) {
+    for (key, default) in defaults {
+        match (table.get_mut(&key), default) {
+            (Some(Value::Table(table)), Value::Table(defaults)) => merge_under(table, defaults),
+            (Some(_), _) | (None, Value::Table(_)) => (),
+            (None, default) => {
+                table.insert(key, default);
+            }
+        }
+    }
+}
+
+/// Deep-merges the given overrides into the given base table.
+fn merge(base: &mut Table, overrides: Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(overrides)) => merge(base, overrides),
+            (Some(base_value), value) => *base_value = value,
+            (None, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime},
+};
+
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_costexplorer::types::{
+    DateInterval, Granularity, GroupDefinition, GroupDefinitionType,
+};
+use serde::Serialize;
+
+use crate::StrRef;
+
+/// The tag with which Ray tags the instances of a cluster with its name; it
+/// must be activated as a cost allocation tag for Cost Explorer to group by
+/// it.
+const CLUSTER_NAME_TAG: &str = "ray-cluster-name";
+
+/// The region whose endpoint serves the Cost Explorer API (for every region).
+const COST_EXPLORER_REGION: &str = "us-east-1";
+
+const METRIC: &str = "UnblendedCost";
+
+/// The spend of a cluster on a day, as reported by Cost Explorer.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct DailyCost {
+    /// In `YYYY-MM-DD` format.
+    pub date: StrRef,
+    pub cluster: StrRef,
+    pub usd: f64,
+}
+
+/// Parses a number of days (e.g., `7d`) or weeks (e.g., `2w`).
+pub fn parse_since(raw: &str) -> Result<u32, String> {
+    let since = crate::parse_duration(
+        raw,
+        &[('d', crate::DAY), ('w', crate::WEEK)],
+        "a number of days or weeks (e.g., `7d` or `2w`)",
+    )?;
+    u32::try_from(since.as_secs() / crate::DAY.as_secs())
+        .map_err(|_| format!("{raw:?} is too far back"))
+}
+
+/// Formats the date of the given time in `YYYY-MM-DD` format (in UTC).
+pub fn format_date(time: SystemTime) -> String {
+    aws_sdk_ec2::primitives::DateTime::from(time)
+        .fmt(aws_sdk_ec2::primitives::DateTimeFormat::DateTime)
+        .map(|date_time| date_time[..10].to_string())
+        .unwrap_or_default()
+}
+
+/// Returns the name of the cluster of the given Cost Explorer group key (e.g.,
+/// `ray-cluster-name$my-cluster`), or `None` for the spend which is not tagged
+/// with a cluster.
+pub fn cluster_name(key: &str) -> Option<&str> {
+    key.strip_prefix(CLUSTER_NAME_TAG)?
+        .strip_prefix('$')
+        .filter(|name| !name.is_empty())
+}
+
+/// Returns the spend of each cluster on each of the given number of days before
+/// today (today's spend is left out, since Cost Explorer only has part of it).
+pub async fn daily_costs(days: u32, now: SystemTime) -> anyhow::Result<Vec<DailyCost>> {
+    let sdk_config = aws_config::defaults(BehaviorVersion::latest())
+        .region(Region::new(COST_EXPLORER_REGION))
+        .load()
+        .await;
+    let client = aws_sdk_costexplorer::Client::new(&sdk_config);
+    let time_period = DateInterval::builder()
+        .start(format_date(
+            now - Duration::from_secs(u64::from(days) * 24 * 3600),
+        ))
+        .end(format_date(now))
+        .build()?;
+    let mut costs = vec![];
+    let mut next_page_token = None;
+    loop {
+        let response = client
+            .get_cost_and_usage()
+            .time_period(time_period.clone())
+            .granularity(Granularity::Daily)
+            .metrics(METRIC)
+            .group_by(
+                GroupDefinition::builder()
+                    .r#type(GroupDefinitionType::Tag)
+                    .key(CLUSTER_NAME_TAG)
+                    .build(),
+            )
+            .set_next_page_token(next_page_token)
+            .send()
+            .await?;
+        for result in response.results_by_time() {
+            let Some(date) = result.time_period().map(DateInterval::start) else {
+                continue;
+            };
+            for group in result.groups() {
+                let Some(cluster) = group.keys().first().and_then(|key| cluster_name(key)) else {
+                    continue;
+                };
+                let usd = group
+                    .metrics()
+                    .and_then(|metrics| metrics.get(METRIC))
+                    .and_then(|metric| metric.amount())
+                    .and_then(|amount| amount.parse().ok())
+                    .unwrap_or_default();
+                costs.push(DailyCost {
+                    date: date.into(),
+                    cluster: cluster.into(),
+                    usd,
+                });
+            }
+        }
+        next_page_token = response.next_page_token().map(str::to_string);
+        if next_page_token.is_none() {
+            break;
+        };
+    }
+    Ok(costs)
+}
+
+/// Sums the given daily costs by cluster.
+pub fn totals(costs: &[DailyCost]) -> BTreeMap<&str, f64> {
+    let mut totals = BTreeMap::new();
+    for cost in costs {
+        *totals.entry(&*cost.cluster).or_default() += cost.usd;
+    }
+    totals
+}
+
+/// Formats the given amount in USD.
+pub fn format_usd(usd: f64) -> String {
+    format!("${usd:.2}")
+}
@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+
+use toml::Value;
+
+/// The key of the array of jobs, which are compared by name rather than by
+/// position.
+const JOBS_KEY: &str = "job";
+
+/// A difference between two configurations, at a dotted path.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Added(String, Value),
+    Removed(String, Value),
+    Changed(String, Value, Value),
+}
+
+impl Change {
+    pub fn path(&self) -> &str {
+        match self {
+            Change::Added(path, _) | Change::Removed(path, _) | Change::Changed(path, _, _) => path,
+        }
+    }
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Added(path, value) => write!(f, "+ {path} = {value}"),
+            Change::Removed(path, value) => write!(f, "- {path} = {value}"),
+            Change::Changed(path, old, new) => write!(f, "~ {path} = {old} -> {new}"),
+        }
+    }
+}
+
+/// Flattens the given value into its leaves, keyed by dotted path; arrays are
+/// leaves, except for the jobs, which are keyed by name.
+fn flatten(path: String, value: &Value, leaves: &mut BTreeMap<String, Value>) {
+    match value {
+        Value::Table(table) => {
+            for (key, value) in table {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                match (path.as_str(), value) {
+                    (JOBS_KEY, Value::Array(jobs)) => {
+                        for job in jobs {
+                            let name = job.get("name").and_then(Value::as_str).unwrap_or_default();
+                            flatten(format!("{JOBS_KEY}.{name}"), job, leaves);
+                        }
+                    }
+                    _ => flatten(path, value, leaves),
+                };
+            }
+        }
+        value => {
+            leaves.insert(path, value.clone());
+        }
+    }
+}
+
+/// Returns the differences between the given configurations (each serialized
+/// with all of its defaults filled in), ordered by path.
+pub fn diff(old: &Value, new: &Value) -> Vec<Change> {
+    let (mut old_leaves, mut new_leaves) = (BTreeMap::new(), BTreeMap::new());
+    flatten(String::new(), old, &mut old_leaves);
+    flatten(String::new(), new, &mut new_leaves);
+
+    let mut changes = vec![];
+    for (path, old_value) in &old_leaves {
+        match new_leaves.remove(path) {
+            None => changes.push(Change::Removed(path.clone(), old_value.clone())),
+            Some(new_value) if new_value != *old_value => {
+                changes.push(Change::Changed(path.clone(), old_value.clone(), new_value))
+            }
+            Some(_) => (),
+        };
+    }
+    changes.extend(
+        new_leaves
+            .into_iter()
+            .map(|(path, new_value)| Change::Added(path, new_value)),
+    );
+    changes.sort_by(|a, b| a.path().cmp(b.path()));
+    changes
+}
@@ -0,0 +1,256 @@
+use std::{
+    fmt,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use aws_config::{BehaviorVersion, Region};
+use aws_credential_types::provider::ProvideCredentials;
+use serde::Serialize;
+use tokio::{net::TcpStream, process::Command, time::timeout};
+
+use crate::{format_duration, read_daft_config, K8sConfig, ProviderConfig};
+
+/// How long to wait for an endpoint to accept a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Credentials which expire sooner than this are reported.
+const EXPIRY_WARNING: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// The outcome of one of the checks of `doctor`.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct Diagnosis {
+    pub check: String,
+    pub status: Status,
+    pub detail: String,
+}
+
+impl Diagnosis {
+    fn new(check: impl Into<String>, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            check: check.into(),
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self.status {
+            Status::Pass => "pass",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+        };
+        write!(f, "[{status}] {}: {}", self.check, self.detail)
+    }
+}
+
+/// Checks that the given tool is on the `PATH`, reporting its version; a
+/// missing tool fails the check only if it is `required`.
+async fn check_tool(name: &str, version_args: &[&str], required: bool) -> Diagnosis {
+    let output = match Command::new(name).args(version_args).output().await {
+        Ok(output) => output,
+        Err(_) => {
+            let status = if required { Status::Fail } else { Status::Warn };
+            return Diagnosis::new(name, status, format!("`{name}` was not found on the PATH"));
+        }
+    };
+    // `ssh -V` prints its version on stderr.
+    let version = if output.stdout.is_empty() {
+        String::from_utf8_lossy(&output.stderr)
+    } else {
+        String::from_utf8_lossy(&output.stdout)
+    };
+    let version = version.lines().next().unwrap_or_default().trim();
+    if output.status.success() {
+        Diagnosis::new(name, Status::Pass, version)
+    } else {
+        Diagnosis::new(
+            name,
+            Status::Warn,
+            format!("`{name} {}` failed: {version}", version_args.join(" ")),
+        )
+    }
+}
+
+/// Describes when credentials with the given expiry expire, and whether that
+/// is soon enough to be a problem.
+pub fn describe_expiry(expiry: Option<SystemTime>, now: SystemTime) -> (Status, String) {
+    let Some(expiry) = expiry else {
+        return (Status::Pass, "found, and they do not expire".to_string());
+    };
+    match expiry.duration_since(now) {
+        Err(_) => (
+            Status::Fail,
+            "expired; log in with the AWS CLI again".to_string(),
+        ),
+        Ok(remaining) if remaining < EXPIRY_WARNING => (
+            Status::Warn,
+            format!(
+                "expire in {}; log in with the AWS CLI again before long-running commands",
+                format_duration(remaining)
+            ),
+        ),
+        Ok(remaining) => (
+            Status::Pass,
+            format!("found, and expire in {}", format_duration(remaining)),
+        ),
+    }
+}
+
+async fn check_aws_credentials(region: &str) -> Diagnosis {
+    const CHECK: &str = "AWS credentials";
+    let sdk_config = aws_config::defaults(BehaviorVersion::latest())
+        .region(Region::new(region.to_string()))
+        .load()
+        .await;
+    let Some(provider) = sdk_config.credentials_provider() else {
+        return Diagnosis::new(CHECK, Status::Fail, "not found");
+    };
+    match provider.provide_credentials().await {
+        Ok(credentials) => {
+            let (status, detail) = describe_expiry(credentials.expiry(), SystemTime::now());
+            Diagnosis::new(CHECK, status, detail)
+        }
+        Err(error) => Diagnosis::new(CHECK, Status::Fail, format!("not found: {error}")),
+    }
+}
+
+/// Returns the `host:port` address of the given endpoint URL (e.g.,
+/// `https://203.0.113.7:6443`), defaulting the port from the scheme.
+pub fn endpoint_address(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    let authority = rest.split('/').next().filter(|host| !host.is_empty())?;
+    let has_port = authority
+        .rsplit_once(':')
+        .is_some_and(|(_, port)| port.parse::<u16>().is_ok());
+    Some(if has_port {
+        authority.to_string()
+    } else {
+        let port = if scheme == "http" { 80 } else { 443 };
+        format!("{authority}:{port}")
+    })
+}
+
+async fn check_reachable(check: &str, address: &str) -> Diagnosis {
+    match timeout(CONNECT_TIMEOUT, TcpStream::connect(address)).await {
+        Ok(Ok(_)) => Diagnosis::new(check, Status::Pass, format!("{address} is reachable")),
+        Ok(Err(error)) => Diagnosis::new(
+            check,
+            Status::Fail,
+            format!("{address} is not reachable: {error}"),
+        ),
+        Err(_) => Diagnosis::new(
+            check,
+            Status::Fail,
+            format!("connecting to {address} timed out"),
+        ),
+    }
+}
+
+/// Checks that the kubeconfig names a cluster, returning the URL of its API
+/// server.
+async fn check_kubeconfig(k8s_config: &K8sConfig) -> (Diagnosis, Option<String>) {
+    const CHECK: &str = "kubeconfig";
+    let output = k8s_config
+        .kubectl()
+        .args(["config", "view", "--minify", "-o"])
+        .arg("jsonpath={.current-context} {.clusters[0].cluster.server}")
+        .output()
+        .await;
+    let output = match output {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            let error = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            return (Diagnosis::new(CHECK, Status::Fail, error), None);
+        }
+        Err(_) => {
+            return (
+                Diagnosis::new(CHECK, Status::Fail, "`kubectl` was not found on the PATH"),
+                None,
+            )
+        }
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (context, server) = stdout.trim().split_once(' ').unwrap_or((stdout.trim(), ""));
+    let context = k8s_config.context.as_deref().unwrap_or(context);
+    if server.is_empty() {
+        return (
+            Diagnosis::new(
+                CHECK,
+                Status::Fail,
+                format!("the context {context:?} has no cluster"),
+            ),
+            None,
+        );
+    };
+    (
+        Diagnosis::new(
+            CHECK,
+            Status::Pass,
+            format!("the context {context:?} points at {server}"),
+        ),
+        Some(server.to_string()),
+    )
+}
+
+/// Diagnoses the environment which the launcher runs in, for the cluster of the
+/// given configuration file: the tools which the launcher runs, the
+/// credentials and kubeconfig which it uses, the configuration file itself, and
+/// whether the cluster's endpoints are reachable.
+pub async fn diagnose(config: &Path, profile: Option<&str>) -> Vec<Diagnosis> {
+    let mut diagnoses = vec![];
+    let daft_config = match read_daft_config(config, profile).await {
+        Ok(daft_config) => {
+            diagnoses.push(Diagnosis::new(
+                "configuration",
+                Status::Pass,
+                format!("{config:?} is valid"),
+            ));
+            Some(daft_config)
+        }
+        Err(error) => {
+            diagnoses.push(Diagnosis::new(
+                "configuration",
+                Status::Fail,
+                error.to_string(),
+            ));
+            None
+        }
+    };
+
+    let provider_config = daft_config
+        .as_ref()
+        .map(|daft_config| &daft_config.setup.provider_config);
+    let is_provisioned = matches!(provider_config, Some(ProviderConfig::Provisioned(..)));
+    let is_byoc = matches!(provider_config, Some(ProviderConfig::Byoc(..)));
+    diagnoses.push(check_tool("ray", &["--version"], is_provisioned).await);
+    diagnoses.push(check_tool("ssh", &["-V"], is_provisioned).await);
+    diagnoses.push(check_tool("kubectl", &["version", "--client"], is_byoc).await);
+
+    match provider_config {
+        Some(ProviderConfig::Provisioned(aws_config)) => {
+            diagnoses.push(check_aws_credentials(aws_config.region()).await);
+            let address = format!("ec2.{}.amazonaws.com:443", aws_config.region());
+            diagnoses.push(check_reachable("AWS EC2 endpoint", &address).await);
+        }
+        Some(ProviderConfig::Byoc(k8s_config)) => {
+            let (diagnosis, server) = check_kubeconfig(k8s_config).await;
+            diagnoses.push(diagnosis);
+            if let Some(address) = server.as_deref().and_then(endpoint_address) {
+                diagnoses.push(check_reachable("Kubernetes API server", &address).await);
+            };
+        }
+        None => (),
+    };
+    diagnoses
+}
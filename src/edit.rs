@@ -0,0 +1,83 @@
+use toml_edit::{DocumentMut, Item, Table, Value};
+
+/// Splits the given dotted path (e.g., `setup.provisioned.number-of-workers`)
+/// into its keys; numeric keys index into arrays (e.g., `job.0.command`).
+fn split_path(path: &str) -> anyhow::Result<Vec<&str>> {
+    let keys = path.split('.').collect::<Vec<_>>();
+    if keys.iter().any(|key| key.is_empty()) {
+        anyhow::bail!("The path {path:?} must be a non-empty list of keys separated by `.`");
+    };
+    Ok(keys)
+}
+
+fn get_item<'a>(item: &'a Item, key: &str) -> Option<&'a Item> {
+    match key.parse::<usize>() {
+        Ok(index) => item.get(index),
+        Err(_) => item.get(key),
+    }
+}
+
+fn get_item_mut<'a>(item: &'a mut Item, key: &str) -> Option<&'a mut Item> {
+    match key.parse::<usize>() {
+        Ok(index) => item.get_mut(index),
+        Err(_) => item.get_mut(key),
+    }
+}
+
+/// Returns the value at the given dotted path in the given configuration file;
+/// strings are returned without their quotes.
+pub fn get(contents: &str, path: &str) -> anyhow::Result<String> {
+    let document = contents.parse::<DocumentMut>()?;
+    let item = split_path(path)?
+        .into_iter()
+        .try_fold(document.as_item(), get_item)
+        .ok_or_else(|| anyhow::anyhow!("The configuration file does not set {path}"))?;
+    Ok(match item {
+        Item::Value(Value::String(string)) => string.value().clone(),
+        Item::Value(value) => value.clone().decorated("", "").to_string(),
+        item => item.to_string().trim().to_string(),
+    })
+}
+
+/// Sets the value at the given dotted path in the given configuration file,
+/// creating any missing tables along the way, and keeping the rest of the file
+/// (including any comment on the value itself) as it was.
+///
+/// The value is parsed as TOML (e.g., `8`, `true` or `["a", "b"]`), except
+/// where it replaces a string, or is not valid TOML, in which case it is taken
+/// to be a string.
+pub fn set(contents: &str, path: &str, raw_value: &str) -> anyhow::Result<String> {
+    let mut document = contents.parse::<DocumentMut>()?;
+    let keys = split_path(path)?;
+    let (last_key, parent_keys) = keys.split_last().expect("the path is not empty");
+
+    let mut parent = document.as_item_mut();
+    for key in parent_keys {
+        if parent.is_table_like() && parent.get(key).is_none() {
+            parent[key] = Item::Table(Table::new());
+        };
+        parent = get_item_mut(parent, key).ok_or_else(|| {
+            anyhow::anyhow!("The configuration file has nothing at {key} in {path}")
+        })?;
+    }
+
+    let parent_is_table = parent.is_table_like();
+    let existing = get_item_mut(parent, last_key);
+    let replaces_string = existing.as_ref().is_some_and(|existing| existing.is_str());
+    let mut value = match raw_value.parse::<Value>() {
+        Ok(value) if !replaces_string => value,
+        _ => Value::from(raw_value),
+    };
+    match existing {
+        Some(Item::Value(existing)) => {
+            *value.decor_mut() = existing.decor().clone();
+            *existing = value;
+        }
+        // Indexing into a table by a missing key adds an empty item for it.
+        Some(item @ Item::None) => *item = Item::Value(value),
+        Some(_) => anyhow::bail!("{path} is a table, so only the values within it can be set"),
+        None if parent_is_table => parent[*last_key] = Item::Value(value),
+        None => anyhow::bail!("{path} cannot be set, since its parent is not a table"),
+    };
+    Ok(document.to_string())
+}
@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::{RayJob, RayJobStatus};
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobEventKind {
+    Submitted,
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+    Stopped,
+}
+
+/// A machine-readable event about a job, emitted as a single line of JSON.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct JobEvent<'a> {
+    pub event: JobEventKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job_name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub submission_id: Option<&'a str>,
+    /// How long the job has been running for, in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u64>,
+    /// Ray's explanation of why the job failed or was stopped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'a str>,
+}
+
+impl<'a> JobEvent<'a> {
+    pub fn submitted(job_name: &'a str, submission_id: &'a str) -> Self {
+        Self {
+            event: JobEventKind::Submitted,
+            job_name: Some(job_name),
+            submission_id: Some(submission_id),
+            duration: None,
+            reason: None,
+        }
+    }
+
+    /// Describes the current status of the given job.
+    pub fn from_ray_job(ray_job: &'a RayJob, now: Duration) -> Self {
+        let event = match ray_job.status {
+            RayJobStatus::Pending => JobEventKind::Pending,
+            RayJobStatus::Running => JobEventKind::Running,
+            RayJobStatus::Succeeded => JobEventKind::Succeeded,
+            RayJobStatus::Failed => JobEventKind::Failed,
+            RayJobStatus::Stopped => JobEventKind::Stopped,
+        };
+        let reason = match event {
+            JobEventKind::Failed | JobEventKind::Stopped => ray_job.message.as_deref(),
+            _ => None,
+        };
+        Self {
+            event,
+            job_name: ray_job.daft_job_name(),
+            submission_id: ray_job.id(),
+            duration: ray_job.duration(now).map(|duration| duration.as_secs()),
+            reason,
+        }
+    }
+}
+
+pub fn emit(event: &JobEvent<'_>) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string(event)?);
+    Ok(())
+}
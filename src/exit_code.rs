@@ -0,0 +1,126 @@
+use std::{error::Error, fmt, iter};
+
+use aws_credential_types::provider::error::CredentialsError;
+use aws_sdk_ssm::error::ProvideErrorMetadata;
+
+/// A class of failure which wrapper scripts can branch on, by the code which
+/// the launcher exits with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// The configuration file could not be read, or is not valid.
+    Config,
+    /// The launcher is not logged in to the cloud (e.g., with the AWS CLI).
+    Auth,
+    /// A request to the cloud's API (e.g., to list instances) failed.
+    CloudApi,
+    /// A job did not succeed.
+    Job,
+    /// A tunnel or port-forward to the cluster could not be established.
+    Tunnel,
+}
+
+/// The code with which the launcher exits on failures of no particular class.
+pub const GENERAL: u8 = 1;
+
+// The code 2 is left to clap, which exits with it on invalid arguments.
+
+impl Failure {
+    pub fn code(self) -> u8 {
+        match self {
+            Self::Config => 3,
+            Self::Auth => 4,
+            Self::CloudApi => 5,
+            Self::Job => 6,
+            Self::Tunnel => 7,
+        }
+    }
+}
+
+/// An error, along with its class of failure; it is shown just as the error
+/// itself is.
+#[derive(Debug)]
+struct Classified {
+    failure: Failure,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for Classified {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl Error for Classified {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.error.source()
+    }
+}
+
+pub trait Classify<T> {
+    /// Marks the error (if any) as a failure of the given class, unless it was
+    /// marked already (by the code closer to its cause).
+    fn classify(self, failure: Failure) -> anyhow::Result<T>;
+}
+
+impl<T, E> Classify<T> for Result<T, E>
+where
+    E: Into<anyhow::Error>,
+{
+    fn classify(self, failure: Failure) -> anyhow::Result<T> {
+        self.map_err(|error| {
+            let error = error.into();
+            if error.chain().any(|cause| cause.is::<Classified>()) {
+                error
+            } else {
+                anyhow::Error::new(Classified { failure, error })
+            }
+        })
+    }
+}
+
+/// The error codes with which AWS refuses a request for its credentials (or
+/// their lack of permissions).
+const AWS_AUTH_ERROR_CODES: &[&str] = &[
+    "AccessDenied",
+    "AccessDeniedException",
+    "AuthFailure",
+    "ExpiredToken",
+    "ExpiredTokenException",
+    "InvalidClientTokenId",
+    "UnauthorizedOperation",
+    "UnrecognizedClientException",
+];
+
+/// Returns the class of the given failed request to AWS, which failed with the
+/// given error code (if any): [`Failure::Auth`] if there were no credentials
+/// to send, or AWS refused them, and [`Failure::CloudApi`] otherwise.
+pub fn of_aws_error(error: &(dyn Error + 'static), code: Option<&str>) -> Failure {
+    let has_no_credentials = iter::successors(Some(error), |error| (*error).source())
+        .any(|cause| cause.is::<CredentialsError>());
+    if has_no_credentials || code.is_some_and(|code| AWS_AUTH_ERROR_CODES.contains(&code)) {
+        Failure::Auth
+    } else {
+        Failure::CloudApi
+    }
+}
+
+/// Marks the given failed request to AWS (if failed) as an authentication
+/// failure or a failure of the cloud's API, by its cause.
+pub fn classify_aws<T, E>(result: Result<T, E>) -> anyhow::Result<T>
+where
+    E: ProvideErrorMetadata + Error + Send + Sync + 'static,
+{
+    result.map_err(|error| {
+        let failure = of_aws_error(&error, error.code());
+        Err::<(), _>(error).classify(failure).unwrap_err()
+    })
+}
+
+/// Returns the code with which to exit on the given error: that of the class
+/// which it was marked as, if any.
+pub fn of(error: &anyhow::Error) -> u8 {
+    error
+        .chain()
+        .find_map(|error| error.downcast_ref::<Classified>())
+        .map_or(GENERAL, |classified| classified.failure.code())
+}
@@ -0,0 +1,37 @@
+use std::{path::Path, process::Stdio};
+
+use tokio::{fs, process::Command};
+
+async fn run_git(dir: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?
+        .wait_with_output()
+        .await?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "`git {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    };
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Checks out the given revision (a branch, tag or commit) of the given
+/// repository into the given (empty) directory, returning the hash of the
+/// commit which was checked out.
+///
+/// Only the requested revision is fetched, and the `.git` directory is removed
+/// afterwards so that it is not uploaded along with the job.
+pub async fn checkout(url: &str, rev: &str, dest: &Path) -> anyhow::Result<String> {
+    run_git(dest, &["init", "--quiet"]).await?;
+    run_git(dest, &["fetch", "--quiet", "--depth", "1", url, rev]).await?;
+    run_git(dest, &["checkout", "--quiet", "FETCH_HEAD"]).await?;
+    let commit = run_git(dest, &["rev-parse", "HEAD"]).await?;
+    fs::remove_dir_all(dest.join(".git")).await?;
+    Ok(commit.trim().to_string())
+}
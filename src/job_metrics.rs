@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::StrRef;
+
+/// How many of the slowest stages are reported.
+const SLOWEST_STAGES: usize = 5;
+
+/// The most tasks which are fetched from the state API for one job.
+const TASK_LIMIT: usize = 10_000;
+
+/// A task of a job, as listed by the Ray state API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Task {
+    /// The function (or actor method) which the task ran, by which tasks are
+    /// grouped into stages.
+    pub name: StrRef,
+    pub state: StrRef,
+    pub start_time_ms: Option<u64>,
+    pub end_time_ms: Option<u64>,
+}
+
+/// The timing of the tasks of a job which ran the same function.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct StageTiming {
+    pub name: StrRef,
+    pub tasks: usize,
+    pub failed_tasks: usize,
+    /// The sum of the durations of the tasks, in milliseconds.
+    pub total_ms: u64,
+    /// The duration of the slowest task, in milliseconds.
+    pub max_ms: u64,
+}
+
+/// A summary of how a job ran, as printed by `job metrics`.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct JobMetrics {
+    pub job_id: StrRef,
+    pub tasks: usize,
+    pub failed_tasks: usize,
+    /// The stages which took the longest in total, slowest first.
+    pub slowest_stages: Vec<StageTiming>,
+    /// The bytes which were spilled from and restored to the object store
+    /// since the cluster started (Ray does not count them per job).
+    pub spilled_bytes: u64,
+    pub restored_bytes: u64,
+    /// The most memory which the cluster's nodes used together while the job
+    /// ran, if Prometheus recorded it (see `[setup.metrics]`).
+    pub peak_memory_bytes: Option<u64>,
+}
+
+/// Returns the path (on the dashboard) which lists the tasks of the given job
+/// through the state API.
+pub fn tasks_path(job_id: &str) -> String {
+    format!("/api/v0/tasks?filter_keys=job_id&filter_predicates=%3D&filter_values={job_id}&detail=true&limit={TASK_LIMIT}")
+}
+
+/// Parses the response of the state API's `/api/v0/tasks` endpoint.
+pub fn parse_tasks(json: &str) -> anyhow::Result<Vec<Task>> {
+    let response = serde_json::from_str::<Value>(json)?;
+    let tasks = response["data"]["result"]["result"]
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("The tasks are missing from the response"))?;
+    Ok(tasks
+        .iter()
+        .map(|task| Task {
+            name: task["func_or_class_name"]
+                .as_str()
+                .or_else(|| task["name"].as_str())
+                .unwrap_or("n/a")
+                .into(),
+            state: task["state"].as_str().unwrap_or("n/a").into(),
+            start_time_ms: task["start_time_ms"].as_u64(),
+            end_time_ms: task["end_time_ms"].as_u64(),
+        })
+        .collect())
+}
+
+/// Parses the spilled and restored bytes of every node from the response of
+/// the dashboard's `/nodes?view=summary` endpoint, returning their totals.
+pub fn parse_spilling(json: &str) -> anyhow::Result<(u64, u64)> {
+    let response = serde_json::from_str::<Value>(json)?;
+    let summary = response["data"]["summary"]
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("The node summary is missing from the response"))?;
+    let total = |key: &str| {
+        summary
+            .iter()
+            .map(|node| {
+                node["raylet"]["storeStats"][key]
+                    .as_f64()
+                    .unwrap_or_default() as u64
+            })
+            .sum::<u64>()
+    };
+    Ok((total("spilledBytesTotal"), total("restoredBytesTotal")))
+}
+
+/// Returns the Prometheus query for the most memory which the cluster's nodes
+/// used together in the given number of seconds.
+pub fn peak_memory_query(seconds: u64) -> String {
+    format!("max_over_time(sum(ray_node_mem_used)[{seconds}s:15s])")
+}
+
+/// Parses the (scalar) result of a Prometheus instant query.
+pub fn parse_prometheus_value(json: &str) -> Option<u64> {
+    let response = serde_json::from_str::<Value>(json).ok()?;
+    let value = response["data"]["result"].as_array()?.first()?["value"][1].as_str()?;
+    value.parse::<f64>().ok().map(|value| value as u64)
+}
+
+/// Groups the given tasks into stages by their function, returning the
+/// slowest of them (by their total duration) first.
+pub fn slowest_stages(tasks: &[Task]) -> Vec<StageTiming> {
+    let mut stages = BTreeMap::<&str, StageTiming>::new();
+    for task in tasks {
+        let stage = stages.entry(&task.name).or_insert_with(|| StageTiming {
+            name: task.name.clone(),
+            tasks: 0,
+            failed_tasks: 0,
+            total_ms: 0,
+            max_ms: 0,
+        });
+        stage.tasks += 1;
+        if &*task.state == "FAILED" {
+            stage.failed_tasks += 1;
+        };
+        if let Some((start, end)) = task.start_time_ms.zip(task.end_time_ms) {
+            let duration = end.saturating_sub(start);
+            stage.total_ms += duration;
+            stage.max_ms = stage.max_ms.max(duration);
+        };
+    }
+    let mut stages = stages.into_values().collect::<Vec<_>>();
+    stages.sort_by(|a, b| {
+        b.total_ms
+            .cmp(&a.total_ms)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    stages.truncate(SLOWEST_STAGES);
+    stages
+}
+
+/// Formats the given number of bytes in MiB or GiB.
+pub fn format_bytes(bytes: u64) -> String {
+    const MIB: f64 = 1024.0 * 1024.0;
+    let mib = bytes as f64 / MIB;
+    if mib < 1024.0 {
+        format!("{mib:.1} MiB")
+    } else {
+        format!("{:.1} GiB", mib / 1024.0)
+    }
+}
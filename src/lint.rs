@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+
+use crate::{working_dir, AwsConfig, DaftConfig, JobSource, ProviderConfig};
+
+/// The size (in MiB) above which a single file in a job's working directory is
+/// reported.
+const HUGE_FILE_MIB: u64 = 50;
+
+/// Reported by `config lint`, and whenever a BYOC configuration with file
+/// mounts is read, since the files would otherwise silently be missing.
+pub const BYOC_FILE_MOUNTS_WARNING: &str = "`setup.file-mounts` only applies to provisioned clusters, so it has no effect on this BYOC cluster; mount the files into the cluster's pods instead";
+
+/// The number of workers above which a cluster of small instances is reported.
+const MANY_WORKERS: usize = 8;
+
+/// The sizes of the EC2 instance types which are too small to run many Ray
+/// workers well.
+const SMALL_INSTANCE_SIZES: &[&str] = &["nano", "micro", "small", "medium"];
+
+/// Returns the package name and version of the given dependency, if it is
+/// pinned to an exact version (e.g., `ray[default]==2.34.0`).
+pub fn pinned_version(dependency: &str) -> Option<(&str, &str)> {
+    let (name, version) = dependency.split_once("==")?;
+    let name = name.split('[').next().unwrap_or(name).trim();
+    Some((name, version.trim()))
+}
+
+/// Returns whether the given version (e.g., `2.34.0`) is one of the versions
+/// which the given requirement (e.g., `2.34`) describes.
+fn version_matches(version: &str, requirement: &str) -> bool {
+    version == requirement
+        || version
+            .strip_prefix(requirement)
+            .is_some_and(|rest| rest.starts_with('.'))
+}
+
+fn lint_aws_config(aws_config: &AwsConfig, warnings: &mut Vec<String>) {
+    if aws_config.iam_instance_profile_name.is_none() {
+        warnings.push("`setup.provisioned` has no `iam-instance-profile-name`, so the cluster's nodes cannot access AWS (e.g., S3); set it to an instance profile with the permissions which your jobs need".to_string());
+    };
+
+    let size = aws_config
+        .instance_type
+        .split_once('.')
+        .map(|(_, size)| size)
+        .unwrap_or_default();
+    if SMALL_INSTANCE_SIZES.contains(&size) && aws_config.number_of_workers > MANY_WORKERS {
+        warnings.push(format!(
+            "{} workers of the small instance type {} will spend much of their memory on Ray itself; consider fewer workers of a larger instance type",
+            aws_config.number_of_workers, aws_config.instance_type
+        ));
+    };
+
+    for command in &aws_config.run {
+        if command.contains("dashboard-host") && command.contains("0.0.0.0") {
+            warnings.push(format!(
+                "The setup command `{command}` makes the Ray dashboard listen on every interface, which lets anyone who can reach the head node submit jobs; use `daft provisioned connect` to reach the dashboard through SSH instead"
+            ));
+        };
+    }
+}
+
+/// Reports any dependencies which are pinned to versions that conflict with
+/// the cluster's Ray version, or with each other.
+fn lint_dependencies(daft_config: &DaftConfig, warnings: &mut Vec<String>) {
+    let mut dependencies = vec![];
+    if let ProviderConfig::Provisioned(aws_config) = &daft_config.setup.provider_config {
+        // Dependencies which cannot be read are left to `config check` and
+        // `provisioned up` to report.
+        dependencies.extend(
+            aws_config
+                .dependencies
+                .resolve()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|dependency| ("`setup.provisioned`".to_string(), dependency)),
+        );
+    };
+    let mut job_names = daft_config.jobs.keys().collect::<Vec<_>>();
+    job_names.sort();
+    for name in job_names {
+        dependencies.extend(
+            daft_config.jobs[name]
+                .dependencies
+                .iter()
+                .cloned()
+                .map(|dependency| (format!("the job {name}"), dependency)),
+        );
+    }
+
+    let ray_version = daft_config.setup.ray_version.to_string();
+    let mut pins = BTreeMap::<&str, (String, &str)>::new();
+    for (owner, dependency) in &dependencies {
+        let Some((package, version)) = pinned_version(dependency) else {
+            continue;
+        };
+        if package == "ray" && !version_matches(version, &ray_version) {
+            warnings.push(format!(
+                "{owner} pins ray=={version}, but the cluster runs Ray {ray_version}; remove the pin or change `setup.ray-version`"
+            ));
+        };
+        match pins.get(package) {
+            Some((other_owner, other_version)) if other_version != &version => {
+                warnings.push(format!(
+                    "{owner} pins {package}=={version}, but {other_owner} pins {package}=={other_version}; only one of them can be installed"
+                ))
+            }
+            Some(_) => (),
+            None => {
+                pins.insert(package, (owner.clone(), version));
+            }
+        };
+    }
+}
+
+/// Reports any files in the jobs' working directories which are large enough
+/// to slow down every submission.
+fn lint_working_dirs(daft_config: &DaftConfig, warnings: &mut Vec<String>) {
+    let mut job_names = daft_config.jobs.keys().collect::<Vec<_>>();
+    job_names.sort();
+    for name in job_names {
+        let job = &daft_config.jobs[name];
+        let JobSource::WorkingDir(working_dir) = &job.source else {
+            continue;
+        };
+        if job.upload_to.is_some() {
+            continue;
+        };
+        // Working directories which do not exist (yet) are left to
+        // `config check` and `job submit` to report.
+        let Ok(entries) = working_dir::walk(working_dir, &job.excludes) else {
+            continue;
+        };
+        for entry in entries {
+            if entry.size > HUGE_FILE_MIB * 1024 * 1024 {
+                warnings.push(format!(
+                    "The job {name} uploads {} ({} MiB) on every submission; add it to the job's `excludes`, or set `upload-to` to upload through S3",
+                    working_dir.join(&entry.relative_path).display(),
+                    entry.size / (1024 * 1024)
+                ));
+            };
+        }
+    }
+}
+
+/// Returns warnings about settings in the given configuration which are valid,
+/// but likely to cause problems, each with a suggestion of how to fix it.
+pub fn lint(daft_config: &DaftConfig) -> Vec<String> {
+    let mut warnings = vec![];
+    match &daft_config.setup.provider_config {
+        ProviderConfig::Provisioned(aws_config) => lint_aws_config(aws_config, &mut warnings),
+        ProviderConfig::Byoc(..) => {
+            if !daft_config.setup.file_mounts.is_empty() {
+                warnings.push(BYOC_FILE_MOUNTS_WARNING.to_string());
+            };
+            if daft_config.setup.metrics.is_some() {
+                warnings.push("`setup.metrics` only applies to provisioned clusters, so it has no effect on this BYOC cluster; use the cluster's own monitoring (e.g., the KubeRay Prometheus integration) instead".to_string());
+            };
+        }
+    };
+    lint_dependencies(daft_config, &mut warnings);
+    lint_working_dirs(daft_config, &mut warnings);
+    warnings
+}
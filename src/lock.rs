@@ -0,0 +1,203 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_ssm::{
+    error::ProvideErrorMetadata, operation::put_parameter::PutParameterError, types::ParameterType,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    current_user,
+    exit_code::{self, Classify},
+    logging, StrRef,
+};
+
+/// The error codes with which AWS refuses a request for lack of permissions.
+const PERMISSION_ERROR_CODES: &[&str] = &[
+    "AccessDenied",
+    "AccessDeniedException",
+    "UnauthorizedOperation",
+];
+
+/// The SSM parameter under which the lock on the cluster with the given name
+/// is kept, in the cluster's region.
+pub fn parameter_name(cluster: &str) -> String {
+    format!("/daft-launcher/locks/{cluster}")
+}
+
+/// Returns whether a request which failed with the given error code (if any)
+/// was refused for lack of permissions, in which case clusters are not locked
+/// (rather than not spun up or down at all).
+pub fn is_permission_error(code: Option<&str>) -> bool {
+    code.is_some_and(|code| PERMISSION_ERROR_CODES.contains(&code))
+}
+
+/// Who holds the lock on a cluster, as stored in its SSM parameter.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Holder {
+    pub user: StrRef,
+    /// The command which the holder is running (e.g., `up`).
+    pub command: StrRef,
+    /// The time at which the lock was taken, in seconds since the Unix epoch.
+    pub since: u64,
+}
+
+/// Formats the given time (in seconds since the Unix epoch) to the minute, in
+/// UTC, e.g. `2024-05-01 10:24 UTC`.
+fn format_time(secs: u64) -> String {
+    let date_time = aws_sdk_ssm::primitives::DateTime::from(UNIX_EPOCH + Duration::from_secs(secs))
+        .fmt(aws_sdk_ssm::primitives::DateTimeFormat::DateTime)
+        .unwrap_or_default();
+    match (date_time.get(..10), date_time.get(11..16)) {
+        (Some(date), Some(time)) => format!("{date} {time} UTC"),
+        _ => date_time,
+    }
+}
+
+/// Describes why the given cluster cannot be locked by anyone else.
+pub fn describe_conflict(cluster: &str, holder: &Holder) -> String {
+    format!(
+        "The cluster {cluster} is locked by {} since {} (running `{}`); wait for that to finish, or pass `--force` if it was interrupted",
+        holder.user,
+        format_time(holder.since),
+        holder.command
+    )
+}
+
+/// An advisory lock on a cluster, which keeps two people from spinning it up
+/// or down at once; it is held until [`Lock::release`] is called.
+pub struct Lock {
+    /// `None` when the lock could not be taken for a reason other than someone
+    /// else holding it (e.g., missing SSM permissions), in which case the
+    /// command goes ahead unlocked.
+    parameter: Option<LockParameter>,
+}
+
+struct LockParameter {
+    client: aws_sdk_ssm::Client,
+    name: String,
+    /// The version of the parameter which was written when taking the lock,
+    /// to tell whether someone has taken it over (with `--force`) since.
+    version: i64,
+}
+
+/// Takes the lock on the cluster with the given name in the given region for
+/// the given command, failing if someone else holds it (unless forced, in
+/// which case it is taken over).
+///
+/// If the lock cannot be written for lack of permissions (or at all, when
+/// forced), the command goes ahead unlocked; any other failure to write it
+/// (e.g., throttling) fails the command.
+pub async fn acquire(
+    region: &str,
+    cluster: &str,
+    command: &str,
+    force: bool,
+) -> anyhow::Result<Lock> {
+    let sdk_config = aws_config::defaults(BehaviorVersion::latest())
+        .region(Region::new(region.to_string()))
+        .load()
+        .await;
+    let client = aws_sdk_ssm::Client::new(&sdk_config);
+    let name = parameter_name(cluster);
+    let holder = Holder {
+        user: current_user(),
+        command: command.into(),
+        since: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    };
+    let result = client
+        .put_parameter()
+        .name(&name)
+        .value(serde_json::to_string(&holder)?)
+        .r#type(ParameterType::String)
+        .overwrite(force)
+        .send()
+        .await;
+    match result {
+        Ok(output) => Ok(Lock {
+            parameter: Some(LockParameter {
+                client,
+                name,
+                version: output.version,
+            }),
+        }),
+        Err(error)
+            if matches!(
+                error.as_service_error(),
+                Some(PutParameterError::ParameterAlreadyExists(_))
+            ) =>
+        {
+            let holder = client
+                .get_parameter()
+                .name(&name)
+                .send()
+                .await
+                .ok()
+                .and_then(|output| output.parameter?.value)
+                .and_then(|value| serde_json::from_str::<Holder>(&value).ok());
+            match holder {
+                Some(holder) => anyhow::bail!(describe_conflict(cluster, &holder)),
+                None => anyhow::bail!(
+                    "The cluster {cluster} is locked (by the SSM parameter {name}); pass `--force` if that is stale"
+                ),
+            }
+        }
+        Err(error) if is_permission_error(error.code()) || force => {
+            logging::warn(format!(
+                "Going ahead without locking the cluster {cluster}, since the SSM parameter {name} could not be written: {error}"
+            ));
+            Ok(Lock { parameter: None })
+        }
+        Err(error) => {
+            let failure = exit_code::of_aws_error(&error, error.code());
+            Err(anyhow::anyhow!(
+                "Failed to lock the cluster {cluster}, since the SSM parameter {name} could not be written: {error}; try again, or pass `--force` to go ahead without the lock"
+            ))
+            .classify(failure)
+        }
+    }
+}
+
+impl Lock {
+    /// Releases the lock, unless someone else has taken it over since; failing
+    /// to is only warned about, since the command itself has finished by then.
+    pub async fn release(self) {
+        let Some(LockParameter {
+            client,
+            name,
+            version,
+        }) = self.parameter
+        else {
+            return;
+        };
+        let current_version = client
+            .get_parameter()
+            .name(&name)
+            .send()
+            .await
+            .map(|output| output.parameter.map(|parameter| parameter.version));
+        match current_version {
+            Ok(Some(current_version)) if current_version == version => (),
+            Ok(_) => {
+                logging::warn(format!(
+                    "Left the lock {name} alone, since it is no longer the one this command took (e.g., someone took it over with `--force`)"
+                ));
+                return;
+            }
+            Err(error) => {
+                logging::warn(format!(
+                    "Failed to release the lock {name}; the next command on the cluster will need `--force`: {error}"
+                ));
+                return;
+            }
+        };
+        if let Err(error) = client.delete_parameter().name(&name).send().await {
+            logging::warn(format!(
+                "Failed to release the lock {name}; the next command on the cluster will need `--force`: {error}"
+            ));
+        };
+    }
+}
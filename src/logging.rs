@@ -0,0 +1,187 @@
+use std::{
+    fmt::Display,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    process::{ExitStatus, Stdio},
+    sync::{Mutex, OnceLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::Command,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+        }
+    }
+}
+
+struct Logger {
+    /// The most verbose level which is shown on the terminal.
+    verbosity: Level,
+    /// The file to which every record (of any level) is appended, if any.
+    file: Option<Mutex<File>>,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+fn logger() -> &'static Logger {
+    LOGGER.get_or_init(|| Logger {
+        verbosity: Level::Info,
+        file: None,
+    })
+}
+
+/// Returns the level up to which messages are shown on the terminal, given the
+/// number of `--verbose` flags and whether `--quiet` was given.
+pub fn verbosity(verbose: u8, quiet: bool) -> Level {
+    match (quiet, verbose) {
+        (true, _) => Level::Warn,
+        (false, 0) => Level::Info,
+        (false, _) => Level::Debug,
+    }
+}
+
+/// Sets up logging for the rest of the process; only the first call has any
+/// effect.
+pub fn init(verbosity: Level, log_file: Option<&Path>) -> anyhow::Result<()> {
+    let file = log_file
+        .map(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|error| {
+                    anyhow::anyhow!("The log file {path:?} could not be opened: {error}")
+                })
+        })
+        .transpose()?
+        .map(Mutex::new);
+    let _ = LOGGER.set(Logger { verbosity, file });
+    Ok(())
+}
+
+/// Formats a record for the log file, as a line of logfmt.
+pub fn format_record(timestamp: Duration, level: Level, message: &str) -> String {
+    format!(
+        "ts={}.{:03} level={} msg={message:?}",
+        timestamp.as_secs(),
+        timestamp.subsec_millis(),
+        level.as_str()
+    )
+}
+
+fn write_to_file(level: Level, message: &str) {
+    let Some(file) = &logger().file else {
+        return;
+    };
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let mut file = file.lock().unwrap_or_else(|error| error.into_inner());
+    // Failing to log is not worth failing the command for.
+    let _ = writeln!(file, "{}", format_record(timestamp, level, message));
+}
+
+/// Logs the given message, which is shown on the terminal (on stderr) if the
+/// verbosity allows it.
+pub fn log(level: Level, message: impl Display) {
+    let message = message.to_string();
+    write_to_file(level, &message);
+    if level <= logger().verbosity {
+        eprintln!("{message}");
+    };
+}
+
+pub fn warn(message: impl Display) {
+    log(Level::Warn, message);
+}
+
+pub fn info(message: impl Display) {
+    log(Level::Info, message);
+}
+
+pub fn debug(message: impl Display) {
+    log(Level::Debug, message);
+}
+
+/// Returns whether `--verbose` was given.
+pub fn is_verbose() -> bool {
+    logger().verbosity >= Level::Debug
+}
+
+/// Returns whether the raw output of external commands (e.g., `scp`) is shown
+/// on the terminal: always with `--verbose`, and otherwise unless it is
+/// captured in the log file instead (or hidden by `--quiet`).
+fn shows_raw_output() -> bool {
+    let logger = logger();
+    logger.verbosity >= Level::Debug || (logger.file.is_none() && logger.verbosity >= Level::Info)
+}
+
+async fn forward(
+    stream: impl AsyncRead + Unpin,
+    is_stderr: bool,
+    show: bool,
+    on_line: &(dyn Fn(&str) + Sync),
+) -> std::io::Result<()> {
+    let mut lines = BufReader::new(stream).lines();
+    while let Some(line) = lines.next_line().await? {
+        write_to_file(Level::Debug, &line);
+        on_line(&line);
+        if show {
+            if is_stderr {
+                eprintln!("{line}");
+            } else {
+                println!("{line}");
+            };
+        };
+    }
+    Ok(())
+}
+
+/// Runs the given command to completion, logging its output line by line (and
+/// showing it on the terminal, as [`shows_raw_output`] says).
+pub async fn run(command: &mut Command) -> anyhow::Result<ExitStatus> {
+    run_with(command, shows_raw_output(), &|_| ()).await
+}
+
+/// Runs the given command to completion, logging its output line by line and
+/// passing each line to `on_line`; the output is shown on the terminal only if
+/// `show` is set.
+pub async fn run_with(
+    command: &mut Command,
+    show: bool,
+    on_line: &(dyn Fn(&str) + Sync),
+) -> anyhow::Result<ExitStatus> {
+    debug(format!("Running {:?}", command.as_std()));
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let (status, (), ()) = tokio::try_join!(
+        child.wait(),
+        forward(stdout, false, show, on_line),
+        forward(stderr, true, show, on_line)
+    )?;
+    debug(format!(
+        "{:?} exited with {status}",
+        command.as_std().get_program()
+    ));
+    Ok(status)
+}
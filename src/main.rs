@@ -26,31 +26,91 @@ macro_rules! not_available_for_byoc {
     };
 }
 
+/// Fails on Windows, for the commands which rely on Unix tools (such as
+/// `rsync` or `crontab`).
+macro_rules! not_available_on_windows {
+    ($command:literal, $instead:literal) => {
+        if cfg!(windows) {
+            anyhow::bail!(concat!(
+                "The command `",
+                $command,
+                "` is not available on Windows; ",
+                $instead
+            ))
+        }
+    };
+}
+
+mod adopt;
+mod autoscaler_log;
+mod cloud_check;
+mod compat;
+mod completion;
+mod config;
+mod cost;
+mod diff;
+mod doctor;
+mod edit;
+mod events;
+mod exit_code;
+mod git;
+mod job_metrics;
+mod lint;
+mod lock;
+mod logging;
+mod manpages;
+mod metrics;
+mod migrate;
+mod monitor;
+mod notifications;
+mod outputs;
+mod pricing;
+mod progress;
+mod ray_import;
+mod ray_jobs;
+mod s3;
+mod schedule;
+mod secrets;
+mod sql;
 mod ssh;
+mod ssm;
+mod state;
 #[cfg(test)]
 mod tests;
+mod tunnel;
+mod watch;
+mod wizard;
+mod working_dir;
 
 use std::{
-    collections::HashMap,
-    io::{Error, ErrorKind},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    io::IsTerminal,
     net::Ipv4Addr,
     path::{Path, PathBuf},
-    process::Stdio,
+    process::{ExitCode, Stdio},
     str::FromStr,
-    sync::Arc,
-    time::Duration,
+    sync::{Arc, OnceLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 #[cfg(not(test))]
 use anyhow::bail;
 use aws_config::{BehaviorVersion, Region};
-use aws_sdk_ec2::{types::InstanceStateName, Client};
-use clap::{Parser, Subcommand, ValueEnum};
+use aws_sdk_ec2::{
+    types::{Filter, InstanceStateName},
+    Client,
+};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::engine::ArgValueCandidates;
 use comfy_table::{
     modifiers, presets, Attribute, Cell, CellAlignment, Color, ContentArrangement, Table,
 };
+use exit_code::{Classify, Failure};
+use ray_jobs::RayJobsClient;
 use regex::Regex;
+use secrets::Secrets;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tempdir::TempDir;
 use tokio::{
     fs,
@@ -61,11 +121,82 @@ use versions::{Requirement, Versioning};
 type StrRef = Arc<str>;
 type PathRef = Arc<Path>;
 
+const EXAMPLES: &str = "\
+Examples:
+  # Create a configuration, spin up a cluster, run a job on it and spin it down
+  daft config init
+  daft provisioned up
+  daft job submit example-job
+  daft provisioned down
+
+  # The same, for an existing Kubernetes cluster
+  daft config init --provider byoc
+  daft byoc info
+  daft job submit example-job";
+
+const UP_EXAMPLES: &str = "\
+Examples:
+  daft provisioned up
+  daft provisioned up --workers 8 --instance-type i3.4xlarge
+  daft provisioned up custom.daft.toml --profile prod";
+
+const SUBMIT_EXAMPLES: &str = "\
+Examples:
+  daft job submit example-job
+  daft job submit example-job -- --date 2024-01-01
+  daft job submit train --set lr=0.01
+  daft job submit example-job --dry-run";
+
+const INIT_EXAMPLES: &str = "\
+Examples:
+  daft config init
+  daft config init --provider byoc
+  daft config init --template gpu --region us-east-1
+  daft config init --interactive";
+
 #[derive(Debug, Parser, Clone, PartialEq, Eq)]
-#[command(name = env!("CARGO_PKG_NAME"), version = env!("CARGO_PKG_VERSION"), about = env!("CARGO_PKG_DESCRIPTION"))]
+#[command(name = env!("CARGO_PKG_NAME"), version = env!("CARGO_PKG_VERSION"), about = env!("CARGO_PKG_DESCRIPTION"), after_long_help = EXAMPLES)]
 struct DaftLauncher {
     #[command(subcommand)]
     sub_command: SubCommand,
+
+    /// The profile in the configuration file whose overrides to apply.
+    #[arg(long, global = true)]
+    profile: Option<StrRef>,
+
+    /// The AWS region to use, instead of the configuration's (for `config
+    /// init`, the region to configure the cluster in). When neither is given,
+    /// the AWS CLI's default region (e.g., `AWS_REGION`) is used.
+    #[arg(long, global = true)]
+    region: Option<StrRef>,
+
+    /// The format in which to print the results of the command; `json` prints
+    /// one JSON object per line (e.g., per instance of `provisioned list`),
+    /// and makes `job submit` emit one event per line instead of streaming the
+    /// job's logs; `csv` prints the rows of `list`, `provisioned list`,
+    /// `history` and `cost` as CSV.
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    /// Show more detail, including the commands which are run and their raw
+    /// output.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Only show warnings, errors and the results of the command.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Append full debug logs (including the raw output of `ray`, `kubectl`
+    /// and `ssh`) to the given file, instead of showing the raw output on the
+    /// terminal.
+    #[arg(long, global = true)]
+    log_file: Option<PathBuf>,
+
+    /// Never ask questions (failing instead, if an answer is needed), as when
+    /// the standard input is not a terminal (e.g., in CI).
+    #[arg(long, global = true)]
+    non_interactive: bool,
 }
 
 #[derive(Debug, Subcommand, Clone, PartialEq, Eq)]
@@ -85,18 +216,142 @@ enum SubCommand {
     /// Manage configurations
     #[command(subcommand)]
     Config(ConfigCommand),
+
+    /// Connect local programs to a cluster
+    #[command(subcommand)]
+    Connect(ConnectCommand),
+
+    /// Manage the tunnels which `provisioned connect --detach` opened in the
+    /// background
+    #[command(subcommand)]
+    Tunnel(TunnelCommand),
+
+    /// Spin up the cluster, whatever its provider (`provisioned up`, or `byoc
+    /// info` to check that an existing cluster is up)
+    #[command(after_long_help = UP_EXAMPLES)]
+    Up(Up),
+
+    /// Spin down the cluster, whatever its provider (`provisioned down`)
+    Down(Down),
+
+    /// Submit a job to the cluster, whatever its provider (`job submit`)
+    #[command(after_long_help = SUBMIT_EXAMPLES)]
+    Submit(Submit),
+
+    /// List the clusters of every provider: the Ray clusters in the AWS region
+    /// and the KubeRay clusters in the Kubernetes contexts
+    List(ListClusters),
+
+    /// Show the local audit log of the clusters which were spun up, spun down
+    /// or killed, and the jobs which were submitted, from this machine
+    History(ActionHistory),
+
+    /// Report the spend of each cluster per day, from AWS Cost Explorer (by
+    /// the `ray-cluster-name` tag, which must be activated as a cost allocation
+    /// tag)
+    Cost(Cost),
+
+    /// Tear down the clusters in the AWS region which are past their
+    /// `ttl-hours` (or older than `--older-than`), after listing them and
+    /// asking for confirmation, e.g. nightly from cron
+    Gc(Gc),
+
+    /// Diagnose the environment: the tools the launcher runs, its credentials
+    /// and kubeconfig, the configuration file, and the cluster's endpoints
+    Doctor(ConfigPath),
+
+    /// Print the script which enables completions for the given shell (e.g.,
+    /// `source <(daft completion bash)`)
+    Completion(Completion),
+
+    /// Write the man pages of the launcher and its commands into the given
+    /// directory (e.g., `/usr/local/share/man/man1`)
+    Manpages(Manpages),
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct ListClusters {
+    /// List the KubeRay clusters in the given Kubernetes context (which may be
+    /// given more than once), instead of in that of the configuration (or the
+    /// current context).
+    #[arg(long = "context")]
+    contexts: Vec<StrRef>,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct ActionHistory {
+    /// Only show the actions on the cluster with the given name.
+    #[arg(long)]
+    cluster: Option<StrRef>,
+
+    /// The number of most recent actions to show.
+    #[arg(long, default_value = "20")]
+    limit: usize,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct TunnelStop {
+    /// The name of the cluster whose tunnels to close.
+    #[arg(required_unless_present = "all")]
+    cluster: Option<StrRef>,
+
+    /// Close the tunnels to every cluster.
+    #[arg(long, conflicts_with = "cluster")]
+    all: bool,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Cost {
+    /// How far back to report, in days or weeks (e.g., `7d` or `2w`).
+    #[arg(long, default_value = "7d", value_parser = cost::parse_since)]
+    since: u32,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Gc {
+    /// Also tear down the clusters spun up by daft-launcher whose head node
+    /// has been up for longer than this (e.g., `24h`, `90m` or `7d`).
+    #[arg(long, value_parser = parse_age)]
+    older_than: Option<Duration>,
+
+    /// List the stale clusters without tearing them down.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Tear the stale clusters down without asking for confirmation first.
+    #[arg(short, long)]
+    yes: bool,
+
+    #[clap(flatten)]
+    lock_args: LockArgs,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Manpages {
+    /// The directory to write the man pages into.
+    dir: PathBuf,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Completion {
+    /// The shell to enable completions for.
+    shell: completion::CompletionShell,
 }
 
 #[derive(Debug, Subcommand, Clone, PartialEq, Eq)]
 enum ProvisionedCommand {
     /// Create a new cluster
-    Up(ConfigPath),
+    #[command(after_long_help = UP_EXAMPLES)]
+    Up(Up),
 
     /// Stop a running cluster
-    Down(ConfigPath),
+    Down(Down),
 
     /// Terminate a cluster
-    Kill(ConfigPath),
+    Kill(Kill),
 
     /// List all clusters
     List(List),
@@ -104,8 +359,32 @@ enum ProvisionedCommand {
     /// Connect to cluster dashboard
     Connect(Connect),
 
+    /// Show a live view of the cluster's nodes, running jobs and autoscaler
+    /// events in the terminal
+    Monitor(Monitor),
+
+    /// Show the autoscaler's events (nodes launched, nodes which failed to
+    /// launch, scale downs) from its logs on the head node
+    Events(Events),
+
+    /// Run a shell command on the head node (or on every node) and print each
+    /// node's output
+    Exec(Exec),
+
+    /// Copy a local file or directory to the head node
+    Cp(Cp),
+
+    /// Sync a local directory to the head node, sending only the files which
+    /// changed
+    Sync(SyncDir),
+
     /// SSH into cluster head node
     Ssh(ConfigPath),
+
+    /// Adopt a running Ray cluster which was launched some other way (e.g.,
+    /// with `ray up`, or by someone else), by creating a configuration file for
+    /// it and recording it as one of this machine's clusters
+    Adopt(Adopt),
 }
 
 #[derive(Debug, Subcommand, Clone, PartialEq, Eq)]
@@ -117,31 +396,223 @@ enum ByocCommand {
     Info(ConfigPath),
 }
 
+#[derive(Debug, Subcommand, Clone, PartialEq, Eq)]
+enum ConnectCommand {
+    /// Open a tunnel to the cluster in the background and print the shell
+    /// exports which point Ray and Daft at it, e.g., `eval "$(daft connect env)"`
+    Env(ConfigPath),
+}
+
+#[derive(Debug, Subcommand, Clone, PartialEq, Eq)]
+enum TunnelCommand {
+    /// List the open tunnels
+    List,
+
+    /// Close the tunnels to a cluster
+    Stop(TunnelStop),
+}
+
 #[derive(Debug, Subcommand, Clone, PartialEq, Eq)]
 enum JobCommand {
     /// Submit a job to the cluster
+    #[command(after_long_help = SUBMIT_EXAMPLES)]
     Submit(Submit),
 
     /// Execute SQL queries
     Sql(Sql),
 
+    /// List all jobs on the cluster, along with the jobs defined in the config
+    List(ConfigPath),
+
+    /// Submit multiple jobs in parallel and wait for all of them to finish
+    SubmitAll(SubmitAll),
+
+    /// Submit a one-off job which is not defined in the config
+    Run(Run),
+
+    /// Submit jobs in dependency order, each once its dependencies succeed
+    RunDag(SubmitAll),
+
+    /// Stop a running job
+    Stop(Stop),
+
+    /// Submit a job periodically on a cron schedule
+    Schedule(Schedule),
+
+    /// Resume streaming the logs of an already-submitted job
+    Attach(JobReference),
+
     /// Check job status
-    Status(ConfigPath),
+    Status(Status),
 
     /// View job logs
-    Logs(ConfigPath),
+    Logs(JobReference),
+
+    /// Summarize how a job ran: its slowest stages, the object store's
+    /// spilling and the cluster's peak memory
+    Metrics(JobReference),
+
+    /// Show the outcomes of past submissions to the cluster
+    History(History),
+
+    /// Download the outputs of a job's most recent (successful) submission
+    Fetch(Fetch),
 }
 
 #[derive(Debug, Subcommand, Clone, PartialEq, Eq)]
 enum ConfigCommand {
     /// Initialize a new configuration
+    #[command(after_long_help = INIT_EXAMPLES)]
     Init(Init),
 
     /// Validate configuration
-    Check(ConfigPath),
+    Check(Check),
 
     /// Export configuration to Ray format
     Export(ConfigPath),
+
+    /// Warn about settings which are valid, but likely to cause problems
+    Lint(ConfigPath),
+
+    /// Print the differences between the effective settings of two
+    /// configurations
+    Diff(Diff),
+
+    /// Rewrite a configuration from an older schema to the current one
+    Migrate(Migrate),
+
+    /// Generate a configuration from an existing Ray autoscaler configuration
+    Import(Import),
+
+    /// Print the configuration with all defaults filled in and paths expanded
+    Show(Show),
+
+    /// Print the value at a dotted path in the configuration file
+    Get(Get),
+
+    /// Set the value at a dotted path in the configuration file, in place
+    Set(Set),
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Get {
+    /// The dotted path of the value, e.g. `setup.provisioned.number-of-workers`
+    /// (or `job.0.command` for the first job).
+    key: StrRef,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Set {
+    /// The dotted path of the value, e.g. `setup.provisioned.number-of-workers`
+    /// (or `job.0.command` for the first job).
+    key: StrRef,
+
+    /// The new value, as TOML (e.g., `8` or `["a", "b"]`); values which are
+    /// not valid TOML, or which replace strings, are set as strings.
+    value: StrRef,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Show {
+    /// The format in which to print the configuration.
+    #[arg(long, value_enum, default_value_t = ShowFormat::Toml)]
+    format: ShowFormat,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
+}
+
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+enum ShowFormat {
+    Toml,
+    Json,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Check {
+    /// Also validate the configuration against the cloud account (e.g., that
+    /// the instance type is offered in the region, and the AMI and key pair
+    /// exist).
+    #[arg(long)]
+    strict: bool,
+
+    /// If the configuration requires a version of the launcher other than this
+    /// one, change the requirement to this version (after confirming).
+    #[arg(long)]
+    fix_version: bool,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Kill {
+    /// Terminate the cluster's instances without listing them and asking for
+    /// confirmation first.
+    #[arg(short, long)]
+    yes: bool,
+
+    #[clap(flatten)]
+    lock_args: LockArgs,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Diff {
+    /// The path of the configuration file to compare from.
+    old: PathBuf,
+
+    /// The path of the configuration file to compare to.
+    new: PathBuf,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Import {
+    /// The path of the Ray autoscaler configuration (YAML) to import.
+    ray_config: PathBuf,
+
+    /// The path at which to create the config file.
+    #[arg(long, default_value = ".daft.toml")]
+    to: PathBuf,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Adopt {
+    /// The name of the Ray cluster (its `ray-cluster-name` tag); the region is
+    /// given with `--region` (or else is the AWS CLI's default region).
+    #[arg(long)]
+    name: StrRef,
+
+    /// The user to SSH into the nodes as [default: ubuntu].
+    #[arg(long)]
+    ssh_user: Option<StrRef>,
+
+    /// The SSH private key of the nodes [default: `~/.ssh/<key pair name>`,
+    /// after the nodes' key pair].
+    #[arg(long)]
+    ssh_private_key: Option<PathBuf>,
+
+    /// The path at which to create the config file.
+    #[arg(long, default_value = ".daft.toml")]
+    to: PathBuf,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Migrate {
+    /// Print the migrated configuration instead of rewriting the file.
+    #[arg(long)]
+    dry_run: bool,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
 }
 
 #[derive(Debug, Parser, Clone, PartialEq, Eq)]
@@ -154,6 +625,110 @@ struct Init {
     /// or 'k8s' for existing Kubernetes clusters
     #[arg(long, default_value_t = DaftProvider::Provisioned)]
     provider: DaftProvider,
+
+    /// The kind of cluster to configure (for the provisioned provider only).
+    #[arg(long, value_enum, default_value_t = InitTemplate::Default)]
+    template: InitTemplate,
+
+    /// Ask for the cluster's settings, rather than leaving them to be edited in
+    /// the generated file.
+    #[arg(short, long)]
+    interactive: bool,
+}
+
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+enum InitTemplate {
+    /// A general-purpose cluster.
+    Default,
+    /// A cluster of NVIDIA GPU instances with PyTorch installed, for training.
+    Gpu,
+    /// A cluster whose workers run on (cheaper, interruptible) spot instances,
+    /// for batch jobs.
+    Spot,
+    /// A cluster of memory-optimized instances.
+    BigMemory,
+    /// A cluster of ARM (Graviton) instances.
+    Arm,
+}
+
+impl InitTemplate {
+    /// The values which the template sets in the provisioned configuration,
+    /// as pairs of dotted paths and TOML values.
+    fn overrides(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Self::Default => &[],
+            Self::Gpu => &[
+                ("setup.provisioned.instance-type", "g5.2xlarge"),
+                ("setup.provisioned.number-of-workers", "2"),
+                (
+                    "setup.provisioned.dependencies",
+                    r#"["torch", "torchvision"]"#,
+                ),
+            ],
+            Self::Spot => &[
+                ("setup.provisioned.instance-type", "m7i.2xlarge"),
+                ("setup.provisioned.number-of-workers", "8"),
+                ("setup.provisioned.spot", "true"),
+            ],
+            Self::BigMemory => &[("setup.provisioned.instance-type", "r7i.4xlarge")],
+            Self::Arm => &[("setup.provisioned.instance-type", "m7g.2xlarge")],
+        }
+    }
+
+    /// The public SSM parameter under which AWS publishes the latest AMI which
+    /// suits the template, in each region.
+    fn ami_parameter(self) -> &'static str {
+        match self {
+            Self::Default | Self::Spot | Self::BigMemory => {
+                "/aws/service/canonical/ubuntu/server/22.04/stable/current/amd64/hvm/ebs-gp2/ami-id"
+            }
+            Self::Gpu => {
+                "/aws/service/deeplearning/ami/x86_64/base-oss-nvidia-driver-gpu-ubuntu-22.04/latest/ami-id"
+            }
+            Self::Arm => {
+                "/aws/service/canonical/ubuntu/server/22.04/stable/current/arm64/hvm/ebs-gp2/ami-id"
+            }
+        }
+    }
+}
+
+/// Returns the configuration file template of the given provider, with the
+/// version requirements filled in from the current environment.
+async fn render_template(provider: &DaftProvider) -> anyhow::Result<String> {
+    Ok(match provider {
+        DaftProvider::Byoc => asset!("template-byoc.toml"),
+        DaftProvider::Provisioned => asset!("template-provisioned.toml"),
+    }
+    .replace("<requires>", concat!("=", env!("CARGO_PKG_VERSION")))
+    .replace(
+        "<python-version>",
+        get_python_version_from_env().await?.to_string().as_str(),
+    )
+    .replace(
+        "<ray-version>",
+        get_ray_version_from_env().await?.to_string().as_str(),
+    ))
+}
+
+/// Looks up the AMI which suits the given template in the given region.
+async fn lookup_ami(template: InitTemplate, region: &str) -> anyhow::Result<String> {
+    let sdk_config = aws_config::defaults(BehaviorVersion::latest())
+        .region(Region::new(region.to_string()))
+        .load()
+        .await;
+    let parameter = template.ami_parameter();
+    aws_sdk_ssm::Client::new(&sdk_config)
+        .get_parameter()
+        .name(parameter)
+        .send()
+        .await
+        .map_err(|error| {
+            anyhow::anyhow!("Failed to look up the AMI for the region {region} (from {parameter}); make sure that you are logged in to AWS: {error}")
+        })
+        .classify(Failure::CloudApi)?
+        .parameter
+        .and_then(|parameter| parameter.value)
+        .ok_or_else(|| anyhow::anyhow!("The SSM parameter {parameter} has no value"))
 }
 
 #[derive(Debug, Parser, Clone, PartialEq, Eq)]
@@ -161,10 +736,6 @@ struct List {
     /// A regex to filter for the Ray clusters which match the given name.
     regex: Option<StrRef>,
 
-    /// The region which to list all the available clusters for.
-    #[arg(long)]
-    region: Option<StrRef>,
-
     /// Only list the head nodes.
     #[arg(long)]
     head: bool,
@@ -173,351 +744,2239 @@ struct List {
     #[arg(long)]
     running: bool,
 
+    /// Only list the instances with the given tag (e.g., `--tag team=data`);
+    /// may be given more than once, in which case every tag must match.
+    #[arg(long = "tag", value_parser = parse_key_value)]
+    tags: Vec<(StrRef, StrRef)>,
+
+    /// Show one row per cluster (with its head node's address, its workers by
+    /// state and its total vCPUs), rather than one per instance.
+    #[arg(long)]
+    group_by_cluster: bool,
+
+    /// Flag the running clusters which have had no active jobs for more than
+    /// the given number of hours (only the clusters spun up from this machine
+    /// can be checked, through a quick tunnel to each).
+    #[arg(long, value_name = "HOURS")]
+    idle_after: Option<u64>,
+
+    /// Offer to kill each of the idle clusters (see `--idle-after`).
+    #[arg(long, requires = "idle_after")]
+    kill_idle: bool,
+
+    /// Only list the clusters which were spun up from this machine, with their
+    /// last known states, from the local records (without asking AWS).
+    #[arg(long, conflicts_with_all = ["head", "running", "tags", "group_by_cluster", "idle_after", "sort_by"])]
+    mine: bool,
+
+    /// The column to sort the instances by (the launch time and uptime sort
+    /// the longest-running instances first).
+    #[arg(long, value_enum)]
+    sort_by: Option<ListColumn>,
+
     #[clap(flatten)]
     config_path: ConfigPath,
 }
 
+/// The columns of `provisioned list`.
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+enum ListColumn {
+    Name,
+    InstanceId,
+    NodeType,
+    Status,
+    Ipv4,
+    LaunchTime,
+    Uptime,
+}
+
 #[derive(Debug, Parser, Clone, PartialEq, Eq)]
 struct Submit {
     /// The name of the job to run.
+    #[arg(add = ArgValueCandidates::new(completion::job_candidates))]
     job_name: StrRef,
 
     #[clap(flatten)]
     config_path: ConfigPath,
+
+    /// Values for the `{name}` placeholders in the job's command, given as
+    /// `name=value`; may be given multiple times.
+    #[arg(long = "set", value_parser = parse_key_value)]
+    variables: Vec<(StrRef, StrRef)>,
+
+    /// Print the job submission instead of submitting it.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Watch the job's working directory, cancelling and resubmitting the job
+    /// whenever any of its files change.
+    #[arg(long, conflicts_with = "dry_run")]
+    watch: bool,
+
+    /// Additional arguments to append to the job's command (e.g., `-- --date
+    /// 2024-01-01`).
+    #[arg(last = true)]
+    args: Vec<StrRef>,
 }
 
 #[derive(Debug, Parser, Clone, PartialEq, Eq)]
-struct Connect {
-    /// The local port to connect to the remote Ray cluster.
-    #[arg(long, default_value = "8265")]
-    port: u16,
+struct SubmitAll {
+    /// The names of the jobs to run; may be given multiple times. Defaults to
+    /// all of the jobs in the config.
+    #[arg(long = "job", short = 'j', add = ArgValueCandidates::new(completion::job_candidates))]
+    job_names: Vec<StrRef>,
 
-    /// Prevent the dashboard from opening automatically.
-    #[arg(long)]
-    no_dashboard: bool,
+    #[clap(flatten)]
+    config_path: ConfigPath,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Run {
+    /// The directory to upload to the cluster as the job's working directory.
+    #[arg(long, default_value = ".")]
+    working_dir: PathBuf,
+
+    /// The name to record for the job on the cluster.
+    #[arg(long, default_value = "adhoc")]
+    name: StrRef,
 
     #[clap(flatten)]
     config_path: ConfigPath,
+
+    /// The command to run (e.g., `-- python train.py`).
+    #[arg(last = true, required = true)]
+    command: Vec<StrRef>,
 }
 
 #[derive(Debug, Parser, Clone, PartialEq, Eq)]
-struct Sql {
-    /// The SQL string to submit to the remote Ray cluster.
-    sql: StrRef,
+struct Stop {
+    /// The submission ID of the job to stop, or the name of a job in the
+    /// config (in which case all of its active submissions are stopped).
+    job: StrRef,
 
     #[clap(flatten)]
     config_path: ConfigPath,
 }
 
 #[derive(Debug, Parser, Clone, PartialEq, Eq)]
-struct ConfigPath {
-    /// Path to configuration file.
-    #[arg(default_value = ".daft.toml")]
-    config: PathBuf,
+struct Fetch {
+    /// The name of the job whose outputs to fetch; the outputs of its most
+    /// recent submission are fetched.
+    #[arg(add = ArgValueCandidates::new(completion::job_candidates))]
+    job_name: StrRef,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
+
+    /// The local directory into which to fetch the outputs.
+    #[arg(long, default_value = "outputs")]
+    to: PathBuf,
 }
 
-#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
-#[serde(rename_all = "kebab-case", deny_unknown_fields)]
-struct DaftConfig {
-    setup: DaftSetup,
-    #[serde(default, rename = "job", deserialize_with = "parse_jobs")]
-    jobs: HashMap<StrRef, DaftJob>,
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Status {
+    /// The submission ID of the job, or the name of a job in the config (in
+    /// which case its most recent submission is used).
+    job: StrRef,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
 }
 
-#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
-#[serde(rename_all = "kebab-case", deny_unknown_fields)]
-struct DaftSetup {
-    name: StrRef,
-    #[serde(deserialize_with = "parse_requirement")]
-    requires: Requirement,
-    #[serde(deserialize_with = "parse_python_version")]
-    python_version: Versioning,
-    #[serde(deserialize_with = "parse_ray_version")]
-    ray_version: Versioning,
-    #[serde(flatten)]
-    provider_config: ProviderConfig,
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    /// Human-readable text.
+    Text,
+    /// One JSON object per line.
+    Json,
+    /// Comma-separated values, with a header row (only for the commands which
+    /// list records: `list`, `provisioned list`, `provisioned exec`,
+    /// `history` and `cost`; the other commands reject it).
+    Csv,
 }
 
-#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
-#[serde(rename_all = "kebab-case", deny_unknown_fields)]
-enum ProviderConfig {
-    Provisioned(AwsConfig),
-    Byoc(K8sConfig),
+/// The output formats of the commands which do not print CSV.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextOrJson {
+    Text,
+    Json,
 }
 
-#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
-#[serde(rename_all = "kebab-case", deny_unknown_fields)]
-struct AwsConfig {
-    region: StrRef,
-    #[serde(default = "default_number_of_workers")]
-    number_of_workers: usize,
-    ssh_user: StrRef,
-    #[serde(deserialize_with = "parse_ssh_private_key")]
-    ssh_private_key: PathRef,
-    #[serde(default = "default_instance_type")]
-    instance_type: StrRef,
-    #[serde(default = "default_image_id")]
-    image_id: StrRef,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    iam_instance_profile_name: Option<StrRef>,
-    #[serde(default)]
-    dependencies: Vec<StrRef>,
-    #[serde(default)]
-    run: Vec<StrRef>,
+impl OutputFormat {
+    /// Fails if CSV was asked for, since the given command does not print
+    /// records.
+    fn text_or_json(self, command: &str) -> anyhow::Result<TextOrJson> {
+        match self {
+            OutputFormat::Text => Ok(TextOrJson::Text),
+            OutputFormat::Json => Ok(TextOrJson::Json),
+            OutputFormat::Csv => Err(anyhow::anyhow!(
+                "`--output csv` is not available for `{command}`"
+            ))
+            .classify(Failure::Config),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
-#[serde(rename_all = "kebab-case", deny_unknown_fields)]
-struct K8sConfig {
-    #[serde(default = "default_k8s_namespace")]
-    namespace: StrRef,
+/// A record which can be printed as a row of CSV.
+trait CsvRecord: Serialize {
+    /// The names of the fields of the record, in the order they are
+    /// serialized, so that the header row is printed even without any rows.
+    const HEADER: &'static [&'static str];
 }
 
-fn parse_jobs<'de, D>(deserializer: D) -> Result<HashMap<StrRef, DaftJob>, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    #[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
-    #[serde(rename_all = "kebab-case")]
-    struct Job {
-        name: StrRef,
-        command: StrRef,
-        working_dir: PathRef,
-    }
-
-    let jobs: Vec<Job> = Deserialize::deserialize(deserializer)?;
-    let jobs = jobs
-        .into_iter()
-        .map(|job| {
-            let working_dir = expand_and_check_path(job.working_dir)?;
-            Ok((
-                job.name,
-                DaftJob {
-                    command: job.command,
-                    working_dir,
-                },
-            ))
-        })
-        .collect::<anyhow::Result<_>>()
-        .map_err(serde::de::Error::custom)?;
-    Ok(jobs)
+impl CsvRecord for InstanceRecord<'_> {
+    const HEADER: &'static [&'static str] = &[
+        "name",
+        "instance_id",
+        "node_type",
+        "state",
+        "public_ipv4_address",
+        "launch_time",
+    ];
 }
 
-fn parse_ssh_private_key<'de, D>(deserializer: D) -> Result<PathRef, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    let path: PathRef = Deserialize::deserialize(deserializer)?;
-    let path = expand_and_check_path(path).map_err(serde::de::Error::custom)?;
-    Ok(path)
+impl CsvRecord for ClusterSummaryRecord<'_> {
+    const HEADER: &'static [&'static str] = &[
+        "name",
+        "head_state",
+        "head_ipv4_address",
+        "workers",
+        "vcpus",
+    ];
 }
 
-fn expand_and_check_path(path: PathRef) -> anyhow::Result<PathRef> {
-    let path = if path.starts_with("~") {
-        let mut home = PathBuf::from(env!("HOME"));
-        for segment in path.into_iter().skip(1) {
-            home.push(segment);
-        }
-        Arc::from(home)
-    } else {
-        path
-    };
-
-    #[cfg(test)]
-    {
-        Ok(path)
-    }
+impl CsvRecord for ClusterRecord {
+    const HEADER: &'static [&'static str] = &["provider", "name", "location", "status", "workers"];
+}
 
-    #[cfg(not(test))]
-    {
-        if path.exists() {
-            Ok(path)
-        } else {
-            anyhow::bail!("The path, {path:?}, does not exist")
-        }
-    }
+impl CsvRecord for state::Cluster {
+    const HEADER: &'static [&'static str] = &[
+        "name",
+        "region",
+        "config",
+        "provider",
+        "head_ip",
+        "created_at",
+        "state",
+        "profile",
+    ];
 }
 
-fn default_number_of_workers() -> usize {
-    4
+impl CsvRecord for state::Action {
+    const HEADER: &'static [&'static str] =
+        &["action", "user", "cluster", "config_hash", "detail", "at"];
 }
 
-fn default_instance_type() -> StrRef {
-    "i3.2xlarge".into()
+impl CsvRecord for cost::DailyCost {
+    const HEADER: &'static [&'static str] = &["date", "cluster", "usd"];
 }
 
-fn default_image_id() -> StrRef {
-    "ami-04dd23e62ed049936".into()
+impl CsvRecord for ssh::NodeOutput {
+    const HEADER: &'static [&'static str] =
+        &["address", "is_head", "exit_code", "stdout", "stderr"];
 }
 
-fn default_k8s_namespace() -> StrRef {
-    "default".into()
+impl<T: CsvRecord> CsvRecord for &T {
+    const HEADER: &'static [&'static str] = T::HEADER;
 }
 
-fn parse_requirement<'de, D>(deserializer: D) -> Result<Requirement, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    let raw: StrRef = Deserialize::deserialize(deserializer)?;
-    let requirement = raw
-        .parse::<Requirement>()
-        .map_err(serde::de::Error::custom)?;
-    let current_version = env!("CARGO_PKG_VERSION")
-        .parse::<Versioning>()
-        .expect("CARGO_PKG_VERSION must exist");
-    if requirement.matches(&current_version) {
-        Ok(requirement)
-    } else {
-        Err(serde::de::Error::custom(format!("You're running daft-launcher version {current_version}, but your configuration file requires version {requirement}")))
+/// Formats the given records as CSV, with a header row of their field names
+/// (which is printed even if there are no records).
+fn format_csv<T: CsvRecord>(records: &[T]) -> anyhow::Result<String> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(vec![]);
+    writer.write_record(T::HEADER)?;
+    for record in records {
+        writer.serialize(record)?;
     }
+    Ok(String::from_utf8(writer.into_inner()?)?)
 }
 
-fn parse_python_version<'de, D>(deserializer: D) -> Result<Versioning, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    let raw: StrRef = Deserialize::deserialize(deserializer)?;
-    let requested_py_version = raw
-        .parse::<Versioning>()
-        .map_err(serde::de::Error::custom)?;
-    let minimum_py_requirement = ">=3.9"
-        .parse::<Requirement>()
-        .expect("Parsing a static, constant version should always succeed");
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct JobReference {
+    /// The submission ID of the job, or the name of a job in the config (in
+    /// which case its most recent submission is used).
+    job: StrRef,
 
-    if minimum_py_requirement.matches(&requested_py_version) {
-        Ok(requested_py_version)
-    } else {
-        Err(serde::de::Error::custom(format!("The minimum supported python version is {minimum_py_requirement}, but your configuration file requested python version {requested_py_version}")))
-    }
+    #[clap(flatten)]
+    config_path: ConfigPath,
 }
 
-fn parse_ray_version<'de, D>(deserializer: D) -> Result<Versioning, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    let raw: StrRef = Deserialize::deserialize(deserializer)?;
-    let version = raw.parse().map_err(serde::de::Error::custom)?;
-    Ok(version)
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct History {
+    /// The name of the job whose submissions to show. Defaults to all jobs.
+    #[arg(add = ArgValueCandidates::new(completion::job_candidates))]
+    job_name: Option<StrRef>,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
 }
 
-#[derive(Debug, ValueEnum, Clone, PartialEq, Eq)]
-enum DaftProvider {
-    Provisioned,
-    Byoc,
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Schedule {
+    /// The name of the job to schedule.
+    #[arg(add = ArgValueCandidates::new(completion::job_candidates))]
+    job_name: StrRef,
+
+    /// The cron expression describing when to submit the job (e.g., "0 2 * *
+    /// *").
+    #[arg(long, required_unless_present = "remove")]
+    cron: Option<StrRef>,
+
+    /// Remove the existing schedule for the job instead of creating one.
+    #[arg(long, conflicts_with = "cron")]
+    remove: bool,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
 }
 
-impl ToString for DaftProvider {
-    fn to_string(&self) -> String {
-        match self {
-            DaftProvider::Provisioned => "provisioned",
-            DaftProvider::Byoc => "byoc",
-        }
-        .to_string()
-    }
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Up {
+    #[clap(flatten)]
+    overrides: AwsOverrides,
+
+    /// Show the raw output of `ray up`, instead of the stage which it has
+    /// reached.
+    #[arg(long)]
+    show_output: bool,
+
+    #[clap(flatten)]
+    lock_args: LockArgs,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
 }
-#[derive(Debug, Clone, PartialEq, Eq)]
-struct DaftJob {
-    command: StrRef,
-    working_dir: PathRef,
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Down {
+    #[clap(flatten)]
+    lock_args: LockArgs,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
 }
 
-#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
-struct RayConfig {
-    cluster_name: StrRef,
-    max_workers: usize,
-    provider: RayProvider,
-    auth: RayAuth,
-    available_node_types: HashMap<StrRef, RayNodeType>,
-    setup_commands: Vec<StrRef>,
+/// Overrides for the AWS configuration, which apply to a single invocation.
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct AwsOverrides {
+    /// Override the number of workers.
+    #[arg(long)]
+    workers: Option<usize>,
+
+    /// Override the instance type.
+    #[arg(long)]
+    instance_type: Option<StrRef>,
 }
 
-#[derive(Default, Debug, Serialize, Clone, PartialEq, Eq)]
-struct RayProvider {
-    r#type: StrRef,
-    region: StrRef,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    cache_stopped_nodes: Option<bool>,
+impl AwsOverrides {
+    fn apply(&self, aws_config: &mut AwsConfig) {
+        if let Some(workers) = self.workers {
+            aws_config.number_of_workers = workers;
+        };
+        if let Some(instance_type) = &self.instance_type {
+            aws_config.instance_type = instance_type.clone();
+        };
+    }
 }
 
-#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
-struct RayAuth {
-    ssh_user: StrRef,
-    ssh_private_key: PathRef,
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Connect {
+    /// The local port to connect to the remote Ray cluster [default: 8265, or
+    /// a free port if that is taken].
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// Prevent the dashboard from opening automatically.
+    #[arg(long)]
+    no_dashboard: bool,
+
+    /// Another port to forward to the head node, as `[LOCAL:]REMOTE`, where
+    /// the remote port may be named (`dashboard`, `client`, `prometheus` or
+    /// `grafana`), e.g., `--forward 10001 --forward 3000:grafana`; in addition
+    /// to the `forwards` in the config.
+    #[arg(long = "forward", value_parser = parse_port_forward)]
+    forwards: Vec<PortForward>,
+
+    /// Also forward the Ray client port (10001), so that a local Python session
+    /// can `ray.init("ray://localhost:10001")` against the cluster; the same as
+    /// `--forward client`.
+    #[arg(long)]
+    client: bool,
+
+    /// Keep the tunnel open in the background (until `daft tunnel stop`), for
+    /// other commands such as `job submit` and `job sql` to share.
+    #[arg(long)]
+    detach: bool,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
 }
 
-#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
-struct RayNodeType {
-    max_workers: usize,
-    node_config: RayNodeConfig,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    resources: Option<RayResources>,
+impl Connect {
+    /// The ports given by `--forward`, along with the Ray client port if
+    /// `--client` was given.
+    fn port_forwards(&self) -> Vec<PortForward> {
+        let client = self.client.then_some(PortForward {
+            local: RAY_CLIENT_PORT,
+            remote: RAY_CLIENT_PORT,
+        });
+        self.forwards.iter().copied().chain(client).collect()
+    }
 }
 
-#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
-#[serde(rename_all = "PascalCase")]
-struct RayNodeConfig {
-    key_name: StrRef,
-    instance_type: StrRef,
-    image_id: StrRef,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    iam_instance_profile: Option<IamInstanceProfile>,
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Monitor {
+    /// How often to refresh the view, in seconds.
+    #[arg(long, default_value = "2")]
+    interval: u64,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
 }
 
-#[derive(Default, Debug, Serialize, Clone, PartialEq, Eq)]
-#[serde(rename_all = "PascalCase")]
-struct IamInstanceProfile {
-    name: StrRef,
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Events {
+    /// Keep printing events as the autoscaler logs them.
+    #[arg(long, short)]
+    follow: bool,
+
+    /// How many of the last lines of the autoscaler's logs to look through.
+    #[arg(long, default_value = "1000")]
+    lines: usize,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
 }
 
-#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
-#[serde(rename_all = "UPPERCASE")]
-struct RayResources {
-    cpu: usize,
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Exec {
+    /// The shell command to run, e.g., `nvidia-smi` or `df -h`.
+    command: StrRef,
+
+    /// Run the command on the worker nodes as well as on the head node.
+    #[arg(long)]
+    all_nodes: bool,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
 }
 
-async fn read_daft_config(daft_config_path: impl AsRef<Path>) -> anyhow::Result<DaftConfig> {
-    let daft_config_path = daft_config_path.as_ref();
-    let contents = fs::read_to_string(daft_config_path)
-        .await
-        .map_err(|error| {
-            if let ErrorKind::NotFound = error.kind() {
-                Error::new(
-                    ErrorKind::NotFound,
-                    format!("The file {daft_config_path:?} does not exist"),
-                )
-            } else {
-                error
-            }
-        })?;
-    let daft_config = toml::from_str::<DaftConfig>(&contents)?;
-    Ok(daft_config)
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Cp {
+    /// The local file or directory (copied recursively) to copy.
+    local: PathBuf,
+
+    /// Where to copy it to on the head node (relative to the home directory,
+    /// unless absolute).
+    remote: StrRef,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
 }
 
-fn convert(
-    daft_config: &DaftConfig,
-    teardown_behaviour: Option<TeardownBehaviour>,
-) -> anyhow::Result<RayConfig> {
-    let ProviderConfig::Provisioned(aws_config) = &daft_config.setup.provider_config else {
-        unreachable!("Can only convert to a ray config-file for provisioned configurations; this should be statically determined");
-    };
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct SyncDir {
+    /// The local directory whose contents to sync.
+    local: PathBuf,
 
-    let key_name = aws_config
-        .ssh_private_key
-        .clone()
-        .file_stem()
-        .ok_or_else(|| {
-            anyhow::anyhow!(r#"Private key doesn't have a name of the format "name.ext""#)
-        })?
-        .to_str()
-        .ok_or_else(|| {
-            anyhow::anyhow!(
-                "The file {:?} does not have a valid UTF-8 name",
-                aws_config.ssh_private_key
-            )
-        })?
-        .into();
+    /// The directory on the head node to sync them into (relative to the home
+    /// directory, unless absolute).
+    remote: StrRef,
 
-    let node_config = RayNodeConfig {
+    /// Remove the files in the remote directory which are not in the local
+    /// one.
+    #[arg(long)]
+    delete: bool,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct Sql {
+    /// The SQL string to submit to the remote Ray cluster. With
+    /// `--interactive`, this is optional and is run before the first prompt.
+    #[arg(required_unless_present = "interactive")]
+    sql: Option<StrRef>,
+
+    /// Print the job submission instead of submitting it.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Keep the connection to the cluster open and prompt for statements
+    /// (terminated by `;`) to submit one after the other.
+    #[arg(long, short = 'i', conflicts_with = "dry_run")]
+    interactive: bool,
+
+    /// Values for the `:name` parameters in the query, given as `name=value`;
+    /// may be given multiple times. Values are substituted as SQL literals.
+    #[arg(long = "param", value_parser = parse_key_value)]
+    params: Vec<(StrRef, StrRef)>,
+
+    #[clap(flatten)]
+    preview: SqlPreview,
+
+    #[clap(flatten)]
+    config_path: ConfigPath,
+}
+
+#[derive(Debug, Parser, Clone, Copy, PartialEq, Eq)]
+struct SqlPreview {
+    /// The maximum number of rows of the result to print.
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// The number of characters beyond which printed values are truncated.
+    #[arg(long)]
+    max_column_width: Option<usize>,
+
+    /// Print the logical and physical plans of the query instead of running
+    /// it.
+    #[arg(long, conflicts_with_all = ["limit", "max_column_width"])]
+    explain: bool,
+}
+
+impl SqlPreview {
+    /// Returns the command which runs the given statement with the generated
+    /// sql.py (after the given setup statements), printing its result (or plan)
+    /// as configured.
+    fn command_segments(&self, setup: &[StrRef], statement: &str) -> Vec<String> {
+        let mut command_segments = vec!["python".to_string(), "sql.py".to_string()];
+        for setup_statement in setup {
+            command_segments.extend(["--setup".to_string(), setup_statement.to_string()]);
+        }
+        if let Some(limit) = self.limit {
+            command_segments.extend(["--limit".to_string(), limit.to_string()]);
+        };
+        if let Some(max_column_width) = self.max_column_width {
+            command_segments.extend([
+                "--max-column-width".to_string(),
+                max_column_width.to_string(),
+            ]);
+        };
+        if self.explain {
+            command_segments.push("--explain".to_string());
+        };
+        // The statement may itself start with a `-` (e.g., a comment).
+        command_segments.extend(["--".to_string(), statement.to_string()]);
+        command_segments
+    }
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct ConfigPath {
+    /// Path to configuration file (or `-` to read it from the standard input, or
+    /// an `s3://` or `https://` URL to fetch it from).
+    #[arg(default_value = ".daft.toml")]
+    config: PathBuf,
+}
+
+#[derive(Debug, Parser, Clone, PartialEq, Eq)]
+struct LockArgs {
+    /// Take the cluster's lock even if someone else holds it (e.g., after an
+    /// interrupted command).
+    #[arg(long)]
+    force: bool,
+}
+
+const MINUTE: Duration = Duration::from_secs(60);
+const HOUR: Duration = Duration::from_secs(60 * 60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+const WEEK: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Parses a positive number of one of the given units, each given by its
+/// suffix (e.g., `24h` with `('h', HOUR)`); `expected` describes what can be
+/// given, for the error.
+fn parse_duration(
+    raw: &str,
+    units: &[(char, Duration)],
+    expected: &str,
+) -> Result<Duration, String> {
+    units
+        .iter()
+        .find_map(|(suffix, unit)| {
+            let number = raw.strip_suffix(*suffix)?.parse::<u32>().ok()?;
+            unit.checked_mul(number)
+        })
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| format!("Expected {expected}, but got {raw:?}"))
+}
+
+/// Parses an age given in minutes, hours, days or weeks (e.g., `90m`, `24h`,
+/// `7d` or `2w`).
+fn parse_age(raw: &str) -> Result<Duration, String> {
+    parse_duration(
+        raw,
+        &[('m', MINUTE), ('h', HOUR), ('d', DAY), ('w', WEEK)],
+        "a number of minutes, hours, days or weeks (e.g., `24h` or `7d`)",
+    )
+}
+
+/// A local port which `connect` forwards to a port on the head node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PortForward {
+    local: u16,
+    remote: u16,
+}
+
+impl std::fmt::Display for PortForward {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.local, self.remote)
+    }
+}
+
+/// Parses a port forward given as `[LOCAL:]REMOTE`, where the remote port is
+/// either a number or the name of a service on the head node; without a local
+/// port, the same port is used locally.
+fn parse_port_forward(raw: &str) -> Result<PortForward, String> {
+    let parse_remote = |remote: &str| {
+        match remote {
+        "dashboard" => Ok(8265),
+        "client" => Ok(RAY_CLIENT_PORT),
+        "prometheus" => Ok(metrics::PROMETHEUS_PORT),
+        "grafana" => Ok(metrics::GRAFANA_PORT),
+        _ => remote.parse::<u16>().map_err(|_| {
+            format!("Expected a port or one of `dashboard`, `client`, `prometheus` and `grafana`, but got {remote:?}")
+        }),
+    }
+    };
+    match raw.split_once(':') {
+        Some((local, remote)) => Ok(PortForward {
+            local: local
+                .parse()
+                .map_err(|_| format!("Expected a local port, but got {local:?}"))?,
+            remote: parse_remote(remote)?,
+        }),
+        None => {
+            let remote = parse_remote(raw)?;
+            Ok(PortForward {
+                local: remote,
+                remote,
+            })
+        }
+    }
+}
+
+fn parse_port_forwards<'de, D>(deserializer: D) -> Result<Vec<PortForward>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let raw: Vec<StrRef> = Deserialize::deserialize(deserializer)?;
+    raw.iter()
+        .map(|raw| parse_port_forward(raw).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+fn serialize_port_forwards<S>(forwards: &[PortForward], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_seq(forwards.iter().map(PortForward::to_string))
+}
+
+/// Combines the given port forwards, as `(local, remote)` pairs, in order; a
+/// later forward of a local port replaces an earlier one.
+fn merge_port_forwards(forwards: impl IntoIterator<Item = (u16, u16)>) -> Vec<(u16, u16)> {
+    let mut merged = Vec::<(u16, u16)>::new();
+    for (local, remote) in forwards {
+        merged.retain(|&(other_local, _)| other_local != local);
+        merged.push((local, remote));
+    }
+    merged
+}
+
+fn parse_key_value(raw: &str) -> Result<(StrRef, StrRef), String> {
+    let (key, value) = raw
+        .split_once('=')
+        .ok_or_else(|| format!("Expected a value of the form `name=value`, but got {raw:?}"))?;
+    Ok((key.into(), value.into()))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct DaftConfig {
+    setup: DaftSetup,
+    #[serde(
+        default,
+        rename = "job",
+        deserialize_with = "parse_jobs",
+        serialize_with = "serialize_jobs"
+    )]
+    jobs: HashMap<StrRef, DaftJob>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    notifications: Option<NotificationsConfig>,
+    #[serde(default)]
+    sql: SqlConfig,
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct SqlConfig {
+    /// Statements which are run before every `job sql` query (e.g., to
+    /// register the tables which the queries refer to).
+    #[serde(default)]
+    setup: Vec<StrRef>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct NotificationsConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    webhook_url: Option<StrRef>,
+    /// Whether to show a desktop notification for each event.
+    #[serde(default)]
+    desktop: bool,
+    /// Whether to show the progress of `up` and of followed jobs in the title
+    /// of the terminal.
+    #[serde(default)]
+    terminal_title: bool,
+    #[serde(default = "default_notification_events")]
+    events: Vec<NotificationEvent>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum NotificationEvent {
+    JobSucceeded,
+    JobFailed,
+    ClusterUp,
+    ClusterDown,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct DaftSetup {
+    name: StrRef,
+    #[serde(
+        deserialize_with = "parse_requirement",
+        serialize_with = "serialize_display"
+    )]
+    requires: Requirement,
+    #[serde(
+        deserialize_with = "parse_python_version",
+        serialize_with = "serialize_display"
+    )]
+    python_version: Versioning,
+    #[serde(
+        deserialize_with = "parse_ray_version",
+        serialize_with = "serialize_display"
+    )]
+    ray_version: Versioning,
+    /// The version of Daft to install on the cluster; defaults to the latest.
+    #[serde(
+        default,
+        deserialize_with = "parse_daft_version",
+        serialize_with = "serialize_optional_display",
+        skip_serializing_if = "Option::is_none"
+    )]
+    daft_version: Option<Versioning>,
+    /// Environment variables which are set on every node of the cluster, and
+    /// for every job (unless the job sets them itself).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    env: BTreeMap<StrRef, StrRef>,
+    /// Local files and directories which are copied to every node of a
+    /// provisioned cluster, keyed by their path on the node.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    file_mounts: BTreeMap<StrRef, PathRef>,
+    /// Patterns of the paths within the file mounts which are not copied.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    file_mounts_exclude: Vec<StrRef>,
+    /// Installs Prometheus and Grafana (with Ray's dashboards) on the head
+    /// node of a provisioned cluster, when present.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metrics: Option<MetricsConfig>,
+    /// How long to wait for the dashboard to answer through a new
+    /// port-forward, in seconds.
+    #[serde(
+        default = "default_tunnel_timeout",
+        skip_serializing_if = "is_default_tunnel_timeout"
+    )]
+    tunnel_timeout: u64,
+    #[serde(flatten)]
+    provider_config: ProviderConfig,
+}
+
+impl DaftSetup {
+    fn tunnel_timeout(&self) -> Duration {
+        Duration::from_secs(self.tunnel_timeout)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct MetricsConfig {
+    /// The local port which `connect` forwards to Prometheus on the head node.
+    #[serde(default = "default_prometheus_port")]
+    prometheus_port: u16,
+    /// The local port which `connect` forwards to Grafana on the head node.
+    #[serde(default = "default_grafana_port")]
+    grafana_port: u16,
+    /// The version of Grafana to install.
+    #[serde(default = "default_grafana_version")]
+    grafana_version: StrRef,
+}
+
+fn default_prometheus_port() -> u16 {
+    metrics::PROMETHEUS_PORT
+}
+
+fn default_grafana_port() -> u16 {
+    metrics::GRAFANA_PORT
+}
+
+fn default_grafana_version() -> StrRef {
+    "11.2.0".into()
+}
+
+// Only one configuration is held at a time, so the size of the larger variant
+// is not worth boxing it for.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+enum ProviderConfig {
+    Provisioned(AwsConfig),
+    Byoc(K8sConfig),
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct AwsConfig {
+    /// The region of the cluster, which is detected once the configuration is
+    /// read if it is not set (see [`resolve_region`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    region: Option<StrRef>,
+    #[serde(default = "default_number_of_workers")]
+    number_of_workers: usize,
+    ssh_user: StrRef,
+    #[serde(deserialize_with = "parse_ssh_private_key")]
+    ssh_private_key: PathRef,
+    /// A bastion through which the launcher reaches the cluster's nodes over
+    /// SSH.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ssh_proxy_jump: Option<ProxyJump>,
+    /// Seconds between the keep-alives which the launcher's SSH connections
+    /// send (so that idle NAT mappings are not dropped), or 0 for none.
+    #[serde(
+        default = "default_ssh_keep_alive",
+        skip_serializing_if = "is_default_ssh_keep_alive"
+    )]
+    ssh_keep_alive: u64,
+    /// Seconds for which an SSH connection to a node is kept open (and reused
+    /// by later commands) after its last command, or 0 to not share them.
+    #[serde(
+        default = "default_ssh_control_persist",
+        skip_serializing_if = "is_default_ssh_control_persist"
+    )]
+    ssh_control_persist: u64,
+    #[serde(default = "default_instance_type")]
+    instance_type: StrRef,
+    #[serde(default = "default_image_id")]
+    image_id: StrRef,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    iam_instance_profile_name: Option<StrRef>,
+    #[serde(default)]
+    dependencies: Dependencies,
+    /// Commands which are run on each node before it is set up.
+    #[serde(default)]
+    pre_setup_commands: Vec<StrRef>,
+    /// Commands which replace the built-in ones that install uv, Python, Ray
+    /// and Daft (and the `dependencies`) on each node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    setup_commands: Option<Vec<StrRef>>,
+    /// A local script which is uploaded to each node, and run there after the
+    /// pre-setup commands.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    setup_script: Option<PathRef>,
+    /// Commands which are run on each node once it has been set up.
+    #[serde(default, alias = "post-setup-commands")]
+    run: Vec<StrRef>,
+    /// Whether to run the workers on spot instances.
+    #[serde(default)]
+    spot: bool,
+    /// How many hours the cluster is meant to live for, after which `daft gc`
+    /// tears it down.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ttl_hours: Option<u64>,
+    /// Ports which `connect` forwards to the head node along with the
+    /// dashboard's, e.g. `["10001", "3000:grafana"]`.
+    #[serde(
+        default,
+        deserialize_with = "parse_port_forwards",
+        serialize_with = "serialize_port_forwards",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    forwards: Vec<PortForward>,
+    /// How `ssh`, `connect` and job submissions reach the head node.
+    #[serde(default, skip_serializing_if = "Connection::is_ssh")]
+    connection: Connection,
+}
+
+/// A bastion (or jump host) in front of the cluster's nodes.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ProxyJump {
+    /// The bastion, as `user@host`.
+    host: StrRef,
+    /// The key with which to log into the bastion.
+    #[serde(deserialize_with = "parse_ssh_private_key")]
+    ssh_private_key: PathRef,
+}
+
+/// How the launcher reaches the head node of a provisioned cluster.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum Connection {
+    /// Over SSH, with the configured key, to the head node's public address.
+    #[default]
+    Ssh,
+    /// Through AWS Systems Manager Session Manager, which needs neither a
+    /// public address nor a key pair (but does need the AWS CLI with its
+    /// Session Manager plugin locally, and the SSM agent and permissions on the
+    /// nodes).
+    Ssm,
+}
+
+impl Connection {
+    fn is_ssh(&self) -> bool {
+        *self == Self::Ssh
+    }
+}
+
+/// The Python packages to install on each node of the cluster.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+enum Dependencies {
+    /// The requirements themselves, e.g. `["requests==2.32.3"]`.
+    List(Vec<StrRef>),
+    /// A `requirements.txt` or `pyproject.toml` file to read the requirements
+    /// from, relative to the configuration file, e.g. `{ file =
+    /// "requirements.txt" }`.
+    #[serde(rename_all = "kebab-case")]
+    File { file: PathRef },
+}
+
+impl Default for Dependencies {
+    fn default() -> Self {
+        Self::List(vec![])
+    }
+}
+
+impl Dependencies {
+    /// Returns the requirements, reading them from the file if there is one.
+    fn resolve(&self) -> anyhow::Result<Vec<StrRef>> {
+        match self {
+            Dependencies::List(dependencies) => Ok(dependencies.clone()),
+            Dependencies::File { file } => {
+                let contents = std::fs::read_to_string(file).map_err(|error| {
+                    anyhow::anyhow!("Failed to read the dependencies from {file:?}: {error}")
+                })?;
+                if file
+                    .file_name()
+                    .is_some_and(|name| name == "pyproject.toml")
+                {
+                    parse_pyproject_dependencies(&contents)
+                } else {
+                    parse_requirements(&contents)
+                }
+                .map_err(|error| {
+                    anyhow::anyhow!("Failed to read the dependencies from {file:?}: {error}")
+                })
+            }
+        }
+    }
+}
+
+/// Returns the requirements in the given `requirements.txt` file, ignoring
+/// comments and blank lines.
+fn parse_requirements(contents: &str) -> anyhow::Result<Vec<StrRef>> {
+    contents
+        .lines()
+        .map(|line| line.split_once(" #").map_or(line, |(line, _)| line).trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            if line.starts_with('-') {
+                anyhow::bail!("Options such as `{line}` are not supported; list the requirements themselves instead");
+            };
+            Ok(line.into())
+        })
+        .collect()
+}
+
+/// Returns the requirements in the `[project]` table of the given
+/// `pyproject.toml` file.
+fn parse_pyproject_dependencies(contents: &str) -> anyhow::Result<Vec<StrRef>> {
+    #[derive(Deserialize)]
+    struct PyProject {
+        project: Project,
+    }
+
+    #[derive(Deserialize)]
+    struct Project {
+        #[serde(default)]
+        dependencies: Vec<StrRef>,
+    }
+
+    Ok(toml::from_str::<PyProject>(contents)?.project.dependencies)
+}
+
+impl AwsConfig {
+    /// The region of the cluster, as resolved when the configuration was read.
+    fn region(&self) -> &StrRef {
+        self.region
+            .as_ref()
+            .expect("the region is resolved when the configuration is read")
+    }
+
+    /// The name of the EC2 key pair, which is that of the private key file.
+    fn key_name(&self) -> anyhow::Result<StrRef> {
+        Ok(self
+            .ssh_private_key
+            .file_stem()
+            .ok_or_else(|| {
+                anyhow::anyhow!(r#"Private key doesn't have a name of the format "name.ext""#)
+            })?
+            .to_str()
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "The file {:?} does not have a valid UTF-8 name",
+                    self.ssh_private_key
+                )
+            })?
+            .into())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct K8sConfig {
+    #[serde(default = "default_k8s_namespace")]
+    namespace: StrRef,
+    /// The kubeconfig context of the cluster; defaults to the current context.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    context: Option<StrRef>,
+}
+
+impl K8sConfig {
+    /// Returns a `kubectl` command which targets the cluster's context and
+    /// namespace.
+    fn kubectl(&self) -> Command {
+        let mut command = Command::new("kubectl");
+        if let Some(context) = &self.context {
+            command.arg("--context").arg(context.as_ref());
+        };
+        command.arg("-n").arg(self.namespace.as_ref());
+        command
+    }
+}
+
+/// A job, as it is written in the configuration file.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+struct RawJob {
+    name: StrRef,
+    command: StrRef,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    working_dir: Option<PathRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<GitSource>,
+    #[serde(default)]
+    env: BTreeMap<StrRef, StrRef>,
+    #[serde(default)]
+    dependencies: Vec<StrRef>,
+    #[serde(default)]
+    excludes: Vec<StrRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    working_dir_size_limit: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upload_to: Option<StrRef>,
+    #[serde(default)]
+    outputs: Vec<StrRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outputs_uri: Option<StrRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entrypoint_num_cpus: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entrypoint_num_gpus: Option<f64>,
+    #[serde(default)]
+    entrypoint_resources: BTreeMap<StrRef, f64>,
+    #[serde(default)]
+    depends_on: Vec<StrRef>,
+}
+
+impl RawJob {
+    fn new(name: &StrRef, daft_job: &DaftJob) -> Self {
+        let (working_dir, source) = match &daft_job.source {
+            JobSource::WorkingDir(working_dir) => (Some(working_dir.clone()), None),
+            JobSource::Git(source) => (None, Some(source.clone())),
+        };
+        Self {
+            name: name.clone(),
+            command: daft_job.command.clone(),
+            working_dir,
+            source,
+            env: daft_job.env.clone(),
+            dependencies: daft_job.dependencies.clone(),
+            excludes: daft_job.excludes.clone(),
+            working_dir_size_limit: daft_job.working_dir_size_limit,
+            upload_to: daft_job.upload_to.clone(),
+            outputs: daft_job.outputs.clone(),
+            outputs_uri: daft_job.outputs_uri.clone(),
+            entrypoint_num_cpus: daft_job.entrypoint_resources.num_cpus,
+            entrypoint_num_gpus: daft_job.entrypoint_resources.num_gpus,
+            entrypoint_resources: daft_job.entrypoint_resources.resources.clone(),
+            depends_on: daft_job.depends_on.clone(),
+        }
+    }
+}
+
+/// Serializes the given jobs in the same form in which they are written in the
+/// configuration file (sorted by name).
+fn serialize_jobs<S>(jobs: &HashMap<StrRef, DaftJob>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    let mut jobs = jobs
+        .iter()
+        .map(|(name, daft_job)| RawJob::new(name, daft_job))
+        .collect::<Vec<_>>();
+    jobs.sort_by(|a, b| a.name.cmp(&b.name));
+    serializer.collect_seq(jobs)
+}
+
+fn serialize_display<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: std::fmt::Display,
+    S: serde::Serializer,
+{
+    serializer.collect_str(value)
+}
+
+fn serialize_optional_display<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: std::fmt::Display,
+    S: serde::Serializer,
+{
+    match value {
+        Some(value) => serializer.collect_str(value),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn parse_jobs<'de, D>(deserializer: D) -> Result<HashMap<StrRef, DaftJob>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let jobs: Vec<RawJob> = Deserialize::deserialize(deserializer)?;
+    let jobs = jobs
+        .into_iter()
+        .map(|job| {
+            split_command(&job.command)?;
+            for uri in job.upload_to.iter().chain(&job.outputs_uri) {
+                s3::parse_s3_uri(uri)?;
+            }
+            for pattern in &job.outputs {
+                outputs::check_relative(pattern)?;
+            }
+            let entrypoint_resources = EntrypointResources {
+                num_cpus: job.entrypoint_num_cpus,
+                num_gpus: job.entrypoint_num_gpus,
+                resources: job.entrypoint_resources,
+            };
+            entrypoint_resources.validate()?;
+            let source = match (job.working_dir, job.source) {
+                (Some(working_dir), None) => {
+                    JobSource::WorkingDir(expand_and_check_path(working_dir)?)
+                }
+                (None, Some(source)) => JobSource::Git(source),
+                (Some(_), Some(_)) => anyhow::bail!(
+                    "The job {} must specify only one of `working-dir` and `source`",
+                    job.name
+                ),
+                (None, None) => anyhow::bail!(
+                    "The job {} must specify either a `working-dir` or a `source`",
+                    job.name
+                ),
+            };
+            Ok((
+                job.name,
+                DaftJob {
+                    command: job.command,
+                    source,
+                    env: job.env,
+                    dependencies: job.dependencies,
+                    excludes: job.excludes,
+                    working_dir_size_limit: job.working_dir_size_limit.or(job
+                        .upload_to
+                        .is_none()
+                        .then_some(working_dir::DEFAULT_SIZE_LIMIT_MIB)),
+                    upload_to: job.upload_to,
+                    outputs: job.outputs,
+                    outputs_uri: job.outputs_uri,
+                    entrypoint_resources,
+                    depends_on: job.depends_on,
+                },
+            ))
+        })
+        .collect::<anyhow::Result<HashMap<_, _>>>()
+        .map_err(serde::de::Error::custom)?;
+    topological_order(&jobs).map_err(serde::de::Error::custom)?;
+    Ok(jobs)
+}
+
+/// Orders the given jobs such that every job comes after all of the jobs it
+/// depends on, breaking ties by name.
+///
+/// Fails if a job depends on a job which does not exist, or if the dependencies
+/// contain a cycle.
+fn topological_order(jobs: &HashMap<StrRef, DaftJob>) -> anyhow::Result<Vec<StrRef>> {
+    let mut remaining_dependencies = BTreeMap::new();
+    for (name, daft_job) in jobs {
+        for dependency in &daft_job.depends_on {
+            if !jobs.contains_key(dependency) {
+                anyhow::bail!(
+                    "The job {name} depends on the job {dependency}, which does not exist"
+                );
+            };
+        }
+        remaining_dependencies.insert(name.clone(), daft_job.depends_on.len());
+    }
+
+    let mut order = Vec::with_capacity(jobs.len());
+    while let Some(name) = remaining_dependencies
+        .iter()
+        .find(|(_, &count)| count == 0)
+        .map(|(name, _)| name.clone())
+    {
+        remaining_dependencies.remove(&name);
+        for (dependent, count) in remaining_dependencies.iter_mut() {
+            *count -= jobs[dependent]
+                .depends_on
+                .iter()
+                .filter(|dependency| **dependency == name)
+                .count();
+        }
+        order.push(name);
+    }
+
+    if !remaining_dependencies.is_empty() {
+        let cycle = remaining_dependencies
+            .keys()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        anyhow::bail!("The dependencies between the jobs {cycle} form a cycle");
+    };
+    Ok(order)
+}
+
+fn parse_ssh_private_key<'de, D>(deserializer: D) -> Result<PathRef, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let path: PathRef = Deserialize::deserialize(deserializer)?;
+    let path = expand_and_check_path(path).map_err(serde::de::Error::custom)?;
+    Ok(path)
+}
+
+/// Returns the user's home directory (in tests, a temporary directory, so that
+/// they neither read nor write the real one).
+fn home_dir() -> Option<PathBuf> {
+    #[cfg(test)]
+    {
+        Some(tests::home_dir().to_path_buf())
+    }
+    #[cfg(not(test))]
+    {
+        dirs::home_dir()
+    }
+}
+
+fn expand_and_check_path(path: PathRef) -> anyhow::Result<PathRef> {
+    // `Path::starts_with` compares components, so this matches both `~/` and
+    // (on Windows) `~\`.
+    let path = if path.starts_with("~") {
+        let mut home = home_dir().ok_or_else(|| {
+            anyhow::anyhow!("Could not determine the home directory, to expand {path:?}")
+        })?;
+        for segment in path.iter().skip(1) {
+            home.push(segment);
+        }
+        Arc::from(home)
+    } else {
+        path
+    };
+
+    #[cfg(test)]
+    {
+        Ok(path)
+    }
+
+    #[cfg(not(test))]
+    {
+        if path.exists() {
+            Ok(path)
+        } else {
+            anyhow::bail!("The path, {path:?}, does not exist")
+        }
+    }
+}
+
+fn default_ssh_keep_alive() -> u64 {
+    30
+}
+
+fn is_default_ssh_keep_alive(ssh_keep_alive: &u64) -> bool {
+    *ssh_keep_alive == default_ssh_keep_alive()
+}
+
+fn default_ssh_control_persist() -> u64 {
+    600
+}
+
+fn is_default_ssh_control_persist(ssh_control_persist: &u64) -> bool {
+    *ssh_control_persist == default_ssh_control_persist()
+}
+
+fn default_tunnel_timeout() -> u64 {
+    15
+}
+
+fn is_default_tunnel_timeout(tunnel_timeout: &u64) -> bool {
+    *tunnel_timeout == default_tunnel_timeout()
+}
+
+fn default_number_of_workers() -> usize {
+    4
+}
+
+fn default_instance_type() -> StrRef {
+    "i3.2xlarge".into()
+}
+
+fn default_image_id() -> StrRef {
+    "ami-04dd23e62ed049936".into()
+}
+
+fn default_k8s_namespace() -> StrRef {
+    "default".into()
+}
+
+/// Splits the given command into its segments the way a POSIX shell would,
+/// honouring single quotes, double quotes and backslash escapes.
+fn split_command(command: &str) -> anyhow::Result<Vec<String>> {
+    shell_words::split(command)
+        .map_err(|_| anyhow::anyhow!("The command {command:?} contains an unterminated quote"))
+}
+
+fn default_notification_events() -> Vec<NotificationEvent> {
+    vec![
+        NotificationEvent::JobSucceeded,
+        NotificationEvent::JobFailed,
+        NotificationEvent::ClusterUp,
+        NotificationEvent::ClusterDown,
+    ]
+}
+
+fn parse_requirement<'de, D>(deserializer: D) -> Result<Requirement, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let raw: StrRef = Deserialize::deserialize(deserializer)?;
+    let requirement = raw
+        .parse::<Requirement>()
+        .map_err(serde::de::Error::custom)?;
+    let current_version = env!("CARGO_PKG_VERSION")
+        .parse::<Versioning>()
+        .expect("CARGO_PKG_VERSION must exist");
+    if requirement.matches(&current_version) {
+        Ok(requirement)
+    } else {
+        Err(serde::de::Error::custom(format!("You're running daft-launcher version {current_version}, but your configuration file requires version {requirement}; run `{FIX_VERSION_COMMAND}` to require this version instead (and `daft config migrate` if the file was written for an older version), or install a version of daft-launcher which matches")))
+    }
+}
+
+/// The result of `config check`, as printed with `--output json`.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+struct CheckReport {
+    valid: bool,
+    /// Why the configuration is not valid, if it is not.
+    problems: Vec<String>,
+}
+
+/// Validates the given configuration file, returning any problems which
+/// `strict` validation (against the cloud account) finds in it.
+async fn check_config(
+    path: &Path,
+    profile: Option<&str>,
+    strict: bool,
+) -> anyhow::Result<Vec<String>> {
+    let daft_config = read_daft_config(path, profile).await?;
+    if let ProviderConfig::Provisioned(aws_config) = &daft_config.setup.provider_config {
+        aws_config.dependencies.resolve()?;
+    };
+    if !strict {
+        return Ok(vec![]);
+    };
+    let ProviderConfig::Provisioned(aws_config) = &daft_config.setup.provider_config else {
+        not_available_for_byoc!("config check --strict");
+    };
+    assert_is_logged_in_with_aws().await?;
+    let mut problems = cloud_check::check(aws_config)
+        .await
+        .classify(Failure::CloudApi)?;
+    if aws_config.setup_commands.is_none() {
+        // Only the built-in setup commands install Daft and Ray.
+        let incompatibilities = compat::check(
+            daft_config.setup.daft_version.as_ref(),
+            &daft_config.setup.ray_version,
+        )
+        .await
+        .map_err(|error| {
+            anyhow::anyhow!("Failed to check the versions of Daft and Ray against PyPI: {error}")
+        })?;
+        problems.extend(incompatibilities);
+    };
+    Ok(problems)
+}
+
+/// A description of a BYOC cluster, as printed by `byoc info`.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+struct ByocInfo<'a> {
+    name: &'a str,
+    context: Option<&'a str>,
+    namespace: &'a str,
+    head_node_service: String,
+}
+
+/// Changes the version requirement of the given configuration file to this
+/// version of the launcher, after confirming, if it does not match already.
+async fn fix_version(path: &Path) -> anyhow::Result<()> {
+    ensure_local_config(path)?;
+    let contents = fs::read_to_string(path).await?;
+    let requirement = edit::get(&contents, "setup.requires")?;
+    let current_version = env!("CARGO_PKG_VERSION")
+        .parse::<Versioning>()
+        .expect("CARGO_PKG_VERSION must exist");
+    if requirement
+        .parse::<Requirement>()
+        .is_ok_and(|requirement| requirement.matches(&current_version))
+    {
+        return Ok(());
+    };
+
+    let fixed = format!("={current_version}");
+    let question = format!(
+        "The configuration requires daft-launcher version {requirement}, but this is version {current_version}; change the requirement to {fixed}?"
+    );
+    wizard::ensure_interactive(
+        "Changing the version requirement needs confirmation",
+        &format!("run `daft config set setup.requires {fixed}` instead"),
+    )?;
+    if !wizard::Wizard::new()?.confirm(&question, false)? {
+        anyhow::bail!("Left the version requirement {requirement} as it was");
+    };
+    fs::write(path, edit::set(&contents, "setup.requires", &fixed)?).await?;
+    println!("Changed the version requirement of {path:?} to {fixed}");
+    Ok(())
+}
+
+fn parse_python_version<'de, D>(deserializer: D) -> Result<Versioning, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let raw: StrRef = Deserialize::deserialize(deserializer)?;
+    let requested_py_version = raw
+        .parse::<Versioning>()
+        .map_err(serde::de::Error::custom)?;
+    let minimum_py_requirement = ">=3.9"
+        .parse::<Requirement>()
+        .expect("Parsing a static, constant version should always succeed");
+    // Ray publishes no wheels for newer versions of python yet.
+    let maximum_py_requirement = "<3.13"
+        .parse::<Requirement>()
+        .expect("Parsing a static, constant version should always succeed");
+
+    if !minimum_py_requirement.matches(&requested_py_version) {
+        Err(serde::de::Error::custom(format!("The minimum supported python version is {minimum_py_requirement}, but your configuration file requested python version {requested_py_version}")))
+    } else if !maximum_py_requirement.matches(&requested_py_version) {
+        Err(serde::de::Error::custom(format!("The supported python versions are {minimum_py_requirement} and {maximum_py_requirement}, but your configuration file requested python version {requested_py_version}")))
+    } else {
+        Ok(requested_py_version)
+    }
+}
+
+fn parse_ray_version<'de, D>(deserializer: D) -> Result<Versioning, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let raw: StrRef = Deserialize::deserialize(deserializer)?;
+    let version = raw.parse().map_err(serde::de::Error::custom)?;
+    Ok(version)
+}
+
+fn parse_daft_version<'de, D>(deserializer: D) -> Result<Option<Versioning>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let raw: StrRef = Deserialize::deserialize(deserializer)?;
+    let requested_daft_version = raw
+        .parse::<Versioning>()
+        .map_err(serde::de::Error::custom)?;
+    let minimum_daft_requirement = ">=0.3"
+        .parse::<Requirement>()
+        .expect("Parsing a static, constant version should always succeed");
+
+    if minimum_daft_requirement.matches(&requested_daft_version) {
+        Ok(Some(requested_daft_version))
+    } else {
+        Err(serde::de::Error::custom(format!("The minimum supported daft version is {minimum_daft_requirement}, but your configuration file requested daft version {requested_daft_version}")))
+    }
+}
+
+#[derive(Debug, ValueEnum, Clone, PartialEq, Eq)]
+enum DaftProvider {
+    Provisioned,
+    Byoc,
+}
+
+impl std::fmt::Display for DaftProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DaftProvider::Provisioned => "provisioned",
+            DaftProvider::Byoc => "byoc",
+        })
+    }
+}
+#[derive(Debug, Clone, PartialEq)]
+struct DaftJob {
+    command: StrRef,
+    source: JobSource,
+    env: BTreeMap<StrRef, StrRef>,
+    dependencies: Vec<StrRef>,
+    excludes: Vec<StrRef>,
+    /// The maximum size (in MiB) of the working directory, after excludes, if
+    /// it is limited: by default, it is unless it is uploaded to S3 (which is
+    /// not limited as the Ray dashboard is).
+    working_dir_size_limit: Option<u64>,
+    /// The `s3://` URI under which to upload the working directory, instead of
+    /// uploading it through the Ray dashboard.
+    upload_to: Option<StrRef>,
+    /// Glob patterns matching the files which the job produces, relative to
+    /// its working directory (or to its `outputs_uri`, if it has one).
+    outputs: Vec<StrRef>,
+    /// The `s3://` URI under which the job writes its outputs, if it does not
+    /// write them to its working directory on the head node.
+    outputs_uri: Option<StrRef>,
+    entrypoint_resources: EntrypointResources,
+    /// The names of the jobs which must succeed before this job is submitted.
+    depends_on: Vec<StrRef>,
+}
+
+/// Where the code which a job runs comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum JobSource {
+    /// A local directory, which is uploaded as-is.
+    WorkingDir(PathRef),
+    /// A revision of a git repository, which is checked out afresh on every
+    /// submission.
+    Git(GitSource),
+}
+
+impl std::fmt::Display for JobSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobSource::WorkingDir(working_dir) => write!(f, "{}", working_dir.display()),
+            JobSource::Git(GitSource { git, rev }) => write!(f, "{git} at {rev}"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct GitSource {
+    git: StrRef,
+    rev: StrRef,
+}
+
+/// A job's working directory, as made available locally for upload.
+struct JobWorkingDir {
+    path: PathRef,
+    /// The temporary directory which holds the checkout of a git source; the
+    /// checkout is deleted once this is dropped.
+    _checkout: Option<TempDir>,
+}
+
+impl DaftJob {
+    /// Splits the job's command into its segments, and then replaces each
+    /// `{name}` placeholder in them with the value given for it (so that a
+    /// value with spaces or quotes in it is still a single segment).
+    ///
+    /// A placeholder is a whole segment (e.g., `{lr}`) or the value of one
+    /// (e.g., `--lr={lr}`); braces anywhere else (e.g., in a Python f-string)
+    /// are left as they are. Every placeholder must be given a value, and every
+    /// value must correspond to a placeholder.
+    fn command_segments(&self, variables: &[(StrRef, StrRef)]) -> anyhow::Result<Vec<String>> {
+        let placeholder = Regex::new(r"^([^{}]*=)?\{([A-Za-z_][A-Za-z0-9_-]*)\}$")
+            .expect("static regex is valid");
+
+        let segments = split_command(&self.command)?;
+        let names = segments
+            .iter()
+            .filter_map(|segment| placeholder.captures(segment))
+            .map(|captures| captures[2].to_string())
+            .collect::<Vec<_>>();
+
+        let mut missing = names
+            .iter()
+            .filter(|name| !variables.iter().any(|(key, _)| **key == ***name))
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        missing.sort_unstable();
+        missing.dedup();
+        if !missing.is_empty() {
+            anyhow::bail!(
+                "No values were given for the placeholders {}; pass them with `--set name=value`",
+                missing.join(", ")
+            );
+        };
+
+        if let Some((unused, _)) = variables
+            .iter()
+            .find(|(key, _)| !names.iter().any(|name| **name == **key))
+        {
+            anyhow::bail!("The job's command has no placeholder named {unused}");
+        };
+
+        Ok(segments
+            .into_iter()
+            .map(|segment| {
+                let Some(captures) = placeholder.captures(&segment) else {
+                    return segment;
+                };
+                let (_, value) = variables
+                    .iter()
+                    .rev()
+                    .find(|(key, _)| **key == captures[2])
+                    .expect("all placeholders were checked to have values");
+                format!(
+                    "{}{value}",
+                    captures.get(1).map_or("", |prefix| prefix.as_str())
+                )
+            })
+            .collect())
+    }
+
+    /// Makes the job's working directory available locally (checking out its
+    /// git source, if it has one), and asserts that it is within the job's size
+    /// limit.
+    ///
+    /// For a dry run, which uploads nothing, a git source is not checked out,
+    /// and its working directory is left empty.
+    async fn prepare_working_dir(&self, dry_run: bool) -> anyhow::Result<JobWorkingDir> {
+        let working_dir = match &self.source {
+            JobSource::WorkingDir(working_dir) => JobWorkingDir {
+                path: working_dir.clone(),
+                _checkout: None,
+            },
+            JobSource::Git(_) if dry_run => {
+                return Ok(JobWorkingDir {
+                    path: Arc::from(Path::new("")),
+                    _checkout: None,
+                })
+            }
+            JobSource::Git(GitSource { git, rev }) => {
+                let temp_dir = TempDir::new("daft-launcher")?;
+                let commit = git::checkout(git, rev, temp_dir.path()).await?;
+                logging::info(format!("Checked out {git} at {rev} ({commit})"));
+                JobWorkingDir {
+                    path: Arc::from(temp_dir.path()),
+                    _checkout: Some(temp_dir),
+                }
+            }
+        };
+        if let Some(size_limit) = self.working_dir_size_limit {
+            working_dir::check_size(&working_dir.path, &self.excludes, size_limit)?;
+        };
+        Ok(working_dir)
+    }
+
+    /// Returns the job's runtime environment, in which its own environment
+    /// variables take precedence over the given cluster-wide ones.
+    fn runtime_env(&self, cluster_env: &BTreeMap<StrRef, StrRef>) -> RayRuntimeEnv {
+        let mut env_vars = cluster_env.clone();
+        env_vars.extend(self.env.clone());
+        RayRuntimeEnv {
+            working_dir: None,
+            env_vars,
+            pip: self.dependencies.clone(),
+        }
+    }
+}
+
+/// The subset of Ray's `runtime_env` which daft-launcher is able to populate
+/// on job submission.
+#[derive(Default, Debug, Serialize, Clone, PartialEq, Eq)]
+struct RayRuntimeEnv {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    working_dir: Option<StrRef>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    env_vars: BTreeMap<StrRef, StrRef>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pip: Vec<StrRef>,
+}
+
+impl EntrypointResources {
+    fn validate(&self) -> anyhow::Result<()> {
+        let amounts = [
+            ("entrypoint-num-cpus", self.num_cpus),
+            ("entrypoint-num-gpus", self.num_gpus),
+        ]
+        .into_iter()
+        .filter_map(|(name, amount)| amount.map(|amount| (name.to_string(), amount)))
+        .chain(
+            self.resources
+                .iter()
+                .map(|(name, &amount)| (format!("entrypoint-resources.{name}"), amount)),
+        );
+        for (name, amount) in amounts {
+            if amount < 0.0 || !amount.is_finite() {
+                anyhow::bail!(
+                    "The amount of `{name}` must be a non-negative number, but got {amount}"
+                );
+            };
+        }
+        Ok(())
+    }
+}
+
+/// Everything needed to submit a single job to the cluster.
+#[derive(Debug, Clone, Copy)]
+struct JobSubmission<'a> {
+    job_name: &'a str,
+    working_dir: &'a Path,
+    excludes: &'a [StrRef],
+    /// The `s3://` URI under which to upload the working directory, if it
+    /// should not be uploaded through the Ray dashboard.
+    upload_to: Option<&'a str>,
+    command_segments: &'a [&'a str],
+    runtime_env: &'a RayRuntimeEnv,
+    resources: &'a EntrypointResources,
+}
+
+/// The resources to reserve for a job's entrypoint (i.e., its driver script),
+/// as understood by the Ray Jobs API.
+#[derive(Default, Debug, Serialize, Clone, PartialEq)]
+struct EntrypointResources {
+    #[serde(
+        rename = "entrypoint_num_cpus",
+        skip_serializing_if = "Option::is_none"
+    )]
+    num_cpus: Option<f64>,
+    #[serde(
+        rename = "entrypoint_num_gpus",
+        skip_serializing_if = "Option::is_none"
+    )]
+    num_gpus: Option<f64>,
+    #[serde(
+        rename = "entrypoint_resources",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    resources: BTreeMap<StrRef, f64>,
+}
+
+/// The port of the Ray dashboard on the head node, and the local port from
+/// which it is forwarded unless that is taken (see [`dashboard_local_port`]).
+const RAY_DASHBOARD_PORT: u16 = 8265;
+
+/// The tag with which the nodes of a cluster with a `ttl-hours` are tagged,
+/// for `gc` to find them by.
+const TTL_TAG: &str = "daft-launcher-ttl-hours";
+
+/// The tag with which the nodes of every cluster spun up by daft-launcher are
+/// tagged (with the version which spun it up), so that `gc` leaves other Ray
+/// clusters alone.
+const LAUNCHER_TAG: &str = "daft-launcher-version";
+
+/// The path on each node to which the `setup-script` is uploaded.
+const SETUP_SCRIPT_PATH: &str = "~/.daft-launcher/setup-script.sh";
+
+/// The configuration file which commands read when given no other.
+const DEFAULT_CONFIG_PATH: &str = ".daft.toml";
+
+/// The command which changes the version requirement of the configuration
+/// file to this version of the launcher.
+const FIX_VERSION_COMMAND: &str = "daft config check --fix-version";
+
+/// The port on which the Ray client server of the cluster listens, both on the
+/// head node and locally once a tunnel to it has been opened.
+const RAY_CLIENT_PORT: u16 = 10001;
+
+/// The metadata key under which the daft-launcher job name is recorded when a
+/// job is submitted to the Ray cluster.
+const DAFT_JOB_NAME_METADATA_KEY: &str = "daft-launcher-job-name";
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+struct RayJob {
+    submission_id: Option<StrRef>,
+    job_id: Option<StrRef>,
+    status: RayJobStatus,
+    entrypoint: StrRef,
+    start_time: Option<u64>,
+    end_time: Option<u64>,
+    metadata: Option<HashMap<StrRef, StrRef>>,
+    message: Option<StrRef>,
+    runtime_env: Option<RayJobRuntimeEnv>,
+}
+
+/// The parts of a job's runtime environment, as reported by Ray, which are of
+/// interest to daft-launcher.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+struct RayJobRuntimeEnv {
+    working_dir: Option<StrRef>,
+}
+
+impl RayJob {
+    fn id(&self) -> Option<&str> {
+        self.submission_id.as_deref().or(self.job_id.as_deref())
+    }
+
+    /// A human-readable reference to the job, for use in messages.
+    fn describe(&self) -> String {
+        let id = self.id().unwrap_or("n/a");
+        self.daft_job_name()
+            .map_or_else(|| id.to_string(), |job_name| format!("{job_name} ({id})"))
+    }
+
+    fn daft_job_name(&self) -> Option<&str> {
+        self.metadata
+            .as_ref()
+            .and_then(|metadata| metadata.get(DAFT_JOB_NAME_METADATA_KEY))
+            .map(AsRef::as_ref)
+    }
+
+    fn duration(&self, now: Duration) -> Option<Duration> {
+        let start_time = Duration::from_millis(self.start_time?);
+        let end_time = self.end_time.map_or(now, Duration::from_millis);
+        Some(end_time.saturating_sub(start_time))
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+enum RayJobStatus {
+    Pending,
+    Running,
+    Stopped,
+    Succeeded,
+    Failed,
+}
+
+impl RayJobStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Running => "running",
+            Self::Stopped => "stopped",
+            Self::Succeeded => "succeeded",
+            Self::Failed => "failed",
+        }
+    }
+
+    fn is_active(self) -> bool {
+        matches!(self, Self::Pending | Self::Running)
+    }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+struct RayConfig {
+    cluster_name: StrRef,
+    max_workers: usize,
+    provider: RayProvider,
+    auth: RayAuth,
+    available_node_types: BTreeMap<StrRef, RayNodeType>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    file_mounts: BTreeMap<StrRef, PathRef>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    rsync_exclude: Vec<StrRef>,
+    setup_commands: Vec<StrRef>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    head_setup_commands: Vec<StrRef>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    head_start_ray_commands: Vec<StrRef>,
+}
+
+#[derive(Default, Debug, Serialize, Clone, PartialEq, Eq)]
+struct RayProvider {
+    r#type: StrRef,
+    region: StrRef,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_stopped_nodes: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+struct RayAuth {
+    ssh_user: StrRef,
+    ssh_private_key: PathRef,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+struct RayNodeType {
+    max_workers: usize,
+    node_config: RayNodeConfig,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resources: Option<RayResources>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+struct RayNodeConfig {
+    key_name: StrRef,
+    instance_type: StrRef,
+    image_id: StrRef,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    iam_instance_profile: Option<IamInstanceProfile>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instance_market_options: Option<InstanceMarketOptions>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tag_specifications: Vec<TagSpecification>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+struct TagSpecification {
+    resource_type: StrRef,
+    tags: Vec<RayTag>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+struct RayTag {
+    key: StrRef,
+    value: StrRef,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+struct InstanceMarketOptions {
+    market_type: StrRef,
+}
+
+#[derive(Default, Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+struct IamInstanceProfile {
+    name: StrRef,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+struct RayResources {
+    cpu: usize,
+}
+
+/// Fails if the given configuration file is not a local file, for the commands
+/// which write to it.
+fn ensure_local_config(path: &Path) -> anyhow::Result<()> {
+    if !config::is_local(path) {
+        anyhow::bail!("This command writes to the configuration file, so it must be a local file, not {path:?}");
+    };
+    Ok(())
+}
+
+async fn read_daft_config(
+    daft_config_path: impl AsRef<Path>,
+    profile: Option<&str>,
+) -> anyhow::Result<DaftConfig> {
+    read_daft_config_with(daft_config_path, profile, Secrets::Resolve).await
+}
+
+/// Reads the given configuration file for a command which only shows it (or
+/// something generated from it), with its secrets redacted rather than
+/// resolved.
+async fn read_daft_config_for_display(
+    daft_config_path: impl AsRef<Path>,
+    profile: Option<&str>,
+) -> anyhow::Result<DaftConfig> {
+    read_daft_config_with(daft_config_path, profile, Secrets::Redact).await
+}
+
+async fn read_daft_config_with(
+    daft_config_path: impl AsRef<Path>,
+    profile: Option<&str>,
+    secrets: Secrets,
+) -> anyhow::Result<DaftConfig> {
+    let daft_config_path = daft_config_path.as_ref();
+    let daft_config = config::read::<DaftConfig>(daft_config_path, profile, secrets)
+        .await
+        .map_err(|error| {
+            // Name the configuration file in the suggested command, unless it
+            // is the default one; any other error is kept as it is, so that it
+            // is still classified by its cause (e.g., a secret which could not
+            // be fetched for lack of credentials).
+            let message = error.to_string();
+            if daft_config_path == Path::new(DEFAULT_CONFIG_PATH)
+                || !message.contains(FIX_VERSION_COMMAND)
+            {
+                return error;
+            };
+            let fix_version_command = format!(
+                "{FIX_VERSION_COMMAND} {}",
+                shell_quote(&daft_config_path.to_string_lossy())
+            );
+            anyhow::anyhow!(message.replace(FIX_VERSION_COMMAND, &fix_version_command))
+        });
+    let mut daft_config = daft_config.classify(Failure::Config)?;
+    if matches!(daft_config.setup.provider_config, ProviderConfig::Byoc(..))
+        && !daft_config.setup.file_mounts.is_empty()
+    {
+        logging::warn(lint::BYOC_FILE_MOUNTS_WARNING);
+    };
+    if let ProviderConfig::Provisioned(aws_config) = &mut daft_config.setup.provider_config {
+        if aws_config.region.as_deref() == Some("") {
+            return Err(anyhow::anyhow!(
+                "`setup.provisioned.region` must not be empty; leave it out to use the AWS CLI's default region"
+            ))
+            .classify(Failure::Config);
+        };
+        aws_config.region = Some(resolve_region(aws_config.region.as_ref()).await?);
+        // A dependencies file is given relative to the configuration file (as
+        // `extends` is), not to the current directory.
+        if let Dependencies::File { file } = &mut aws_config.dependencies {
+            if let Some(dir) = daft_config_path
+                .parent()
+                .filter(|_| config::is_local(daft_config_path))
+            {
+                *file = Arc::from(dir.join(&**file));
+            };
+        };
+    };
+    Ok(daft_config)
+}
+
+/// Returns whether the given configuration file is the default one, and does
+/// not exist (e.g., on a machine without the project's checkout).
+fn is_missing_default_config(config: &Path) -> bool {
+    config == Path::new(DEFAULT_CONFIG_PATH) && !config.exists()
+}
+
+/// Returns the configuration file of the provisioned cluster to operate on: the
+/// given one, that of the cluster which the given path names instead of a file
+/// (e.g., `daft provisioned ssh my-cluster`) or,
+/// if the default one does not exist, that of a cluster which the user picks
+/// from those which were spun up from this machine or are running (as found on
+/// EC2 by their `ray-cluster-name` tag).
+async fn locate_config(config: &Path) -> anyhow::Result<PathBuf> {
+    if let Some(cluster_config) = config_of_cluster_named(config).await? {
+        return Ok(cluster_config);
+    };
+    if !is_missing_default_config(config) {
+        return Ok(config.to_path_buf());
+    };
+    let clusters = state::read_clusters().await?;
+    let known = state::known_clusters(&clusters, Path::is_file);
+
+    // The clusters running in the regions of the known ones (and in the
+    // current one) are found on EC2 by their `ray-cluster-name` tag; failing
+    // to list them only leaves them out.
+    let mut regions = known
+        .iter()
+        .map(|cluster| cluster.region.clone())
+        .collect::<BTreeSet<_>>();
+    if let Ok(region) = resolve_region(None).await {
+        regions.insert(region);
+    };
+    let mut instances = vec![];
+    for region in regions {
+        match get_ray_clusters_from_aws(region.clone(), &[]).await {
+            Ok(found) => {
+                instances.extend(found.into_iter().map(|instance| (instance, region.clone())))
+            }
+            Err(error) => logging::debug(format!(
+                "Failed to list the clusters in the region {region}: {error}"
+            )),
+        };
+    }
+    let running = instances
+        .iter()
+        .filter(|(instance, _)| {
+            instance.node_type == NodeType::Head
+                && instance.state == Some(InstanceStateName::Running)
+        })
+        .map(|(instance, region)| (instance.regular_name.clone(), region.clone()))
+        .collect::<HashSet<_>>();
+    let mut unknown = running
+        .iter()
+        .filter(|(name, region)| {
+            !known
+                .iter()
+                .any(|cluster| cluster.name == *name && cluster.region == *region)
+        })
+        .collect::<Vec<_>>();
+    unknown.sort();
+
+    if known.is_empty() && unknown.is_empty() {
+        return Err(anyhow::anyhow!("No configuration file was found at {DEFAULT_CONFIG_PATH:?}, and no clusters were spun up from this machine or are running; pass the path to the cluster's configuration file"))
+            .classify(Failure::Config);
+    };
+    wizard::ensure_interactive(
+        &format!("No configuration file was found at {DEFAULT_CONFIG_PATH:?}"),
+        "pass the path to the cluster's configuration file, or the cluster's name",
+    )?;
+
+    println!("No configuration file was found at {DEFAULT_CONFIG_PATH:?}; these clusters were spun up from this machine, or are running:");
+    let mut options = format_cluster_options(&known, &running);
+    options.extend(
+        unknown
+            .iter()
+            .map(|(name, region)| format!("{name} ({region}, running) found on EC2")),
+    );
+    let picked = wizard::Wizard::new()?.select("Which cluster?", &options, "1")?;
+    let index = options
+        .iter()
+        .position(|option| *option == picked)
+        .expect("the picked option is one of the options");
+    if let Some(cluster) = known.get(index) {
+        return Ok(cluster.config.clone());
+    };
+    let (name, region) = unknown[index - known.len()];
+    let cluster_instances = instances
+        .iter()
+        .filter(|(instance, instance_region)| {
+            instance.regular_name == *name && instance_region == region
+        })
+        .map(|(instance, _)| instance)
+        .collect::<Vec<_>>();
+    adopted_config(name, region, &cluster_instances).await
+}
+
+/// Returns the configuration file of the cluster which the given path names
+/// (in the region given with `--region`, if any), if it is a bare name rather
+/// than an existing file: that of the cluster spun up from this machine or,
+/// failing that, one reconstructed from the running cluster with that name.
+async fn config_of_cluster_named(config: &Path) -> anyhow::Result<Option<PathBuf>> {
+    let is_bare_name = config.components().count() == 1
+        && config.extension().is_none()
+        && config != Path::new("-")
+        && !config.exists();
+    let Some(name) = config.to_str().filter(|_| is_bare_name) else {
+        return Ok(None);
+    };
+    let clusters = state::read_clusters().await?;
+    let known = state::known_clusters(&clusters, Path::is_file);
+    let region = region_override().map(|region| &**region);
+    if let Some(cluster) = state::find_cluster(&known, name, region) {
+        logging::debug(format!(
+            "Using the configuration file {} of cluster {name}",
+            cluster.config.display()
+        ));
+        return Ok(Some(cluster.config.clone()));
+    };
+
+    // A name which does not turn out to be a cluster's is left to be reported
+    // as a missing configuration file.
+    let discovered = async {
+        let region = resolve_region(None).await?;
+        let instances =
+            get_ray_clusters_from_aws(region.clone(), &[("ray-cluster-name".into(), name.into())])
+                .await?;
+        let instances = instances.iter().collect::<Vec<_>>();
+        if adopt::head(&instances).is_none() {
+            return Ok(None);
+        };
+        adopted_config(name, &region, &instances).await.map(Some)
+    }
+    .await;
+    Ok(discovered.unwrap_or_else(|error: anyhow::Error| {
+        logging::debug(format!(
+            "Failed to find a running cluster named {name}: {error}"
+        ));
+        None
+    }))
+}
+
+/// Reconstructs the configuration file of the running cluster with the given
+/// name (in the given region) from its instances, as `provisioned adopt` does,
+/// keeping it in the local state; this is for operating on a cluster which has
+/// no configuration file on this machine.
+async fn adopted_config(
+    name: &str,
+    region: &StrRef,
+    instances: &[&AwsInstance],
+) -> anyhow::Result<PathBuf> {
+    let template = render_template(&DaftProvider::Provisioned).await?;
+    let (contents, warnings) = adopt::adopt(&template, name, region, instances, None, None)?;
+    for warning in warnings {
+        logging::warn(warning);
+    }
+    let path = state::adopted_config_path(region, name)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    };
+    fs::write(&path, contents).await?;
+    logging::info(format!(
+        "Using a configuration file reconstructed from the running cluster {name}, at {}",
+        path.display()
+    ));
+    Ok(path)
+}
+
+/// Describes each of the given clusters for the picker, noting whether it is
+/// running (as given by its name and region).
+fn format_cluster_options(
+    clusters: &[&state::Cluster],
+    running: &HashSet<(StrRef, StrRef)>,
+) -> Vec<String> {
+    clusters
+        .iter()
+        .map(|cluster| {
+            let is_running = running.contains(&(cluster.name.clone(), cluster.region.clone()));
+            format!(
+                "{} ({}, {}) from {}",
+                cluster.name,
+                cluster.region,
+                if is_running { "running" } else { "not running" },
+                cluster.config.display()
+            )
+        })
+        .collect()
+}
+
+/// The region given with `--region`, if any.
+static REGION: OnceLock<StrRef> = OnceLock::new();
+
+fn region_override() -> Option<&'static StrRef> {
+    REGION.get()
+}
+
+/// Returns the region to use, given the one which is configured (if any): that
+/// given with `--region`, that configured, or otherwise the AWS CLI's default
+/// region.
+async fn resolve_region(configured: Option<&StrRef>) -> anyhow::Result<StrRef> {
+    if let Some(region) = region_override().or(configured) {
+        return Ok(region.clone());
+    };
+    aws_config::meta::region::RegionProviderChain::default_provider()
+        .region()
+        .await
+        .map(|region| StrRef::from(region.as_ref()))
+        .ok_or_else(|| {
+            anyhow::anyhow!("No region is configured; set `setup.provisioned.region`, pass `--region`, or set a default region for the AWS CLI (e.g., with `aws configure`)")
+        })
+        .classify(Failure::Config)
+}
+
+fn convert(
+    daft_config: &DaftConfig,
+    teardown_behaviour: Option<TeardownBehaviour>,
+) -> anyhow::Result<RayConfig> {
+    let ProviderConfig::Provisioned(aws_config) = &daft_config.setup.provider_config else {
+        unreachable!("Can only convert to a ray config-file for provisioned configurations; this should be statically determined");
+    };
+
+    let key_name = aws_config.key_name()?;
+
+    let node_config = RayNodeConfig {
         key_name,
         instance_type: aws_config.instance_type.clone(),
         image_id: aws_config.image_id.clone(),
@@ -525,396 +2984,2779 @@ fn convert(
             .iam_instance_profile_name
             .clone()
             .map(|name| IamInstanceProfile { name }),
+        instance_market_options: None,
+        tag_specifications: vec![TagSpecification {
+            resource_type: "instance".into(),
+            tags: std::iter::once(RayTag {
+                key: LAUNCHER_TAG.into(),
+                value: env!("CARGO_PKG_VERSION").into(),
+            })
+            .chain(aws_config.ttl_hours.map(|ttl_hours| RayTag {
+                key: TTL_TAG.into(),
+                value: ttl_hours.to_string().into(),
+            }))
+            .collect(),
+        }],
+    };
+    let dependencies = aws_config.dependencies.resolve()?;
+    let setup_commands = match &aws_config.setup_commands {
+        Some(setup_commands) => {
+            if !dependencies.is_empty() {
+                anyhow::bail!("The `dependencies` cannot be installed when `setup-commands` replaces the built-in setup commands; install them in `setup-commands` instead");
+            };
+            setup_commands
+                .iter()
+                .chain(&aws_config.run)
+                .cloned()
+                .collect()
+        }
+        None => generate_setup_commands(
+            daft_config.setup.python_version.clone(),
+            daft_config.setup.ray_version.clone(),
+            daft_config.setup.daft_version.clone(),
+            &dependencies,
+            &aws_config.run,
+        ),
+    };
+    let mut file_mounts = BTreeMap::new();
+    for (remote_path, local_path) in &daft_config.setup.file_mounts {
+        if !remote_path.starts_with('/') && !remote_path.starts_with('~') {
+            anyhow::bail!("The file mount {remote_path:?} must be an absolute path (or start with `~`) on the node");
+        };
+        if **remote_path == *SETUP_SCRIPT_PATH {
+            anyhow::bail!("The file mount {remote_path:?} is where the `setup-script` is uploaded to; choose another path");
+        };
+        file_mounts.insert(
+            remote_path.clone(),
+            expand_and_check_path(local_path.clone())?,
+        );
+    }
+    let mut setup_script_commands = vec![];
+    if let Some(setup_script) = &aws_config.setup_script {
+        file_mounts.insert(
+            SETUP_SCRIPT_PATH.into(),
+            expand_and_check_path(setup_script.clone())?,
+        );
+        setup_script_commands.push(format!("bash {SETUP_SCRIPT_PATH}").into());
+    };
+    let setup_commands = generate_env_commands(&daft_config.setup.env)?
+        .into_iter()
+        .chain(aws_config.pre_setup_commands.iter().cloned())
+        .chain(setup_script_commands)
+        .chain(setup_commands)
+        .collect();
+
+    let worker_node_config = RayNodeConfig {
+        instance_market_options: aws_config.spot.then(|| InstanceMarketOptions {
+            market_type: "spot".into(),
+        }),
+        ..node_config.clone()
+    };
+
+    Ok(RayConfig {
+        cluster_name: daft_config.setup.name.clone(),
+        max_workers: aws_config.number_of_workers,
+        provider: RayProvider {
+            r#type: "aws".into(),
+            region: aws_config.region().clone(),
+            cache_stopped_nodes: teardown_behaviour.map(TeardownBehaviour::to_cache_stopped_nodes),
+        },
+        auth: RayAuth {
+            ssh_user: aws_config.ssh_user.clone(),
+            ssh_private_key: aws_config.ssh_private_key.clone(),
+        },
+        available_node_types: vec![
+            (
+                "ray.head.default".into(),
+                RayNodeType {
+                    max_workers: 0,
+                    node_config,
+                    resources: Some(RayResources { cpu: 0 }),
+                },
+            ),
+            (
+                "ray.worker.default".into(),
+                RayNodeType {
+                    max_workers: aws_config.number_of_workers,
+                    node_config: worker_node_config,
+                    resources: None,
+                },
+            ),
+        ]
+        .into_iter()
+        .collect(),
+        file_mounts,
+        rsync_exclude: daft_config.setup.file_mounts_exclude.clone(),
+        setup_commands,
+        head_setup_commands: daft_config
+            .setup
+            .metrics
+            .as_ref()
+            .map(metrics::head_setup_commands)
+            .unwrap_or_default(),
+        head_start_ray_commands: daft_config
+            .setup
+            .metrics
+            .as_ref()
+            .map(metrics::head_start_ray_commands)
+            .unwrap_or_default(),
+    })
+}
+
+async fn write_ray_config(ray_config: &RayConfig, dest: impl AsRef<Path>) -> anyhow::Result<()> {
+    let ray_config = serde_yaml::to_string(ray_config)?;
+    fs::write(dest, ray_config).await?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SpinDirection {
+    Up,
+    Down,
+}
+
+impl SpinDirection {
+    fn as_str(&self) -> &str {
+        match self {
+            Self::Up => "up",
+            Self::Down => "down",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TeardownBehaviour {
+    Down,
+    Kill,
+}
+
+impl TeardownBehaviour {
+    fn to_cache_stopped_nodes(self) -> bool {
+        match self {
+            Self::Down => true,
+            Self::Kill => false,
+        }
+    }
+}
+
+fn create_temp_file(name: &str) -> anyhow::Result<(TempDir, PathRef)> {
+    let temp_dir = TempDir::new("daft-launcher")?;
+    let mut temp_path = temp_dir.path().to_owned();
+    temp_path.push(name);
+    let temp_path = Arc::from(temp_path);
+    Ok((temp_dir, temp_path))
+}
+
+fn create_temp_ray_file() -> anyhow::Result<(TempDir, PathRef)> {
+    create_temp_file("ray.yaml")
+}
+
+/// Returns the given (cached) Ray configuration, set to tear its cluster down
+/// with the given behaviour, and otherwise unchanged; fails unless it is for
+/// the given region.
+fn teardown_ray_config(
+    contents: &str,
+    teardown_behaviour: TeardownBehaviour,
+    region: &str,
+) -> anyhow::Result<String> {
+    let mut ray_config: serde_yaml::Mapping = serde_yaml::from_str(contents)?;
+    let Some(provider) = ray_config
+        .get_mut("provider")
+        .and_then(serde_yaml::Value::as_mapping_mut)
+    else {
+        anyhow::bail!("The Ray configuration has no provider");
+    };
+    let cached_region = provider.get("region").and_then(serde_yaml::Value::as_str);
+    if cached_region != Some(region) {
+        anyhow::bail!(
+            "The Ray configuration is for the region {}, not {region}",
+            cached_region.unwrap_or("(none)")
+        );
+    };
+    provider.insert(
+        "cache_stopped_nodes".into(),
+        teardown_behaviour.to_cache_stopped_nodes().into(),
+    );
+    Ok(serde_yaml::to_string(&ray_config)?)
+}
+
+/// Writes the Ray configuration with which to tear down the cluster of the
+/// given configuration to the given path: the one which the cluster was spun
+/// up with from this machine, if cached, so that changes to the configuration
+/// file since (e.g., to its node types) cannot leave instances behind;
+/// otherwise, one converted from the configuration.
+async fn write_teardown_ray_config(
+    daft_config: &DaftConfig,
+    teardown_behaviour: TeardownBehaviour,
+    dest: impl AsRef<Path>,
+) -> anyhow::Result<()> {
+    let ProviderConfig::Provisioned(aws_config) = &daft_config.setup.provider_config else {
+        unreachable!("Only provisioned clusters are torn down with Ray");
+    };
+    let name = &daft_config.setup.name;
+    match state::cached_ray_config(aws_config.region(), name).await {
+        Ok(Some(cached)) => {
+            match teardown_ray_config(&cached.contents, teardown_behaviour, aws_config.region()) {
+                Ok(contents) => {
+                    if cached.config_hash.as_deref() != Some(&*config_hash(daft_config)) {
+                        logging::info(format!(
+                            "The configuration of {name} has changed since it was spun up; using the Ray configuration which it was spun up with"
+                        ));
+                    };
+                    fs::write(dest, contents).await?;
+                    return Ok(());
+                }
+                Err(error) => logging::warn(format!(
+                    "The cached Ray configuration of {name} cannot be used, so converting the configuration file instead: {error}"
+                )),
+            }
+        }
+        Ok(None) => (),
+        Err(error) => logging::warn(format!(
+            "Failed to read the Ray configuration which {name} was spun up with, so converting the configuration file instead: {error}"
+        )),
+    };
+    let ray_config = convert(daft_config, Some(teardown_behaviour))?;
+    write_ray_config(&ray_config, dest).await
+}
+
+/// Runs `ray up` or `ray down` for the cluster of the given configuration; the
+/// progress of `ray up` is reported stage by stage, unless its raw output is
+/// shown instead.
+async fn run_ray_up_or_down_command(
+    spin_direction: SpinDirection,
+    daft_config: &DaftConfig,
+    ray_path: impl AsRef<Path>,
+    show_output: bool,
+) -> anyhow::Result<()> {
+    let mut command = Command::new("ray");
+    command
+        .arg(spin_direction.as_str())
+        .arg(ray_path.as_ref())
+        .arg("-y");
+    let status = match spin_direction {
+        SpinDirection::Up if !show_output && !logging::is_verbose() => {
+            let progress = progress::Progress::new(daft_config);
+            logging::run_with(&mut command, false, &|line| progress.observe(line)).await?
+        }
+        SpinDirection::Up => logging::run_with(&mut command, true, &|_| ()).await?,
+        SpinDirection::Down => logging::run(&mut command).await?,
+    };
+    if !status.success() {
+        anyhow::bail!(
+            "`ray {}` failed with {status}; rerun it with `--verbose` to see its output",
+            spin_direction.as_str()
+        );
+    };
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AwsInstance {
+    instance_id: StrRef,
+    regular_name: StrRef,
+    ray_name: StrRef,
+    key_pair_name: Option<StrRef>,
+    public_ipv4_address: Option<Ipv4Addr>,
+    private_ipv4_address: Option<Ipv4Addr>,
+    state: Option<InstanceStateName>,
+    node_type: NodeType,
+    launch_time: Option<SystemTime>,
+    instance_type: Option<StrRef>,
+    vcpus: Option<u32>,
+    /// How long the instance is meant to live for (from its `ttl-hours`),
+    /// after which `gc` tears its cluster down.
+    ttl: Option<Duration>,
+    /// Whether the instance was spun up by daft-launcher (rather than, e.g.,
+    /// by `ray up` directly).
+    launched_by_launcher: bool,
+}
+
+impl AwsInstance {
+    /// The address at which the launcher reaches the instance: its public
+    /// address, or else (e.g., from inside the VPC, or through a bastion) its
+    /// private one.
+    fn address(&self) -> Option<Ipv4Addr> {
+        self.public_ipv4_address.or(self.private_ipv4_address)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NodeType {
+    Head,
+    Worker,
+}
+
+impl NodeType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Head => "head",
+            Self::Worker => "worker",
+        }
+    }
+}
+
+impl FromStr for NodeType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "head" => Ok(NodeType::Head),
+            "worker" => Ok(NodeType::Worker),
+            _ => anyhow::bail!("Unrecognized node type: {}", s),
+        }
+    }
+}
+
+/// Returns the `DescribeInstances` filters which only match the instances
+/// which have all of the given tags.
+fn tag_filters(tags: &[(StrRef, StrRef)]) -> Vec<Filter> {
+    tags.iter()
+        .map(|(key, value)| {
+            Filter::builder()
+                .name(format!("tag:{key}"))
+                .values(value.to_string())
+                .build()
+        })
+        .collect()
+}
+
+/// Returns the instances of the Ray clusters in the given region which have
+/// all of the given tags.
+async fn get_ray_clusters_from_aws(
+    region: StrRef,
+    tags: &[(StrRef, StrRef)],
+) -> anyhow::Result<Vec<AwsInstance>> {
+    let region = Region::new(region.to_string());
+    let sdk_config = aws_config::defaults(BehaviorVersion::latest())
+        .region(region)
+        .load()
+        .await;
+    let client = Client::new(&sdk_config);
+    let instances = client
+        .describe_instances()
+        .set_filters(Some(tag_filters(tags)).filter(|filters| !filters.is_empty()))
+        .send()
+        .await
+        .classify(Failure::CloudApi)?;
+    let reservations = instances.reservations.unwrap_or_default();
+    let instances = reservations
+        .iter()
+        .filter_map(|reservation| reservation.instances.as_ref())
+        .flatten()
+        .filter_map(|instance| {
+            instance.tags.as_ref().map(|tags| {
+                (
+                    instance,
+                    tags.iter().filter_map(|tag| tag.key().zip(tag.value())),
+                )
+            })
+        })
+        .filter_map(|(instance, tags)| {
+            let mut ray_name = None;
+            let mut regular_name = None;
+            let mut node_type = None;
+            let mut ttl = None;
+            let mut launched_by_launcher = false;
+            for (key, value) in tags {
+                if key == "Name" {
+                    ray_name = Some(value.into());
+                } else if key == "ray-cluster-name" {
+                    regular_name = Some(value.into());
+                } else if key == "ray-node-type" {
+                    node_type = value.parse().ok();
+                } else if key == TTL_TAG {
+                    ttl = value
+                        .parse::<u64>()
+                        .ok()
+                        .and_then(|hours| hours.checked_mul(3600))
+                        .map(Duration::from_secs);
+                } else if key == LAUNCHER_TAG {
+                    launched_by_launcher = true;
+                }
+            }
+            let ray_name = ray_name?;
+            let regular_name = regular_name?;
+            let instance_id = instance.instance_id.as_deref()?.into();
+            let node_type = node_type?;
+            Some(AwsInstance {
+                instance_id,
+                regular_name,
+                ray_name,
+                key_pair_name: instance.key_name().map(Into::into),
+                public_ipv4_address: instance
+                    .public_ip_address()
+                    .and_then(|ip_addr| ip_addr.parse().ok()),
+                private_ipv4_address: instance
+                    .private_ip_address()
+                    .and_then(|ip_addr| ip_addr.parse().ok()),
+                state: instance
+                    .state()
+                    .and_then(|instance_state| instance_state.name())
+                    .cloned(),
+                node_type,
+                launch_time: instance
+                    .launch_time()
+                    .and_then(|launch_time| SystemTime::try_from(*launch_time).ok()),
+                instance_type: instance
+                    .instance_type()
+                    .map(|instance_type| instance_type.as_str().into()),
+                vcpus: instance.cpu_options().and_then(|cpu_options| {
+                    let vcpus = cpu_options.core_count()? * cpu_options.threads_per_core()?;
+                    u32::try_from(vcpus).ok()
+                }),
+                ttl,
+                launched_by_launcher,
+            })
+        })
+        .collect();
+    Ok(instances)
+}
+
+/// Returns the instances which match the given name regex, and (if asked) are
+/// head nodes or are running.
+fn filter_instances<'a>(
+    instances: &'a [AwsInstance],
+    regex: Option<&str>,
+    head: bool,
+    running: bool,
+) -> anyhow::Result<Vec<&'a AwsInstance>> {
+    let regex = regex.map(Regex::new).transpose()?;
+    Ok(instances
+        .iter()
+        .filter(|instance| {
+            if (head && instance.node_type != NodeType::Head)
+                || (running && instance.state != Some(InstanceStateName::Running))
+            {
+                return false;
+            } else if let Some(regex) = regex.as_ref() {
+                if !regex.is_match(&instance.regular_name) {
+                    return false;
+                };
+            };
+            true
+        })
+        .collect())
+}
+
+/// A machine-readable description of an instance, as printed by `provisioned
+/// list --output json`.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+struct InstanceRecord<'a> {
+    name: &'a str,
+    instance_id: &'a str,
+    node_type: &'static str,
+    state: Option<&'a str>,
+    public_ipv4_address: Option<Ipv4Addr>,
+    /// In RFC 3339 format.
+    launch_time: Option<String>,
+}
+
+impl<'a> From<&'a AwsInstance> for InstanceRecord<'a> {
+    fn from(instance: &'a AwsInstance) -> Self {
+        Self {
+            name: &instance.regular_name,
+            instance_id: &instance.instance_id,
+            node_type: instance.node_type.as_str(),
+            state: instance.state.as_ref().map(InstanceStateName::as_str),
+            public_ipv4_address: instance.public_ipv4_address,
+            launch_time: instance.launch_time.map(format_launch_time),
+        }
+    }
+}
+
+/// Formats the given launch time in RFC 3339 format (e.g.,
+/// `2023-11-14T22:13:20Z`).
+fn format_launch_time(launch_time: SystemTime) -> String {
+    aws_sdk_ec2::primitives::DateTime::from(launch_time)
+        .fmt(aws_sdk_ec2::primitives::DateTimeFormat::DateTime)
+        .unwrap_or_else(|_| "n/a".to_string())
+}
+
+/// Sorts the given instances by the given column.
+fn sort_instances(instances: &mut [&AwsInstance], column: ListColumn) {
+    match column {
+        ListColumn::Name => instances.sort_by(|a, b| a.regular_name.cmp(&b.regular_name)),
+        ListColumn::InstanceId => instances.sort_by(|a, b| a.instance_id.cmp(&b.instance_id)),
+        ListColumn::NodeType => instances.sort_by_key(|instance| instance.node_type.as_str()),
+        ListColumn::Status => {
+            let status = |instance: &AwsInstance| {
+                instance
+                    .state
+                    .as_ref()
+                    .map(InstanceStateName::as_str)
+                    .map(str::to_string)
+            };
+            instances.sort_by_cached_key(|instance| status(instance))
+        }
+        ListColumn::Ipv4 => instances.sort_by_key(|instance| instance.public_ipv4_address),
+        // The longest-running first, and those of unknown launch time last.
+        ListColumn::LaunchTime | ListColumn::Uptime => {
+            instances.sort_by_key(|instance| (instance.launch_time.is_none(), instance.launch_time))
+        }
+    }
+}
+
+/// Sums the hourly prices of the live instances of each cluster, of those
+/// whose price is known.
+fn cluster_hourly_costs<'a>(
+    instances: &[&'a AwsInstance],
+    prices: &HashMap<StrRef, f64>,
+) -> BTreeMap<&'a str, f64> {
+    let mut costs = BTreeMap::new();
+    for instance in instances {
+        let is_live = matches!(
+            instance.state,
+            Some(InstanceStateName::Pending | InstanceStateName::Running)
+        );
+        let price = instance
+            .instance_type
+            .as_ref()
+            .and_then(|instance_type| prices.get(instance_type));
+        if let (true, Some(price)) = (is_live, price) {
+            *costs.entry(&*instance.regular_name).or_default() += price;
+        };
+    }
+    costs
+}
+
+/// A summary of the instances of a cluster, as listed by `provisioned list
+/// --group-by-cluster`.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+struct ClusterSummary<'a> {
+    name: &'a str,
+    head_state: Option<&'a str>,
+    head_ipv4_address: Option<Ipv4Addr>,
+    /// The number of workers in each state.
+    workers: BTreeMap<&'a str, usize>,
+    /// The total vCPUs of the live (pending or running) instances.
+    vcpus: u32,
+}
+
+/// Collapses the given instances into one summary per cluster, in the order in
+/// which the clusters first appear.
+fn summarize_clusters<'a>(instances: &[&'a AwsInstance]) -> Vec<ClusterSummary<'a>> {
+    let mut summaries = Vec::<ClusterSummary<'a>>::new();
+    for instance in instances {
+        let index = match summaries
+            .iter()
+            .position(|summary| summary.name == &*instance.regular_name)
+        {
+            Some(index) => index,
+            None => {
+                summaries.push(ClusterSummary {
+                    name: &instance.regular_name,
+                    head_state: None,
+                    head_ipv4_address: None,
+                    workers: BTreeMap::new(),
+                    vcpus: 0,
+                });
+                summaries.len() - 1
+            }
+        };
+        let summary = &mut summaries[index];
+        let state = instance.state.as_ref().map(InstanceStateName::as_str);
+        match instance.node_type {
+            NodeType::Head => {
+                summary.head_state = state;
+                summary.head_ipv4_address = instance.public_ipv4_address;
+            }
+            NodeType::Worker => {
+                *summary.workers.entry(state.unwrap_or("n/a")).or_default() += 1;
+            }
+        };
+        if matches!(
+            instance.state,
+            Some(InstanceStateName::Pending | InstanceStateName::Running)
+        ) {
+            summary.vcpus += instance.vcpus.unwrap_or_default();
+        };
+    }
+    summaries
+}
+
+/// Formats the number of workers in each state (e.g., `1 pending, 3 running`).
+fn format_workers(workers: &BTreeMap<&str, usize>) -> String {
+    if workers.is_empty() {
+        return "none".to_string();
+    };
+    workers
+        .iter()
+        .map(|(state, count)| format!("{count} {state}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A [`ClusterSummary`] flattened into a row of `provisioned list
+/// --group-by-cluster --output csv`.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+struct ClusterSummaryRecord<'a> {
+    name: &'a str,
+    head_state: Option<&'a str>,
+    head_ipv4_address: Option<Ipv4Addr>,
+    workers: String,
+    vcpus: u32,
+}
+
+impl<'a> From<&ClusterSummary<'a>> for ClusterSummaryRecord<'a> {
+    fn from(summary: &ClusterSummary<'a>) -> Self {
+        Self {
+            name: summary.name,
+            head_state: summary.head_state,
+            head_ipv4_address: summary.head_ipv4_address,
+            workers: format_workers(&summary.workers),
+            vcpus: summary.vcpus,
+        }
+    }
+}
+
+fn format_cluster_summaries_table(
+    summaries: &[ClusterSummary],
+    costs: &BTreeMap<&str, f64>,
+) -> Table {
+    let mut table = Table::default();
+    table
+        .load_preset(presets::UTF8_FULL)
+        .apply_modifier(modifiers::UTF8_ROUND_CORNERS)
+        .apply_modifier(modifiers::UTF8_SOLID_INNER_BORDERS)
+        .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+        .set_header(
+            [
+                "Name",
+                "Head Status",
+                "Head IPv4",
+                "Workers",
+                "vCPUs",
+                "$/hr",
+            ]
+            .map(|header| {
+                Cell::new(header)
+                    .set_alignment(CellAlignment::Center)
+                    .add_attribute(Attribute::Bold)
+            }),
+        );
+    for summary in summaries {
+        table.add_row(vec![
+            Cell::new(summary.name).fg(Color::Cyan),
+            Cell::new(summary.head_state.unwrap_or("n/a")),
+            Cell::new(
+                summary
+                    .head_ipv4_address
+                    .map_or_else(|| "n/a".to_string(), |ipv4| ipv4.to_string()),
+            ),
+            Cell::new(format_workers(&summary.workers)),
+            Cell::new(summary.vcpus),
+            Cell::new(
+                costs
+                    .get(summary.name)
+                    .map_or_else(|| "n/a".to_string(), |cost| pricing::format_price(*cost)),
+            ),
+        ]);
+    }
+    table
+}
+
+fn format_table(
+    instances: &[&AwsInstance],
+    now: SystemTime,
+    prices: &HashMap<StrRef, f64>,
+) -> Table {
+    let mut table = Table::default();
+    table
+        .load_preset(presets::UTF8_FULL)
+        .apply_modifier(modifiers::UTF8_ROUND_CORNERS)
+        .apply_modifier(modifiers::UTF8_SOLID_INNER_BORDERS)
+        .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+        .set_header(
+            [
+                "Name",
+                "Instance ID",
+                "Node Type",
+                "Status",
+                "IPv4",
+                "Launched",
+                "Uptime",
+                "$/hr",
+            ]
+            .map(|header| {
+                Cell::new(header)
+                    .set_alignment(CellAlignment::Center)
+                    .add_attribute(Attribute::Bold)
+            }),
+        );
+    for instance in instances {
+        let status = instance.state.as_ref().map_or_else(
+            || Cell::new("n/a").add_attribute(Attribute::Dim),
+            |status| {
+                let cell = Cell::new(status);
+                match status {
+                    InstanceStateName::Running => cell.fg(Color::Green),
+                    InstanceStateName::Pending => cell.fg(Color::Yellow),
+                    InstanceStateName::ShuttingDown | InstanceStateName::Stopping => {
+                        cell.fg(Color::DarkYellow)
+                    }
+                    InstanceStateName::Stopped | InstanceStateName::Terminated => {
+                        cell.fg(Color::Red)
+                    }
+                    _ => cell,
+                }
+            },
+        );
+        let ipv4 = instance
+            .public_ipv4_address
+            .as_ref()
+            .map_or("n/a".into(), ToString::to_string);
+        let launched = instance
+            .launch_time
+            .map_or_else(|| "n/a".to_string(), format_launch_time);
+        let uptime = instance
+            .launch_time
+            .and_then(|launch_time| now.duration_since(launch_time).ok())
+            .map_or_else(|| "n/a".to_string(), format_duration);
+        table.add_row(vec![
+            Cell::new(instance.regular_name.to_string()).fg(Color::Cyan),
+            Cell::new(instance.instance_id.as_ref()),
+            Cell::new(instance.node_type.as_str()),
+            status,
+            Cell::new(ipv4),
+            Cell::new(launched),
+            Cell::new(uptime),
+            instance
+                .instance_type
+                .as_ref()
+                .and_then(|instance_type| prices.get(instance_type))
+                .map_or_else(
+                    || Cell::new("n/a").add_attribute(Attribute::Dim),
+                    |price| Cell::new(pricing::format_price(*price)),
+                ),
+        ]);
+    }
+    table
+}
+
+/// A cluster of any provider, as listed by the top-level `list`.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+struct ClusterRecord {
+    provider: &'static str,
+    name: String,
+    /// The AWS region, or the Kubernetes context and namespace.
+    location: String,
+    status: String,
+    workers: usize,
+}
+
+/// Summarizes the given instances (in the given region) by cluster: the state
+/// of its head node and the number of its live workers.
+fn aws_cluster_records(instances: &[AwsInstance], region: &str) -> Vec<ClusterRecord> {
+    let mut clusters = BTreeMap::<&str, ClusterRecord>::new();
+    for instance in instances {
+        let cluster = clusters
+            .entry(&*instance.regular_name)
+            .or_insert_with(|| ClusterRecord {
+                provider: "aws",
+                name: instance.regular_name.to_string(),
+                location: region.to_string(),
+                status: "n/a".to_string(),
+                workers: 0,
+            });
+        match instance.node_type {
+            NodeType::Head => {
+                cluster.status = instance
+                    .state
+                    .as_ref()
+                    .map_or("n/a", InstanceStateName::as_str)
+                    .to_string();
+            }
+            NodeType::Worker => {
+                if matches!(
+                    instance.state,
+                    Some(InstanceStateName::Pending | InstanceStateName::Running)
+                ) {
+                    cluster.workers += 1;
+                };
+            }
+        }
+    }
+    clusters.into_values().collect()
+}
+
+/// Parses the output of `kubectl get rayclusters -o json` (in the given
+/// context, if any).
+fn parse_kuberay_clusters(json: &str, context: Option<&str>) -> anyhow::Result<Vec<ClusterRecord>> {
+    let list = serde_json::from_str::<serde_json::Value>(json)?;
+    let items = list["items"]
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("Expected a list of Ray clusters from kubectl"))?;
+    Ok(items
+        .iter()
+        .map(|item| {
+            let namespace = item["metadata"]["namespace"].as_str().unwrap_or("default");
+            ClusterRecord {
+                provider: "kubernetes",
+                name: item["metadata"]["name"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                location: context.map_or_else(
+                    || namespace.to_string(),
+                    |context| format!("{context}/{namespace}"),
+                ),
+                status: item["status"]["state"]
+                    .as_str()
+                    .unwrap_or("n/a")
+                    .to_string(),
+                workers: item["status"]["availableWorkerReplicas"]
+                    .as_u64()
+                    .unwrap_or_default() as usize,
+            }
+        })
+        .collect())
+}
+
+/// Lists the KubeRay clusters in the given Kubernetes context (or the current
+/// context), across all of its namespaces.
+async fn list_kuberay_clusters(context: Option<&str>) -> anyhow::Result<Vec<ClusterRecord>> {
+    let mut command = Command::new("kubectl");
+    if let Some(context) = context {
+        command.arg("--context").arg(context);
+    };
+    let output = command
+        .args(["get", "rayclusters", "--all-namespaces", "-o", "json"])
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "kubectl get rayclusters failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    };
+    parse_kuberay_clusters(&String::from_utf8_lossy(&output.stdout), context)
+}
+
+/// Lists the clusters of every provider: those in the AWS region of the given
+/// configuration (or the default region), and those in the given Kubernetes
+/// contexts (or, if none are given, in the configuration's context or the
+/// current context). A provider (or context) which cannot be reached (e.g.,
+/// without AWS credentials or `kubectl`) is skipped with a warning.
+async fn list_clusters(
+    config: &Path,
+    contexts: &[StrRef],
+    profile: Option<&str>,
+) -> anyhow::Result<Vec<ClusterRecord>> {
+    let daft_config = if is_missing_default_config(config) {
+        None
+    } else {
+        Some(read_daft_config(config, profile).await?)
+    };
+    let provider_config = daft_config
+        .as_ref()
+        .map(|daft_config| &daft_config.setup.provider_config);
+
+    let mut clusters = vec![];
+    let region = match provider_config {
+        Some(ProviderConfig::Provisioned(aws_config)) => Ok(aws_config.region().clone()),
+        _ => resolve_region(None).await,
+    };
+    let aws_clusters = async move {
+        let region = region?;
+        assert_is_logged_in_with_aws().await?;
+        let instances = get_ray_clusters_from_aws(region.clone(), &[]).await?;
+        anyhow::Ok(aws_cluster_records(&instances, &region))
+    };
+    match aws_clusters.await {
+        Ok(aws_clusters) => clusters.extend(aws_clusters),
+        Err(error) => logging::warn(format!("Skipped the AWS clusters: {error}")),
+    };
+
+    let contexts = if contexts.is_empty() {
+        vec![match provider_config {
+            Some(ProviderConfig::Byoc(k8s_config)) => k8s_config.context.clone(),
+            _ => None,
+        }]
+    } else {
+        contexts.iter().cloned().map(Some).collect()
+    };
+    for context in &contexts {
+        match list_kuberay_clusters(context.as_deref()).await {
+            Ok(kuberay_clusters) => clusters.extend(kuberay_clusters),
+            Err(error) => match context {
+                Some(context) => logging::warn(format!(
+                    "Skipped the Kubernetes clusters in the context {context}: {error}"
+                )),
+                None => logging::warn(format!("Skipped the Kubernetes clusters: {error}")),
+            },
+        };
+    }
+    Ok(clusters)
+}
+
+fn format_clusters_table(clusters: &[ClusterRecord]) -> Table {
+    let mut table = Table::default();
+    table
+        .load_preset(presets::UTF8_FULL)
+        .apply_modifier(modifiers::UTF8_ROUND_CORNERS)
+        .apply_modifier(modifiers::UTF8_SOLID_INNER_BORDERS)
+        .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+        .set_header(
+            ["Provider", "Name", "Location", "Status", "Workers"].map(|header| {
+                Cell::new(header)
+                    .set_alignment(CellAlignment::Center)
+                    .add_attribute(Attribute::Bold)
+            }),
+        );
+    for cluster in clusters {
+        table.add_row(vec![
+            Cell::new(cluster.provider),
+            Cell::new(&cluster.name).fg(Color::Cyan),
+            Cell::new(&cluster.location),
+            Cell::new(&cluster.status),
+            Cell::new(cluster.workers),
+        ]);
+    }
+    table
+}
+
+/// Formats the given daily costs as a table with a row per day and a column
+/// per cluster.
+fn format_costs_table(costs: &[cost::DailyCost]) -> Table {
+    let clusters = costs
+        .iter()
+        .map(|cost| &*cost.cluster)
+        .collect::<BTreeSet<_>>();
+    let mut by_date = BTreeMap::<&str, BTreeMap<&str, f64>>::new();
+    for cost in costs {
+        *by_date
+            .entry(&cost.date)
+            .or_default()
+            .entry(&cost.cluster)
+            .or_default() += cost.usd;
+    }
+    let mut table = Table::default();
+    table
+        .load_preset(presets::UTF8_FULL)
+        .apply_modifier(modifiers::UTF8_ROUND_CORNERS)
+        .apply_modifier(modifiers::UTF8_SOLID_INNER_BORDERS)
+        .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+        .set_header(
+            std::iter::once("Date")
+                .chain(clusters.iter().copied())
+                .map(|header| {
+                    Cell::new(header)
+                        .set_alignment(CellAlignment::Center)
+                        .add_attribute(Attribute::Bold)
+                }),
+        );
+    for (date, costs) in by_date {
+        table.add_row(
+            std::iter::once(Cell::new(date).fg(Color::Cyan)).chain(clusters.iter().map(
+                |cluster| {
+                    Cell::new(
+                        costs
+                            .get(cluster)
+                            .map_or_else(|| "-".to_string(), |usd| cost::format_usd(*usd)),
+                    )
+                },
+            )),
+        );
+    }
+    table
+}
+
+async fn assert_is_logged_in_with_aws() -> anyhow::Result<()> {
+    let sdk_config = aws_config::defaults(aws_config::BehaviorVersion::latest())
+        .region(aws_config::meta::region::RegionProviderChain::default_provider())
+        .load()
+        .await;
+    let client = aws_sdk_sts::Client::new(&sdk_config);
+    if client.get_caller_identity().send().await.is_ok() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("You are not logged in with the AWS cli tool; please authenticate with it first before re-running"))
+            .classify(Failure::Auth)
+    }
+}
+
+async fn find_head_node_service(k8s_config: &K8sConfig) -> anyhow::Result<String> {
+    let namespace = &k8s_config.namespace;
+    let output = k8s_config
+        .kubectl()
+        .arg("get")
+        .arg("svc")
+        .arg("-l")
+        .arg("ray.io/node-type=head")
+        .arg("--no-headers")
+        .arg("-o")
+        .arg("custom-columns=:metadata.name")
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    logging::debug(format!(
+        "kubectl get svc exited with {}: {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    ));
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to get Ray head node services with kubectl in namespace {}",
+            namespace
+        );
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if stdout.trim().is_empty() {
+        anyhow::bail!("Ray head node service not found in namespace {}", namespace);
+    }
+
+    let head_node_service_name = stdout
+        .lines()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get the head node service name"))?;
+    Ok(head_node_service_name.to_string())
+}
+
+async fn establish_kubernetes_port_forward(
+    k8s_config: &K8sConfig,
+    local_port: u16,
+    ready_timeout: Duration,
+) -> anyhow::Result<Child> {
+    let namespace = &k8s_config.namespace;
+    let head_node_service_name = find_head_node_service(k8s_config).await?;
+    logging::info(format!(
+        "Found Ray head node service: {} in namespace {}",
+        head_node_service_name, namespace
+    ));
+
+    // Only stderr is piped, for the error if the port-forward fails; kubectl
+    // reports every connection on stdout, which is left unread.
+    let mut port_forward = k8s_config
+        .kubectl()
+        .arg("port-forward")
+        .arg(format!("svc/{}", head_node_service_name))
+        .arg(format!("{local_port}:{RAY_DASHBOARD_PORT}"))
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    tunnel::wait_for_dashboard(&mut port_forward, local_port, ready_timeout)
+        .await
+        .map_err(|error| {
+            anyhow::anyhow!("Failed to port-forward to {head_node_service_name}: {error}")
+        })?;
+    logging::info("Port-forwarding started successfully");
+    Ok(port_forward)
+}
+
+/// Establishes a port-forward to the Ray dashboard of the cluster described by
+/// the given configuration, regardless of its provider, which is reopened
+/// whenever it closes.
+///
+/// The port-forward is torn down when the returned tunnel is dropped; its
+/// address is where the dashboard is reachable.
+async fn establish_ray_dashboard_port_forward(
+    daft_config: &DaftConfig,
+) -> anyhow::Result<tunnel::Tunnel> {
+    if let Some(local_port) = detached_dashboard_port(&daft_config.setup.name).await {
+        logging::debug("Using the detached tunnel to the cluster's dashboard");
+        return Ok(tunnel::Tunnel::shared(local_port));
+    };
+    let local_port = dashboard_local_port()?;
+    let child = start_ray_dashboard_port_forward(daft_config, local_port)
+        .await
+        .classify(Failure::Tunnel)?;
+    let daft_config = daft_config.clone();
+    Ok(tunnel::supervise(child, local_port, move || {
+        let daft_config = daft_config.clone();
+        async move { start_ray_dashboard_port_forward(&daft_config, local_port).await }
+    }))
+}
+
+/// Returns the local port from which to forward the Ray dashboard: its own,
+/// unless something (e.g., a tunnel to another cluster) already listens on
+/// that, in which case a free one is picked.
+fn dashboard_local_port() -> anyhow::Result<u16> {
+    if std::net::TcpListener::bind(("127.0.0.1", RAY_DASHBOARD_PORT)).is_ok() {
+        return Ok(RAY_DASHBOARD_PORT);
+    };
+    let port = free_local_port()?;
+    logging::info(format!(
+        "Port {RAY_DASHBOARD_PORT} is in use, so the Ray dashboard is forwarded from localhost:{port} instead"
+    ));
+    Ok(port)
+}
+
+async fn start_ray_dashboard_port_forward(
+    daft_config: &DaftConfig,
+    local_port: u16,
+) -> anyhow::Result<Child> {
+    match &daft_config.setup.provider_config {
+        ProviderConfig::Provisioned(aws_config) => {
+            assert_is_logged_in_with_aws().await?;
+
+            if aws_config.connection == Connection::Ssm {
+                let mut sessions =
+                    ssm_port_forward(daft_config, aws_config, &[(local_port, RAY_DASHBOARD_PORT)])
+                        .await?;
+                return Ok(sessions.remove(0));
+            };
+
+            ssh::ssh_portforward(
+                &daft_config.setup.name,
+                aws_config,
+                local_port,
+                &[],
+                daft_config.setup.tunnel_timeout(),
+            )
+            .await
+        }
+        ProviderConfig::Byoc(k8s_config) => {
+            establish_kubernetes_port_forward(
+                k8s_config,
+                local_port,
+                daft_config.setup.tunnel_timeout(),
+            )
+            .await
+        }
+    }
+}
+
+/// Like [`establish_ray_dashboard_port_forward`], but also forwards the local
+/// Prometheus port for provisioned clusters with `[setup.metrics]`, returning
+/// the address of Prometheus if so.
+async fn establish_metrics_port_forward(
+    daft_config: &DaftConfig,
+) -> anyhow::Result<(tunnel::Tunnel, Option<String>)> {
+    let prometheus_port = match (
+        &daft_config.setup.provider_config,
+        &daft_config.setup.metrics,
+    ) {
+        (ProviderConfig::Provisioned(aws_config), Some(_))
+            if aws_config.connection == Connection::Ssm =>
+        {
+            logging::warn(
+                "Prometheus is not forwarded over SSM sessions, so the peak memory is left out",
+            );
+            None
+        }
+        (ProviderConfig::Provisioned(_), Some(metrics)) => Some(metrics.prometheus_port),
+        _ => None,
+    };
+    let Some(prometheus_port) = prometheus_port else {
+        return Ok((
+            establish_ray_dashboard_port_forward(daft_config).await?,
+            None,
+        ));
+    };
+    let local_port = dashboard_local_port()?;
+    let child = start_metrics_port_forward(daft_config, local_port, prometheus_port)
+        .await
+        .classify(Failure::Tunnel)?;
+    let tunnel = {
+        let daft_config = daft_config.clone();
+        tunnel::supervise(child, local_port, move || {
+            let daft_config = daft_config.clone();
+            async move { start_metrics_port_forward(&daft_config, local_port, prometheus_port).await }
+        })
+    };
+    Ok((tunnel, Some(format!("http://localhost:{prometheus_port}"))))
+}
+
+async fn start_metrics_port_forward(
+    daft_config: &DaftConfig,
+    local_port: u16,
+    prometheus_port: u16,
+) -> anyhow::Result<Child> {
+    let ProviderConfig::Provisioned(aws_config) = &daft_config.setup.provider_config else {
+        anyhow::bail!("Prometheus is only forwarded for provisioned clusters");
+    };
+    assert_is_logged_in_with_aws().await?;
+    ssh::ssh_portforward(
+        &daft_config.setup.name,
+        aws_config,
+        local_port,
+        &[(prometheus_port, metrics::PROMETHEUS_PORT)],
+        daft_config.setup.tunnel_timeout(),
+    )
+    .await
+}
+
+/// Returns the id of the running head node of the given provisioned cluster.
+async fn head_instance_id(
+    daft_config: &DaftConfig,
+    aws_config: &AwsConfig,
+) -> anyhow::Result<StrRef> {
+    let instances = get_ray_clusters_from_aws(
+        aws_config.region().clone(),
+        &[("ray-cluster-name".into(), daft_config.setup.name.clone())],
+    )
+    .await?;
+    instances
+        .into_iter()
+        .find(|instance| {
+            instance.node_type == NodeType::Head
+                && instance.state == Some(InstanceStateName::Running)
+        })
+        .map(|instance| instance.instance_id)
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Cluster {} has no running head node",
+                daft_config.setup.name
+            )
+        })
+}
+
+/// Forwards the given `(local, remote)` pairs of ports to the head node of the
+/// given provisioned cluster through SSM Session Manager, returning the
+/// sessions in the same order.
+async fn ssm_port_forward(
+    daft_config: &DaftConfig,
+    aws_config: &AwsConfig,
+    portforwards: &[(u16, u16)],
+) -> anyhow::Result<Vec<Child>> {
+    let instance_id = head_instance_id(daft_config, aws_config).await?;
+    ssm::port_forward(&instance_id, aws_config.region(), portforwards).await
+}
+
+/// Opens a tunnel from the local Ray client port to the Ray client server of the
+/// cluster described by the given configuration, returning the pid of the
+/// process which holds the tunnel open.
+///
+/// Unlike the dashboard port-forward, the tunnel is left open once the launcher
+/// exits.
+async fn open_ray_client_tunnel(daft_config: &DaftConfig) -> anyhow::Result<u32> {
+    start_ray_client_tunnel(daft_config)
+        .await
+        .classify(Failure::Tunnel)
+}
+
+async fn start_ray_client_tunnel(daft_config: &DaftConfig) -> anyhow::Result<u32> {
+    let mut tunnel = match &daft_config.setup.provider_config {
+        ProviderConfig::Provisioned(aws_config) => {
+            assert_is_logged_in_with_aws().await?;
+
+            match aws_config.connection {
+                Connection::Ssh => {
+                    ssh::ssh_tunnel(
+                        &daft_config.setup.name,
+                        aws_config,
+                        &[(RAY_CLIENT_PORT, RAY_CLIENT_PORT)],
+                    )
+                    .await?
+                }
+                Connection::Ssm => {
+                    let instance_id = head_instance_id(daft_config, aws_config).await?;
+                    ssm::tunnel(
+                        &instance_id,
+                        aws_config.region(),
+                        RAY_CLIENT_PORT,
+                        RAY_CLIENT_PORT,
+                    )?
+                }
+            }
+        }
+        ProviderConfig::Byoc(k8s_config) => {
+            let head_node_service_name = find_head_node_service(k8s_config).await?;
+            k8s_config
+                .kubectl()
+                .arg("port-forward")
+                .arg(format!("svc/{head_node_service_name}"))
+                .arg(format!("{RAY_CLIENT_PORT}:{RAY_CLIENT_PORT}"))
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()?
+        }
+    };
+    wait_until_listening(&mut tunnel, RAY_CLIENT_PORT, "the Ray client server").await
+}
+
+/// Waits for the given tunnel (to the given service) to start accepting
+/// connections on the given local port, returning its pid.
+async fn wait_until_listening(tunnel: &mut Child, port: u16, service: &str) -> anyhow::Result<u32> {
+    let pid = tunnel
+        .id()
+        .ok_or_else(|| anyhow::anyhow!("The tunnel to {service} exited immediately"))?;
+
+    let start = tokio::time::Instant::now();
+    loop {
+        if let Some(status) = tunnel.try_wait()? {
+            anyhow::bail!("The tunnel to {service} exited with status: {status}");
+        };
+        if tokio::net::TcpStream::connect(("localhost", port))
+            .await
+            .is_ok()
+        {
+            break Ok(pid);
+        };
+        if start.elapsed() > Duration::from_secs(10) {
+            let _ = tunnel.kill().await;
+            anyhow::bail!("Opening a tunnel to {service} timed out");
+        };
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
+
+/// Opens a tunnel forwarding the given ports (the first of which is the
+/// dashboard's) to the head node of the given provisioned cluster in the
+/// background, and records it in the local state, returning its pid.
+async fn open_detached_tunnel(
+    daft_config: &DaftConfig,
+    aws_config: &AwsConfig,
+    portforwards: &[(u16, u16)],
+) -> anyhow::Result<u32> {
+    if aws_config.connection == Connection::Ssm {
+        anyhow::bail!("`--detach` is not available with `connection = \"ssm\"`");
+    };
+    let mut tunnel = ssh::ssh_tunnel(&daft_config.setup.name, aws_config, portforwards)
+        .await
+        .classify(Failure::Tunnel)?;
+    let pid = wait_until_listening(&mut tunnel, portforwards[0].0, "the Ray dashboard")
+        .await
+        .classify(Failure::Tunnel)?;
+    state::record_tunnel(&state::DetachedTunnel {
+        cluster: daft_config.setup.name.clone(),
+        pid,
+        ports: portforwards.to_vec(),
+        opened_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        command: process_command(pid).await,
+    })
+    .await?;
+    Ok(pid)
+}
+
+/// Returns the command line of the process with the given pid (or, on
+/// Windows, its image name), or `None` if it is not running.
+async fn process_command(pid: u32) -> Option<StrRef> {
+    if cfg!(windows) {
+        // `tasklist` succeeds either way, but lists the process only if it is
+        // running, as `"<image name>","<pid>",...`.
+        let output = Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {pid}"), "/NH", "/FO", "CSV"])
+            .stderr(Stdio::null())
+            .output()
+            .await
+            .ok()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let (image_name, rest) = stdout.trim().strip_prefix('"')?.split_once('"')?;
+        return rest
+            .starts_with(&format!(",\"{pid}\""))
+            .then(|| image_name.into());
+    };
+    let output = Command::new("ps")
+        .args(["-o", "command=", "-p", &pid.to_string()])
+        .stderr(Stdio::null())
+        .output()
+        .await
+        .ok()?;
+    let command = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !command.is_empty()).then(|| command.into())
+}
+
+/// Returns whether the given detached tunnel is still open: whether its pid is
+/// still that of the ssh process which was recorded, rather than of another
+/// process which reused it.
+async fn is_open(tunnel: &state::DetachedTunnel) -> bool {
+    match (&tunnel.command, process_command(tunnel.pid).await) {
+        (Some(recorded), Some(running)) => *recorded == running,
+        // Without a recorded command line, a process with its pid cannot be
+        // told to be the tunnel, so it is left alone.
+        _ => false,
+    }
+}
+
+/// Terminates the process with the given pid, returning whether it succeeded.
+async fn terminate(pid: u32) -> anyhow::Result<bool> {
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("taskkill");
+        command.args(["/PID", &pid.to_string(), "/F"]);
+        command
+    } else {
+        let mut command = Command::new("kill");
+        command.arg(pid.to_string());
+        command
+    };
+    Ok(command.stdout(Stdio::null()).status().await?.success())
+}
+
+/// Returns the recorded detached tunnels which are still open.
+async fn open_detached_tunnels() -> anyhow::Result<Vec<state::DetachedTunnel>> {
+    let mut open = vec![];
+    for tunnel in state::read_tunnels().await? {
+        if is_open(&tunnel).await {
+            open.push(tunnel);
+        };
+    }
+    Ok(open)
+}
+
+/// Returns the local port from which a detached tunnel to the given cluster
+/// already forwards the Ray dashboard (if it accepts connections on it), so
+/// that commands can use it instead of opening their own.
+async fn detached_dashboard_port(cluster: &str) -> Option<u16> {
+    let tunnels = open_detached_tunnels().await.ok()?;
+    let local_port = tunnels
+        .iter()
+        .filter(|tunnel| &*tunnel.cluster == cluster)
+        .find_map(|tunnel| {
+            tunnel
+                .ports
+                .iter()
+                .find(|&&(_, remote)| remote == RAY_DASHBOARD_PORT)
+                .map(|&(local, _)| local)
+        })?;
+    tokio::net::TcpStream::connect(("localhost", local_port))
+        .await
+        .is_ok()
+        .then_some(local_port)
+}
+
+/// Renders the shell exports which point Ray and Daft at the cluster through
+/// the tunnel held open by the process with the given pid.
+fn format_connect_env(pid: u32) -> String {
+    format!(
+        "export RAY_ADDRESS=ray://localhost:{RAY_CLIENT_PORT}\nexport DAFT_RUNNER=ray\nexport DAFT_LAUNCHER_TUNNEL_PID={pid}\n"
+    )
+}
+
+fn describe_ray_dashboard_port_forward(daft_config: &DaftConfig) -> String {
+    match &daft_config.setup.provider_config {
+        ProviderConfig::Provisioned(aws_config) => format!(
+            "ssh port-forward to the head node of cluster {} in region {}",
+            daft_config.setup.name,
+            aws_config.region()
+        ),
+        ProviderConfig::Byoc(k8s_config) => format!(
+            "kubectl port-forward to the ray head service in namespace {}",
+            k8s_config.namespace
+        ),
+    }
+}
+
+/// Describes the address at which the job would be submitted, as
+/// [`establish_ray_dashboard_port_forward`] would pick it, but without
+/// forwarding anything.
+async fn describe_dry_run_address(daft_config: &DaftConfig) -> String {
+    if let Some(local_port) = detached_dashboard_port(&daft_config.setup.name).await {
+        return format!("http://localhost:{local_port} (via the detached tunnel to the cluster)");
+    };
+    let port_forward = describe_ray_dashboard_port_forward(daft_config);
+    if std::net::TcpListener::bind(("127.0.0.1", RAY_DASHBOARD_PORT)).is_ok() {
+        format!("http://localhost:{RAY_DASHBOARD_PORT} (via {port_forward})")
+    } else {
+        format!("a free local port, picked at submit time since port {RAY_DASHBOARD_PORT} is in use (via {port_forward})")
+    }
+}
+
+/// Prints the job submission which would be made, without establishing a
+/// port-forward to the cluster or uploading anything.
+async fn print_dry_run(
+    daft_config: &DaftConfig,
+    source: &JobSource,
+    submission: &JobSubmission<'_>,
+) -> anyhow::Result<()> {
+    let request = ray_jobs::submit_request(submission, submission.runtime_env);
+    println!("Address: {}", describe_dry_run_address(daft_config).await);
+    println!("Working directory: {source}");
+    if !submission.excludes.is_empty() {
+        println!("Excludes: {}", submission.excludes.join(", "));
+    };
+    if let Some(upload_to) = submission.upload_to {
+        println!("Upload to: {upload_to}");
+    };
+    println!("Request: {}", serde_json::to_string_pretty(&request)?);
+    Ok(())
+}
+
+/// Submits the job without waiting for it to finish, and records the
+/// submission in the local state so that it can later be referred to by name.
+async fn submit_no_wait(
+    client: &RayJobsClient,
+    daft_config: &DaftConfig,
+    submission: &JobSubmission<'_>,
+) -> anyhow::Result<StrRef> {
+    let submission_id = submit_and_record(client, daft_config, submission).await?;
+    println!(
+        "Submitted job {} with submission ID {submission_id}",
+        submission.job_name
+    );
+    Ok(submission_id)
+}
+
+/// Returns a short hash of the given (effective) configuration, by which to
+/// tell which version of it an action was taken with.
+fn config_hash(daft_config: &DaftConfig) -> StrRef {
+    // Going through a `Value` sorts the keys of maps (e.g., the jobs), so that
+    // the hash does not depend on their order.
+    let contents = serde_json::to_value(daft_config)
+        .map(|value| value.to_string())
+        .unwrap_or_default();
+    Sha256::digest(contents.as_bytes())
+        .iter()
+        .take(6)
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>()
+        .into()
+}
+
+/// Returns the name of the local user, for the audit log.
+fn current_user() -> StrRef {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+        .into()
+}
+
+/// Appends the given action on the cluster of the given configuration to the
+/// local audit log (shown by `daft history`); failing to do so is not worth
+/// failing the action for.
+async fn record_action(action: state::ActionKind, daft_config: &DaftConfig, detail: Option<&str>) {
+    record_cluster_action(
+        action,
+        &daft_config.setup.name,
+        config_hash(daft_config),
+        detail,
+    )
+    .await;
+}
+
+/// Appends the given action on the cluster with the given name, spun up from
+/// the configuration with the given hash, to the local audit log.
+async fn record_cluster_action(
+    action: state::ActionKind,
+    cluster: &str,
+    config_hash: StrRef,
+    detail: Option<&str>,
+) {
+    let record = state::Action {
+        action,
+        user: current_user(),
+        cluster: cluster.into(),
+        config_hash,
+        detail: detail.map(Into::into),
+        at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    };
+    if let Err(error) = state::record_action(&record).await {
+        logging::warn(format!(
+            "Failed to record the {} in the audit log: {error}",
+            action.as_str()
+        ));
+    };
+}
+
+/// Records the given state of a cluster which was spun up from this machine,
+/// warning (rather than failing, since the cluster has already changed) if
+/// that fails.
+async fn record_cluster_state(name: &str, region: &str, cluster_state: state::ClusterState) {
+    if let Err(error) = state::record_cluster_state(name, region, cluster_state).await {
+        logging::warn(format!(
+            "Failed to record that the cluster {name} is {}: {error}",
+            cluster_state.as_str()
+        ));
     };
+}
+
+/// Formats the given actions as a table, most recent first.
+fn format_actions_table(actions: &[&state::Action]) -> Table {
+    let mut table = Table::default();
+    table
+        .load_preset(presets::UTF8_FULL)
+        .apply_modifier(modifiers::UTF8_ROUND_CORNERS)
+        .apply_modifier(modifiers::UTF8_SOLID_INNER_BORDERS)
+        .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+        .set_header(
+            ["When", "User", "Action", "Cluster", "Config", "Detail"].map(|header| {
+                Cell::new(header)
+                    .set_alignment(CellAlignment::Center)
+                    .add_attribute(Attribute::Bold)
+            }),
+        );
+    for action in actions {
+        table.add_row(vec![
+            Cell::new(format_launch_time(
+                UNIX_EPOCH + Duration::from_secs(action.at),
+            )),
+            Cell::new(&action.user),
+            Cell::new(action.action.as_str()).fg(match action.action {
+                state::ActionKind::Up | state::ActionKind::Submit => Color::Green,
+                state::ActionKind::Down | state::ActionKind::Kill => Color::Red,
+            }),
+            Cell::new(&action.cluster).fg(Color::Cyan),
+            Cell::new(&action.config_hash),
+            Cell::new(action.detail.as_deref().unwrap_or("")),
+        ]);
+    }
+    table
+}
 
-    Ok(RayConfig {
-        cluster_name: daft_config.setup.name.clone(),
-        max_workers: aws_config.number_of_workers,
-        provider: RayProvider {
-            r#type: "aws".into(),
-            region: aws_config.region.clone(),
-            cache_stopped_nodes: teardown_behaviour.map(TeardownBehaviour::to_cache_stopped_nodes),
-        },
-        auth: RayAuth {
-            ssh_user: aws_config.ssh_user.clone(),
-            ssh_private_key: aws_config.ssh_private_key.clone(),
-        },
-        available_node_types: vec![
-            (
-                "ray.head.default".into(),
-                RayNodeType {
-                    max_workers: 0,
-                    node_config: node_config.clone(),
-                    resources: Some(RayResources { cpu: 0 }),
-                },
-            ),
-            (
-                "ray.worker.default".into(),
-                RayNodeType {
-                    max_workers: aws_config.number_of_workers,
-                    node_config,
-                    resources: None,
-                },
-            ),
-        ]
-        .into_iter()
-        .collect(),
-        setup_commands: generate_setup_commands(
-            daft_config.setup.python_version.clone(),
-            daft_config.setup.ray_version.clone(),
-            &aws_config.dependencies,
-            &aws_config.run,
-        ),
+/// Like [`submit_no_wait`], but without printing anything.
+async fn submit_and_record(
+    client: &RayJobsClient,
+    daft_config: &DaftConfig,
+    submission: &JobSubmission<'_>,
+) -> anyhow::Result<StrRef> {
+    let submission_id = client.submit(submission).await?;
+    state::record_submission(&state::Submission {
+        job_name: submission.job_name.into(),
+        submission_id: submission_id.clone(),
+        cluster: daft_config.setup.name.clone(),
+        submitted_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
     })
+    .await?;
+    record_action(
+        state::ActionKind::Submit,
+        daft_config,
+        Some(&format!("{} ({submission_id})", submission.job_name)),
+    )
+    .await;
+
+    Ok(submission_id)
 }
 
-async fn write_ray_config(ray_config: &RayConfig, dest: impl AsRef<Path>) -> anyhow::Result<()> {
-    let ray_config = serde_yaml::to_string(ray_config)?;
-    fs::write(dest, ray_config).await?;
+async fn submit(
+    client: &RayJobsClient,
+    daft_config: &DaftConfig,
+    submission: &JobSubmission<'_>,
+) -> anyhow::Result<()> {
+    let submission_id = submit_no_wait(client, daft_config, submission).await?;
+
+    follow(client, daft_config, &submission_id).await
+}
+
+/// Submits the job and streams its logs, cancelling and resubmitting it
+/// whenever the watched working directory changes.
+///
+/// This only returns if an error occurs; otherwise it runs until interrupted.
+async fn submit_and_watch(
+    client: &RayJobsClient,
+    daft_config: &DaftConfig,
+    daft_job: &DaftJob,
+    submission: &JobSubmission<'_>,
+    watcher: &mut watch::WorkingDirWatcher,
+) -> anyhow::Result<()> {
+    loop {
+        let submission_id = submit_no_wait(client, daft_config, submission).await?;
+        let changed = tokio::select! {
+            result = follow(client, daft_config, &submission_id) => {
+                if let Err(error) = result {
+                    eprintln!("{error}");
+                };
+                false
+            }
+            result = watcher.changed() => {
+                result?;
+                true
+            }
+        };
+        if changed {
+            println!("Detected changes to the working directory; resubmitting the job");
+            client.stop(&submission_id).await?;
+        } else {
+            println!("Waiting for changes to the working directory to resubmit the job...");
+            watcher.changed().await?;
+        };
+
+        // The working directory may have grown past its limit in the meantime.
+        if let Some(size_limit) = daft_job.working_dir_size_limit {
+            working_dir::check_size(submission.working_dir, submission.excludes, size_limit)?;
+        };
+    }
+}
+
+/// Submits the job and polls it until it finishes, emitting a JSON event
+/// whenever its status changes (instead of streaming its logs), and failing if
+/// it did not succeed.
+async fn submit_with_events(
+    client: &RayJobsClient,
+    daft_config: &DaftConfig,
+    submission: &JobSubmission<'_>,
+) -> anyhow::Result<()> {
+    let submission_id = submit_and_record(client, daft_config, submission).await?;
+    events::emit(&events::JobEvent::submitted(
+        submission.job_name,
+        &submission_id,
+    ))?;
+
+    let mut last_status = None;
+    loop {
+        let ray_job = client.get(&submission_id).await?;
+        if last_status != Some(ray_job.status) {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+            events::emit(&events::JobEvent::from_ray_job(&ray_job, now))?;
+            last_status = Some(ray_job.status);
+        };
+        if !ray_job.status.is_active() {
+            job_finished(daft_config, &ray_job).await?;
+            if ray_job.status == RayJobStatus::Succeeded {
+                break Ok(());
+            };
+            return Err(anyhow::anyhow!(
+                "Job {} finished with status {}",
+                ray_job.describe(),
+                ray_job.status.as_str()
+            ))
+            .classify(Failure::Job);
+        };
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+/// Prompts for SQL statements and submits each of them in turn (after the
+/// initial statement, if one is given), until the user exits. The given
+/// parameters are bound in every statement.
+///
+/// A statement which fails is reported, but does not end the session.
+async fn run_sql_repl(
+    client: &RayJobsClient,
+    daft_config: &DaftConfig,
+    working_dir: &Path,
+    initial_statement: Option<&str>,
+    params: &[(StrRef, StrRef)],
+    preview: &SqlPreview,
+) -> anyhow::Result<()> {
+    wizard::ensure_interactive(
+        "The SQL REPL reads statements from the terminal",
+        "pass the query as an argument instead of `--interactive`",
+    )?;
+    let mut repl = sql::Repl::new()?;
+    println!(
+        "Connected to cluster {}; end statements with `;`, and exit with `exit` or Ctrl-D",
+        daft_config.setup.name
+    );
+    // Notifying of every statement would flood the configured channels, so no
+    // notifications are sent for the session's statements.
+    let daft_config = &DaftConfig {
+        notifications: None,
+        ..daft_config.clone()
+    };
+    let runtime_env = RayRuntimeEnv {
+        env_vars: daft_config.setup.env.clone(),
+        ..RayRuntimeEnv::default()
+    };
+    let resources = EntrypointResources::default();
+    let mut next_statement = initial_statement.map(ToString::to_string);
+    loop {
+        let statement = match next_statement.take() {
+            Some(statement) => statement,
+            None => match repl.read_statement()? {
+                Some(statement) => statement,
+                None => break Ok(()),
+            },
+        };
+        let statement = match sql::bind_parameters(&statement, params) {
+            Ok(statement) => statement,
+            Err(error) => {
+                eprintln!("{error}");
+                continue;
+            }
+        };
+        let command_segments = preview.command_segments(&daft_config.sql.setup, &statement);
+        let submission = JobSubmission {
+            job_name: "sql",
+            working_dir,
+            excludes: &[],
+            upload_to: None,
+            command_segments: &command_segments
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>(),
+            runtime_env: &runtime_env,
+            resources: &resources,
+        };
+        if let Err(error) = submit(client, daft_config, &submission).await {
+            eprintln!("{error}");
+        };
+    }
+}
+
+/// Streams the logs of the given job until it finishes, failing if it did not
+/// succeed.
+async fn follow(client: &RayJobsClient, daft_config: &DaftConfig, id: &str) -> anyhow::Result<()> {
+    notifications::show_progress(
+        daft_config.notifications.as_ref(),
+        &format!("Job {id} is running on cluster {}", daft_config.setup.name),
+    );
+    let ray_job = client.follow(id).await?;
+    job_finished(daft_config, &ray_job).await?;
+    if ray_job.status == RayJobStatus::Succeeded {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "Job {} finished with status {}",
+            ray_job.describe(),
+            ray_job.status.as_str()
+        ))
+        .classify(Failure::Job)
+    }
+}
+
+/// Records the outcome of the given finished job in the local state, and sends
+/// a notification about it (if configured to).
+async fn job_finished(daft_config: &DaftConfig, ray_job: &RayJob) -> anyhow::Result<()> {
+    if let Some(submission_id) = &ray_job.submission_id {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        state::record_outcome(&state::Outcome {
+            submission_id: submission_id.clone(),
+            status: ray_job.status.as_str().into(),
+            duration: ray_job.duration(now).map(|duration| duration.as_secs()),
+            message: ray_job.message.clone(),
+        })
+        .await?;
+    };
+
+    let (event, outcome) = if ray_job.status == RayJobStatus::Succeeded {
+        (NotificationEvent::JobSucceeded, "succeeded".to_string())
+    } else {
+        let reason = ray_job
+            .message
+            .as_deref()
+            .map_or_else(String::new, |message| format!(": {message}"));
+        (
+            NotificationEvent::JobFailed,
+            format!("finished with status {}{reason}", ray_job.status.as_str()),
+        )
+    };
+    let message = format!(
+        "Job {} on cluster {} {outcome}",
+        ray_job.describe(),
+        daft_config.setup.name
+    );
+    notifications::notify(daft_config.notifications.as_ref(), event, &message).await;
     Ok(())
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum SpinDirection {
-    Up,
-    Down,
+async fn notify_cluster_event(daft_config: &DaftConfig, event: NotificationEvent, outcome: &str) {
+    let message = format!("Cluster {} {outcome}", daft_config.setup.name);
+    notifications::notify(daft_config.notifications.as_ref(), event, &message).await;
 }
 
-impl SpinDirection {
-    fn as_str(&self) -> &str {
-        match self {
-            Self::Up => "up",
-            Self::Down => "down",
+/// The jobs of `job submit-all` which could not be submitted, along with why.
+type SubmissionFailures = Vec<(StrRef, anyhow::Error)>;
+
+/// How many times in a row listing the cluster's jobs may fail before
+/// `job submit-all` stops waiting on them.
+const MAX_LIST_FAILURES: u32 = 5;
+
+/// Submits all of the given jobs at once without waiting on their logs, and
+/// then polls the cluster until every one of them has finished.
+///
+/// A job which fails to be submitted does not stop the others from being
+/// submitted; it is returned along with its error instead, as is a job which
+/// disappears from the cluster (or which cannot be waited on, since the
+/// cluster's jobs could not be listed) before it finishes.
+async fn submit_all(
+    client: &RayJobsClient,
+    daft_config: &DaftConfig,
+    jobs: &[(&StrRef, &DaftJob, JobWorkingDir)],
+) -> anyhow::Result<(Vec<RayJob>, SubmissionFailures)> {
+    let submitted = futures_util::future::join_all(jobs.iter().map(
+        |(job_name, daft_job, working_dir)| async move {
+            let command_segments = daft_job.command_segments(&[])?;
+            let submission = JobSubmission {
+                job_name,
+                working_dir: &working_dir.path,
+                excludes: &daft_job.excludes,
+                upload_to: daft_job.upload_to.as_deref(),
+                command_segments: &command_segments
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>(),
+                runtime_env: &daft_job.runtime_env(&daft_config.setup.env),
+                resources: &daft_job.entrypoint_resources,
+            };
+            submit_no_wait(client, daft_config, &submission).await
+        },
+    ))
+    .await;
+    let mut submissions = Vec::with_capacity(jobs.len());
+    let mut failures = SubmissionFailures::new();
+    for ((job_name, ..), submitted) in jobs.iter().zip(submitted) {
+        match submitted {
+            Ok(submission_id) => submissions.push(((*job_name).clone(), submission_id)),
+            Err(error) => failures.push(((*job_name).clone(), error)),
         }
     }
+
+    let mut list_failures = 0;
+    loop {
+        if submissions.is_empty() {
+            break Ok((vec![], failures));
+        };
+        let listed = match client.list().await {
+            Ok(listed) => {
+                list_failures = 0;
+                listed
+            }
+            Err(error) => {
+                list_failures += 1;
+                if list_failures >= MAX_LIST_FAILURES {
+                    for (job_name, submission_id) in submissions {
+                        failures.push((
+                            job_name,
+                            anyhow::anyhow!("Stopped waiting on submission {submission_id}, since the cluster's jobs could not be listed: {error:#}"),
+                        ));
+                    }
+                    break Ok((vec![], failures));
+                };
+                logging::warn(format!(
+                    "Failed to list the cluster's jobs (retrying): {error:#}"
+                ));
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+        let (ray_jobs, missing) = match_submissions(&submissions, listed);
+        if !missing.is_empty() {
+            submissions.retain(|(job_name, _)| {
+                !missing
+                    .iter()
+                    .any(|(missing_name, _)| missing_name == job_name)
+            });
+            failures.extend(missing);
+            continue;
+        };
+        if ray_jobs.iter().all(|ray_job| !ray_job.status.is_active()) {
+            break Ok((ray_jobs, failures));
+        };
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum TeardownBehaviour {
-    Down,
-    Kill,
+/// Finds the jobs of the given `(job name, submission ID)` pairs among the
+/// given jobs listed on the cluster, returning those which were found along
+/// with a failure for each submission which is no longer listed.
+fn match_submissions(
+    submissions: &[(StrRef, StrRef)],
+    listed: Vec<RayJob>,
+) -> (Vec<RayJob>, SubmissionFailures) {
+    let ray_jobs = listed
+        .into_iter()
+        .filter(|ray_job| {
+            ray_job.submission_id.as_ref().is_some_and(|id| {
+                submissions
+                    .iter()
+                    .any(|(_, submission_id)| submission_id == id)
+            })
+        })
+        .collect::<Vec<_>>();
+    let missing = submissions
+        .iter()
+        .filter(|(_, submission_id)| {
+            !ray_jobs
+                .iter()
+                .any(|ray_job| ray_job.submission_id.as_ref() == Some(submission_id))
+        })
+        .map(|(job_name, submission_id)| {
+            (
+                job_name.clone(),
+                anyhow::anyhow!("Submission {submission_id} is no longer listed on the cluster"),
+            )
+        })
+        .collect();
+    (ray_jobs, missing)
 }
 
-impl TeardownBehaviour {
-    fn to_cache_stopped_nodes(self) -> bool {
-        match self {
-            Self::Down => true,
-            Self::Kill => false,
+/// Summarizes the outcome of `job submit-all`: which of the jobs were
+/// submitted, and which could not be (and why).
+fn format_submit_all_summary(ray_jobs: &[RayJob], failures: &SubmissionFailures) -> String {
+    let total = ray_jobs.len() + failures.len();
+    let mut submitted = ray_jobs
+        .iter()
+        .map(|ray_job| {
+            ray_job
+                .daft_job_name()
+                .or(ray_job.submission_id.as_deref())
+                .unwrap_or("n/a")
+        })
+        .collect::<Vec<_>>();
+    submitted.sort_unstable();
+    let mut summary = if submitted.is_empty() {
+        "Submitted none of the jobs".to_string()
+    } else {
+        format!(
+            "Submitted {} of the {total} jobs: {}",
+            submitted.len(),
+            submitted.join(", ")
+        )
+    };
+    for (job_name, error) in failures {
+        summary.push_str(&format!("\nFailed to submit {job_name}: {error:#}"));
+    }
+    summary
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DagJobState {
+    Waiting,
+    Running(StrRef),
+    Finished(RayJobStatus),
+    Skipped,
+}
+
+/// Returns the given jobs along with all of their transitive dependencies.
+fn with_dependencies(jobs: &HashMap<StrRef, DaftJob>, job_names: &[StrRef]) -> Vec<StrRef> {
+    let mut selected = Vec::new();
+    let mut stack = job_names.to_vec();
+    while let Some(job_name) = stack.pop() {
+        if selected.contains(&job_name) {
+            continue;
+        };
+        if let Some(daft_job) = jobs.get(&job_name) {
+            stack.extend(daft_job.depends_on.iter().cloned());
+        };
+        selected.push(job_name);
+    }
+    selected
+}
+
+/// Submits the given jobs in topological order, submitting each job as soon as
+/// all of its dependencies have succeeded (and skipping it if any of them did
+/// not).
+async fn run_dag(
+    client: &RayJobsClient,
+    daft_config: &DaftConfig,
+    order: &[StrRef],
+    working_dirs: &HashMap<StrRef, JobWorkingDir>,
+) -> anyhow::Result<BTreeMap<StrRef, DagJobState>> {
+    let mut states = order
+        .iter()
+        .map(|job_name| (job_name.clone(), DagJobState::Waiting))
+        .collect::<BTreeMap<_, _>>();
+
+    loop {
+        for job_name in order {
+            if states[job_name] != DagJobState::Waiting {
+                continue;
+            };
+            let daft_job = &daft_config.jobs[job_name];
+            let dependency_states = daft_job
+                .depends_on
+                .iter()
+                .map(|dependency| &states[dependency])
+                .collect::<Vec<_>>();
+            if dependency_states.iter().any(|state| {
+                matches!(state, DagJobState::Skipped)
+                    || matches!(state, DagJobState::Finished(status) if *status != RayJobStatus::Succeeded)
+            }) {
+                println!("Skipping job {job_name} since one of its dependencies did not succeed");
+                states.insert(job_name.clone(), DagJobState::Skipped);
+            } else if dependency_states
+                .iter()
+                .all(|state| **state == DagJobState::Finished(RayJobStatus::Succeeded))
+            {
+                let command_segments = daft_job.command_segments(&[])?;
+                let submission = JobSubmission {
+                    job_name,
+                    working_dir: &working_dirs[job_name].path,
+                    excludes: &daft_job.excludes,
+                    upload_to: daft_job.upload_to.as_deref(),
+                    command_segments: &command_segments
+                        .iter()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>(),
+                    runtime_env: &daft_job.runtime_env(&daft_config.setup.env),
+                    resources: &daft_job.entrypoint_resources,
+                };
+                let submission_id =
+                    submit_no_wait(client, daft_config, &submission).await?;
+                states.insert(job_name.clone(), DagJobState::Running(submission_id));
+            };
+        }
+
+        let running = states
+            .iter()
+            .filter_map(|(job_name, state)| match state {
+                DagJobState::Running(submission_id) => {
+                    Some((job_name.clone(), submission_id.clone()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if running.is_empty() {
+            break Ok(states);
+        };
+
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        for (job_name, submission_id) in running {
+            let ray_job = client.get(&submission_id).await?;
+            if !ray_job.status.is_active() {
+                println!(
+                    "Job {job_name} finished with status {}",
+                    ray_job.status.as_str()
+                );
+                job_finished(daft_config, &ray_job).await?;
+                states.insert(job_name, DagJobState::Finished(ray_job.status));
+            };
         }
     }
 }
 
-fn create_temp_file(name: &str) -> anyhow::Result<(TempDir, PathRef)> {
-    let temp_dir = TempDir::new("daft-launcher")?;
-    let mut temp_path = temp_dir.path().to_owned();
-    temp_path.push(name);
-    let temp_path = Arc::from(temp_path);
-    Ok((temp_dir, temp_path))
+/// Resolves the given job reference into the ID of a single job.
+///
+/// If the reference is the name of a job which was previously submitted to the
+/// given cluster, the ID of its most recent submission is returned. Otherwise,
+/// the reference is treated as a raw submission ID.
+fn resolve_job_reference<'a>(
+    submissions: &'a [state::Submission],
+    cluster: &str,
+    job: &'a str,
+) -> &'a str {
+    state::latest_submission(submissions, cluster, job)
+        .map_or(job, |submission| &*submission.submission_id)
 }
 
-fn create_temp_ray_file() -> anyhow::Result<(TempDir, PathRef)> {
-    create_temp_file("ray.yaml")
+/// Resolves the given job reference into the IDs of the jobs which should be
+/// stopped.
+///
+/// A job reference is first matched against the names of the jobs which were
+/// submitted by daft-launcher; if any active submissions of a job with that
+/// name exist, all of them are returned. Otherwise, the reference is resolved
+/// as per [`resolve_job_reference`].
+fn resolve_jobs_to_stop<'a>(
+    ray_jobs: &'a [RayJob],
+    submissions: &'a [state::Submission],
+    cluster: &str,
+    job: &'a str,
+) -> Vec<&'a str> {
+    let ids = ray_jobs
+        .iter()
+        .filter(|ray_job| ray_job.status.is_active() && ray_job.daft_job_name() == Some(job))
+        .filter_map(RayJob::id)
+        .collect::<Vec<_>>();
+    if ids.is_empty() {
+        vec![resolve_job_reference(submissions, cluster, job)]
+    } else {
+        ids
+    }
 }
 
-async fn run_ray_up_or_down_command(
-    spin_direction: SpinDirection,
-    ray_path: impl AsRef<Path>,
+/// Quotes the given string so that it is interpreted as a single word by a
+/// POSIX shell, leaving it as-is if it contains no special characters.
+fn shell_quote(s: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@".contains(c);
+    if !s.is_empty() && s.chars().all(is_safe) {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', r#"'"'"'"#))
+    }
+}
+
+/// Describes the given instances (one per line, with how long each has been
+/// up), for confirming that they should be terminated.
+fn format_instances_to_kill(instances: &[&AwsInstance], now: SystemTime) -> String {
+    instances
+        .iter()
+        .map(|instance| {
+            let uptime = instance
+                .launch_time
+                .and_then(|launch_time| now.duration_since(launch_time).ok())
+                .map_or_else(|| "n/a".to_string(), format_duration);
+            format!(
+                "  {} {} ({}, up {uptime})",
+                instance.regular_name,
+                instance.instance_id,
+                instance.node_type.as_str()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A cluster which `gc` tears down, along with why.
+#[derive(Debug, PartialEq)]
+struct StaleCluster<'a> {
+    name: StrRef,
+    /// How long its head node has been up.
+    age: Duration,
+    reason: String,
+    instances: Vec<&'a AwsInstance>,
+}
+
+/// Returns the clusters among the given instances whose head node is past its
+/// TTL, or (if spun up by daft-launcher) has been up for longer than the given
+/// age (if any), oldest first.
+fn stale_clusters<'a>(
+    instances: &[&'a AwsInstance],
+    older_than: Option<Duration>,
+    now: SystemTime,
+) -> Vec<StaleCluster<'a>> {
+    let live = instances
+        .iter()
+        .copied()
+        .filter(|instance| {
+            !matches!(
+                instance.state,
+                Some(InstanceStateName::ShuttingDown | InstanceStateName::Terminated)
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut stale = vec![];
+    for head in live
+        .iter()
+        .filter(|instance| instance.node_type == NodeType::Head)
+    {
+        let Some(age) = head
+            .launch_time
+            .and_then(|launch_time| now.duration_since(launch_time).ok())
+        else {
+            continue;
+        };
+        let reason = match (head.ttl, older_than) {
+            (Some(ttl), _) if age > ttl => format!("past its TTL of {}", format_duration(ttl)),
+            (_, Some(older_than)) if head.launched_by_launcher && age > older_than => {
+                format!("older than {}", format_duration(older_than))
+            }
+            _ => continue,
+        };
+        stale.push(StaleCluster {
+            name: head.regular_name.clone(),
+            age,
+            reason,
+            instances: live
+                .iter()
+                .copied()
+                .filter(|instance| instance.regular_name == head.regular_name)
+                .collect(),
+        });
+    }
+    stale.sort_by_key(|cluster| std::cmp::Reverse(cluster.age));
+    stale
+}
+
+/// Terminates the given instances in the given region through the EC2 API
+/// (which, unlike `kill`, needs no configuration file for their cluster).
+async fn terminate_instances(region: &StrRef, instances: &[&AwsInstance]) -> anyhow::Result<()> {
+    let sdk_config = aws_config::defaults(BehaviorVersion::latest())
+        .region(Region::new(region.to_string()))
+        .load()
+        .await;
+    Client::new(&sdk_config)
+        .terminate_instances()
+        .set_instance_ids(Some(
+            instances
+                .iter()
+                .map(|instance| instance.instance_id.to_string())
+                .collect(),
+        ))
+        .send()
+        .await
+        .classify(Failure::CloudApi)?;
+    Ok(())
+}
+
+/// Lists the live instances of the given cluster and asks whether to terminate
+/// them, returning whether to go ahead.
+async fn confirm_kill(cluster_name: &str, region: &StrRef) -> anyhow::Result<bool> {
+    let instances = get_ray_clusters_from_aws(region.clone(), &[]).await?;
+    let instances = instances
+        .iter()
+        .filter(|instance| {
+            *instance.regular_name == *cluster_name
+                && !matches!(
+                    instance.state,
+                    Some(InstanceStateName::ShuttingDown | InstanceStateName::Terminated)
+                )
+        })
+        .collect::<Vec<_>>();
+    if instances.is_empty() {
+        return Ok(true);
+    };
+    println!(
+        "The following instances of the cluster {cluster_name} will be terminated:\n{}",
+        format_instances_to_kill(&instances, SystemTime::now())
+    );
+    wizard::ensure_interactive(
+        "Killing the cluster needs confirmation",
+        "pass `--yes` to kill it without confirming",
+    )?;
+    wizard::Wizard::new()?.confirm(
+        &format!("Terminate these {} instances?", instances.len()),
+        false,
+    )
+}
+
+/// Terminates the instances of the (provisioned) cluster of the given
+/// configuration, under its lock (taken over if forced).
+async fn kill_cluster(daft_config: &DaftConfig, force: bool) -> anyhow::Result<()> {
+    let ProviderConfig::Provisioned(aws_config) = &daft_config.setup.provider_config else {
+        not_available_for_byoc!("kill")
+    };
+    let (_temp_dir, ray_path) = create_temp_ray_file()?;
+    write_teardown_ray_config(daft_config, TeardownBehaviour::Kill, &ray_path).await?;
+    let lock = lock::acquire(aws_config.region(), &daft_config.setup.name, "kill", force).await?;
+    let result =
+        run_ray_up_or_down_command(SpinDirection::Down, daft_config, ray_path, false).await;
+    lock.release().await;
+    result?;
+    record_action(state::ActionKind::Kill, daft_config, None).await;
+    record_cluster_state(
+        &daft_config.setup.name,
+        aws_config.region(),
+        state::ClusterState::Killed,
+    )
+    .await;
+    // Its instances are gone, so there is nothing left to spin down with it.
+    if let Err(error) =
+        state::remove_cached_ray_config(aws_config.region(), &daft_config.setup.name).await
+    {
+        logging::warn(format!(
+            "Failed to remove the cached Ray configuration of {}: {error}",
+            daft_config.setup.name
+        ));
+    };
+    notify_cluster_event(daft_config, NotificationEvent::ClusterDown, "was killed").await;
+    Ok(())
+}
+
+/// Terminates the instances of the given stale cluster under its lock, just as
+/// `kill` would, and forgets it.
+async fn gc_cluster(
+    region: &StrRef,
+    cluster: &StaleCluster<'_>,
+    force: bool,
 ) -> anyhow::Result<()> {
-    let _ = Command::new("ray")
-        .arg(spin_direction.as_str())
-        .arg(ray_path.as_ref())
-        .arg("-y")
-        .spawn()?
-        .wait_with_output()
-        .await?;
+    let lock = lock::acquire(region, &cluster.name, "gc", force).await?;
+    let result = terminate_instances(region, &cluster.instances).await;
+    lock.release().await;
+    result?;
+    // The cached Ray configuration holds the hash of the configuration file
+    // which it was spun up from, if it was spun up from this machine.
+    let config_hash = state::cached_ray_config(region, &cluster.name)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|cached| cached.config_hash)
+        .unwrap_or_default();
+    record_cluster_action(
+        state::ActionKind::Kill,
+        &cluster.name,
+        config_hash,
+        Some(&cluster.reason),
+    )
+    .await;
+    record_cluster_state(&cluster.name, region, state::ClusterState::Killed).await;
+    if let Err(error) = state::remove_cached_ray_config(region, &cluster.name).await {
+        logging::warn(format!(
+            "Failed to remove the cached Ray configuration of {}: {error}",
+            cluster.name
+        ));
+    };
     Ok(())
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-struct AwsInstance {
-    instance_id: StrRef,
-    regular_name: StrRef,
-    ray_name: StrRef,
-    key_pair_name: Option<StrRef>,
-    public_ipv4_address: Option<Ipv4Addr>,
-    state: Option<InstanceStateName>,
-    node_type: NodeType,
+/// Returns how long a cluster with the given jobs has had none of them active:
+/// since its last job ended, or else since its head node was launched. Returns
+/// `None` if a job is active (or the launch time is not known).
+fn idle_for(jobs: &[RayJob], launched: Option<SystemTime>, now: SystemTime) -> Option<Duration> {
+    if jobs.iter().any(|job| job.status.is_active()) {
+        return None;
+    };
+    let last_job_end = jobs
+        .iter()
+        .filter_map(|job| job.end_time)
+        .max()
+        .map(|end_time| UNIX_EPOCH + Duration::from_millis(end_time));
+    let since = last_job_end.into_iter().chain(launched).max()?;
+    Some(now.duration_since(since).unwrap_or_default())
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum NodeType {
-    Head,
-    Worker,
+/// Returns a local port which is free to forward from.
+fn free_local_port() -> anyhow::Result<u16> {
+    Ok(std::net::TcpListener::bind(("127.0.0.1", 0))?
+        .local_addr()?
+        .port())
+}
+
+/// Returns how long the cluster of the given configuration has had no active
+/// jobs (see [`idle_for`]), through a temporary tunnel to its dashboard.
+async fn check_idle(
+    daft_config: &DaftConfig,
+    launched: Option<SystemTime>,
+    now: SystemTime,
+) -> anyhow::Result<Option<Duration>> {
+    let ProviderConfig::Provisioned(..) = &daft_config.setup.provider_config else {
+        anyhow::bail!("Only provisioned clusters can be checked for idleness");
+    };
+    // A port of its own keeps the tunnel clear of any `connect` session.
+    let port = free_local_port()?;
+    let _port_forward = start_ray_dashboard_port_forward(daft_config, port)
+        .await
+        .classify(Failure::Tunnel)?;
+    let jobs = RayJobsClient::new(format!("http://localhost:{port}"))
+        .list()
+        .await?;
+    Ok(idle_for(&jobs, launched, now))
 }
 
-impl NodeType {
-    pub fn as_str(self) -> &'static str {
-        match self {
-            Self::Head => "head",
-            Self::Worker => "worker",
+/// Returns the running clusters among the given instances which have had no
+/// active jobs for longer than the given threshold, along with their
+/// configurations and how long they have been idle.
+///
+/// Only the clusters which were spun up from this machine (and whose
+/// configuration files still exist) can be checked; the others are skipped
+/// with a warning.
+async fn find_idle_clusters(
+    instances: &[&AwsInstance],
+    region: &StrRef,
+    threshold: Duration,
+) -> anyhow::Result<Vec<(DaftConfig, Duration)>> {
+    let clusters = state::read_clusters().await?;
+    let known = state::known_clusters(&clusters, Path::is_file);
+    let now = SystemTime::now();
+    let mut idle = vec![];
+    for head in instances.iter().filter(|instance| {
+        instance.node_type == NodeType::Head && instance.state == Some(InstanceStateName::Running)
+    }) {
+        let name = &head.regular_name;
+        let Some(cluster) = known
+            .iter()
+            .find(|cluster| cluster.name == *name && cluster.region == *region)
+        else {
+            logging::warn(format!(
+                "Skipped checking whether the cluster {name} is idle, as it was not spun up from this machine"
+            ));
+            continue;
+        };
+        let result = async {
+            // The configuration file is read with the profile which the
+            // cluster was spun up with, and must still be for this cluster,
+            // since the cluster may be killed with it.
+            let daft_config = read_daft_config(&cluster.config, cluster.profile.as_deref()).await?;
+            if daft_config.setup.name != *name {
+                anyhow::bail!(
+                    "its configuration file {:?} is now for the cluster {}",
+                    cluster.config,
+                    daft_config.setup.name
+                );
+            };
+            let idle_for = check_idle(&daft_config, head.launch_time, now).await?;
+            anyhow::Ok((daft_config, idle_for))
         }
+        .await;
+        match result {
+            Ok((daft_config, Some(idle_for))) if idle_for > threshold => {
+                idle.push((daft_config, idle_for))
+            }
+            Ok(_) => (),
+            Err(error) => logging::warn(format!(
+                "Failed to check whether the cluster {name} is idle: {error}"
+            )),
+        };
     }
+    Ok(idle)
 }
 
-impl FromStr for NodeType {
-    type Err = anyhow::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "head" => Ok(NodeType::Head),
-            "worker" => Ok(NodeType::Worker),
-            _ => anyhow::bail!("Unrecognized node type: {}", s),
-        }
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, (seconds / 60) % 60, seconds % 60);
+    if hours > 0 {
+        format!("{hours}h {minutes}m {seconds}s")
+    } else if minutes > 0 {
+        format!("{minutes}m {seconds}s")
+    } else {
+        format!("{seconds}s")
     }
 }
 
-async fn get_ray_clusters_from_aws(region: StrRef) -> anyhow::Result<Vec<AwsInstance>> {
-    let region = Region::new(region.to_string());
-    let sdk_config = aws_config::defaults(BehaviorVersion::latest())
-        .region(region)
-        .load()
+/// Collects the metrics of the given job from the state API (and, if its
+/// address is given, from Prometheus).
+async fn collect_job_metrics(
+    client: &RayJobsClient,
+    id: &str,
+    prometheus_address: Option<&str>,
+) -> anyhow::Result<job_metrics::JobMetrics> {
+    let ray_job = client.get(id).await?;
+    if ray_job.status.is_active() {
+        logging::warn(format!(
+            "The job {} is still {}, so its metrics are incomplete",
+            ray_job.describe(),
+            ray_job.status.as_str()
+        ));
+    };
+    let job_id = ray_job
+        .job_id
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("The job {} has not started yet", ray_job.describe()))?;
+    let tasks =
+        job_metrics::parse_tasks(&client.get_text(&job_metrics::tasks_path(&job_id)).await?)?;
+    let (spilled_bytes, restored_bytes) =
+        job_metrics::parse_spilling(&client.get_text("/nodes?view=summary").await?)?;
+
+    // The peak memory is only for information, so failing to query it is not
+    // worth failing the command for.
+    let mut peak_memory_bytes = None;
+    if let (Some(address), Some(start_time)) = (prometheus_address, ray_job.start_time) {
+        let end_time = ray_job
+            .end_time
+            .unwrap_or(SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64);
+        let seconds = (end_time.saturating_sub(start_time) / 1000).max(15);
+        let response = async {
+            reqwest::Client::new()
+                .get(format!("{address}/api/v1/query"))
+                .query(&[
+                    ("query", job_metrics::peak_memory_query(seconds)),
+                    ("time", (end_time / 1000).to_string()),
+                ])
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await
+        }
         .await;
-    let client = Client::new(&sdk_config);
-    let instances = client.describe_instances().send().await?;
-    let reservations = instances.reservations.unwrap_or_default();
-    let instances = reservations
-        .iter()
-        .filter_map(|reservation| reservation.instances.as_ref())
-        .flatten()
-        .filter_map(|instance| {
-            instance.tags.as_ref().map(|tags| {
-                (
-                    instance,
-                    tags.iter().filter_map(|tag| tag.key().zip(tag.value())),
-                )
-            })
-        })
-        .filter_map(|(instance, tags)| {
-            let mut ray_name = None;
-            let mut regular_name = None;
-            let mut node_type = None;
-            for (key, value) in tags {
-                if key == "Name" {
-                    ray_name = Some(value.into());
-                } else if key == "ray-cluster-name" {
-                    regular_name = Some(value.into());
-                } else if key == "ray-node-type" {
-                    node_type = value.parse().ok();
-                }
-            }
-            let ray_name = ray_name?;
-            let regular_name = regular_name?;
-            let instance_id = instance.instance_id.as_deref()?.into();
-            let node_type = node_type?;
-            Some(AwsInstance {
-                instance_id,
-                regular_name,
-                ray_name,
-                key_pair_name: instance.key_name().map(Into::into),
-                public_ipv4_address: instance
-                    .public_ip_address()
-                    .and_then(|ip_addr| ip_addr.parse().ok()),
-                state: instance
-                    .state()
-                    .and_then(|instance_state| instance_state.name())
-                    .cloned(),
-                node_type,
-            })
-        })
-        .collect();
-    Ok(instances)
+        match response {
+            Ok(response) => peak_memory_bytes = job_metrics::parse_prometheus_value(&response),
+            Err(error) => logging::warn(format!("Failed to query Prometheus: {error}")),
+        };
+    };
+
+    let failed_tasks = tasks.iter().filter(|task| &*task.state == "FAILED").count();
+    Ok(job_metrics::JobMetrics {
+        job_id,
+        tasks: tasks.len(),
+        failed_tasks,
+        slowest_stages: job_metrics::slowest_stages(&tasks),
+        spilled_bytes,
+        restored_bytes,
+        peak_memory_bytes,
+    })
 }
 
-fn format_table(
-    instances: &[AwsInstance],
-    regex: Option<&str>,
-    head: bool,
-    running: bool,
-) -> anyhow::Result<Table> {
+/// Formats the given job metrics as a summary, with a table of the slowest
+/// stages.
+fn format_job_metrics(metrics: &job_metrics::JobMetrics) -> String {
     let mut table = Table::default();
     table
         .load_preset(presets::UTF8_FULL)
         .apply_modifier(modifiers::UTF8_ROUND_CORNERS)
         .apply_modifier(modifiers::UTF8_SOLID_INNER_BORDERS)
         .set_content_arrangement(ContentArrangement::DynamicFullWidth)
-        .set_header(["Name", "Instance ID", "Status", "IPv4"].map(|header| {
-            Cell::new(header)
-                .set_alignment(CellAlignment::Center)
-                .add_attribute(Attribute::Bold)
-        }));
-    let regex = regex.as_deref().map(Regex::new).transpose()?;
-    for instance in instances.iter().filter(|instance| {
-        if head && instance.node_type != NodeType::Head {
-            return false;
-        } else if running && instance.state != Some(InstanceStateName::Running) {
-            return false;
-        } else if let Some(regex) = regex.as_ref() {
-            if !regex.is_match(&instance.regular_name) {
-                return false;
-            };
-        };
-        true
-    }) {
-        let status = instance.state.as_ref().map_or_else(
-            || Cell::new("n/a").add_attribute(Attribute::Dim),
-            |status| {
-                let cell = Cell::new(status);
-                match status {
-                    InstanceStateName::Running => cell.fg(Color::Green),
-                    InstanceStateName::Pending => cell.fg(Color::Yellow),
-                    InstanceStateName::ShuttingDown | InstanceStateName::Stopping => {
-                        cell.fg(Color::DarkYellow)
-                    }
-                    InstanceStateName::Stopped | InstanceStateName::Terminated => {
-                        cell.fg(Color::Red)
-                    }
-                    _ => cell,
-                }
-            },
+        .set_header(
+            ["Stage", "Tasks", "Failed", "Total Time", "Slowest Task"].map(|header| {
+                Cell::new(header)
+                    .set_alignment(CellAlignment::Center)
+                    .add_attribute(Attribute::Bold)
+            }),
         );
-        let ipv4 = instance
-            .public_ipv4_address
-            .as_ref()
-            .map_or("n/a".into(), ToString::to_string);
+    for stage in &metrics.slowest_stages {
         table.add_row(vec![
-            Cell::new(instance.regular_name.to_string()).fg(Color::Cyan),
-            Cell::new(instance.instance_id.as_ref()),
-            Cell::new(instance.node_type.as_str()),
-            status,
-            Cell::new(ipv4),
+            Cell::new(&stage.name).fg(Color::Cyan),
+            Cell::new(stage.tasks),
+            Cell::new(stage.failed_tasks).fg(if stage.failed_tasks > 0 {
+                Color::Red
+            } else {
+                Color::Reset
+            }),
+            Cell::new(format_duration(Duration::from_millis(stage.total_ms))),
+            Cell::new(format_duration(Duration::from_millis(stage.max_ms))),
         ]);
     }
-    Ok(table)
-}
-
-async fn assert_is_logged_in_with_aws() -> anyhow::Result<()> {
-    let sdk_config = aws_config::defaults(aws_config::BehaviorVersion::latest())
-        .region(aws_config::meta::region::RegionProviderChain::default_provider())
-        .load()
-        .await;
-    let client = aws_sdk_sts::Client::new(&sdk_config);
-    if client.get_caller_identity().send().await.is_ok() {
-        Ok(())
-    } else {
-        anyhow::bail!("You are not logged in with the AWS cli tool; please authenticate with it first before re-running")
-    }
+    let peak_memory = metrics.peak_memory_bytes.map_or_else(
+        || "n/a (add `[setup.metrics]` to the configuration to record it)".to_string(),
+        job_metrics::format_bytes,
+    );
+    format!(
+        "Job {}: {} tasks ({} failed)\n{table}\nSpilled {} (and restored {}) since the cluster started\nPeak memory: {peak_memory}",
+        metrics.job_id,
+        metrics.tasks,
+        metrics.failed_tasks,
+        job_metrics::format_bytes(metrics.spilled_bytes),
+        job_metrics::format_bytes(metrics.restored_bytes),
+    )
 }
 
-async fn establish_kubernetes_port_forward(namespace: &str) -> anyhow::Result<Child> {
-    let output = Command::new("kubectl")
-        .arg("get")
-        .arg("svc")
-        .arg("-n")
-        .arg(namespace)
-        .arg("-l")
-        .arg("ray.io/node-type=head")
-        .arg("--no-headers")
-        .arg("-o")
-        .arg("custom-columns=:metadata.name")
-        .kill_on_drop(true)
-        .output()
-        .await?;
-    if !output.status.success() {
-        anyhow::bail!(
-            "Failed to get Ray head node services with kubectl in namespace {}",
-            namespace
+fn format_history_table(
+    history: &[(&state::Submission, Option<&state::Outcome>)],
+    now: Duration,
+) -> Table {
+    let mut table = Table::default();
+    table
+        .load_preset(presets::UTF8_FULL)
+        .apply_modifier(modifiers::UTF8_ROUND_CORNERS)
+        .apply_modifier(modifiers::UTF8_SOLID_INNER_BORDERS)
+        .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+        .set_header(
+            [
+                "Job Name",
+                "Submission ID",
+                "Submitted",
+                "Status",
+                "Duration",
+                "Detail",
+            ]
+            .map(|header| {
+                Cell::new(header)
+                    .set_alignment(CellAlignment::Center)
+                    .add_attribute(Attribute::Bold)
+            }),
         );
-    }
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    if stdout.trim().is_empty() {
-        anyhow::bail!("Ray head node service not found in namespace {}", namespace);
+    let not_available = || Cell::new("n/a").add_attribute(Attribute::Dim);
+
+    for (submission, outcome) in history {
+        let submitted = now.saturating_sub(Duration::from_secs(submission.submitted_at));
+        let (status, duration, detail) = match outcome {
+            Some(outcome) => {
+                let status = Cell::new(&outcome.status);
+                let status = match &*outcome.status {
+                    "SUCCEEDED" => status,
+                    "STOPPED" => status.fg(Color::DarkYellow),
+                    _ => status.fg(Color::Red),
+                };
+                let duration = outcome.duration.map_or_else(not_available, |duration| {
+                    Cell::new(format_duration(Duration::from_secs(duration)))
+                });
+                let detail = outcome
+                    .message
+                    .as_deref()
+                    .map_or_else(not_available, Cell::new);
+                (status, duration, detail)
+            }
+            // The job may still be running, or it may have finished without
+            // being followed to completion.
+            None => (
+                Cell::new("UNKNOWN").add_attribute(Attribute::Dim),
+                not_available(),
+                not_available(),
+            ),
+        };
+        table.add_row(vec![
+            Cell::new(&submission.job_name).fg(Color::Cyan),
+            Cell::new(&submission.submission_id),
+            Cell::new(format!("{} ago", format_duration(submitted))),
+            status,
+            duration,
+            detail,
+        ]);
     }
 
-    let head_node_service_name = stdout
-        .lines()
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("Failed to get the head node service name"))?;
-    println!(
-        "Found Ray head node service: {} in namespace {}",
-        head_node_service_name, namespace
-    );
-
-    // Start port-forward with stderr piped so we can monitor the process
-    let mut port_forward = Command::new("kubectl")
-        .arg("port-forward")
-        .arg("-n")
-        .arg(namespace)
-        .arg(format!("svc/{}", head_node_service_name))
-        .arg("8265:8265")
-        .stderr(Stdio::piped())
-        .stdout(Stdio::piped()) // Capture stdout too
-        .kill_on_drop(true)
-        .spawn()?;
-
-    // Give the port-forward a moment to start and check for immediate failures
-    tokio::time::sleep(Duration::from_secs(2)).await;
-
-    // Check if process is still running
-    match port_forward.try_wait()? {
-        Some(status) => {
-            anyhow::bail!(
-                "Port-forward process exited immediately with status: {}",
-                status
-            );
-        }
-        None => {
-            println!("Port-forwarding started successfully");
-            Ok(port_forward)
-        }
-    }
+    table
 }
 
-async fn submit(
-    working_dir: impl AsRef<Path>,
-    command_segments: impl AsRef<[&str]>,
-) -> anyhow::Result<()> {
-    let exit_status = Command::new("ray")
-        .env("PYTHONUNBUFFERED", "1")
-        .args(["job", "submit", "--address", "http://localhost:8265"])
-        .arg("--working-dir")
-        .arg(working_dir.as_ref())
-        .arg("--")
-        .args(command_segments.as_ref())
-        .spawn()?
-        .wait()
-        .await?;
+fn format_jobs_table(
+    ray_jobs: &[RayJob],
+    daft_jobs: &HashMap<StrRef, DaftJob>,
+    now: Duration,
+) -> Table {
+    let mut table = Table::default();
+    table
+        .load_preset(presets::UTF8_FULL)
+        .apply_modifier(modifiers::UTF8_ROUND_CORNERS)
+        .apply_modifier(modifiers::UTF8_SOLID_INNER_BORDERS)
+        .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+        .set_header(
+            [
+                "Job Name",
+                "Submission ID",
+                "Status",
+                "Duration",
+                "Entrypoint",
+            ]
+            .map(|header| {
+                Cell::new(header)
+                    .set_alignment(CellAlignment::Center)
+                    .add_attribute(Attribute::Bold)
+            }),
+        );
 
-    if exit_status.success() {
-        Ok(())
-    } else {
-        Err(anyhow::anyhow!("Failed to submit job to the ray cluster"))
+    let not_available = || Cell::new("n/a").add_attribute(Attribute::Dim);
+
+    for ray_job in ray_jobs {
+        let job_name = ray_job
+            .daft_job_name()
+            .map_or_else(not_available, |name| Cell::new(name).fg(Color::Cyan));
+        let id = ray_job.id().map_or_else(not_available, Cell::new);
+        let status = Cell::new(ray_job.status.as_str());
+        let status = match ray_job.status {
+            RayJobStatus::Running => status.fg(Color::Green),
+            RayJobStatus::Pending => status.fg(Color::Yellow),
+            RayJobStatus::Stopped => status.fg(Color::DarkYellow),
+            RayJobStatus::Failed => status.fg(Color::Red),
+            RayJobStatus::Succeeded => status,
+        };
+        let duration = ray_job
+            .duration(now)
+            .map_or_else(not_available, |duration| {
+                Cell::new(format_duration(duration))
+            });
+        table.add_row(vec![
+            job_name,
+            id,
+            status,
+            duration,
+            Cell::new(ray_job.entrypoint.as_ref()),
+        ]);
     }
-}
-
-async fn submit_k8s(
-    working_dir: impl AsRef<Path>,
-    command_segments: impl AsRef<[&str]>,
-    namespace: &str,
-) -> anyhow::Result<()> {
-    // Start port forwarding - it will be automatically killed when _port_forward is
-    // dropped
-    let _port_forward = establish_kubernetes_port_forward(namespace).await?;
-
-    // Give the port-forward a moment to fully establish
-    tokio::time::sleep(Duration::from_secs(1)).await;
 
-    submit(working_dir, command_segments).await?;
+    // Jobs which are defined in the config file but have never been submitted
+    // to this cluster are still listed, so that the full set of runnable jobs is
+    // visible in one place.
+    let mut unsubmitted_jobs = daft_jobs
+        .iter()
+        .filter(|(name, _)| {
+            !ray_jobs
+                .iter()
+                .any(|ray_job| ray_job.daft_job_name() == Some(name.as_ref()))
+        })
+        .collect::<Vec<_>>();
+    unsubmitted_jobs.sort_by_key(|(name, _)| *name);
+    for (name, daft_job) in unsubmitted_jobs {
+        table.add_row(vec![
+            Cell::new(name.as_ref()).fg(Color::Cyan),
+            not_available(),
+            Cell::new("not submitted").add_attribute(Attribute::Dim),
+            not_available(),
+            Cell::new(daft_job.command.as_ref()),
+        ]);
+    }
 
-    Ok(())
+    table
 }
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    DaftLauncher::parse().run().await
+async fn main() -> ExitCode {
+    // Answers the shell's requests for completions (and exits), if this is one.
+    clap_complete::CompleteEnv::with_factory(DaftLauncher::command)
+        .var(completion::COMPLETE_VAR)
+        .complete();
+    match DaftLauncher::parse().run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            logging::debug(format!("Failed with {error:?}"));
+            eprintln!("Error: {error:?}");
+            ExitCode::from(exit_code::of(&error))
+        }
+    }
+}
+
+/// Generates the commands which export the given environment variables in the
+/// shells of each node, including those which set up and start Ray.
+fn generate_env_commands(env: &BTreeMap<StrRef, StrRef>) -> anyhow::Result<Vec<StrRef>> {
+    let name = Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").expect("static regex is valid");
+    env.iter()
+        .map(|(key, value)| {
+            if !name.is_match(key) {
+                anyhow::bail!("The environment variable name {key:?} is not valid");
+            };
+            let export = format!("export {key}={}", shell_quote(value));
+            Ok(format!("echo {} >> ~/.bashrc", shell_quote(&export)).into())
+        })
+        .collect()
 }
 
 fn generate_setup_commands(
     python_version: Versioning,
     ray_version: Versioning,
+    daft_version: Option<Versioning>,
     dependencies: &[StrRef],
     run: &[StrRef],
 ) -> Vec<StrRef> {
+    let daft = match daft_version {
+        Some(daft_version) => format!(r#""getdaft=={daft_version}""#),
+        None => "getdaft".to_string(),
+    };
     let mut commands = vec![
         "curl -LsSf https://astral.sh/uv/install.sh | sh".into(),
         format!("uv python install {python_version}").into(),
@@ -923,7 +5765,7 @@ fn generate_setup_commands(
         "echo 'source $HOME/.venv/bin/activate' >> ~/.bashrc".into(),
         "source ~/.bashrc".into(),
         format!(
-            r#"uv pip install boto3 pip py-spy deltalake getdaft "ray[default]=={ray_version}""#
+            r#"uv pip install boto3 pip py-spy deltalake {daft} "ray[default]=={ray_version}""#
         )
         .into(),
     ];
@@ -931,7 +5773,7 @@ fn generate_setup_commands(
     if !dependencies.is_empty() {
         let deps = dependencies
             .iter()
-            .map(|dep| format!(r#""{dep}""#))
+            .map(|dep| shell_quote(dep))
             .collect::<Vec<_>>()
             .join(" ");
         let deps = format!("uv pip install {deps}").into();
@@ -974,159 +5816,1022 @@ async fn get_ray_version_from_env() -> anyhow::Result<Versioning> {
     Ok(python_version)
 }
 
-impl DaftLauncher {
-    async fn run(&self) -> anyhow::Result<()> {
-        match &self.sub_command {
-            SubCommand::Config(config_cmd) => config_cmd.run().await,
-            SubCommand::Job(job_cmd) => job_cmd.run().await,
-            SubCommand::Provisioned(provisioned_cmd) => provisioned_cmd.run().await,
-            SubCommand::Byoc(byoc_cmd) => byoc_cmd.run().await,
+impl DaftLauncher {
+    async fn run(&self) -> anyhow::Result<()> {
+        logging::init(
+            logging::verbosity(self.verbose, self.quiet),
+            self.log_file.as_deref(),
+        )?;
+        wizard::set_interactive(!self.non_interactive && std::io::stdin().is_terminal());
+        if let Some(region) = &self.region {
+            let _ = REGION.set(region.clone());
+        };
+        let profile = self.profile.as_deref();
+        let output = self.output;
+        let sub_command = match &self.sub_command {
+            SubCommand::Up(Up { config_path, .. }) | SubCommand::Down(Down { config_path, .. }) => {
+                if config_path.config == Path::new(config::STDIN_PATH) {
+                    anyhow::bail!("The provider of a configuration on the standard input cannot be looked up ahead of running the command; use `daft provisioned` or `daft byoc` instead");
+                };
+                // The configuration is located once, here, so that the
+                // subcommand does not look up the cluster (or ask for it) again;
+                // only its provider is needed, so its secrets are left for the
+                // subcommand to resolve.
+                let config = locate_config(&config_path.config).await?;
+                let daft_config = read_daft_config_for_display(&config, profile).await?;
+                let mut sub_command = self.sub_command.clone();
+                if let SubCommand::Up(Up { config_path, .. })
+                | SubCommand::Down(Down { config_path, .. }) = &mut sub_command
+                {
+                    config_path.config = config;
+                };
+                resolve_shorthand(&sub_command, &daft_config.setup.provider_config)?
+            }
+            SubCommand::Submit(submit) => SubCommand::Job(JobCommand::Submit(submit.clone())),
+            sub_command => sub_command.clone(),
+        };
+        match &sub_command {
+            SubCommand::Up(..) | SubCommand::Down(..) | SubCommand::Submit(..) => {
+                unreachable!("shorthands are resolved above")
+            }
+            SubCommand::Config(config_cmd) => config_cmd.run(profile, output).await,
+            SubCommand::Job(job_cmd) => job_cmd.run(profile, output).await,
+            SubCommand::Provisioned(provisioned_cmd) => provisioned_cmd.run(profile, output).await,
+            SubCommand::Byoc(byoc_cmd) => byoc_cmd.run(profile, output).await,
+            SubCommand::Connect(connect_cmd) => connect_cmd.run(profile).await,
+            SubCommand::Tunnel(tunnel_cmd) => tunnel_cmd.run(output).await,
+            SubCommand::List(ListClusters {
+                contexts,
+                config_path,
+            }) => {
+                let clusters = list_clusters(&config_path.config, contexts, profile).await?;
+                match output {
+                    OutputFormat::Text => println!("{}", format_clusters_table(&clusters)),
+                    OutputFormat::Json => {
+                        for cluster in &clusters {
+                            println!("{}", serde_json::to_string(cluster)?);
+                        }
+                    }
+                    OutputFormat::Csv => print!("{}", format_csv(&clusters)?),
+                }
+                Ok(())
+            }
+            SubCommand::History(ActionHistory { cluster, limit }) => {
+                let actions = state::read_actions().await?;
+                let actions = state::recent_actions(&actions, cluster.as_deref(), *limit);
+                match output {
+                    OutputFormat::Text if actions.is_empty() => {
+                        println!("No actions were recorded on this machine")
+                    }
+                    OutputFormat::Text => println!("{}", format_actions_table(&actions)),
+                    OutputFormat::Json => {
+                        for action in &actions {
+                            println!("{}", serde_json::to_string(action)?);
+                        }
+                    }
+                    OutputFormat::Csv => print!("{}", format_csv(&actions)?),
+                }
+                Ok(())
+            }
+            SubCommand::Cost(Cost { since }) => {
+                let costs = cost::daily_costs(*since, SystemTime::now())
+                    .await
+                    .classify(Failure::CloudApi)?;
+                match output {
+                    OutputFormat::Text => {
+                        if costs.is_empty() {
+                            println!("No spend was found for any cluster in the last {since} days; note that the `ray-cluster-name` tag must be activated as a cost allocation tag (in the AWS Billing console) for Cost Explorer to report by it");
+                        } else {
+                            println!("{}", format_costs_table(&costs));
+                            for (cluster, total) in cost::totals(&costs) {
+                                println!(
+                                    "{cluster}: {} in the last {since} days",
+                                    cost::format_usd(total)
+                                );
+                            }
+                        }
+                    }
+                    OutputFormat::Json => {
+                        for cost in &costs {
+                            println!("{}", serde_json::to_string(cost)?);
+                        }
+                    }
+                    OutputFormat::Csv => print!("{}", format_csv(&costs)?),
+                }
+                Ok(())
+            }
+            SubCommand::Gc(Gc {
+                older_than,
+                dry_run,
+                yes,
+                lock_args,
+            }) => {
+                let region = resolve_region(None).await?;
+                assert_is_logged_in_with_aws().await?;
+                let instances = get_ray_clusters_from_aws(region.clone(), &[]).await?;
+                let instances = instances.iter().collect::<Vec<_>>();
+                let now = SystemTime::now();
+                let stale = stale_clusters(&instances, *older_than, now);
+                if stale.is_empty() {
+                    println!("No clusters in {region} are stale");
+                    return Ok(());
+                };
+                for cluster in &stale {
+                    println!(
+                        "{} (up {}, {}):\n{}",
+                        cluster.name,
+                        format_duration(cluster.age),
+                        cluster.reason,
+                        format_instances_to_kill(&cluster.instances, now)
+                    );
+                }
+                if *dry_run {
+                    return Ok(());
+                };
+                if !*yes {
+                    wizard::ensure_interactive(
+                        "Tearing down the stale clusters needs confirmation",
+                        "pass `--yes` to tear them down without confirming",
+                    )?;
+                    let confirmed = wizard::Wizard::new()?
+                        .confirm(&format!("Terminate these {} clusters?", stale.len()), false)?;
+                    if !confirmed {
+                        println!("Left the clusters running");
+                        return Ok(());
+                    };
+                };
+                let mut failures = 0;
+                for cluster in &stale {
+                    match gc_cluster(&region, cluster, lock_args.force).await {
+                        Ok(()) => println!("Terminated the cluster {}", cluster.name),
+                        Err(error) => {
+                            failures += 1;
+                            logging::warn(format!(
+                                "Failed to terminate the cluster {}: {error:?}",
+                                cluster.name
+                            ));
+                        }
+                    };
+                }
+                if failures > 0 {
+                    anyhow::bail!("Failed to terminate {failures} of the stale clusters");
+                };
+                Ok(())
+            }
+            SubCommand::Doctor(ConfigPath { config }) => {
+                let output = output.text_or_json("doctor")?;
+                let diagnoses = doctor::diagnose(config, profile).await;
+                for diagnosis in &diagnoses {
+                    match output {
+                        TextOrJson::Text => println!("{diagnosis}"),
+                        TextOrJson::Json => println!("{}", serde_json::to_string(diagnosis)?),
+                    }
+                }
+                let failures = diagnoses
+                    .iter()
+                    .filter(|diagnosis| diagnosis.status == doctor::Status::Fail)
+                    .count();
+                if failures > 0 {
+                    anyhow::bail!("{failures} of the checks failed");
+                };
+                Ok(())
+            }
+            SubCommand::Completion(Completion { shell }) => {
+                completion::write_registration(*shell, &mut std::io::stdout())
+            }
+            SubCommand::Manpages(Manpages { dir }) => {
+                let paths = manpages::write_all(&DaftLauncher::command(), dir)?;
+                println!("Wrote {} man pages to {dir:?}", paths.len());
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Resolves the shorthands `up` and `down` to the subcommands which they stand
+/// for with the given provider; other subcommands are returned as they are.
+fn resolve_shorthand(
+    sub_command: &SubCommand,
+    provider_config: &ProviderConfig,
+) -> anyhow::Result<SubCommand> {
+    Ok(match (sub_command, provider_config) {
+        (SubCommand::Up(up), ProviderConfig::Provisioned(..)) => {
+            SubCommand::Provisioned(ProvisionedCommand::Up(up.clone()))
         }
-    }
+        (SubCommand::Up(Up { config_path, .. }), ProviderConfig::Byoc(..)) => {
+            SubCommand::Byoc(ByocCommand::Info(config_path.clone()))
+        }
+        (SubCommand::Down(down), ProviderConfig::Provisioned(..)) => {
+            SubCommand::Provisioned(ProvisionedCommand::Down(down.clone()))
+        }
+        (SubCommand::Down(..), ProviderConfig::Byoc(..)) => {
+            anyhow::bail!("BYOC clusters are not managed by the launcher, so there is nothing for `down` to spin down")
+        }
+        (sub_command, _) => sub_command.clone(),
+    })
 }
 
 impl ConfigCommand {
-    async fn run(&self) -> anyhow::Result<()> {
+    async fn run(&self, profile: Option<&str>, output: OutputFormat) -> anyhow::Result<()> {
         match self {
-            ConfigCommand::Init(Init { path, provider }) => {
+            ConfigCommand::Init(Init {
+                path,
+                provider,
+                template,
+                interactive,
+            }) => {
+                let region = region_override();
                 #[cfg(not(test))]
                 if path.exists() {
                     bail!("The path {path:?} already exists; the path given must point to a new location on your filesystem");
                 }
-                let contents = match provider {
-                    DaftProvider::Byoc => asset!("template-byoc.toml"),
-                    DaftProvider::Provisioned => asset!("template-provisioned.toml"),
+                if *provider == DaftProvider::Byoc
+                    && (*template != InitTemplate::Default || region.is_some())
+                {
+                    anyhow::bail!("The `--template` and `--region` options are only available for the provisioned provider");
+                };
+                let mut contents = render_template(provider).await?;
+                // The AMI in the template suits the template's region.
+                let template_region = match provider {
+                    DaftProvider::Provisioned => {
+                        Some(edit::get(&contents, "setup.provisioned.region")?)
+                    }
+                    DaftProvider::Byoc => None,
+                };
+                for (key, value) in template.overrides() {
+                    contents = edit::set(&contents, key, value)?;
                 }
-                .replace("<requires>", concat!("=", env!("CARGO_PKG_VERSION")))
-                .replace(
-                    "<python-version>",
-                    get_python_version_from_env().await?.to_string().as_str(),
-                )
-                .replace(
-                    "<ray-version>",
-                    get_ray_version_from_env().await?.to_string().as_str(),
-                );
+                if let Some(region) = region {
+                    contents = edit::set(&contents, "setup.provisioned.region", region)?;
+                };
+                if *interactive {
+                    wizard::ensure_interactive(
+                        "`--interactive` asks questions about the cluster",
+                        "leave it out, and edit the generated file instead",
+                    )?;
+                    let mut wizard = wizard::Wizard::new()?;
+                    contents = match provider {
+                        DaftProvider::Byoc => wizard::byoc(&mut wizard, contents).await?,
+                        DaftProvider::Provisioned => wizard::provisioned(&mut wizard, contents)?,
+                    };
+                };
+                if let Some(template_region) = template_region {
+                    let region = edit::get(&contents, "setup.provisioned.region")?;
+                    if *template != InitTemplate::Default || region != template_region {
+                        let image_id = lookup_ami(*template, &region).await?;
+                        contents = edit::set(&contents, "setup.provisioned.image-id", &image_id)?;
+                    };
+                };
                 fs::write(path, contents).await?;
             }
-            ConfigCommand::Check(ConfigPath { config }) => {
-                let _ = read_daft_config(config).await?;
+            ConfigCommand::Check(Check {
+                strict,
+                fix_version: should_fix_version,
+                config_path,
+            }) => {
+                if *should_fix_version {
+                    fix_version(&config_path.config).await?;
+                };
+                let output = output.text_or_json("config check")?;
+                let result = check_config(&config_path.config, profile, *strict).await;
+                match output {
+                    TextOrJson::Text => {
+                        let problems = result?;
+                        if !problems.is_empty() {
+                            return Err(anyhow::anyhow!(
+                                "The configuration is not valid for the AWS account:\n- {}",
+                                problems.join("\n- ")
+                            ))
+                            .classify(Failure::Config);
+                        };
+                    }
+                    TextOrJson::Json => {
+                        let problems = result.unwrap_or_else(|error| vec![error.to_string()]);
+                        let report = CheckReport {
+                            valid: problems.is_empty(),
+                            problems,
+                        };
+                        println!("{}", serde_json::to_string(&report)?);
+                        if !report.valid {
+                            return Err(anyhow::anyhow!("The configuration is not valid"))
+                                .classify(Failure::Config);
+                        };
+                    }
+                }
             }
             ConfigCommand::Export(ConfigPath { config }) => {
-                let daft_config = read_daft_config(config).await?;
+                let daft_config = read_daft_config_for_display(config, profile).await?;
                 let ray_config = convert(&daft_config, None)?;
                 let ray_config_str = serde_yaml::to_string(&ray_config)?;
                 println!("{ray_config_str}");
             }
+            ConfigCommand::Lint(ConfigPath { config }) => {
+                let daft_config = read_daft_config(config, profile).await?;
+                let warnings = lint::lint(&daft_config);
+                if warnings.is_empty() {
+                    println!("No problems found in {config:?}");
+                };
+                for warning in warnings {
+                    println!("warning: {warning}");
+                }
+            }
+            ConfigCommand::Diff(Diff { old, new }) => {
+                let old_config =
+                    toml::Value::try_from(read_daft_config_for_display(old, profile).await?)?;
+                let new_config =
+                    toml::Value::try_from(read_daft_config_for_display(new, profile).await?)?;
+                let changes = diff::diff(&old_config, &new_config);
+                if changes.is_empty() {
+                    println!("The effective settings of {old:?} and {new:?} are the same");
+                };
+                for change in changes {
+                    println!("{change}");
+                }
+            }
+            ConfigCommand::Import(Import { ray_config, to }) => {
+                if to.exists() {
+                    anyhow::bail!("The path {to:?} already exists; the path given must point to a new location on your filesystem");
+                };
+                let ray_yaml = fs::read_to_string(ray_config).await?;
+                let template = render_template(&DaftProvider::Provisioned).await?;
+                let (contents, warnings) = ray_import::import(&ray_yaml, &template)?;
+                for warning in warnings {
+                    logging::warn(warning);
+                }
+                fs::write(to, contents).await?;
+                println!("Imported {ray_config:?} into {to:?}; review it before running `daft provisioned up`");
+            }
+            ConfigCommand::Migrate(Migrate {
+                dry_run,
+                config_path,
+            }) => {
+                if !*dry_run {
+                    ensure_local_config(&config_path.config)?;
+                };
+                let contents = config::read_to_string(&config_path.config).await?;
+                let (migrated, notes) = migrate::migrate(
+                    &contents,
+                    &get_python_version_from_env().await?.to_string(),
+                    &get_ray_version_from_env().await?.to_string(),
+                )?;
+                for note in notes {
+                    logging::info(note);
+                }
+                if *dry_run {
+                    print!("{migrated}");
+                } else {
+                    fs::write(&config_path.config, migrated).await?;
+                    println!("Migrated {:?} to the current schema", config_path.config);
+                }
+            }
+            ConfigCommand::Show(Show {
+                format,
+                config_path,
+            }) => {
+                let daft_config =
+                    read_daft_config_for_display(&config_path.config, profile).await?;
+                match format {
+                    ShowFormat::Toml => print!("{}", toml::to_string(&daft_config)?),
+                    ShowFormat::Json => {
+                        println!("{}", serde_json::to_string_pretty(&daft_config)?)
+                    }
+                }
+            }
+            ConfigCommand::Get(Get { key, config_path }) => {
+                let contents = config::read_to_string(&config_path.config).await?;
+                println!("{}", edit::get(&contents, key)?);
+            }
+            ConfigCommand::Set(Set {
+                key,
+                value,
+                config_path,
+            }) => {
+                ensure_local_config(&config_path.config)?;
+                let contents = fs::read_to_string(&config_path.config).await?;
+                let contents = edit::set(&contents, key, value)?;
+                fs::write(&config_path.config, contents).await?;
+            }
         }
         Ok(())
     }
 }
 
 impl JobCommand {
-    async fn run(&self) -> anyhow::Result<()> {
+    async fn run(&self, profile: Option<&str>, output: OutputFormat) -> anyhow::Result<()> {
         match self {
             JobCommand::Submit(Submit {
                 config_path,
                 job_name,
+                variables,
+                dry_run,
+                watch,
+                args,
             }) => {
-                let daft_config = read_daft_config(&config_path.config).await?;
+                let output = output.text_or_json("job submit")?;
+                if output == TextOrJson::Json && (*dry_run || *watch) {
+                    anyhow::bail!(
+                        "`--output json` cannot be combined with `--dry-run` or `--watch`"
+                    );
+                };
+                let daft_config = read_daft_config(&config_path.config, profile).await?;
                 let daft_job = daft_config.jobs.get(job_name).ok_or_else(|| {
                     anyhow::anyhow!("A job with the name {job_name} was not found")
                 })?;
 
-                let working_dir = daft_job.working_dir.as_ref();
-                let command_segments = daft_job.command.as_ref().split(' ').collect::<Vec<_>>();
-
-                match &daft_config.setup.provider_config {
-                    ProviderConfig::Provisioned(aws_config) => {
-                        assert_is_logged_in_with_aws().await?;
-
-                        let ray_config = convert(&daft_config, None)?;
-                        let (_temp_dir, ray_path) = create_temp_ray_file()?;
-                        write_ray_config(&ray_config, &ray_path).await?;
-
-                        let _child = ssh::ssh_portforward(ray_path, aws_config, None).await?;
-                        submit(working_dir, command_segments).await?;
+                let command = daft_job.command_segments(variables)?;
+                let mut command_segments = command.iter().map(String::as_str).collect::<Vec<_>>();
+                command_segments.extend(args.iter().map(AsRef::as_ref));
+                let working_dir = daft_job.prepare_working_dir(*dry_run).await?;
+                let submission = JobSubmission {
+                    job_name,
+                    working_dir: &working_dir.path,
+                    excludes: &daft_job.excludes,
+                    upload_to: daft_job.upload_to.as_deref(),
+                    command_segments: &command_segments,
+                    runtime_env: &daft_job.runtime_env(&daft_config.setup.env),
+                    resources: &daft_job.entrypoint_resources,
+                };
+
+                if *dry_run {
+                    print_dry_run(&daft_config, &daft_job.source, &submission).await?;
+                } else if *watch {
+                    let JobSource::WorkingDir(local_working_dir) = &daft_job.source else {
+                        anyhow::bail!("Only jobs with a local `working-dir` can be watched");
+                    };
+                    let mut watcher =
+                        watch::WorkingDirWatcher::new(local_working_dir, &daft_job.excludes)?;
+                    let port_forward = establish_ray_dashboard_port_forward(&daft_config).await?;
+                    let client = RayJobsClient::new(port_forward.address());
+                    submit_and_watch(&client, &daft_config, daft_job, &submission, &mut watcher)
+                        .await?;
+                } else {
+                    let port_forward = establish_ray_dashboard_port_forward(&daft_config).await?;
+                    let client = RayJobsClient::new(port_forward.address());
+                    match output {
+                        TextOrJson::Text => submit(&client, &daft_config, &submission).await?,
+                        TextOrJson::Json => {
+                            submit_with_events(&client, &daft_config, &submission).await?
+                        }
                     }
-                    ProviderConfig::Byoc(k8s_config) => {
-                        submit_k8s(working_dir, command_segments, k8s_config.namespace.as_ref())
-                            .await?;
+                }
+            }
+            JobCommand::SubmitAll(SubmitAll {
+                job_names,
+                config_path,
+            }) => {
+                let daft_config = read_daft_config(&config_path.config, profile).await?;
+                let mut jobs = if job_names.is_empty() {
+                    daft_config.jobs.iter().collect::<Vec<_>>()
+                } else {
+                    job_names
+                        .iter()
+                        .map(|job_name| {
+                            daft_config.jobs.get_key_value(job_name).ok_or_else(|| {
+                                anyhow::anyhow!("A job with the name {job_name} was not found")
+                            })
+                        })
+                        .collect::<anyhow::Result<_>>()?
+                };
+                jobs.sort_by_key(|(job_name, _)| *job_name);
+                jobs.dedup_by(|(a, _), (b, _)| a == b);
+                let mut prepared_jobs = Vec::with_capacity(jobs.len());
+                let mut failures = SubmissionFailures::new();
+                for (job_name, daft_job) in jobs {
+                    match daft_job.prepare_working_dir(false).await {
+                        Ok(working_dir) => prepared_jobs.push((job_name, daft_job, working_dir)),
+                        Err(error) => failures.push((job_name.clone(), error)),
                     }
                 }
+
+                let ray_jobs = if prepared_jobs.is_empty() {
+                    vec![]
+                } else {
+                    let port_forward = establish_ray_dashboard_port_forward(&daft_config).await?;
+                    let client = RayJobsClient::new(port_forward.address());
+                    let (ray_jobs, submission_failures) =
+                        submit_all(&client, &daft_config, &prepared_jobs).await?;
+                    failures.extend(submission_failures);
+                    ray_jobs
+                };
+                for ray_job in &ray_jobs {
+                    job_finished(&daft_config, ray_job).await?;
+                }
+
+                if !ray_jobs.is_empty() {
+                    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                    let table = format_jobs_table(&ray_jobs, &HashMap::default(), now);
+                    println!("{table}");
+                };
+                println!("{}", format_submit_all_summary(&ray_jobs, &failures));
+                if !failures.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "Failed to submit {} of the {} jobs",
+                        failures.len(),
+                        ray_jobs.len() + failures.len()
+                    ))
+                    .classify(Failure::Job);
+                };
+
+                let failed = ray_jobs
+                    .iter()
+                    .filter(|ray_job| ray_job.status != RayJobStatus::Succeeded)
+                    .filter_map(RayJob::daft_job_name)
+                    .collect::<Vec<_>>();
+                if !failed.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "The following jobs did not succeed: {}",
+                        failed.join(", ")
+                    ))
+                    .classify(Failure::Job);
+                };
+            }
+            JobCommand::Run(Run {
+                working_dir,
+                name,
+                config_path,
+                command,
+            }) => {
+                let daft_config = read_daft_config(&config_path.config, profile).await?;
+                let working_dir = expand_and_check_path(Arc::from(working_dir.as_path()))?;
+                let command_segments = command.iter().map(AsRef::as_ref).collect::<Vec<_>>();
+                working_dir::check_size(&working_dir, &[], working_dir::DEFAULT_SIZE_LIMIT_MIB)?;
+
+                let port_forward = establish_ray_dashboard_port_forward(&daft_config).await?;
+                let client = RayJobsClient::new(port_forward.address());
+                let submission = JobSubmission {
+                    job_name: name,
+                    working_dir: &working_dir,
+                    excludes: &[],
+                    upload_to: None,
+                    command_segments: &command_segments,
+                    runtime_env: &RayRuntimeEnv {
+                        env_vars: daft_config.setup.env.clone(),
+                        ..RayRuntimeEnv::default()
+                    },
+                    resources: &EntrypointResources::default(),
+                };
+                submit(&client, &daft_config, &submission).await?;
+            }
+            JobCommand::RunDag(SubmitAll {
+                job_names,
+                config_path,
+            }) => {
+                let daft_config = read_daft_config(&config_path.config, profile).await?;
+                for job_name in job_names {
+                    if !daft_config.jobs.contains_key(job_name) {
+                        anyhow::bail!("A job with the name {job_name} was not found");
+                    };
+                }
+                let mut order = topological_order(&daft_config.jobs)?;
+                if !job_names.is_empty() {
+                    let selected = with_dependencies(&daft_config.jobs, job_names);
+                    order.retain(|job_name| selected.contains(job_name));
+                };
+                let mut working_dirs = HashMap::with_capacity(order.len());
+                for job_name in &order {
+                    let working_dir = daft_config.jobs[job_name]
+                        .prepare_working_dir(false)
+                        .await?;
+                    working_dirs.insert(job_name.clone(), working_dir);
+                }
+
+                let port_forward = establish_ray_dashboard_port_forward(&daft_config).await?;
+                let client = RayJobsClient::new(port_forward.address());
+                let states = run_dag(&client, &daft_config, &order, &working_dirs).await?;
+
+                let unsuccessful = states
+                    .iter()
+                    .filter(|(_, state)| **state != DagJobState::Finished(RayJobStatus::Succeeded))
+                    .map(|(job_name, _)| job_name.to_string())
+                    .collect::<Vec<_>>();
+                if !unsuccessful.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "The following jobs did not succeed: {}",
+                        unsuccessful.join(", ")
+                    ))
+                    .classify(Failure::Job);
+                };
             }
-            JobCommand::Sql(Sql { sql, config_path }) => {
-                let daft_config = read_daft_config(&config_path.config).await?;
+            JobCommand::Sql(Sql {
+                sql,
+                dry_run,
+                interactive,
+                params,
+                preview,
+                config_path,
+            }) => {
+                let daft_config = read_daft_config(&config_path.config, profile).await?;
                 let (temp_sql_dir, sql_path) = create_temp_file("sql.py")?;
                 fs::write(sql_path, asset!("sql.py")).await?;
-
                 let working_dir = temp_sql_dir.path();
-                let command_segments = vec!["python", "sql.py", sql.as_ref()];
 
-                match &daft_config.setup.provider_config {
-                    ProviderConfig::Provisioned(aws_config) => {
-                        assert_is_logged_in_with_aws().await?;
+                if *interactive {
+                    let port_forward = establish_ray_dashboard_port_forward(&daft_config).await?;
+                    let client = RayJobsClient::new(port_forward.address());
+                    run_sql_repl(
+                        &client,
+                        &daft_config,
+                        working_dir,
+                        sql.as_deref(),
+                        params,
+                        preview,
+                    )
+                    .await?;
+                    return Ok(());
+                };
+
+                let sql = sql
+                    .as_deref()
+                    .expect("clap requires a query unless in interactive mode");
+                let sql = sql::bind_parameters(sql, params)?;
+                let command_segments = preview.command_segments(&daft_config.sql.setup, &sql);
+                let submission = JobSubmission {
+                    job_name: "sql",
+                    working_dir,
+                    excludes: &[],
+                    upload_to: None,
+                    command_segments: &command_segments
+                        .iter()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>(),
+                    runtime_env: &RayRuntimeEnv {
+                        env_vars: daft_config.setup.env.clone(),
+                        ..RayRuntimeEnv::default()
+                    },
+                    resources: &EntrypointResources::default(),
+                };
+
+                if *dry_run {
+                    print_dry_run(
+                        &daft_config,
+                        &JobSource::WorkingDir(Arc::from(working_dir)),
+                        &submission,
+                    )
+                    .await?;
+                } else {
+                    let port_forward = establish_ray_dashboard_port_forward(&daft_config).await?;
+                    let client = RayJobsClient::new(port_forward.address());
+                    submit(&client, &daft_config, &submission).await?;
+                }
+            }
+            JobCommand::List(ConfigPath { config }) => {
+                let daft_config = read_daft_config(config, profile).await?;
 
-                        let ray_config = convert(&daft_config, None)?;
-                        let (_temp_dir, ray_path) = create_temp_ray_file()?;
-                        write_ray_config(&ray_config, &ray_path).await?;
+                let port_forward = establish_ray_dashboard_port_forward(&daft_config).await?;
+                let ray_jobs = RayJobsClient::new(port_forward.address()).list().await?;
 
-                        let _child = ssh::ssh_portforward(ray_path, aws_config, None).await?;
-                        submit(working_dir, command_segments).await?;
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                let table = format_jobs_table(&ray_jobs, &daft_config.jobs, now);
+                println!("{table}");
+            }
+            JobCommand::Stop(Stop { job, config_path }) => {
+                let daft_config = read_daft_config(&config_path.config, profile).await?;
+
+                let port_forward = establish_ray_dashboard_port_forward(&daft_config).await?;
+                let client = RayJobsClient::new(port_forward.address());
+                let ray_jobs = client.list().await?;
+                let submissions = state::read_submissions().await?;
+                for id in
+                    resolve_jobs_to_stop(&ray_jobs, &submissions, &daft_config.setup.name, job)
+                {
+                    client.stop(id).await?;
+                }
+            }
+            JobCommand::Schedule(Schedule {
+                job_name,
+                cron,
+                remove,
+                config_path,
+            }) => {
+                not_available_on_windows!(
+                    "job schedule",
+                    "schedule `daft job submit` with the Task Scheduler instead"
+                );
+                let daft_config = read_daft_config(&config_path.config, profile).await?;
+                if !daft_config.jobs.contains_key(job_name) {
+                    anyhow::bail!("A job with the name {job_name} was not found");
+                };
+
+                // The scheduled invocation does not run from the current working
+                // directory, so the config must be referred to by its absolute path.
+                let config = if config::remote_url(&config_path.config).is_some() {
+                    config_path.config.clone()
+                } else {
+                    ensure_local_config(&config_path.config)?;
+                    fs::canonicalize(&config_path.config).await?
+                };
+                if *remove {
+                    schedule::unschedule(&config, job_name).await?;
+                } else {
+                    let cron = cron
+                        .as_ref()
+                        .expect("clap requires --cron without --remove");
+                    schedule::schedule(
+                        cron,
+                        &config,
+                        job_name,
+                        profile,
+                        region_override().map(|region| &**region),
+                    )
+                    .await?;
+                    println!("Scheduled job {job_name} to be submitted on {cron:?}");
+                }
+            }
+            JobCommand::Attach(JobReference { job, config_path }) => {
+                let daft_config = read_daft_config(&config_path.config, profile).await?;
+                let submissions = state::read_submissions().await?;
+                let id = resolve_job_reference(&submissions, &daft_config.setup.name, job);
+
+                let port_forward = establish_ray_dashboard_port_forward(&daft_config).await?;
+                let client = RayJobsClient::new(port_forward.address());
+                println!("Attaching to job {id}");
+                follow(&client, &daft_config, id).await?;
+            }
+            JobCommand::Status(Status { job, config_path }) => {
+                let output = output.text_or_json("job status")?;
+                let daft_config = read_daft_config(&config_path.config, profile).await?;
+                let submissions = state::read_submissions().await?;
+                let id = resolve_job_reference(&submissions, &daft_config.setup.name, job);
+
+                let port_forward = establish_ray_dashboard_port_forward(&daft_config).await?;
+                let ray_job = RayJobsClient::new(port_forward.address()).get(id).await?;
+
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                match output {
+                    TextOrJson::Text => {
+                        let table = format_jobs_table(&[ray_job], &HashMap::default(), now);
+                        println!("{table}");
                     }
-                    ProviderConfig::Byoc(k8s_config) => {
-                        submit_k8s(working_dir, command_segments, k8s_config.namespace.as_ref())
-                            .await?;
+                    TextOrJson::Json => {
+                        events::emit(&events::JobEvent::from_ray_job(&ray_job, now))?;
+                    }
+                }
+            }
+            JobCommand::Metrics(JobReference { job, config_path }) => {
+                let output = output.text_or_json("job metrics")?;
+                let daft_config = read_daft_config(&config_path.config, profile).await?;
+                let submissions = state::read_submissions().await?;
+                let id = resolve_job_reference(&submissions, &daft_config.setup.name, job);
+
+                let (port_forward, prometheus_address) =
+                    establish_metrics_port_forward(&daft_config).await?;
+                let metrics = collect_job_metrics(
+                    &RayJobsClient::new(port_forward.address()),
+                    id,
+                    prometheus_address.as_deref(),
+                )
+                .await?;
+                match output {
+                    TextOrJson::Text => println!("{}", format_job_metrics(&metrics)),
+                    TextOrJson::Json => println!("{}", serde_json::to_string(&metrics)?),
+                }
+            }
+            JobCommand::Fetch(Fetch {
+                job_name,
+                config_path,
+                to,
+            }) => {
+                let daft_config = read_daft_config(&config_path.config, profile).await?;
+                let daft_job = daft_config.jobs.get(job_name).ok_or_else(|| {
+                    anyhow::anyhow!("A job with the name {job_name} was not found")
+                })?;
+                if daft_job.outputs.is_empty() {
+                    anyhow::bail!("The job {job_name} does not define any `outputs`");
+                };
+                let submissions = state::read_submissions().await?;
+                let Some(submission) =
+                    state::latest_submission(&submissions, &daft_config.setup.name, job_name)
+                else {
+                    anyhow::bail!("The job {job_name} has not been submitted to this cluster");
+                };
+
+                let ray_job = {
+                    let port_forward = establish_ray_dashboard_port_forward(&daft_config).await?;
+                    RayJobsClient::new(port_forward.address())
+                        .get(&submission.submission_id)
+                        .await?
+                };
+                if ray_job.status != RayJobStatus::Succeeded {
+                    anyhow::bail!(
+                        "The outputs of job {} cannot be fetched since it has status {}",
+                        ray_job.describe(),
+                        ray_job.status.as_str()
+                    );
+                };
+
+                let fetched = match (&daft_job.outputs_uri, &daft_config.setup.provider_config) {
+                    (Some(outputs_uri), _) => {
+                        outputs::fetch_from_s3(outputs_uri, &daft_job.outputs, to).await?
+                    }
+                    (None, ProviderConfig::Provisioned(aws_config))
+                        if aws_config.connection == Connection::Ssm =>
+                    {
+                        anyhow::bail!(
+                            "Outputs can only be fetched from an `outputs-uri` for clusters with `connection = \"ssm\"`"
+                        )
                     }
+                    (None, ProviderConfig::Provisioned(aws_config)) => {
+                        let working_dir_uri = ray_job
+                            .runtime_env
+                            .as_ref()
+                            .and_then(|runtime_env| runtime_env.working_dir.as_deref())
+                            .ok_or_else(|| {
+                                anyhow::anyhow!("The job {job_name} has no working directory")
+                            })?;
+                        let remote_dir = outputs::head_node_working_dir(working_dir_uri)?;
+
+                        assert_is_logged_in_with_aws().await?;
+                        ssh::fetch_from_head_node(
+                            &daft_config.setup.name,
+                            aws_config,
+                            &remote_dir,
+                            &daft_job.outputs,
+                            to,
+                        )
+                        .await?
+                    }
+                    (None, ProviderConfig::Byoc(..)) => anyhow::bail!(
+                        "Outputs can only be fetched from an `outputs-uri` for byoc configurations"
+                    ),
+                };
+                println!("Fetched {} files into {}", fetched.len(), to.display());
+                for path in fetched {
+                    println!("  {}", path.display());
                 }
             }
-            JobCommand::Status(..) => todo!(),
-            JobCommand::Logs(..) => todo!(),
+            JobCommand::History(History {
+                job_name,
+                config_path,
+            }) => {
+                let daft_config = read_daft_config(&config_path.config, profile).await?;
+                let submissions = state::read_submissions().await?;
+                let outcomes = state::read_outcomes().await?;
+                let history = state::history(
+                    &submissions,
+                    &outcomes,
+                    &daft_config.setup.name,
+                    job_name.as_deref(),
+                );
+
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                println!("{}", format_history_table(&history, now));
+            }
+            JobCommand::Logs(JobReference { job, config_path }) => {
+                let daft_config = read_daft_config(&config_path.config, profile).await?;
+                let submissions = state::read_submissions().await?;
+                let id = resolve_job_reference(&submissions, &daft_config.setup.name, job);
+
+                let port_forward = establish_ray_dashboard_port_forward(&daft_config).await?;
+                let logs = RayJobsClient::new(port_forward.address()).logs(id).await?;
+                print!("{logs}");
+            }
         }
         Ok(())
     }
 }
 
 impl ProvisionedCommand {
-    async fn run(&self) -> anyhow::Result<()> {
+    async fn run(&self, profile: Option<&str>, output: OutputFormat) -> anyhow::Result<()> {
         match self {
-            ProvisionedCommand::Up(ConfigPath { config }) => {
-                let daft_config = read_daft_config(config).await?;
-                match daft_config.setup.provider_config {
-                    ProviderConfig::Provisioned(..) => {
+            ProvisionedCommand::Up(Up {
+                overrides,
+                show_output,
+                lock_args,
+                config_path,
+            }) => {
+                let mut daft_config = read_daft_config(&config_path.config, profile).await?;
+                if let ProviderConfig::Provisioned(aws_config) =
+                    &mut daft_config.setup.provider_config
+                {
+                    overrides.apply(aws_config);
+                };
+                match &daft_config.setup.provider_config {
+                    ProviderConfig::Provisioned(aws_config) => {
                         assert_is_logged_in_with_aws().await?;
 
-                        let ray_config = convert(&daft_config, None)?;
-                        let (_temp_dir, ray_path) = create_temp_ray_file()?;
-                        write_ray_config(&ray_config, &ray_path).await?;
-                        run_ray_up_or_down_command(SpinDirection::Up, ray_path).await?;
+                        let ray_config = serde_yaml::to_string(&convert(&daft_config, None)?)?;
+                        let lock = lock::acquire(
+                            aws_config.region(),
+                            &daft_config.setup.name,
+                            "up",
+                            lock_args.force,
+                        )
+                        .await?;
+                        // Cached before spinning up, so that even a cluster
+                        // which fails to come up can be spun down with it, but
+                        // only once locked, so as not to replace the one which
+                        // someone else is spinning it up with.
+                        let result = async {
+                            let ray_path = state::cache_ray_config(
+                                aws_config.region(),
+                                &daft_config.setup.name,
+                                &ray_config,
+                                &config_hash(&daft_config),
+                            )
+                            .await?;
+                            run_ray_up_or_down_command(
+                                SpinDirection::Up,
+                                &daft_config,
+                                ray_path,
+                                *show_output,
+                            )
+                            .await
+                        }
+                        .await;
+                        lock.release().await;
+                        result?;
+                        record_action(state::ActionKind::Up, &daft_config, None).await;
+                        if config::is_local(&config_path.config) {
+                            // The address is only recorded for information, so
+                            // failing to find it is not worth failing for.
+                            let head_ip =
+                                ssh::get_head_node_ip(&daft_config.setup.name, aws_config)
+                                    .await
+                                    .ok();
+                            state::record_cluster(&state::Cluster {
+                                name: daft_config.setup.name.clone(),
+                                region: aws_config.region().clone(),
+                                config: config_path.config.canonicalize()?,
+                                provider: "provisioned".into(),
+                                head_ip: head_ip.map(|head_ip| head_ip.to_string().into()),
+                                created_at: Some(
+                                    SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+                                ),
+                                state: Some(state::ClusterState::Up),
+                                profile: profile.map(Into::into),
+                            })
+                            .await?;
+                        };
+                        notify_cluster_event(&daft_config, NotificationEvent::ClusterUp, "is up")
+                            .await;
                     }
                     ProviderConfig::Byoc(..) => not_available_for_byoc!("up"),
                 }
             }
-            ProvisionedCommand::Down(ConfigPath { config }) => {
-                let daft_config = read_daft_config(config).await?;
-                match daft_config.setup.provider_config {
-                    ProviderConfig::Provisioned(..) => {
+            ProvisionedCommand::Down(Down {
+                lock_args,
+                config_path,
+            }) => {
+                let config = locate_config(&config_path.config).await?;
+                let daft_config = read_daft_config(&config, profile).await?;
+                match &daft_config.setup.provider_config {
+                    ProviderConfig::Provisioned(aws_config) => {
                         assert_is_logged_in_with_aws().await?;
 
-                        let ray_config = convert(&daft_config, Some(TeardownBehaviour::Down))?;
                         let (_temp_dir, ray_path) = create_temp_ray_file()?;
-                        write_ray_config(&ray_config, &ray_path).await?;
-                        run_ray_up_or_down_command(SpinDirection::Down, ray_path).await?;
+                        write_teardown_ray_config(&daft_config, TeardownBehaviour::Down, &ray_path)
+                            .await?;
+                        let lock = lock::acquire(
+                            aws_config.region(),
+                            &daft_config.setup.name,
+                            "down",
+                            lock_args.force,
+                        )
+                        .await?;
+                        let result = run_ray_up_or_down_command(
+                            SpinDirection::Down,
+                            &daft_config,
+                            ray_path,
+                            false,
+                        )
+                        .await;
+                        lock.release().await;
+                        result?;
+                        record_action(state::ActionKind::Down, &daft_config, None).await;
+                        record_cluster_state(
+                            &daft_config.setup.name,
+                            aws_config.region(),
+                            state::ClusterState::Down,
+                        )
+                        .await;
+                        notify_cluster_event(
+                            &daft_config,
+                            NotificationEvent::ClusterDown,
+                            "was spun down",
+                        )
+                        .await;
                     }
                     ProviderConfig::Byoc(..) => not_available_for_byoc!("down"),
                 }
             }
-            ProvisionedCommand::Kill(ConfigPath { config }) => {
-                let daft_config = read_daft_config(config).await?;
-                match daft_config.setup.provider_config {
-                    ProviderConfig::Provisioned(..) => {
+            ProvisionedCommand::Kill(Kill {
+                yes,
+                lock_args,
+                config_path,
+            }) => {
+                let config = locate_config(&config_path.config).await?;
+                let daft_config = read_daft_config(&config, profile).await?;
+                match &daft_config.setup.provider_config {
+                    ProviderConfig::Provisioned(aws_config) => {
                         assert_is_logged_in_with_aws().await?;
+                        if !*yes
+                            && !confirm_kill(&daft_config.setup.name, aws_config.region()).await?
+                        {
+                            println!("Left the cluster {} running", daft_config.setup.name);
+                            return Ok(());
+                        };
 
-                        let ray_config = convert(&daft_config, Some(TeardownBehaviour::Kill))?;
-                        let (_temp_dir, ray_path) = create_temp_ray_file()?;
-                        write_ray_config(&ray_config, &ray_path).await?;
-                        run_ray_up_or_down_command(SpinDirection::Down, ray_path).await?;
+                        kill_cluster(&daft_config, lock_args.force).await?;
                     }
                     ProviderConfig::Byoc(..) => not_available_for_byoc!("kill"),
                 }
@@ -1134,74 +6839,649 @@ impl ProvisionedCommand {
             &ProvisionedCommand::List(List {
                 ref config_path,
                 ref regex,
-                ref region,
                 head,
                 running,
+                ref tags,
+                group_by_cluster,
+                idle_after,
+                kill_idle,
+                mine,
+                sort_by,
                 ..
             }) => {
-                let daft_config = read_daft_config(&config_path.config).await?;
+                if mine {
+                    let clusters = state::read_clusters().await?;
+                    let known = state::known_clusters(&clusters, |_| true);
+                    let known = match regex {
+                        Some(regex) => {
+                            let regex = Regex::new(regex)?;
+                            known
+                                .into_iter()
+                                .filter(|cluster| regex.is_match(&cluster.name))
+                                .collect()
+                        }
+                        None => known,
+                    };
+                    match output {
+                        OutputFormat::Text => {
+                            println!("{}", format_known_clusters_table(&known, SystemTime::now()))
+                        }
+                        OutputFormat::Json => {
+                            for cluster in &known {
+                                println!("{}", serde_json::to_string(cluster)?);
+                            }
+                        }
+                        OutputFormat::Csv => print!("{}", format_csv(&known)?),
+                    }
+                    return Ok(());
+                };
+                // Without a configuration file, every cluster in the region is
+                // listed.
+                let region = if is_missing_default_config(&config_path.config) {
+                    resolve_region(None).await?
+                } else {
+                    let daft_config = read_daft_config(&config_path.config, profile).await?;
+                    match daft_config.setup.provider_config {
+                        ProviderConfig::Provisioned(aws_config) => aws_config.region().clone(),
+                        ProviderConfig::Byoc(..) => not_available_for_byoc!("list"),
+                    }
+                };
+                assert_is_logged_in_with_aws().await?;
+
+                let instances = get_ray_clusters_from_aws(region.clone(), tags).await?;
+                let mut instances = filter_instances(&instances, regex.as_deref(), head, running)?;
+                if let Some(column) = sort_by {
+                    sort_instances(&mut instances, column);
+                };
+                match output {
+                    OutputFormat::Text => {
+                        let instance_types = instances
+                            .iter()
+                            .filter_map(|instance| instance.instance_type.clone())
+                            .collect();
+                        let prices = pricing::hourly_prices(&region, &instance_types).await;
+                        let costs = cluster_hourly_costs(&instances, &prices);
+                        if group_by_cluster {
+                            println!(
+                                "{}",
+                                format_cluster_summaries_table(
+                                    &summarize_clusters(&instances),
+                                    &costs
+                                )
+                            );
+                        } else {
+                            println!("{}", format_table(&instances, SystemTime::now(), &prices));
+                            for (cluster, cost) in costs {
+                                println!(
+                                    "{cluster}: {}/hr for the live instances",
+                                    pricing::format_price(cost)
+                                );
+                            }
+                        }
+                    }
+                    OutputFormat::Json if group_by_cluster => {
+                        for summary in summarize_clusters(&instances) {
+                            println!("{}", serde_json::to_string(&summary)?);
+                        }
+                    }
+                    OutputFormat::Json => {
+                        for instance in &instances {
+                            println!(
+                                "{}",
+                                serde_json::to_string(&InstanceRecord::from(*instance))?
+                            );
+                        }
+                    }
+                    OutputFormat::Csv if group_by_cluster => print!(
+                        "{}",
+                        format_csv(
+                            &summarize_clusters(&instances)
+                                .iter()
+                                .map(ClusterSummaryRecord::from)
+                                .collect::<Vec<_>>()
+                        )?
+                    ),
+                    OutputFormat::Csv => print!(
+                        "{}",
+                        format_csv(
+                            &instances
+                                .iter()
+                                .copied()
+                                .map(InstanceRecord::from)
+                                .collect::<Vec<_>>()
+                        )?
+                    ),
+                }
+
+                if let Some(idle_after) = idle_after {
+                    let threshold = idle_after
+                        .checked_mul(3600)
+                        .map(Duration::from_secs)
+                        .ok_or_else(|| anyhow::anyhow!("`--idle-after {idle_after}` is too long"))
+                        .classify(Failure::Config)?;
+                    let idle = find_idle_clusters(&instances, &region, threshold).await?;
+                    for (daft_config, idle_for) in &idle {
+                        let message = format!(
+                            "The cluster {} has had no active jobs for {}",
+                            daft_config.setup.name,
+                            format_duration(*idle_for)
+                        );
+                        match output {
+                            OutputFormat::Text => println!("{message}"),
+                            OutputFormat::Json | OutputFormat::Csv => logging::warn(message),
+                        };
+                    }
+                    if kill_idle {
+                        for (daft_config, _) in &idle {
+                            if confirm_kill(&daft_config.setup.name, &region).await? {
+                                kill_cluster(daft_config, false).await?;
+                            } else {
+                                println!("Left the cluster {} running", daft_config.setup.name);
+                            };
+                        }
+                    };
+                };
+            }
+            ProvisionedCommand::Connect(connect) => {
+                let &Connect {
+                    port,
+                    no_dashboard,
+                    detach,
+                    ref config_path,
+                    ..
+                } = connect;
+                let forwards = connect.port_forwards();
+                let config = locate_config(&config_path.config).await?;
+                let daft_config = read_daft_config(&config, profile).await?;
+                let open_dashboard = !no_dashboard;
                 match &daft_config.setup.provider_config {
                     ProviderConfig::Provisioned(aws_config) => {
                         assert_is_logged_in_with_aws().await?;
+                        let port = match port {
+                            Some(port) => port,
+                            None => dashboard_local_port()?,
+                        };
+                        let dashboard_address = format!("http://localhost:{port}");
+
+                        let metrics_forwards = daft_config
+                            .setup
+                            .metrics
+                            .as_ref()
+                            .map(metrics::port_forwards)
+                            .unwrap_or_default();
+                        let other_forwards = merge_port_forwards(
+                            metrics_forwards.into_iter().chain(
+                                aws_config
+                                    .forwards
+                                    .iter()
+                                    .chain(&forwards)
+                                    .map(|forward| (forward.local, forward.remote)),
+                            ),
+                        )
+                        .into_iter()
+                        .filter(|&(local, _)| local != port)
+                        .collect::<Vec<_>>();
+                        let ray_client_uri = other_forwards
+                            .iter()
+                            .find(|&&(_, remote)| remote == RAY_CLIENT_PORT)
+                            .map(|&(local, _)| format!("ray://localhost:{local}"));
+                        if detach {
+                            let mut portforwards = vec![(port, RAY_DASHBOARD_PORT)];
+                            portforwards.extend_from_slice(&other_forwards);
+                            let pid = open_detached_tunnel(&daft_config, aws_config, &portforwards)
+                                .await?;
+                            println!(
+                                "Opened a tunnel to cluster {} in the background (pid {pid}); close it with `daft tunnel stop {}`",
+                                daft_config.setup.name, daft_config.setup.name
+                            );
+                            if let Some(ray_client_uri) = &ray_client_uri {
+                                println!("Ray client: ray.init(\"{ray_client_uri}\")");
+                            };
+                            if open_dashboard {
+                                open::that(&dashboard_address)?;
+                            };
+                            return Ok(());
+                        };
 
-                        let region = region.as_ref().unwrap_or_else(|| &aws_config.region);
-                        let instances = get_ray_clusters_from_aws(region.clone()).await?;
-                        let table = format_table(&instances, regex.as_deref(), head, running)?;
-                        println!("{table}");
+                        // Over SSM, each port has a session of its own, all of which
+                        // are held open until the dashboard's closes.
+                        let (child, _other_sessions) = match aws_config.connection {
+                            Connection::Ssh => {
+                                let child = ssh::ssh_portforward(
+                                    &daft_config.setup.name,
+                                    aws_config,
+                                    port,
+                                    &other_forwards,
+                                    daft_config.setup.tunnel_timeout(),
+                                )
+                                .await?;
+                                (child, vec![])
+                            }
+                            Connection::Ssm => {
+                                let mut portforwards = vec![(port, RAY_DASHBOARD_PORT)];
+                                portforwards.extend_from_slice(&other_forwards);
+                                let mut sessions =
+                                    ssm_port_forward(&daft_config, aws_config, &portforwards)
+                                        .await?;
+                                (sessions.remove(0), sessions)
+                            }
+                        };
+                        if let Some(metrics) = &daft_config.setup.metrics {
+                            println!(
+                                "Prometheus is at http://localhost:{}, and Grafana at http://localhost:{}",
+                                metrics.prometheus_port, metrics.grafana_port
+                            );
+                        };
+                        for (local, remote) in &other_forwards {
+                            logging::info(format!(
+                                "Forwarding localhost:{local} to port {remote} on the head node"
+                            ));
+                        }
+                        if let Some(ray_client_uri) = &ray_client_uri {
+                            println!("Ray client: ray.init(\"{ray_client_uri}\")");
+                        };
+
+                        if open_dashboard {
+                            open::that(&dashboard_address)?;
+                        };
+
+                        child.wait_with_output().await?;
                     }
-                    ProviderConfig::Byoc(..) => not_available_for_byoc!("list"),
+                    ProviderConfig::Byoc(..) => not_available_for_byoc!("connect"),
                 }
             }
-            &ProvisionedCommand::Connect(Connect {
-                port,
-                no_dashboard,
-                ref config_path,
+            ProvisionedCommand::Monitor(Monitor {
+                interval,
+                config_path,
             }) => {
-                let daft_config = read_daft_config(&config_path.config).await?;
-                let open_dashboard = !no_dashboard;
+                if !std::io::stdout().is_terminal() {
+                    anyhow::bail!("`provisioned monitor` needs a terminal to draw in; use `provisioned list` or `job status` in scripts");
+                };
+                let config = locate_config(&config_path.config).await?;
+                let daft_config = read_daft_config(&config, profile).await?;
+                let port_forward = establish_ray_dashboard_port_forward(&daft_config).await?;
+                monitor::run(
+                    &port_forward.address(),
+                    &daft_config.setup.name,
+                    Duration::from_secs((*interval).max(1)),
+                )
+                .await?;
+            }
+            ProvisionedCommand::Events(Events {
+                follow,
+                lines,
+                config_path,
+            }) => {
+                let output = output.text_or_json("provisioned events")?;
+                let config = locate_config(&config_path.config).await?;
+                let daft_config = read_daft_config(&config, profile).await?;
                 match &daft_config.setup.provider_config {
                     ProviderConfig::Provisioned(aws_config) => {
                         assert_is_logged_in_with_aws().await?;
 
-                        let ray_config = convert(&daft_config, None)?;
-                        let (_temp_dir, ray_path) = create_temp_ray_file()?;
-                        write_ray_config(&ray_config, &ray_path).await?;
-
-                        let child = ssh::ssh_portforward(ray_path, aws_config, Some(port)).await?;
+                        ssh::ssh_lines(
+                            &daft_config.setup.name,
+                            aws_config,
+                            &autoscaler_log::tail_command(*lines, *follow),
+                            |line| {
+                                if let Some(event) = autoscaler_log::parse_line(line) {
+                                    match output {
+                                        TextOrJson::Text => {
+                                            println!("{}", autoscaler_log::format_event(&event))
+                                        }
+                                        TextOrJson::Json => {
+                                            println!("{}", serde_json::to_string(&event)?)
+                                        }
+                                    }
+                                };
+                                Ok(())
+                            },
+                        )
+                        .await
+                        .classify(Failure::Tunnel)?;
+                    }
+                    ProviderConfig::Byoc(..) => not_available_for_byoc!("events"),
+                }
+            }
+            ProvisionedCommand::Exec(Exec {
+                command,
+                all_nodes,
+                config_path,
+            }) => {
+                let config = locate_config(&config_path.config).await?;
+                let daft_config = read_daft_config(&config, profile).await?;
+                match &daft_config.setup.provider_config {
+                    ProviderConfig::Provisioned(aws_config) => {
+                        assert_is_logged_in_with_aws().await?;
 
-                        if open_dashboard {
-                            open::that("http://localhost:8265")?;
+                        let outputs =
+                            ssh::exec(&daft_config.setup.name, aws_config, command, *all_nodes)
+                                .await?;
+                        match output {
+                            OutputFormat::Text => print!("{}", ssh::format_node_outputs(&outputs)),
+                            OutputFormat::Json => println!("{}", serde_json::to_string(&outputs)?),
+                            OutputFormat::Csv => print!("{}", format_csv(&outputs)?),
+                        }
+                        let failures = outputs
+                            .iter()
+                            .filter(|output| output.exit_code != Some(0))
+                            .count();
+                        if failures > 0 {
+                            anyhow::bail!(
+                                "The command failed on {failures} of the {} nodes",
+                                outputs.len()
+                            );
                         };
+                    }
+                    ProviderConfig::Byoc(..) => not_available_for_byoc!("exec"),
+                }
+            }
+            ProvisionedCommand::Cp(Cp {
+                local,
+                remote,
+                config_path,
+            }) => {
+                if !local.exists() {
+                    anyhow::bail!("No such file or directory: {}", local.display());
+                };
+                let config = locate_config(&config_path.config).await?;
+                let daft_config = read_daft_config(&config, profile).await?;
+                match &daft_config.setup.provider_config {
+                    ProviderConfig::Provisioned(aws_config) => {
+                        assert_is_logged_in_with_aws().await?;
 
-                        child.wait_with_output().await?;
+                        ssh::copy_to_head_node(&daft_config.setup.name, aws_config, local, remote)
+                            .await?;
+                        logging::info(format!("Copied {} to {remote}", local.display()));
                     }
-                    ProviderConfig::Byoc(..) => not_available_for_byoc!("connect"),
+                    ProviderConfig::Byoc(..) => not_available_for_byoc!("cp"),
+                }
+            }
+            ProvisionedCommand::Sync(SyncDir {
+                local,
+                remote,
+                delete,
+                config_path,
+            }) => {
+                not_available_on_windows!(
+                    "provisioned sync",
+                    "run it from WSL, or copy the files with `provisioned cp` instead"
+                );
+                if !local.is_dir() {
+                    anyhow::bail!("{} is not a directory", local.display());
+                };
+                let config = locate_config(&config_path.config).await?;
+                let daft_config = read_daft_config(&config, profile).await?;
+                match &daft_config.setup.provider_config {
+                    ProviderConfig::Provisioned(aws_config) => {
+                        assert_is_logged_in_with_aws().await?;
+
+                        ssh::sync_to_head_node(
+                            &daft_config.setup.name,
+                            aws_config,
+                            local,
+                            remote,
+                            *delete,
+                        )
+                        .await?;
+                        logging::info(format!("Synced {} to {remote}", local.display()));
+                    }
+                    ProviderConfig::Byoc(..) => not_available_for_byoc!("sync"),
                 }
             }
             ProvisionedCommand::Ssh(ConfigPath { config }) => {
-                let daft_config = read_daft_config(config).await?;
+                let config = locate_config(config).await?;
+                let daft_config = read_daft_config(&config, profile).await?;
                 match &daft_config.setup.provider_config {
                     ProviderConfig::Provisioned(aws_config) => {
                         assert_is_logged_in_with_aws().await?;
 
-                        let ray_config = convert(&daft_config, None)?;
-                        let (_temp_dir, ray_path) = create_temp_ray_file()?;
-                        write_ray_config(&ray_config, &ray_path).await?;
-                        ssh::ssh(ray_path, aws_config).await?;
+                        if aws_config.connection == Connection::Ssm {
+                            let instance_id = head_instance_id(&daft_config, aws_config).await?;
+                            ssm::start_session(&instance_id, aws_config.region()).await?;
+                            return Ok(());
+                        };
+
+                        ssh::ssh(&daft_config.setup.name, aws_config).await?;
                     }
                     ProviderConfig::Byoc(..) => not_available_for_byoc!("ssh"),
                 }
             }
+            ProvisionedCommand::Adopt(Adopt {
+                name,
+                ssh_user,
+                ssh_private_key,
+                to,
+            }) => {
+                if to.exists() {
+                    anyhow::bail!("The path {to:?} already exists; the path given must point to a new location on your filesystem");
+                };
+                let region = resolve_region(None).await?;
+                assert_is_logged_in_with_aws().await?;
+
+                let instances = get_ray_clusters_from_aws(
+                    region.clone(),
+                    &[("ray-cluster-name".into(), name.clone())],
+                )
+                .await?;
+                let instances = instances.iter().collect::<Vec<_>>();
+                let template = render_template(&DaftProvider::Provisioned).await?;
+                let (contents, warnings) = adopt::adopt(
+                    &template,
+                    name,
+                    &region,
+                    &instances,
+                    ssh_user.as_deref(),
+                    ssh_private_key.as_deref(),
+                )?;
+                for warning in warnings {
+                    logging::warn(warning);
+                }
+                fs::write(to, contents).await?;
+
+                let head = adopt::head(&instances);
+                state::record_cluster(&state::Cluster {
+                    name: name.clone(),
+                    region,
+                    config: to.canonicalize()?,
+                    provider: "provisioned".into(),
+                    head_ip: head
+                        .and_then(AwsInstance::address)
+                        .map(|head_ip| head_ip.to_string().into()),
+                    created_at: head
+                        .and_then(|head| head.launch_time)
+                        .and_then(|launch_time| launch_time.duration_since(UNIX_EPOCH).ok())
+                        .map(|launched| launched.as_secs()),
+                    state: Some(state::ClusterState::Up),
+                    profile: None,
+                })
+                .await?;
+                println!("Adopted the cluster {name} into {to:?}; `daft provisioned ssh {name}` (and `connect`, `kill` and the like) now work against it, as does `daft job submit` with {to:?}");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TunnelCommand {
+    async fn run(&self, output: OutputFormat) -> anyhow::Result<()> {
+        match self {
+            TunnelCommand::List => {
+                let output = output.text_or_json("tunnel list")?;
+                let tunnels = open_detached_tunnels().await?;
+                match output {
+                    TextOrJson::Text => {
+                        if tunnels.is_empty() {
+                            println!("No tunnels are open");
+                        } else {
+                            println!("{}", format_tunnels_table(&tunnels, SystemTime::now()));
+                        }
+                    }
+                    TextOrJson::Json => println!("{}", serde_json::to_string(&tunnels)?),
+                }
+            }
+            TunnelCommand::Stop(TunnelStop { cluster, all }) => {
+                let tunnels = open_detached_tunnels().await?;
+                let (stopped, kept) = tunnels.into_iter().partition::<Vec<_>, _>(|tunnel| {
+                    *all || cluster.as_deref() == Some(&*tunnel.cluster)
+                });
+                if stopped.is_empty() {
+                    anyhow::bail!(
+                        "No tunnels to {} are open",
+                        cluster.as_deref().unwrap_or("any cluster")
+                    );
+                };
+                let mut still_open = kept;
+                let mut failures = vec![];
+                for tunnel in stopped {
+                    // The pid is checked again, in case the tunnel closed (and
+                    // its pid was reused) since it was listed.
+                    let closed = is_open(&tunnel).await && terminate(tunnel.pid).await?;
+                    let description = format!(
+                        "the tunnel to cluster {} (pid {})",
+                        tunnel.cluster, tunnel.pid
+                    );
+                    if closed {
+                        println!("Closed {description}");
+                    } else {
+                        failures.push(description);
+                        still_open.push(tunnel);
+                    };
+                }
+                // The tunnels which have closed by themselves are dropped too.
+                state::write_tunnels(&still_open).await?;
+                if !failures.is_empty() {
+                    anyhow::bail!("Failed to close {}", failures.join(", "));
+                };
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats the given clusters which were spun up from this machine as a table,
+/// most recent first.
+fn format_known_clusters_table(clusters: &[&state::Cluster], now: SystemTime) -> Table {
+    let mut table = Table::default();
+    table
+        .load_preset(presets::UTF8_FULL)
+        .apply_modifier(modifiers::UTF8_ROUND_CORNERS)
+        .apply_modifier(modifiers::UTF8_SOLID_INNER_BORDERS)
+        .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+        .set_header(
+            ["Cluster", "Region", "State", "Head IP", "Created", "Config"].map(|header| {
+                Cell::new(header)
+                    .set_alignment(CellAlignment::Center)
+                    .add_attribute(Attribute::Bold)
+            }),
+        );
+    for cluster in clusters {
+        let state = match cluster.state {
+            Some(state @ state::ClusterState::Up) => Cell::new(state.as_str()).fg(Color::Green),
+            Some(state) => Cell::new(state.as_str()).fg(Color::Red),
+            None => Cell::new("-"),
+        };
+        let created = cluster.created_at.map_or_else(
+            || "-".to_string(),
+            |created_at| {
+                let age = now
+                    .duration_since(UNIX_EPOCH + Duration::from_secs(created_at))
+                    .unwrap_or_default();
+                format!("{} ago", format_duration(age))
+            },
+        );
+        table.add_row(vec![
+            Cell::new(&cluster.name).fg(Color::Cyan),
+            Cell::new(&cluster.region),
+            state,
+            Cell::new(cluster.head_ip.as_deref().unwrap_or("-")),
+            Cell::new(created),
+            Cell::new(cluster.config.display()),
+        ]);
+    }
+    table
+}
+
+/// Formats the given detached tunnels as a table, with how long each has been
+/// open.
+fn format_tunnels_table(tunnels: &[state::DetachedTunnel], now: SystemTime) -> Table {
+    let mut table = Table::default();
+    table
+        .load_preset(presets::UTF8_FULL)
+        .apply_modifier(modifiers::UTF8_ROUND_CORNERS)
+        .apply_modifier(modifiers::UTF8_SOLID_INNER_BORDERS)
+        .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+        .set_header(["Cluster", "PID", "Ports", "Open For"].map(|header| {
+            Cell::new(header)
+                .set_alignment(CellAlignment::Center)
+                .add_attribute(Attribute::Bold)
+        }));
+    for tunnel in tunnels {
+        let ports = tunnel
+            .ports
+            .iter()
+            .map(|(local, remote)| format!("{local}:{remote}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let open_for = now
+            .duration_since(UNIX_EPOCH + Duration::from_secs(tunnel.opened_at))
+            .unwrap_or_default();
+        table.add_row(vec![
+            Cell::new(&tunnel.cluster).fg(Color::Cyan),
+            Cell::new(tunnel.pid),
+            Cell::new(ports),
+            Cell::new(format_duration(open_for)),
+        ]);
+    }
+    table
+}
+
+impl ConnectCommand {
+    async fn run(&self, profile: Option<&str>) -> anyhow::Result<()> {
+        match self {
+            ConnectCommand::Env(ConfigPath { config }) => {
+                let daft_config = read_daft_config(config, profile).await?;
+                let pid = open_ray_client_tunnel(&daft_config).await?;
+                eprintln!(
+                    "Opened a tunnel to the Ray client server of cluster {}; close it with `kill {pid}`",
+                    daft_config.setup.name
+                );
+                print!("{}", format_connect_env(pid));
+            }
         }
         Ok(())
     }
 }
 
 impl ByocCommand {
-    async fn run(&self) -> anyhow::Result<()> {
+    async fn run(&self, profile: Option<&str>, output: OutputFormat) -> anyhow::Result<()> {
         match self {
             ByocCommand::Verify(..) => todo!(),
-            ByocCommand::Info(..) => todo!(),
+            ByocCommand::Info(ConfigPath { config }) => {
+                let output = output.text_or_json("byoc info")?;
+                let daft_config = read_daft_config(config, profile).await?;
+                let ProviderConfig::Byoc(k8s_config) = &daft_config.setup.provider_config else {
+                    anyhow::bail!(
+                        "The command `byoc info` is only available for byoc configurations"
+                    );
+                };
+                let info = ByocInfo {
+                    name: &daft_config.setup.name,
+                    context: k8s_config.context.as_deref(),
+                    namespace: &k8s_config.namespace,
+                    head_node_service: find_head_node_service(k8s_config).await?,
+                };
+                match output {
+                    TextOrJson::Text => {
+                        println!("Name: {}", info.name);
+                        println!("Context: {}", info.context.unwrap_or("(current)"));
+                        println!("Namespace: {}", info.namespace);
+                        println!("Head node service: {}", info.head_node_service);
+                    }
+                    TextOrJson::Json => println!("{}", serde_json::to_string(&info)?),
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -0,0 +1,46 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::Command;
+use clap_mangen::Man;
+
+/// Writes the man page of the given command, and one for each of its
+/// subcommands (e.g., `daft-provisioned-up.1`), into the given directory,
+/// returning their paths.
+pub fn write_all(command: &Command, dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)
+        .map_err(|error| anyhow::anyhow!("The directory {dir:?} could not be created: {error}"))?;
+    let mut command = command.clone();
+    command.build();
+    let mut paths = vec![];
+    let name = command.get_name().to_string();
+    write(&command, &name, dir, &mut paths)?;
+    Ok(paths)
+}
+
+fn write(
+    command: &Command,
+    name: &str,
+    dir: &Path,
+    paths: &mut Vec<PathBuf>,
+) -> anyhow::Result<()> {
+    let mut page = vec![];
+    Man::new(command.clone().name(name.to_string())).render(&mut page)?;
+    let path = dir.join(format!("{name}.1"));
+    fs::write(&path, page)?;
+    paths.push(path);
+    for subcommand in command
+        .get_subcommands()
+        .filter(|subcommand| subcommand.get_name() != "help")
+    {
+        write(
+            subcommand,
+            &format!("{name}-{}", subcommand.get_name()),
+            dir,
+            paths,
+        )?;
+    }
+    Ok(())
+}
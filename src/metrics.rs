@@ -0,0 +1,59 @@
+use crate::{MetricsConfig, StrRef};
+
+/// The port on which Prometheus listens on the head node (`ray metrics
+/// launch-prometheus` always uses it).
+pub const PROMETHEUS_PORT: u16 = 9090;
+
+/// The port on which Grafana listens on the head node (Ray's generated
+/// `grafana.ini` always uses it).
+pub const GRAFANA_PORT: u16 = 3000;
+
+/// Where Grafana is installed on the head node.
+const GRAFANA_HOME: &str = "~/grafana";
+
+/// The directory in which Ray writes the Prometheus and Grafana configuration
+/// (and dashboards) for the running session.
+const RAY_METRICS_DIR: &str = "/tmp/ray/session_latest/metrics";
+
+/// The commands which install Grafana on the head node (Prometheus is
+/// downloaded by Ray itself once it has started).
+pub fn head_setup_commands(metrics: &MetricsConfig) -> Vec<StrRef> {
+    let version = &metrics.grafana_version;
+    vec![
+        format!(
+            "[ -x {GRAFANA_HOME}/bin/grafana ] || (mkdir -p {GRAFANA_HOME} && curl -LsSf https://dl.grafana.com/oss/release/grafana-{version}.linux-$(dpkg --print-architecture).tar.gz | tar -xz --strip-components=1 -C {GRAFANA_HOME})"
+        )
+        .into(),
+    ]
+}
+
+/// The commands which start Ray on the head node, pointing its dashboard at
+/// Prometheus and Grafana, and then start them with the configuration and
+/// dashboards which Ray generates.
+///
+/// These replace Ray's default commands for the head node, which are the same
+/// apart from the metrics.
+pub fn head_start_ray_commands(metrics: &MetricsConfig) -> Vec<StrRef> {
+    vec![
+        "ray stop".into(),
+        format!(
+            "ulimit -n 65536; RAY_PROMETHEUS_HOST=http://localhost:{PROMETHEUS_PORT} RAY_GRAFANA_HOST=http://localhost:{GRAFANA_PORT} RAY_GRAFANA_IFRAME_HOST=http://localhost:{} ray start --head --port=6379 --object-manager-port=8076 --autoscaling-config=~/ray_bootstrap_config.yaml",
+            metrics.grafana_port
+        )
+        .into(),
+        "ray metrics launch-prometheus".into(),
+        format!(
+            "nohup {GRAFANA_HOME}/bin/grafana server --homepath {GRAFANA_HOME} --config {RAY_METRICS_DIR}/grafana/grafana.ini web > /tmp/grafana.log 2>&1 &"
+        )
+        .into(),
+    ]
+}
+
+/// The local ports which `connect` forwards to Prometheus and Grafana on the
+/// head node, as `(local, remote)` pairs.
+pub fn port_forwards(metrics: &MetricsConfig) -> Vec<(u16, u16)> {
+    vec![
+        (metrics.prometheus_port, PROMETHEUS_PORT),
+        (metrics.grafana_port, GRAFANA_PORT),
+    ]
+}
@@ -0,0 +1,115 @@
+use toml_edit::{DocumentMut, Item, Table};
+
+/// The providers which a configuration file can set up, each of which has its
+/// own table under `[setup]`.
+const PROVIDERS: &[&str] = &["provisioned", "byoc"];
+
+/// Rewrites the given configuration file from the old schema (with a `version`
+/// and `provider` in `[setup]`, and a separate `[run]` section) to the current
+/// one, keeping its comments and formatting where possible.
+///
+/// The version requirement is bumped to the current version, and the given
+/// Python and Ray versions are filled in if the file does not set them.
+/// Returns the migrated file, along with notes about anything which could not
+/// be migrated as-is.
+pub fn migrate(
+    contents: &str,
+    python_version: &str,
+    ray_version: &str,
+) -> anyhow::Result<(String, Vec<String>)> {
+    let mut document = contents.parse::<DocumentMut>()?;
+    let mut notes = vec![];
+
+    let run = document.remove("run");
+    let setup = document
+        .get_mut("setup")
+        .and_then(Item::as_table_mut)
+        .ok_or_else(|| anyhow::anyhow!("The configuration file has no [setup] section"))?;
+
+    setup.remove("version");
+    setup.insert(
+        "requires",
+        toml_edit::value(concat!("=", env!("CARGO_PKG_VERSION"))),
+    );
+    if !setup.contains_key("python-version") {
+        setup.insert("python-version", toml_edit::value(python_version));
+    };
+    if !setup.contains_key("ray-version") {
+        setup.insert("ray-version", toml_edit::value(ray_version));
+    };
+
+    let provider = match setup.remove("provider") {
+        Some(provider) => provider
+            .as_str()
+            .filter(|provider| PROVIDERS.contains(provider))
+            .ok_or_else(|| {
+                anyhow::anyhow!("The provider must be one of: {}", PROVIDERS.join(", "))
+            })?
+            .to_string(),
+        None => PROVIDERS
+            .iter()
+            .find(|provider| setup.contains_key(provider))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "The configuration file must have one of the sections: {}",
+                    PROVIDERS
+                        .iter()
+                        .map(|provider| format!("[setup.{provider}]"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })?
+            .to_string(),
+    };
+    let dependencies = setup.remove_entry("dependencies");
+    if !setup.contains_key(&provider) {
+        let mut provider_table = Table::new();
+        provider_table.set_implicit(false);
+        setup.insert(&provider, Item::Table(provider_table));
+    };
+    let provider_table = setup
+        .get_mut(&provider)
+        .and_then(Item::as_table_mut)
+        .ok_or_else(|| anyhow::anyhow!("[setup.{provider}] must be a table"))?;
+
+    // Cluster-wide dependencies and setup commands are now part of the
+    // provisioned setup, since BYOC clusters are set up outside of the
+    // launcher.
+    if let Some((key, dependencies)) = dependencies {
+        if provider == "provisioned" {
+            provider_table.insert_formatted(&key, dependencies);
+        } else if dependencies
+            .as_array()
+            .is_some_and(|array| !array.is_empty())
+        {
+            notes.push("Removed `setup.dependencies`, since BYOC clusters must have their dependencies installed already".to_string());
+        };
+    };
+    if let Some(run) = run {
+        let mut has_commands = false;
+        for (old_key, key) in [
+            ("pre-setup-commands", "pre-setup-commands"),
+            ("post-setup-commands", "run"),
+        ] {
+            let Some(commands) = run.get(old_key).and_then(Item::as_array) else {
+                continue;
+            };
+            if commands.is_empty() {
+                continue;
+            };
+            has_commands = true;
+            if provider == "provisioned" {
+                let mut commands = commands.clone();
+                commands.fmt();
+                provider_table.insert(key, toml_edit::value(commands));
+            };
+        }
+        if has_commands && provider != "provisioned" {
+            notes.push(
+                "Removed the [run] section, since BYOC clusters must be set up already".to_string(),
+            );
+        };
+    };
+
+    Ok((document.to_string(), notes))
+}
@@ -0,0 +1,283 @@
+use std::time::{Duration, Instant};
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, List, ListItem, Row, Table},
+    DefaultTerminal, Frame,
+};
+use serde_json::Value;
+
+use crate::{ray_jobs::RayJobsClient, RayJob, StrRef};
+
+/// How many of the most recent autoscaler events are shown.
+const EVENTS_TO_SHOW: usize = 50;
+
+/// The resource usage of a node, as reported by the Ray dashboard.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeUsage {
+    pub ip: StrRef,
+    pub is_head: bool,
+    pub state: StrRef,
+    /// In percent.
+    pub cpu: f64,
+    /// In bytes.
+    pub memory_used: u64,
+    pub memory_total: u64,
+    pub object_store_used: u64,
+    pub object_store_total: u64,
+}
+
+/// An event of the Ray autoscaler (e.g., nodes being added or removed).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutoscalerEvent {
+    pub time: StrRef,
+    pub severity: StrRef,
+    pub message: StrRef,
+}
+
+/// Everything which is shown by one refresh of the monitor.
+#[derive(Debug, Default)]
+struct Snapshot {
+    nodes: Vec<NodeUsage>,
+    jobs: Vec<RayJob>,
+    events: Vec<AutoscalerEvent>,
+    /// Why the last refresh failed, if it did (the previous snapshot is kept
+    /// on screen in the meantime).
+    error: Option<String>,
+    refreshed_at: Option<Instant>,
+}
+
+/// Parses the response of the dashboard's `/nodes?view=summary` endpoint.
+pub fn parse_nodes(json: &str) -> anyhow::Result<Vec<NodeUsage>> {
+    let response = serde_json::from_str::<Value>(json)?;
+    let summary = response["data"]["summary"]
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("The node summary is missing from the response"))?;
+    let bytes = |value: &Value| value.as_f64().unwrap_or_default() as u64;
+    let mut nodes = summary
+        .iter()
+        .map(|node| {
+            let raylet = &node["raylet"];
+            let memory = &node["mem"];
+            let object_store_used = bytes(&raylet["objectStoreUsedMemory"]);
+            NodeUsage {
+                ip: node["ip"].as_str().unwrap_or("n/a").into(),
+                is_head: raylet["isHeadNode"].as_bool().unwrap_or_default(),
+                state: raylet["state"].as_str().unwrap_or("n/a").into(),
+                cpu: node["cpu"].as_f64().unwrap_or_default(),
+                memory_total: bytes(&memory[0]),
+                memory_used: bytes(&memory[0]).saturating_sub(bytes(&memory[1])),
+                object_store_used,
+                object_store_total: object_store_used
+                    + bytes(&raylet["objectStoreAvailableMemory"]),
+            }
+        })
+        .collect::<Vec<_>>();
+    nodes.sort_by(|a, b| b.is_head.cmp(&a.is_head).then_with(|| a.ip.cmp(&b.ip)));
+    Ok(nodes)
+}
+
+/// Parses the response of the state API's `/api/v0/cluster_events` endpoint,
+/// keeping the autoscaler's events, most recent first.
+pub fn parse_autoscaler_events(json: &str) -> anyhow::Result<Vec<AutoscalerEvent>> {
+    let response = serde_json::from_str::<Value>(json)?;
+    let events = response["data"]["result"]["result"]
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("The cluster events are missing from the response"))?;
+    let mut events = events
+        .iter()
+        .filter(|event| event["source_type"].as_str() == Some("AUTOSCALER"))
+        .map(|event| AutoscalerEvent {
+            time: event["time"].as_str().unwrap_or("n/a").into(),
+            severity: event["severity"].as_str().unwrap_or("n/a").into(),
+            message: event["message"].as_str().unwrap_or_default().trim().into(),
+        })
+        .collect::<Vec<_>>();
+    events.sort_by(|a, b| b.time.cmp(&a.time));
+    events.truncate(EVENTS_TO_SHOW);
+    Ok(events)
+}
+
+async fn fetch(client: &reqwest::Client, address: &str, path: &str) -> anyhow::Result<String> {
+    Ok(client
+        .get(format!("{address}{path}"))
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?)
+}
+
+async fn refresh(address: &str, client: &reqwest::Client, snapshot: &mut Snapshot) {
+    let jobs_client = RayJobsClient::new(address);
+    let result = async {
+        let nodes = parse_nodes(&fetch(client, address, "/nodes?view=summary").await?)?;
+        let mut jobs = jobs_client.list().await?;
+        jobs.retain(|job| job.status.is_active());
+        jobs.sort_by_key(|job| job.start_time);
+        let events = parse_autoscaler_events(
+            &fetch(
+                client,
+                address,
+                &format!("/api/v0/cluster_events?limit={}", EVENTS_TO_SHOW * 4),
+            )
+            .await?,
+        )?;
+        anyhow::Ok((nodes, jobs, events))
+    }
+    .await;
+    match result {
+        Ok((nodes, jobs, events)) => {
+            snapshot.nodes = nodes;
+            snapshot.jobs = jobs;
+            snapshot.events = events;
+            snapshot.error = None;
+            snapshot.refreshed_at = Some(Instant::now());
+        }
+        Err(error) => snapshot.error = Some(format!("{error}")),
+    };
+}
+
+/// Formats the given usage as e.g. `1.2/4.0 GiB (30%)`.
+pub fn format_usage(used: u64, total: u64) -> String {
+    const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+    let percent = if total == 0 {
+        0.0
+    } else {
+        used as f64 / total as f64 * 100.0
+    };
+    format!(
+        "{:.1}/{:.1} GiB ({percent:.0}%)",
+        used as f64 / GIB,
+        total as f64 / GIB
+    )
+}
+
+fn draw(frame: &mut Frame, cluster_name: &str, snapshot: &Snapshot) {
+    let [nodes_area, jobs_area, events_area, footer_area] = Layout::vertical([
+        Constraint::Length(snapshot.nodes.len() as u16 + 3),
+        Constraint::Length(snapshot.jobs.len().max(1) as u16 + 3),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let header_style = Style::default().add_modifier(Modifier::BOLD);
+
+    let nodes = Table::new(
+        snapshot.nodes.iter().map(|node| {
+            Row::new([
+                format!("{}{}", node.ip, if node.is_head { " (head)" } else { "" }),
+                node.state.to_string(),
+                format!("{:.0}%", node.cpu),
+                format_usage(node.memory_used, node.memory_total),
+                format_usage(node.object_store_used, node.object_store_total),
+            ])
+        }),
+        [
+            Constraint::Percentage(25),
+            Constraint::Percentage(10),
+            Constraint::Percentage(10),
+            Constraint::Percentage(25),
+            Constraint::Percentage(30),
+        ],
+    )
+    .header(Row::new(["Node", "State", "CPU", "Memory", "Object Store"]).style(header_style))
+    .block(Block::bordered().title(format!(" {cluster_name}: nodes ")));
+    frame.render_widget(nodes, nodes_area);
+
+    let jobs = Table::new(
+        snapshot.jobs.iter().map(|job| {
+            Row::new([
+                job.id().unwrap_or("n/a").to_string(),
+                job.daft_job_name().unwrap_or("n/a").to_string(),
+                job.status.as_str().to_string(),
+                job.entrypoint.to_string(),
+            ])
+        }),
+        [
+            Constraint::Percentage(25),
+            Constraint::Percentage(15),
+            Constraint::Percentage(10),
+            Constraint::Percentage(50),
+        ],
+    )
+    .header(Row::new(["Submission ID", "Job", "Status", "Entrypoint"]).style(header_style))
+    .block(Block::bordered().title(" Running jobs "));
+    frame.render_widget(jobs, jobs_area);
+
+    let events = List::new(snapshot.events.iter().map(|event| {
+        let color = match &*event.severity {
+            "ERROR" | "FATAL" => Color::Red,
+            "WARNING" => Color::Yellow,
+            _ => Color::Reset,
+        };
+        ListItem::new(format!("{} {}", event.time, event.message)).style(Style::default().fg(color))
+    }))
+    .block(Block::bordered().title(" Autoscaler events "));
+    frame.render_widget(events, events_area);
+
+    let footer = match (&snapshot.error, snapshot.refreshed_at) {
+        (Some(error), _) => Line::styled(
+            format!("Failed to refresh: {error} (q to quit)"),
+            Style::default().fg(Color::Red),
+        ),
+        (None, Some(refreshed_at)) => Line::from(format!(
+            "Refreshed {}s ago (q to quit)",
+            refreshed_at.elapsed().as_secs()
+        )),
+        (None, None) => Line::from("Loading... (q to quit)"),
+    };
+    frame.render_widget(footer, footer_area);
+}
+
+/// Shows a live view of the cluster whose dashboard is served at the given
+/// address until the user quits (with `q` or escape), refreshing it at the given
+/// interval.
+pub async fn run(address: &str, cluster_name: &str, interval: Duration) -> anyhow::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = show(&mut terminal, address, cluster_name, interval).await;
+    ratatui::restore();
+    result
+}
+
+async fn show(
+    terminal: &mut DefaultTerminal,
+    address: &str,
+    cluster_name: &str,
+    interval: Duration,
+) -> anyhow::Result<()> {
+    let client = reqwest::Client::builder()
+        .timeout(interval.max(Duration::from_secs(5)))
+        .build()?;
+    let mut snapshot = Snapshot::default();
+    let mut next_refresh = Instant::now();
+    loop {
+        if Instant::now() >= next_refresh {
+            refresh(address, &client, &mut snapshot).await;
+            next_refresh = Instant::now() + interval;
+        };
+        terminal.draw(|frame| draw(frame, cluster_name, &snapshot))?;
+        let timeout = next_refresh
+            .saturating_duration_since(Instant::now())
+            .min(Duration::from_secs(1));
+        let key = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+            if event::poll(timeout)? {
+                if let Event::Key(key) = event::read()? {
+                    return Ok(Some(key));
+                };
+            };
+            Ok(None)
+        })
+        .await??;
+        if let Some(key) = key {
+            if key.kind == KeyEventKind::Press
+                && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+            {
+                return Ok(());
+            };
+        };
+    }
+}
@@ -0,0 +1,101 @@
+use std::{io::IsTerminal, time::Duration};
+
+use serde::Serialize;
+
+use crate::{logging, NotificationEvent, NotificationsConfig};
+
+/// The summary of desktop notifications.
+const DESKTOP_SUMMARY: &str = "Daft launcher";
+
+/// How long the webhook has to answer, so that an unresponsive one does not
+/// hold up the command which is done by then.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Serialize)]
+struct WebhookPayload<'a> {
+    text: &'a str,
+}
+
+/// Sends the given message to each of the configured channels (the webhook,
+/// the desktop and the terminal's title), if notifications are configured and
+/// subscribed to the given event.
+///
+/// The webhook's payload is of the form `{"text": "..."}`, which is understood
+/// by Slack incoming webhooks (as well as most other chat services' webhooks).
+///
+/// Failing to deliver a notification is only warned about; it never fails the
+/// command which triggered it.
+pub async fn notify(
+    notifications: Option<&NotificationsConfig>,
+    event: NotificationEvent,
+    message: &str,
+) {
+    let Some(notifications) = notifications else {
+        return;
+    };
+    if !notifications.events.contains(&event) {
+        return;
+    };
+
+    show_progress(Some(notifications), message);
+    if notifications.desktop {
+        if let Err(error) = notify_desktop(message).await {
+            logging::warn(format!("Failed to show a desktop notification: {error}"));
+        };
+    };
+    let Some(webhook_url) = &notifications.webhook_url else {
+        return;
+    };
+    let result = async {
+        reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()?
+            .post(&**webhook_url)
+            .json(&WebhookPayload { text: message })
+            .send()
+            .await?
+            .error_for_status()
+    }
+    .await;
+    if let Err(error) = result {
+        logging::warn(format!(
+            "Failed to send a notification to the configured webhook: {error}"
+        ));
+    };
+}
+
+async fn notify_desktop(message: &str) -> anyhow::Result<()> {
+    let message = message.to_string();
+    tokio::task::spawn_blocking(move || {
+        notify_rust::Notification::new()
+            .summary(DESKTOP_SUMMARY)
+            .body(&message)
+            .show()
+            .map(|_| ())
+    })
+    .await??;
+    Ok(())
+}
+
+/// Shows the given progress (e.g., the stage which `up` has reached) in the
+/// title of the terminal, if configured to.
+pub fn show_progress(notifications: Option<&NotificationsConfig>, progress: &str) {
+    if notifications.is_some_and(|notifications| notifications.terminal_title) {
+        set_terminal_title(progress);
+    };
+}
+
+/// Returns the escape sequence which sets the title of the terminal.
+pub fn terminal_title_sequence(title: &str) -> String {
+    // Control characters would end the sequence early.
+    let title = title.replace(char::is_control, " ");
+    format!("\x1b]0;{title}\x07")
+}
+
+fn set_terminal_title(title: &str) {
+    // The title is set through stderr, so that it does not end up in the
+    // (possibly redirected) results of the command.
+    if std::io::stderr().is_terminal() {
+        eprint!("{}", terminal_title_sequence(title));
+    };
+}
@@ -0,0 +1,124 @@
+use std::path::{Path, PathBuf};
+
+use aws_config::BehaviorVersion;
+use glob::Pattern;
+use tokio::fs;
+
+use crate::{logging, s3, StrRef};
+
+/// The directory on a Ray node into which uploaded working directories are
+/// extracted.
+const RAY_WORKING_DIR_FILES: &str = "/tmp/ray/session_latest/runtime_resources/working_dir_files";
+
+/// Returns whether the given path (relative to the job's working directory or
+/// outputs URI, and `/`-separated) matches any of the given output patterns.
+pub fn matches_any(relative_path: &str, patterns: &[StrRef]) -> bool {
+    patterns.iter().any(|pattern| {
+        Pattern::new(pattern.trim_start_matches("./"))
+            .is_ok_and(|pattern| pattern.matches(relative_path))
+    })
+}
+
+/// Fails if the given output pattern (or path of an output) is absolute or has
+/// `..` segments, since the files it names would then be fetched outside of the
+/// destination directory.
+pub fn check_relative(path: &str) -> anyhow::Result<()> {
+    if path.starts_with(['/', '\\']) || Path::new(path).is_absolute() {
+        anyhow::bail!("The output {path:?} must be relative to the job's working directory");
+    };
+    if path.split(['/', '\\']).any(|segment| segment == "..") {
+        anyhow::bail!("The output {path:?} must not contain `..`");
+    };
+    Ok(())
+}
+
+/// Returns the directory on the head node into which Ray extracted the working
+/// directory with the given URI.
+///
+/// Only working directories which were uploaded through the Ray dashboard (as
+/// opposed to via S3) are supported.
+pub fn head_node_working_dir(working_dir_uri: &str) -> anyhow::Result<String> {
+    let package = working_dir_uri
+        .strip_prefix("gcs://")
+        .and_then(|package| package.strip_suffix(".zip"))
+        .ok_or_else(|| {
+            anyhow::anyhow!("Outputs can only be fetched from the head node for jobs whose working directory was uploaded through the Ray dashboard (not {working_dir_uri}); set the job's `outputs-uri` instead")
+        })?;
+    Ok(format!("{RAY_WORKING_DIR_FILES}/{package}"))
+}
+
+/// Returns the path (relative to the outputs URI, and `/`-separated) of the
+/// object with the given key relative to that URI, as normalized by
+/// [`s3::normalize_key`], or `None` if the object is a "directory" (or nothing
+/// is left); keys with `..` segments, which would be downloaded outside of the
+/// destination directory, are rejected.
+pub fn normalize_key(relative_key: &str) -> anyhow::Result<Option<String>> {
+    // Keys which end with a `/` stand for directories, which hold no output.
+    if relative_key.ends_with('/') {
+        return Ok(None);
+    };
+    let path = s3::normalize_key(relative_key)?;
+    Ok(Some(path).filter(|path| !path.is_empty()))
+}
+
+/// Downloads the objects under the given `s3://` URI which match any of the
+/// given patterns into the given directory, returning the paths of the
+/// downloaded files (relative to that directory).
+pub async fn fetch_from_s3(
+    outputs_uri: &str,
+    patterns: &[StrRef],
+    dest: &Path,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let (bucket, prefix) = s3::parse_s3_uri(outputs_uri)?;
+    let prefix = if prefix.is_empty() {
+        String::new()
+    } else {
+        format!("{prefix}/")
+    };
+
+    let sdk_config = aws_config::defaults(BehaviorVersion::latest())
+        .region(aws_config::meta::region::RegionProviderChain::default_provider())
+        .load()
+        .await;
+    let client = aws_sdk_s3::Client::new(&sdk_config);
+
+    let mut keys = vec![];
+    let mut pages = client
+        .list_objects_v2()
+        .bucket(bucket)
+        .prefix(&prefix)
+        .into_paginator()
+        .send();
+    while let Some(page) = pages.next().await {
+        keys.extend(
+            page?
+                .contents
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|object| object.key),
+        );
+    }
+
+    let mut fetched = vec![];
+    for key in keys {
+        let relative_path = match normalize_key(&key[prefix.len()..]) {
+            Ok(Some(relative_path)) => relative_path,
+            Ok(None) => continue,
+            Err(error) => {
+                logging::warn(format!("Skipping the object {key:?}: {error}"));
+                continue;
+            }
+        };
+        if !matches_any(&relative_path, patterns) {
+            continue;
+        };
+        let local_path = dest.join(&relative_path);
+        if let Some(parent) = local_path.parent() {
+            fs::create_dir_all(parent).await?;
+        };
+        let object = client.get_object().bucket(bucket).key(&key).send().await?;
+        fs::write(&local_path, object.body.collect().await?.into_bytes()).await?;
+        fetched.push(PathBuf::from(relative_path));
+    }
+    Ok(fetched)
+}
@@ -0,0 +1,153 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_pricing::types::{Filter, FilterType};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{logging, state, StrRef};
+
+/// The file (in the state directory) in which prices are cached.
+const CACHE_FILE_NAME: &str = "prices.json";
+
+/// How long a cached price is used for before it is fetched again.
+const CACHE_TTL: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// The region whose endpoint serves the Pricing API (for every region).
+const PRICING_REGION: &str = "us-east-1";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Cache {
+    /// Keyed by `{region}/{instance_type}`.
+    prices: BTreeMap<String, CachedPrice>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedPrice {
+    usd_per_hour: f64,
+    /// In seconds since the Unix epoch.
+    fetched_at: u64,
+}
+
+/// Returns the on-demand price (in USD per hour) of the given product, as
+/// listed by the Pricing API's `GetProducts`.
+pub fn parse_price(product: &str) -> Option<f64> {
+    let product = serde_json::from_str::<serde_json::Value>(product).ok()?;
+    let term = product["terms"]["OnDemand"].as_object()?.values().next()?;
+    let dimension = term["priceDimensions"].as_object()?.values().next()?;
+    dimension["pricePerUnit"]["USD"].as_str()?.parse().ok()
+}
+
+async fn fetch_price(
+    client: &aws_sdk_pricing::Client,
+    region: &str,
+    instance_type: &str,
+) -> anyhow::Result<f64> {
+    let mut request = client.get_products().service_code("AmazonEC2");
+    for (field, value) in [
+        ("regionCode", region),
+        ("instanceType", instance_type),
+        ("operatingSystem", "Linux"),
+        ("tenancy", "Shared"),
+        ("preInstalledSw", "NA"),
+        ("capacitystatus", "Used"),
+    ] {
+        request = request.filters(
+            Filter::builder()
+                .r#type(FilterType::TermMatch)
+                .field(field)
+                .value(value)
+                .build()?,
+        );
+    }
+    let response = request.send().await?;
+    response
+        .price_list()
+        .iter()
+        .find_map(|product| parse_price(product))
+        .ok_or_else(|| {
+            anyhow::anyhow!("No on-demand price is listed for {instance_type} in {region}")
+        })
+}
+
+async fn read_cache() -> Cache {
+    let Ok(path) = state::state_dir().map(|dir| dir.join(CACHE_FILE_NAME)) else {
+        return Cache::default();
+    };
+    fs::read_to_string(path)
+        .await
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+async fn write_cache(cache: &Cache) -> anyhow::Result<()> {
+    let dir = state::state_dir()?;
+    fs::create_dir_all(&dir).await?;
+    fs::write(dir.join(CACHE_FILE_NAME), serde_json::to_string(cache)?).await?;
+    Ok(())
+}
+
+/// Returns the on-demand prices (in USD per hour) of the given instance types
+/// in the given region, from the local cache or else from the Pricing API.
+///
+/// Prices which cannot be fetched (e.g., without permission to use the Pricing
+/// API) are left out with a warning; they never fail the command.
+pub async fn hourly_prices(
+    region: &str,
+    instance_types: &BTreeSet<StrRef>,
+) -> HashMap<StrRef, f64> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut cache = read_cache().await;
+    let mut prices = HashMap::new();
+    let mut client = None;
+    for instance_type in instance_types {
+        let key = format!("{region}/{instance_type}");
+        if let Some(cached) = cache.prices.get(&key) {
+            if now.saturating_sub(cached.fetched_at) < CACHE_TTL.as_secs() {
+                prices.insert(instance_type.clone(), cached.usd_per_hour);
+                continue;
+            };
+        };
+        if client.is_none() {
+            let sdk_config = aws_config::defaults(BehaviorVersion::latest())
+                .region(Region::new(PRICING_REGION))
+                .load()
+                .await;
+            client = Some(aws_sdk_pricing::Client::new(&sdk_config));
+        };
+        let client = client.as_ref().expect("the client was just created");
+        match fetch_price(client, region, instance_type).await {
+            Ok(usd_per_hour) => {
+                cache.prices.insert(
+                    key,
+                    CachedPrice {
+                        usd_per_hour,
+                        fetched_at: now,
+                    },
+                );
+                prices.insert(instance_type.clone(), usd_per_hour);
+            }
+            Err(error) => logging::warn(format!(
+                "Failed to look up the price of {instance_type} in {region}: {error}"
+            )),
+        };
+    }
+    if client.is_some() {
+        if let Err(error) = write_cache(&cache).await {
+            logging::debug(format!("Failed to cache the prices: {error}"));
+        };
+    };
+    prices
+}
+
+/// Formats the given price in USD per hour.
+pub fn format_price(usd_per_hour: f64) -> String {
+    format!("${usd_per_hour:.3}")
+}
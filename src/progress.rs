@@ -0,0 +1,117 @@
+use std::{sync::Mutex, time::Instant};
+
+use crate::{format_duration, logging, notifications, DaftConfig, NotificationsConfig, StrRef};
+
+/// The stages which `ray up` goes through, in order, as recognised from its
+/// output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Provisioning,
+    WaitingForSsh,
+    SyncingFiles,
+    RunningSetupCommands,
+    StartingRay,
+}
+
+impl Stage {
+    const ALL: [Self; 5] = [
+        Self::Provisioning,
+        Self::WaitingForSsh,
+        Self::SyncingFiles,
+        Self::RunningSetupCommands,
+        Self::StartingRay,
+    ];
+
+    fn description(self) -> &'static str {
+        match self {
+            Self::Provisioning => "Provisioning the head node",
+            Self::WaitingForSsh => "Waiting for SSH to become available",
+            Self::SyncingFiles => "Syncing the file mounts",
+            Self::RunningSetupCommands => "Running the setup commands",
+            Self::StartingRay => "Starting Ray",
+        }
+    }
+
+    /// Returns the stage which the given line of `ray up`'s output starts, if
+    /// any.
+    pub fn of(line: &str) -> Option<Self> {
+        const MARKERS: [(&str, Stage); 7] = [
+            ("Acquiring an up-to-date head node", Stage::Provisioning),
+            ("Launched instance", Stage::Provisioning),
+            ("Waiting for SSH to become available", Stage::WaitingForSsh),
+            ("Processing file mounts", Stage::SyncingFiles),
+            ("initialization commands", Stage::RunningSetupCommands),
+            ("setup commands", Stage::RunningSetupCommands),
+            ("Starting the Ray runtime", Stage::StartingRay),
+        ];
+        MARKERS
+            .iter()
+            .find(|(marker, _)| line.contains(marker))
+            .map(|(_, stage)| *stage)
+    }
+}
+
+/// Tracks the stage which `ray up` has reached, reporting each stage as it
+/// starts (stages which are skipped, e.g. provisioning when the head node is
+/// already up, are not reported).
+pub struct Progress {
+    start: Instant,
+    stage: Mutex<Option<Stage>>,
+    /// The name of the cluster, and the notification settings which say
+    /// whether to also show its stage in the title of the terminal.
+    cluster: Option<(StrRef, NotificationsConfig)>,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            stage: Mutex::new(None),
+            cluster: None,
+        }
+    }
+}
+
+impl Progress {
+    /// Tracks the progress of spinning up the cluster of the given
+    /// configuration.
+    pub fn new(daft_config: &DaftConfig) -> Self {
+        Self {
+            cluster: daft_config
+                .notifications
+                .clone()
+                .map(|notifications| (daft_config.setup.name.clone(), notifications)),
+            ..Self::default()
+        }
+    }
+    /// Advances to the stage which the given line starts, returning it if it
+    /// comes after the stage reached so far.
+    pub fn advance(&self, line: &str) -> Option<Stage> {
+        let stage = Stage::of(line)?;
+        let mut reached = self.stage.lock().unwrap_or_else(|error| error.into_inner());
+        if reached.is_some_and(|reached| reached >= stage) {
+            return None;
+        };
+        *reached = Some(stage);
+        Some(stage)
+    }
+
+    /// Reports the stage which the given line starts, if it is a new one.
+    pub fn observe(&self, line: &str) {
+        if let Some(stage) = self.advance(line) {
+            let index = Stage::ALL
+                .iter()
+                .position(|other| *other == stage)
+                .expect("every stage is listed")
+                + 1;
+            let step = format!("[{index}/{}] {}", Stage::ALL.len(), stage.description());
+            logging::info(format!(
+                "{step}... ({} elapsed)",
+                format_duration(self.start.elapsed())
+            ));
+            if let Some((cluster, notifications)) = &self.cluster {
+                notifications::show_progress(Some(notifications), &format!("{cluster}: {step}"));
+            };
+        };
+    }
+}
@@ -0,0 +1,257 @@
+use serde_yaml::{Mapping, Value};
+use toml_edit::{value, Array, DocumentMut, Table};
+
+use crate::edit;
+
+/// The top-level keys of a Ray autoscaler configuration which are imported.
+const IMPORTED_KEYS: &[&str] = &[
+    "cluster_name",
+    "max_workers",
+    "provider",
+    "auth",
+    "available_node_types",
+    "head_node_type",
+    "setup_commands",
+    "file_mounts",
+    "rsync_exclude",
+];
+
+/// The keys of a Ray node type which are imported.
+const IMPORTED_NODE_TYPE_KEYS: &[&str] =
+    &["node_config", "max_workers", "min_workers", "resources"];
+
+/// The keys of a Ray node config which are imported.
+const IMPORTED_NODE_CONFIG_KEYS: &[&str] =
+    &["InstanceType", "ImageId", "IamInstanceProfile", "KeyName"];
+
+fn get<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    value.as_mapping()?.get(key)
+}
+
+fn get_str<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    get(value, key)?.as_str()
+}
+
+/// Adds a warning for each key of the given mapping which is not imported.
+fn warn_unsupported(
+    mapping: &Mapping,
+    imported: &[&str],
+    within: &str,
+    warnings: &mut Vec<String>,
+) {
+    for key in mapping.keys() {
+        let key = key.as_str().unwrap_or_default();
+        if !imported.contains(&key) {
+            warnings.push(format!("Skipped `{within}{key}`, which has no equivalent"));
+        };
+    }
+}
+
+/// Returns the names of the head and worker node types of the given Ray
+/// configuration.
+fn node_types<'a>(
+    ray_config: &'a Value,
+    available_node_types: &'a Mapping,
+    warnings: &mut Vec<String>,
+) -> (Option<&'a str>, Option<&'a str>) {
+    let names = available_node_types
+        .keys()
+        .filter_map(Value::as_str)
+        .collect::<Vec<_>>();
+    let head = get_str(ray_config, "head_node_type")
+        .or_else(|| names.iter().copied().find(|name| name.contains("head")));
+    let workers = names
+        .iter()
+        .copied()
+        .filter(|name| Some(*name) != head)
+        .collect::<Vec<_>>();
+    if workers.len() > 1 {
+        warnings.push(format!(
+            "Imported only the worker node type `{}`, since the launcher's workers are all of one type; skipped: {}",
+            workers[0],
+            workers[1..].join(", ")
+        ));
+    };
+    (head, workers.first().copied())
+}
+
+/// Converts the given Ray autoscaler configuration (in YAML) into a
+/// provisioned configuration file, by filling in the given provisioned
+/// template.
+///
+/// The conversion is best-effort: returns the configuration file, along with
+/// warnings about anything which could not be imported as-is.
+pub fn import(ray_yaml: &str, template: &str) -> anyhow::Result<(String, Vec<String>)> {
+    let ray_config = serde_yaml::from_str::<Value>(ray_yaml)?;
+    let mapping = ray_config
+        .as_mapping()
+        .ok_or_else(|| anyhow::anyhow!("The Ray configuration must be a mapping"))?;
+    let mut warnings = vec![];
+    warn_unsupported(mapping, IMPORTED_KEYS, "", &mut warnings);
+
+    let provider = get(&ray_config, "provider")
+        .ok_or_else(|| anyhow::anyhow!("The Ray configuration has no `provider`"))?;
+    match get_str(provider, "type") {
+        Some("aws") => (),
+        other => anyhow::bail!(
+            "Only Ray configurations for the `aws` provider can be imported, not {}",
+            other.unwrap_or("(none)")
+        ),
+    };
+    if let Some(provider) = provider.as_mapping() {
+        warn_unsupported(
+            provider,
+            &["type", "region", "cache_stopped_nodes"],
+            "provider.",
+            &mut warnings,
+        );
+    };
+
+    let mut contents = template.to_string();
+    let mut set = |key: &str, value: &str| -> anyhow::Result<()> {
+        contents = edit::set(&contents, key, value)?;
+        Ok(())
+    };
+    if let Some(name) = get_str(&ray_config, "cluster_name") {
+        set("setup.name", name)?;
+    };
+    if let Some(region) = get_str(provider, "region") {
+        set("setup.provisioned.region", region)?;
+    };
+    if let Some(auth) = get(&ray_config, "auth") {
+        if let Some(ssh_user) = get_str(auth, "ssh_user") {
+            set("setup.provisioned.ssh-user", ssh_user)?;
+        };
+        if let Some(ssh_private_key) = get_str(auth, "ssh_private_key") {
+            set("setup.provisioned.ssh-private-key", ssh_private_key)?;
+        };
+    };
+
+    let mut number_of_workers = get(&ray_config, "max_workers").and_then(Value::as_u64);
+    let mut iam_instance_profile_name = None;
+    let empty = Mapping::new();
+    let available_node_types = get(&ray_config, "available_node_types")
+        .and_then(Value::as_mapping)
+        .unwrap_or(&empty);
+    let (head, worker) = node_types(&ray_config, available_node_types, &mut warnings);
+    let mut instance_types = vec![];
+    // The worker's node config is imported last, so that its values win.
+    for name in [head, worker].into_iter().flatten() {
+        let Some(node_type) = available_node_types.get(name) else {
+            continue;
+        };
+        if let Some(node_type) = node_type.as_mapping() {
+            warn_unsupported(
+                node_type,
+                IMPORTED_NODE_TYPE_KEYS,
+                &format!("available_node_types.{name}."),
+                &mut warnings,
+            );
+        };
+        if Some(name) == worker {
+            if let Some(max_workers) = get(node_type, "max_workers").and_then(Value::as_u64) {
+                number_of_workers = Some(max_workers);
+            };
+        };
+        let Some(node_config) = get(node_type, "node_config") else {
+            continue;
+        };
+        if let Some(node_config) = node_config.as_mapping() {
+            warn_unsupported(
+                node_config,
+                IMPORTED_NODE_CONFIG_KEYS,
+                &format!("available_node_types.{name}.node_config."),
+                &mut warnings,
+            );
+        };
+        if let Some(instance_type) = get_str(node_config, "InstanceType") {
+            instance_types.push(instance_type);
+            set("setup.provisioned.instance-type", instance_type)?;
+        };
+        if let Some(image_id) = get_str(node_config, "ImageId") {
+            set("setup.provisioned.image-id", image_id)?;
+        };
+        if let Some(profile) = get(node_config, "IamInstanceProfile") {
+            match get_str(profile, "Name") {
+                Some(profile_name) => iam_instance_profile_name = Some(profile_name),
+                None => warnings.push(format!(
+                    "Skipped the IAM instance profile of `{name}`, since only profiles given by `Name` can be imported"
+                )),
+            };
+        };
+        if get(node_config, "KeyName").is_some() {
+            warnings.push(format!(
+                "Skipped the `KeyName` of `{name}`; the key pair is named after the SSH private key file instead"
+            ));
+        };
+    }
+    instance_types.dedup();
+    if instance_types.len() > 1 {
+        warnings.push(format!(
+            "All nodes will use the instance type {}, since the head and workers share one instance type",
+            instance_types.last().expect("there are several instance types")
+        ));
+    };
+    if let Some(number_of_workers) = number_of_workers {
+        set(
+            "setup.provisioned.number-of-workers",
+            &number_of_workers.to_string(),
+        )?;
+    };
+    if let Some(iam_instance_profile_name) = iam_instance_profile_name {
+        set(
+            "setup.provisioned.iam-instance-profile-name",
+            iam_instance_profile_name,
+        )?;
+    };
+
+    let setup_commands = get(&ray_config, "setup_commands")
+        .and_then(Value::as_sequence)
+        .map(|commands| {
+            commands
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if !setup_commands.is_empty() {
+        let mut run = setup_commands.into_iter().collect::<Array>();
+        run.fmt();
+        set("setup.provisioned.run", &run.to_string())?;
+        warnings.push("Imported the `setup_commands` as `run`; the launcher installs Python and Ray itself, so review them for commands which are no longer needed".to_string());
+    };
+
+    // The template's example job and placeholder IAM instance profile do not
+    // come from the Ray configuration.
+    let mut document = contents.parse::<DocumentMut>()?;
+    document.remove("job");
+
+    // The remote paths of the file mounts contain dots, so they cannot be
+    // set by dotted path.
+    if let Some(ray_file_mounts) = get(&ray_config, "file_mounts").and_then(Value::as_mapping) {
+        let mut file_mounts = Table::new();
+        for (remote_path, local_path) in ray_file_mounts {
+            if let (Some(remote_path), Some(local_path)) =
+                (remote_path.as_str(), local_path.as_str())
+            {
+                file_mounts.insert(remote_path, value(local_path));
+            };
+        }
+        if !file_mounts.is_empty() {
+            document["setup"]["file-mounts"] = toml_edit::Item::Table(file_mounts);
+        };
+    };
+    let rsync_exclude = get(&ray_config, "rsync_exclude")
+        .and_then(Value::as_sequence)
+        .map(|patterns| patterns.iter().filter_map(Value::as_str).collect::<Array>())
+        .unwrap_or_default();
+    if !rsync_exclude.is_empty() {
+        document["setup"]["file-mounts-exclude"] = value(rsync_exclude);
+    };
+    if iam_instance_profile_name.is_none() {
+        if let Some(provisioned) = document["setup"]["provisioned"].as_table_mut() {
+            provisioned.remove("iam-instance-profile-name");
+        };
+    };
+    Ok((document.to_string(), warnings))
+}
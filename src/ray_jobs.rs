@@ -0,0 +1,287 @@
+use std::{
+    collections::BTreeMap,
+    io::{Cursor, Write},
+    path::Path,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    s3, shell_quote, working_dir, EntrypointResources, JobSubmission, RayJob, RayRuntimeEnv,
+    StrRef, DAFT_JOB_NAME_METADATA_KEY,
+};
+
+/// How long to wait for the dashboard to accept a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for the dashboard to send any more of a response (so that
+/// a dead tunnel fails a request, rather than hanging it); large uploads are
+/// not cut short, since this is not a limit on the whole request.
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A minimal client for the Ray Jobs REST API, which is served by the Ray
+/// dashboard.
+///
+/// Using the REST API directly means that users do not need a locally installed
+/// (and version-matched) Ray in order to submit and manage jobs.
+pub struct RayJobsClient {
+    address: StrRef,
+    client: reqwest::Client,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobSubmitRequest<'a> {
+    entrypoint: String,
+    runtime_env: &'a RayRuntimeEnv,
+    metadata: BTreeMap<&'a str, &'a str>,
+    #[serde(flatten)]
+    resources: &'a EntrypointResources,
+}
+
+#[derive(Debug, Deserialize)]
+struct JobSubmitResponse {
+    submission_id: StrRef,
+}
+
+#[derive(Debug, Deserialize)]
+struct JobStopResponse {
+    stopped: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct JobLogsResponse {
+    logs: String,
+}
+
+impl RayJobsClient {
+    pub fn new(address: impl Into<StrRef>) -> Self {
+        Self {
+            address: address.into(),
+            // As `reqwest::Client::new` does, this only fails if the TLS
+            // backend cannot be initialized.
+            client: reqwest::Client::builder()
+                .connect_timeout(CONNECT_TIMEOUT)
+                .read_timeout(READ_TIMEOUT)
+                .build()
+                .expect("The HTTP client should build"),
+        }
+    }
+
+    /// Packages the given working directory and uploads it to the cluster (or
+    /// to the given `s3://` URI, if one is given), returning the URI under which
+    /// the cluster can find it.
+    ///
+    /// Packages are content-addressed, so re-submitting an unchanged working
+    /// directory skips the upload.
+    pub async fn upload_working_dir(
+        &self,
+        working_dir: &Path,
+        excludes: &[StrRef],
+        upload_to: Option<&str>,
+    ) -> anyhow::Result<StrRef> {
+        let working_dir = working_dir.to_path_buf();
+        let excludes = excludes.to_vec();
+        let package =
+            tokio::task::spawn_blocking(move || zip_directory(&working_dir, &excludes)).await??;
+        let hash = Sha256::digest(&package)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        let package_name = format!("_ray_pkg_{hash}.zip");
+        if let Some(upload_to) = upload_to {
+            return s3::upload_package(upload_to, &package_name, package).await;
+        };
+
+        let url = format!("{}/api/packages/gcs/{package_name}", self.address);
+
+        let exists = self.client.get(&url).send().await?.status().is_success();
+        if !exists {
+            self.client
+                .put(&url)
+                .body(package)
+                .send()
+                .await?
+                .error_for_status()?;
+        };
+
+        Ok(format!("gcs://{package_name}").into())
+    }
+
+    /// Uploads the working directory and submits the job without waiting for it
+    /// to finish, returning the submission ID of the new job.
+    pub async fn submit(&self, submission: &JobSubmission<'_>) -> anyhow::Result<StrRef> {
+        let working_dir = self
+            .upload_working_dir(
+                submission.working_dir,
+                submission.excludes,
+                submission.upload_to,
+            )
+            .await?;
+        let runtime_env = RayRuntimeEnv {
+            working_dir: Some(working_dir),
+            ..submission.runtime_env.clone()
+        };
+        let request = submit_request(submission, &runtime_env);
+        let response = self
+            .client
+            .post(format!("{}/api/jobs/", self.address))
+            .json(&request)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            anyhow::bail!(
+                "Failed to submit job to the ray cluster: {}",
+                response.text().await?.trim()
+            );
+        };
+        let response = response.json::<JobSubmitResponse>().await?;
+        Ok(response.submission_id)
+    }
+
+    /// Fetches the given path (e.g., `/nodes?view=summary`) from the rest of
+    /// the dashboard's API, returning the body of the response.
+    pub async fn get_text(&self, path: &str) -> anyhow::Result<String> {
+        let text = self
+            .client
+            .get(format!("{}{path}", self.address))
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        Ok(text)
+    }
+
+    pub async fn list(&self) -> anyhow::Result<Vec<RayJob>> {
+        let jobs = self
+            .client
+            .get(format!("{}/api/jobs/", self.address))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(jobs)
+    }
+
+    pub async fn get(&self, id: &str) -> anyhow::Result<RayJob> {
+        let response = self
+            .client
+            .get(format!("{}/api/jobs/{id}", self.address))
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            anyhow::bail!("A job with the ID {id} was not found on the ray cluster");
+        };
+        let job = response.error_for_status()?.json().await?;
+        Ok(job)
+    }
+
+    pub async fn stop(&self, id: &str) -> anyhow::Result<()> {
+        let response = self
+            .client
+            .post(format!("{}/api/jobs/{id}/stop", self.address))
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            anyhow::bail!("A job with the ID {id} was not found on the ray cluster");
+        };
+        let response = response
+            .error_for_status()?
+            .json::<JobStopResponse>()
+            .await?;
+        if response.stopped {
+            println!("Stopped job {id}");
+        } else {
+            println!("Job {id} has already finished");
+        };
+        Ok(())
+    }
+
+    pub async fn logs(&self, id: &str) -> anyhow::Result<String> {
+        let response = self
+            .client
+            .get(format!("{}/api/jobs/{id}/logs", self.address))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<JobLogsResponse>()
+            .await?;
+        Ok(response.logs)
+    }
+
+    /// Prints the logs of the given job as they are produced, returning the
+    /// final state of the job once it has finished.
+    pub async fn follow(&self, id: &str) -> anyhow::Result<RayJob> {
+        let mut printed = 0;
+        loop {
+            let job = self.get(id).await?;
+            let logs = self.logs(id).await?;
+            if let Some(new_logs) = logs.get(printed..) {
+                print!("{new_logs}");
+                std::io::stdout().flush()?;
+                printed = logs.len();
+            };
+            if !job.status.is_active() {
+                break Ok(job);
+            };
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+/// Builds the request for the given submission, with the given runtime
+/// environment (which may differ from the submission's own once its working
+/// directory has been uploaded).
+pub fn submit_request<'a>(
+    submission: &JobSubmission<'a>,
+    runtime_env: &'a RayRuntimeEnv,
+) -> JobSubmitRequest<'a> {
+    JobSubmitRequest {
+        entrypoint: entrypoint(submission.command_segments),
+        runtime_env,
+        metadata: [(DAFT_JOB_NAME_METADATA_KEY, submission.job_name)]
+            .into_iter()
+            .collect(),
+        resources: submission.resources,
+    }
+}
+
+/// Joins the given command segments into a single entrypoint string, quoting
+/// the segments which would otherwise be split apart by the shell.
+pub fn entrypoint(command_segments: &[&str]) -> String {
+    command_segments
+        .iter()
+        .map(|segment| shell_quote(segment))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Zips the contents of the given directory (without the directory itself and
+/// without any excluded paths) in a deterministic order, so that identical
+/// directories produce identical packages.
+fn zip_directory(dir: &Path, excludes: &[StrRef]) -> anyhow::Result<Vec<u8>> {
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for entry in working_dir::walk(dir, excludes)? {
+        let name = entry
+            .relative_path
+            .iter()
+            .map(|segment| segment.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if entry.is_dir {
+            writer.add_directory(name, options)?;
+        } else {
+            writer.start_file(name, options)?;
+            std::io::copy(
+                &mut std::fs::File::open(dir.join(&entry.relative_path))?,
+                &mut writer,
+            )?;
+        };
+    }
+    Ok(writer.finish()?.into_inner())
+}
@@ -0,0 +1,95 @@
+use aws_config::BehaviorVersion;
+use aws_sdk_s3::primitives::ByteStream;
+
+use crate::StrRef;
+
+/// Returns the given key (or prefix) without any empty or `.` segments, so
+/// that e.g. `a//./b/` and `a/b` name the same objects.
+///
+/// Keys with `..` segments are rejected: S3 does not resolve them, so they
+/// would name objects which nothing else (e.g., a local path) could.
+pub fn normalize_key(key: &str) -> anyhow::Result<String> {
+    let segments = key
+        .split('/')
+        .filter(|segment| !segment.is_empty() && *segment != ".")
+        .collect::<Vec<_>>();
+    if segments.contains(&"..") {
+        anyhow::bail!("The S3 key {key:?} must not contain `..`");
+    };
+    Ok(segments.join("/"))
+}
+
+/// Splits an `s3://bucket/prefix` URI into its bucket and (possibly empty,
+/// and normalized) prefix.
+pub fn parse_s3_uri(uri: &str) -> anyhow::Result<(&str, String)> {
+    let path = uri
+        .strip_prefix("s3://")
+        .ok_or_else(|| anyhow::anyhow!("The URI {uri:?} must start with `s3://`"))?;
+    let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+    if bucket.is_empty() {
+        anyhow::bail!("The URI {uri:?} must name a bucket");
+    };
+    Ok((bucket, normalize_key(prefix)?))
+}
+
+/// Uploads the given working directory package under the given `s3://` URI,
+/// returning the URI of the uploaded package.
+///
+/// Packages are content-addressed by their names, so an identical package
+/// which was uploaded previously is reused instead of being uploaded again.
+pub async fn upload_package(
+    upload_to: &str,
+    package_name: &str,
+    package: Vec<u8>,
+) -> anyhow::Result<StrRef> {
+    let (bucket, prefix) = parse_s3_uri(upload_to)?;
+    let key = if prefix.is_empty() {
+        package_name.to_string()
+    } else {
+        format!("{prefix}/{package_name}")
+    };
+
+    let sdk_config = aws_config::defaults(BehaviorVersion::latest())
+        .region(aws_config::meta::region::RegionProviderChain::default_provider())
+        .load()
+        .await;
+    let client = aws_sdk_s3::Client::new(&sdk_config);
+    let exists = client
+        .head_object()
+        .bucket(bucket)
+        .key(&key)
+        .send()
+        .await
+        .is_ok();
+    if !exists {
+        client
+            .put_object()
+            .bucket(bucket)
+            .key(&key)
+            .body(ByteStream::from(package))
+            .send()
+            .await?;
+    };
+
+    Ok(format!("s3://{bucket}/{key}").into())
+}
+
+/// Downloads the object at the given `s3://bucket/key` URI.
+pub async fn download(uri: &str) -> anyhow::Result<Vec<u8>> {
+    let (bucket, key) = parse_s3_uri(uri)?;
+    if key.is_empty() {
+        anyhow::bail!("The URI {uri:?} must name an object");
+    };
+
+    let sdk_config = aws_config::defaults(BehaviorVersion::latest())
+        .region(aws_config::meta::region::RegionProviderChain::default_provider())
+        .load()
+        .await;
+    let object = aws_sdk_s3::Client::new(&sdk_config)
+        .get_object()
+        .bucket(bucket)
+        .key(&key)
+        .send()
+        .await?;
+    Ok(object.body.collect().await?.into_bytes().to_vec())
+}
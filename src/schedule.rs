@@ -0,0 +1,161 @@
+use std::{path::Path, process::Stdio};
+
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::shell_quote;
+
+const MARKER_PREFIX: &str = "# daft-launcher:";
+
+/// The cron shorthands which are accepted in place of the five time-and-date
+/// fields.
+const CRON_SHORTHANDS: &[&str] = &[
+    "@yearly",
+    "@annually",
+    "@monthly",
+    "@weekly",
+    "@daily",
+    "@midnight",
+    "@hourly",
+];
+
+pub fn validate_cron_expression(cron: &str) -> anyhow::Result<()> {
+    let cron = cron.trim();
+    if CRON_SHORTHANDS.contains(&cron) {
+        return Ok(());
+    };
+
+    let fields = cron.split_whitespace().collect::<Vec<_>>();
+    if fields.len() != 5 {
+        anyhow::bail!("The cron expression {cron:?} must consist of exactly 5 fields (minute, hour, day of month, month, day of week)");
+    };
+    if let Some(field) = fields.iter().find(|field| {
+        !field
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '*' | ',' | '-' | '/'))
+    }) {
+        anyhow::bail!("The cron expression {cron:?} contains an invalid field {field:?}");
+    };
+    Ok(())
+}
+
+/// Escapes the `%` characters in the given command, which cron would otherwise
+/// turn into newlines.
+fn escape_percent_signs(command: &str) -> String {
+    command.replace('%', "\\%")
+}
+
+/// The comment which uniquely identifies the crontab entry for a given job in
+/// a given configuration file.
+pub fn marker(config: &Path, job_name: &str) -> String {
+    escape_percent_signs(&format!("{MARKER_PREFIX}{}:{job_name}", config.display()))
+}
+
+/// Returns the crontab entry which submits the given job on the given
+/// schedule, with the given profile and region (if any), just as the job
+/// would be submitted from the command line.
+pub fn generate_crontab_entry(
+    cron: &str,
+    exe: &Path,
+    config: &Path,
+    job_name: &str,
+    profile: Option<&str>,
+    region: Option<&str>,
+) -> String {
+    let mut command = vec![exe.to_string_lossy().into_owned()];
+    if let Some(profile) = profile {
+        command.extend(["--profile".to_string(), profile.to_string()]);
+    };
+    if let Some(region) = region {
+        command.extend(["--region".to_string(), region.to_string()]);
+    };
+    command.extend([
+        "job".to_string(),
+        "submit".to_string(),
+        job_name.to_string(),
+        config.to_string_lossy().into_owned(),
+    ]);
+    let command = command
+        .iter()
+        .map(|segment| shell_quote(segment))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "{} {} {}",
+        cron.trim(),
+        escape_percent_signs(&command),
+        marker(config, job_name),
+    )
+}
+
+/// Removes any existing entry with the given marker from the crontab, and
+/// appends the new entry (if one is given).
+pub fn update_crontab(existing: &str, marker: &str, entry: Option<&str>) -> String {
+    let mut lines = existing
+        .lines()
+        .filter(|line| !line.ends_with(marker))
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    if let Some(entry) = entry {
+        lines.push(entry.to_string());
+    };
+    let mut crontab = lines.join("\n");
+    crontab.push('\n');
+    crontab
+}
+
+async fn read_crontab() -> anyhow::Result<String> {
+    let output = Command::new("crontab")
+        .arg("-l")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?
+        .wait_with_output()
+        .await?;
+
+    // `crontab -l` exits with a non-zero status if the user has no crontab yet.
+    if output.status.success() {
+        Ok(String::from_utf8(output.stdout)?)
+    } else {
+        Ok(String::new())
+    }
+}
+
+async fn write_crontab(contents: &str) -> anyhow::Result<()> {
+    let mut child = Command::new("crontab")
+        .arg("-")
+        .stdin(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().expect("stdin must exist");
+    stdin.write_all(contents.as_bytes()).await?;
+    drop(stdin);
+
+    if child.wait().await?.success() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Failed to install the updated crontab"))
+    }
+}
+
+pub async fn schedule(
+    cron: &str,
+    config: &Path,
+    job_name: &str,
+    profile: Option<&str>,
+    region: Option<&str>,
+) -> anyhow::Result<()> {
+    validate_cron_expression(cron)?;
+    let exe = std::env::current_exe()?;
+    let entry = generate_crontab_entry(cron, &exe, config, job_name, profile, region);
+    let existing = read_crontab().await?;
+    write_crontab(&update_crontab(
+        &existing,
+        &marker(config, job_name),
+        Some(&entry),
+    ))
+    .await
+}
+
+pub async fn unschedule(config: &Path, job_name: &str) -> anyhow::Result<()> {
+    let existing = read_crontab().await?;
+    write_crontab(&update_crontab(&existing, &marker(config, job_name), None)).await
+}
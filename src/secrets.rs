@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+
+use anyhow::Context;
+use aws_config::{BehaviorVersion, Region};
+use toml::{Table, Value};
+
+use crate::exit_code;
+
+/// The key of the inline table with which a configuration value refers to a
+/// secret, e.g. `ssh-user = { secret = "ssm:/team/daft/ssh-user" }`.
+const SECRET_KEY: &str = "secret";
+
+/// Where the value of a secret is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretReference<'a> {
+    /// The name of an (optionally encrypted) AWS SSM parameter.
+    Ssm(&'a str),
+    /// The name or ARN of an AWS Secrets Manager secret.
+    SecretsManager(&'a str),
+}
+
+impl<'a> SecretReference<'a> {
+    pub fn parse(reference: &'a str) -> anyhow::Result<Self> {
+        let reference = match reference.split_once(':') {
+            Some(("ssm", name)) if !name.is_empty() => Self::Ssm(name),
+            Some(("secretsmanager", id)) if !id.is_empty() => Self::SecretsManager(id),
+            _ => anyhow::bail!("The secret {reference:?} must be of the form `ssm:<parameter-name>` or `secretsmanager:<secret-id>`"),
+        };
+        Ok(reference)
+    }
+}
+
+/// Returns the secret which the given value refers to, if it is a reference
+/// to one.
+fn as_reference(value: &Value) -> Option<&str> {
+    match value {
+        Value::Table(table) if table.len() == 1 => table.get(SECRET_KEY)?.as_str(),
+        _ => None,
+    }
+}
+
+fn collect_references<'a>(value: &'a Value, references: &mut Vec<&'a str>) {
+    if let Some(reference) = as_reference(value) {
+        references.push(reference);
+        return;
+    };
+    match value {
+        Value::Table(table) => table
+            .values()
+            .for_each(|value| collect_references(value, references)),
+        Value::Array(array) => array
+            .iter()
+            .for_each(|value| collect_references(value, references)),
+        _ => (),
+    }
+}
+
+/// Returns the secrets which the given configuration refers to.
+pub fn references(table: &Table) -> Vec<&str> {
+    let mut references = vec![];
+    table
+        .values()
+        .for_each(|value| collect_references(value, &mut references));
+    references
+}
+
+/// Replaces every reference to a secret in the given value with the secret's
+/// resolved value.
+fn substitute(value: &mut Value, resolved: &BTreeMap<String, String>) {
+    if let Some(reference) = as_reference(value) {
+        *value = Value::String(resolved[reference].clone());
+        return;
+    };
+    match value {
+        Value::Table(table) => table
+            .iter_mut()
+            .for_each(|(_, value)| substitute(value, resolved)),
+        Value::Array(array) => array
+            .iter_mut()
+            .for_each(|value| substitute(value, resolved)),
+        _ => (),
+    }
+}
+
+/// Replaces every reference to a secret in the given configuration with the
+/// given resolved values (keyed by reference).
+pub fn substitute_all(table: &mut Table, resolved: &BTreeMap<String, String>) {
+    table
+        .iter_mut()
+        .for_each(|(_, value)| substitute(value, resolved));
+}
+
+/// What reading a configuration does with the references to secrets in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Secrets {
+    /// Replace them with the secrets' values, fetched from AWS.
+    Resolve,
+    /// Replace them with a placeholder naming the secret, for commands which
+    /// only show the configuration (e.g., `config show`), so that its secrets
+    /// are not printed in plaintext.
+    Redact,
+}
+
+/// Replaces every reference to a secret in the given configuration with a
+/// placeholder naming the secret (e.g., `<secret ssm:/team/daft/ssh-user>`).
+pub fn redact_all(table: &mut Table) {
+    let redacted = references(table)
+        .into_iter()
+        .map(|reference| (reference.to_string(), format!("<secret {reference}>")))
+        .collect();
+    substitute_all(table, &redacted);
+}
+
+/// Fetches the value of the given secret from AWS, in the given region (or
+/// the default one).
+async fn fetch(reference: &str, region: Option<&str>) -> anyhow::Result<String> {
+    let mut loader = aws_config::defaults(BehaviorVersion::latest());
+    if let Some(region) = region {
+        loader = loader.region(Region::new(region.to_string()));
+    };
+    let sdk_config = loader.load().await;
+    let value = match SecretReference::parse(reference)? {
+        SecretReference::Ssm(name) => exit_code::classify_aws(
+            aws_sdk_ssm::Client::new(&sdk_config)
+                .get_parameter()
+                .name(name)
+                .with_decryption(true)
+                .send()
+                .await,
+        )?
+        .parameter
+        .and_then(|parameter| parameter.value),
+        SecretReference::SecretsManager(id) => {
+            exit_code::classify_aws(
+                aws_sdk_secretsmanager::Client::new(&sdk_config)
+                    .get_secret_value()
+                    .secret_id(id)
+                    .send()
+                    .await,
+            )?
+            .secret_string
+        }
+    };
+    value.ok_or_else(|| anyhow::anyhow!("The secret {reference:?} has no (string) value"))
+}
+
+/// Resolves every reference to a secret in the given configuration, replacing
+/// each with the secret's value.
+///
+/// Secrets are fetched from the region which the configuration provisions its
+/// cluster in, if any, and from the default region otherwise.
+pub async fn resolve(table: &mut Table) -> anyhow::Result<()> {
+    let region = table
+        .get("setup")
+        .and_then(|setup| setup.get("provisioned"))
+        .and_then(|provisioned| provisioned.get("region"))
+        .and_then(Value::as_str)
+        .map(ToString::to_string);
+    let mut resolved = BTreeMap::new();
+    for reference in references(table) {
+        if !resolved.contains_key(reference) {
+            let value = fetch(reference, region.as_deref())
+                .await
+                .with_context(|| format!("Failed to resolve the secret {reference:?}"))?;
+            resolved.insert(reference.to_string(), value);
+        };
+    }
+    substitute_all(table, &resolved);
+    Ok(())
+}
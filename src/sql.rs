@@ -0,0 +1,153 @@
+use std::path::PathBuf;
+
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+use crate::{state, StrRef};
+
+const HISTORY_FILE_NAME: &str = "sql_history";
+
+const PROMPT: &str = "daft> ";
+
+const CONTINUATION_PROMPT: &str = "   -> ";
+
+const EXIT_COMMANDS: &[&str] = &["exit", "quit", "\\q"];
+
+/// Returns the statement in the given input buffer (without its terminating
+/// semicolon) if the buffer holds a complete statement, i.e., one which ends
+/// with a semicolon.
+pub fn complete_statement(buffer: &str) -> Option<&str> {
+    buffer
+        .trim_end()
+        .strip_suffix(';')
+        .map(|statement| statement.trim())
+}
+
+/// Renders the given value as a SQL literal: numbers and booleans are left
+/// as-is, and anything else becomes a string literal with its quotes escaped.
+fn sql_literal(value: &str) -> String {
+    let is_number =
+        value.parse::<i64>().is_ok() || value.parse::<f64>().is_ok_and(|value| value.is_finite());
+    let is_boolean = value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false");
+    if is_number || is_boolean {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "''"))
+    }
+}
+
+/// Replaces each `:name` parameter in the given query with the value given
+/// for it, rendered as a SQL literal.
+///
+/// Parameters inside string literals, quoted identifiers and `::` casts are
+/// left untouched, and every parameter must be given a value.
+pub fn bind_parameters(sql: &str, params: &[(StrRef, StrRef)]) -> anyhow::Result<String> {
+    let chars = sql.chars().collect::<Vec<_>>();
+    let mut bound = String::with_capacity(sql.len());
+    let mut quote = None;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == ':'
+                && chars
+                    .get(i + 1)
+                    .is_some_and(|&c| c.is_ascii_alphabetic() || c == '_')
+                && (i == 0 || chars[i - 1] != ':') =>
+            {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&c| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .map_or(chars.len(), |offset| i + 1 + offset);
+                let name = chars[i + 1..end].iter().collect::<String>();
+                let (_, value) = params
+                    .iter()
+                    .rev()
+                    .find(|(key, _)| **key == *name)
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "No value was given for the parameter :{name}; pass it with `--param {name}=value`"
+                        )
+                    })?;
+                bound.push_str(&sql_literal(value));
+                i = end;
+                continue;
+            }
+            None => (),
+        };
+        bound.push(c);
+        i += 1;
+    }
+    Ok(bound)
+}
+
+/// A readline-style prompt for SQL statements, with persistent history and
+/// support for statements which span multiple lines.
+pub struct Repl {
+    editor: DefaultEditor,
+    history_path: PathBuf,
+}
+
+impl Repl {
+    pub fn new() -> anyhow::Result<Self> {
+        let state_dir = state::state_dir()?;
+        std::fs::create_dir_all(&state_dir)?;
+        let history_path = state_dir.join(HISTORY_FILE_NAME);
+
+        let mut editor = DefaultEditor::new()?;
+        // There is no history to load the first time the REPL is used.
+        let _ = editor.load_history(&history_path);
+        Ok(Self {
+            editor,
+            history_path,
+        })
+    }
+
+    /// Reads lines until a complete statement has been entered, returning
+    /// `None` once the user asks to exit.
+    ///
+    /// Pressing Ctrl-C discards the statement which is being entered.
+    pub fn read_statement(&mut self) -> anyhow::Result<Option<String>> {
+        let mut buffer = String::new();
+        loop {
+            let prompt = if buffer.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            };
+            let line = match tokio::task::block_in_place(|| self.editor.readline(prompt)) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => {
+                    buffer.clear();
+                    continue;
+                }
+                Err(ReadlineError::Eof) => break Ok(None),
+                Err(error) => break Err(error.into()),
+            };
+            if buffer.is_empty() {
+                if line.trim().is_empty() {
+                    continue;
+                };
+                if EXIT_COMMANDS.contains(&line.trim()) {
+                    break Ok(None);
+                };
+            } else {
+                buffer.push('\n');
+            };
+            buffer.push_str(&line);
+
+            let Some(statement) = complete_statement(&buffer) else {
+                continue;
+            };
+            let statement = statement.to_string();
+            self.editor.add_history_entry(&buffer)?;
+            self.editor.save_history(&self.history_path)?;
+            if !statement.is_empty() {
+                break Ok(Some(statement));
+            };
+            buffer.clear();
+        }
+    }
+}
@@ -1,53 +1,178 @@
-use std::{net::Ipv4Addr, path::Path, process::Stdio, time::Duration};
+use std::{
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
 
+use aws_sdk_ec2::types::InstanceStateName;
+use serde::Serialize;
 use tokio::{
-    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    io::{AsyncBufReadExt, BufReader},
     process::{Child, Command},
-    time::timeout,
 };
 
-use crate::AwsConfig;
+use crate::{
+    get_ray_clusters_from_aws, logging, outputs, shell_quote, state, tunnel, wizard, AwsConfig,
+    AwsInstance, Connection, NodeType, ProxyJump, StrRef, RAY_DASHBOARD_PORT,
+};
 
-async fn get_head_node_ip(ray_path: impl AsRef<Path>) -> anyhow::Result<Ipv4Addr> {
-    let mut ray_command = Command::new("ray")
-        .arg("get-head-ip")
-        .arg(ray_path.as_ref())
-        .stdout(Stdio::piped())
-        .spawn()?;
+/// The directory (within the launcher's state directory) in which ssh keeps
+/// the sockets of shared connections.
+const CONTROL_DIR_NAME: &str = "ssh";
 
-    let mut tail_command = Command::new("tail")
-        .args(["-n", "1"])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()?;
+/// How many keep-alives may go unanswered before ssh gives up on a connection.
+const KEEP_ALIVE_COUNT_MAX: u32 = 3;
 
-    let mut writer = tail_command.stdin.take().expect("stdin must exist");
+/// Looks up the addresses of the given cluster's running nodes of the given
+/// type from the EC2 API (by the tags with which Ray tags them).
+async fn get_node_ips(
+    cluster_name: &str,
+    aws_config: &AwsConfig,
+    node_type: NodeType,
+) -> anyhow::Result<Vec<Ipv4Addr>> {
+    let instances = get_ray_clusters_from_aws(
+        aws_config.region().clone(),
+        &[
+            ("ray-cluster-name".into(), cluster_name.into()),
+            ("ray-node-type".into(), node_type.as_str().into()),
+        ],
+    )
+    .await?;
+    Ok(instances
+        .iter()
+        .filter(|instance| instance.state == Some(InstanceStateName::Running))
+        .filter_map(|instance| node_address(instance, aws_config))
+        .collect())
+}
 
-    tokio::spawn(async move {
-        let mut reader = BufReader::new(ray_command.stdout.take().expect("stdout must exist"));
-        let mut buffer = Vec::new();
-        reader.read_to_end(&mut buffer).await?;
-        writer.write_all(&buffer).await?;
-        Ok::<_, anyhow::Error>(())
-    });
-    let output = tail_command.wait_with_output().await?;
-    if !output.status.success() {
-        anyhow::bail!("Failed to fetch ip address of head node");
+/// The address at which ssh reaches the given node: through a bastion, its
+/// private address, since the bastion is inside the cluster's VPC (where nodes
+/// in private subnets have no public address, and the security groups may only
+/// let the bastion in privately); otherwise, as [`AwsInstance::address`].
+pub fn node_address(instance: &AwsInstance, aws_config: &AwsConfig) -> Option<Ipv4Addr> {
+    if aws_config.ssh_proxy_jump.is_some() {
+        instance
+            .private_ipv4_address
+            .or(instance.public_ipv4_address)
+    } else {
+        instance.address()
+    }
+}
+
+/// Looks up the address of the given cluster's running head node.
+pub async fn get_head_node_ip(
+    cluster_name: &str,
+    aws_config: &AwsConfig,
+) -> anyhow::Result<Ipv4Addr> {
+    get_node_ips(cluster_name, aws_config, NodeType::Head)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Cluster {cluster_name} has no running head node"))
+}
+
+/// Fails for clusters which are reached through SSM rather than SSH.
+fn assert_uses_ssh(aws_config: &AwsConfig) -> anyhow::Result<()> {
+    if aws_config.connection == Connection::Ssm {
+        anyhow::bail!("This command needs SSH to the head node, so it is not available with `connection = \"ssm\"`");
     };
-    let addr = String::from_utf8_lossy(&output.stdout)
-        .trim()
-        .parse::<Ipv4Addr>()?;
-    Ok(addr)
+    Ok(())
 }
 
 async fn generate_ssh_command(
-    ray_path: impl AsRef<Path>,
+    cluster_name: &str,
     aws_config: &AwsConfig,
-    portforward: Option<u16>,
-    verbose: bool,
+    portforwards: &[(u16, u16)],
 ) -> anyhow::Result<(Ipv4Addr, Command)> {
+    assert_uses_ssh(aws_config)?;
+    let addr = get_head_node_ip(cluster_name, aws_config).await?;
+    let command = ssh_command_to(addr, aws_config, portforwards);
+    Ok((addr, command))
+}
+
+/// The command through which ssh (and scp and rsync) reach the cluster's nodes
+/// by way of the given bastion.
+pub fn proxy_command(proxy_jump: &ProxyJump) -> String {
+    format!(
+        "ssh -i {} -o StrictHostKeyChecking=no -W %h:%p {}",
+        command_line_path(&proxy_jump.ssh_private_key),
+        proxy_jump.host
+    )
+}
+
+/// Formats the given path for a command line which ssh runs itself (such as the
+/// `ProxyCommand`): quoted for `sh`, or, on Windows (where OpenSSH runs it
+/// through `cmd.exe`, which does not understand single quotes), with forward
+/// slashes and double quotes.
+fn command_line_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if !cfg!(windows) {
+        return shell_quote(&path);
+    };
+    let path = path.replace('\\', "/");
+    if path.contains(' ') {
+        format!("\"{path}\"")
+    } else {
+        path
+    }
+}
+
+/// Returns the directory in which ssh keeps the sockets of the connections to
+/// the cluster's nodes which it shares between commands, or `None` if they are
+/// not to be shared.
+fn control_dir(aws_config: &AwsConfig) -> Option<PathBuf> {
+    // The OpenSSH which ships with Windows cannot share connections.
+    if aws_config.ssh_control_persist == 0 || cfg!(windows) {
+        return None;
+    };
+    let control_dir = state::state_dir().ok()?.join(CONTROL_DIR_NAME);
+    match std::fs::create_dir_all(&control_dir) {
+        Ok(()) => Some(control_dir),
+        Err(error) => {
+            logging::debug(format!(
+                "Not sharing ssh connections, since {} could not be created: {error}",
+                control_dir.display()
+            ));
+            None
+        }
+    }
+}
+
+/// The options with which ssh (or scp) reaches the cluster's nodes: through
+/// the configured bastion (if any), with keep-alives, and sharing connections
+/// through sockets in the given directory (if any).
+pub fn ssh_options(aws_config: &AwsConfig, control_dir: Option<&Path>) -> Vec<String> {
+    let mut options = vec![];
+    if let Some(proxy_jump) = &aws_config.ssh_proxy_jump {
+        options.push(format!("ProxyCommand={}", proxy_command(proxy_jump)));
+    };
+    if aws_config.ssh_keep_alive > 0 {
+        options.push(format!("ServerAliveInterval={}", aws_config.ssh_keep_alive));
+        options.push(format!("ServerAliveCountMax={KEEP_ALIVE_COUNT_MAX}"));
+    };
+    match control_dir {
+        Some(control_dir) => {
+            options.push("ControlMaster=auto".to_string());
+            options.push(format!("ControlPath={}/%C", control_dir.display()));
+            options.push(format!("ControlPersist={}", aws_config.ssh_control_persist));
+        }
+        None => options.push("ControlPath=none".to_string()),
+    }
+    options
+        .into_iter()
+        .flat_map(|option| ["-o".to_string(), option])
+        .collect()
+}
+
+/// Returns the command which connects to the given node, forwarding the given
+/// `(local, remote)` pairs of ports (if any) rather than running a shell.
+pub fn ssh_command_to(
+    addr: Ipv4Addr,
+    aws_config: &AwsConfig,
+    portforwards: &[(u16, u16)],
+) -> Command {
     let user = aws_config.ssh_user.as_ref();
-    let addr = get_head_node_ip(ray_path).await?;
 
     let mut command = Command::new("ssh");
 
@@ -57,24 +182,38 @@ async fn generate_ssh_command(
         .arg("-o")
         .arg("StrictHostKeyChecking=no");
 
-    if let Some(portforward) = portforward {
-        command
-            .arg("-N")
-            .arg("-L")
-            .arg(format!("{portforward}:localhost:8265"));
+    // Port-forwards are never shared: a forward which is added to a shared
+    // connection outlives the process which asked for it.
+    if portforwards.is_empty() {
+        command.args(ssh_options(aws_config, control_dir(aws_config).as_deref()));
+    } else {
+        command.args(ssh_options(aws_config, None));
     };
 
-    if verbose {
-        command.arg("-v");
+    // Fail, rather than ask for a password or passphrase, when no one can
+    // answer.
+    if !wizard::is_interactive() {
+        command.arg("-o").arg("BatchMode=yes");
+    };
+
+    // Exit, rather than run without the tunnel, when a port cannot be
+    // forwarded (e.g., because it is already in use locally).
+    if !portforwards.is_empty() {
+        command.arg("-N").arg("-o").arg("ExitOnForwardFailure=yes");
+    };
+    for (local_port, remote_port) in portforwards {
+        command
+            .arg("-L")
+            .arg(format!("{local_port}:localhost:{remote_port}"));
     }
 
     command.arg(format!("{user}@{addr}")).kill_on_drop(true);
 
-    Ok((addr, command))
+    command
 }
 
-pub async fn ssh(ray_path: impl AsRef<Path>, aws_config: &AwsConfig) -> anyhow::Result<()> {
-    let (addr, mut command) = generate_ssh_command(ray_path, aws_config, None, false).await?;
+pub async fn ssh(cluster_name: &str, aws_config: &AwsConfig) -> anyhow::Result<()> {
+    let (addr, mut command) = generate_ssh_command(cluster_name, aws_config, &[]).await?;
     let exit_status = command.spawn()?.wait().await?;
     if exit_status.success() {
         Ok(())
@@ -85,44 +224,295 @@ pub async fn ssh(ray_path: impl AsRef<Path>, aws_config: &AwsConfig) -> anyhow::
     }
 }
 
+/// Forwards the given local port to the Ray dashboard on the head node, along
+/// with any other given `(local, remote)` pairs of ports, returning once the
+/// dashboard answers through it.
 pub async fn ssh_portforward(
-    ray_path: impl AsRef<Path>,
+    cluster_name: &str,
     aws_config: &AwsConfig,
-    portforward: Option<u16>,
+    local_port: u16,
+    other_portforwards: &[(u16, u16)],
+    ready_timeout: Duration,
 ) -> anyhow::Result<Child> {
-    let (addr, mut command) = generate_ssh_command(
-        ray_path,
-        aws_config,
-        Some(portforward.unwrap_or(8265)),
-        true,
-    )
-    .await?;
+    let mut portforwards = vec![(local_port, RAY_DASHBOARD_PORT)];
+    portforwards.extend_from_slice(other_portforwards);
+    let (addr, mut command) = generate_ssh_command(cluster_name, aws_config, &portforwards).await?;
     let mut child = command.stderr(Stdio::piped()).spawn()?;
+    tunnel::wait_for_dashboard(&mut child, local_port, ready_timeout)
+        .await
+        .map_err(|error| {
+            anyhow::anyhow!("Failed to establish ssh port-forward to {addr}: {error}")
+        })?;
+    Ok(child)
+}
+
+/// Forwards each of the given `(local, remote)` pairs of ports to the head
+/// node, in an ssh process which is detached from the launcher (and so outlives
+/// it).
+pub async fn ssh_tunnel(
+    cluster_name: &str,
+    aws_config: &AwsConfig,
+    portforwards: &[(u16, u16)],
+) -> anyhow::Result<Child> {
+    let (_, mut command) = generate_ssh_command(cluster_name, aws_config, portforwards).await?;
+    let child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(false)
+        .spawn()?;
+    Ok(child)
+}
 
-    // We wait for the ssh port-forwarding process to write a specific string to the
-    // output.
-    //
-    // This is a little hacky (and maybe even incorrect across platforms) since we
-    // are just parsing the output and observing if a specific string has been
-    // printed. It may be incorrect across platforms because the SSH standard
-    // does *not* specify a standard "success-message" to printout if the ssh
-    // port-forward was successful.
-    timeout(Duration::from_secs(5), {
-        let stderr = child.stderr.take().expect("stderr must exist");
-        async move {
-            let mut lines = BufReader::new(stderr).lines();
-            loop {
-                let Some(line) = lines.next_line().await? else {
-                    anyhow::bail!("Failed to establish ssh port-forward to {addr}");
-                };
-                if line.starts_with(format!("Authenticated to {addr}").as_str()) {
-                    break Ok(());
-                }
-            }
+/// Runs the given command on the head node, calling `on_line` with each line
+/// of its output as soon as it is printed.
+pub async fn ssh_lines(
+    cluster_name: &str,
+    aws_config: &AwsConfig,
+    remote_command: &str,
+    mut on_line: impl FnMut(&str) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let (addr, mut command) = generate_ssh_command(cluster_name, aws_config, &[]).await?;
+    let mut child = command.arg(remote_command).stdout(Stdio::piped()).spawn()?;
+    let mut lines = BufReader::new(child.stdout.take().expect("stdout must exist")).lines();
+    while let Some(line) = lines.next_line().await? {
+        on_line(&line)?;
+    }
+    if !child.wait().await?.success() {
+        anyhow::bail!("Failed to run `{remote_command}` on the head node at address {addr}");
+    };
+    Ok(())
+}
+
+/// The output of a command which `exec` ran on a node of the cluster.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct NodeOutput {
+    pub address: Ipv4Addr,
+    pub is_head: bool,
+    /// `None` if the command was killed by a signal (or ssh could not run it).
+    pub exit_code: Option<i32>,
+    pub stdout: StrRef,
+    pub stderr: StrRef,
+}
+
+/// Runs the given command on the head node (and, if `all_nodes`, on every
+/// worker node too, at the same time), returning the output of each node.
+pub async fn exec(
+    cluster_name: &str,
+    aws_config: &AwsConfig,
+    remote_command: &str,
+    all_nodes: bool,
+) -> anyhow::Result<Vec<NodeOutput>> {
+    assert_uses_ssh(aws_config)?;
+    let mut nodes = vec![(get_head_node_ip(cluster_name, aws_config).await?, true)];
+    if all_nodes {
+        nodes.extend(
+            get_node_ips(cluster_name, aws_config, NodeType::Worker)
+                .await?
+                .into_iter()
+                .map(|addr| (addr, false)),
+        );
+    };
+
+    // Every node's command is started before any is waited for, so that they
+    // run in parallel.
+    let mut children = vec![];
+    for (addr, is_head) in nodes {
+        let child = ssh_command_to(addr, aws_config, &[])
+            .arg(remote_command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        children.push((addr, is_head, child));
+    }
+    let mut outputs = vec![];
+    for (address, is_head, child) in children {
+        let output = child.wait_with_output().await?;
+        outputs.push(NodeOutput {
+            address,
+            is_head,
+            exit_code: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).into(),
+            stderr: String::from_utf8_lossy(&output.stderr).into(),
+        });
+    }
+    Ok(outputs)
+}
+
+/// Formats the outputs of `exec`, each under a header naming its node.
+pub fn format_node_outputs(outputs: &[NodeOutput]) -> String {
+    let mut formatted = String::new();
+    for output in outputs {
+        let role = if output.is_head { "head" } else { "worker" };
+        let status = match output.exit_code {
+            Some(0) => String::new(),
+            Some(code) => format!(" (exited with {code})"),
+            None => " (killed)".to_string(),
+        };
+        formatted.push_str(&format!("==> {} ({role}){status} <==\n", output.address));
+        for stream in [&output.stdout, &output.stderr] {
+            formatted.push_str(stream);
+            if !stream.is_empty() && !stream.ends_with('\n') {
+                formatted.push('\n');
+            };
         }
-    })
-    .await
-    .map_err(|_| anyhow::anyhow!("Establishing an ssh port-forward to {addr} timed out"))??;
+    }
+    formatted
+}
 
-    Ok(child)
+/// Lists the files in the given directory on the head node which match any of
+/// the given glob patterns (relative to that directory), and copies them into
+/// the given local directory, returning the paths of the copied files
+/// (relative to both directories).
+pub async fn fetch_from_head_node(
+    cluster_name: &str,
+    aws_config: &AwsConfig,
+    remote_dir: &str,
+    patterns: &[StrRef],
+    local_dir: &Path,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let (addr, mut command) = generate_ssh_command(cluster_name, aws_config, &[]).await?;
+
+    // The patterns are deliberately left unquoted so that the remote shell
+    // expands them.
+    let patterns = patterns.join(" ");
+    let list_command = format!(
+        r#"cd {} && shopt -s nullglob globstar && for path in {patterns}; do [ -f "$path" ] && echo "$path"; done; true"#,
+        shell_quote(remote_dir),
+    );
+    let output = command
+        .arg(list_command)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?
+        .wait_with_output()
+        .await?;
+    logging::debug(format!(
+        "Listed the job's outputs on the head node: {}",
+        String::from_utf8_lossy(&output.stdout).trim()
+    ));
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to list the job's outputs on the head node at address {addr}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    };
+
+    let user = aws_config.ssh_user.as_ref();
+    let mut fetched = vec![];
+    for relative_path in String::from_utf8(output.stdout)?.lines() {
+        // The patterns were checked when the configuration was read, but the
+        // remote shell might still list paths outside of the directory.
+        if let Err(error) = outputs::check_relative(relative_path) {
+            logging::warn(format!("Skipping {relative_path:?}: {error}"));
+            continue;
+        };
+        let local_path = local_dir.join(relative_path);
+        if let Some(parent) = local_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        };
+        let exit_status = logging::run(
+            Command::new("scp")
+                .arg("-i")
+                .arg(aws_config.ssh_private_key.as_ref())
+                .arg("-o")
+                .arg("StrictHostKeyChecking=no")
+                .args(ssh_options(aws_config, control_dir(aws_config).as_deref()))
+                .arg(format!("{user}@{addr}:{remote_dir}/{relative_path}"))
+                .arg(&local_path),
+        )
+        .await?;
+        if !exit_status.success() {
+            anyhow::bail!("Failed to copy {relative_path} from the head node at address {addr}");
+        };
+        fetched.push(PathBuf::from(relative_path));
+    }
+    Ok(fetched)
+}
+
+/// The ssh command (with the configured key and options, sharing connections
+/// through sockets in the given directory, if any) which rsync runs to reach
+/// the cluster's nodes.
+pub fn rsync_remote_shell(aws_config: &AwsConfig, control_dir: Option<&Path>) -> String {
+    let mut remote_shell = format!(
+        "ssh -i {} -o StrictHostKeyChecking=no",
+        shell_quote(&aws_config.ssh_private_key.to_string_lossy())
+    );
+    for option in ssh_options(aws_config, control_dir) {
+        remote_shell.push(' ');
+        remote_shell.push_str(&shell_quote(&option));
+    }
+    remote_shell
+}
+
+/// Copies the given local file (or directory, recursively) to the given path
+/// on the head node.
+pub async fn copy_to_head_node(
+    cluster_name: &str,
+    aws_config: &AwsConfig,
+    local_path: &Path,
+    remote_path: &str,
+) -> anyhow::Result<()> {
+    assert_uses_ssh(aws_config)?;
+    let user = aws_config.ssh_user.as_ref();
+    let addr = get_head_node_ip(cluster_name, aws_config).await?;
+    let exit_status = logging::run(
+        Command::new("scp")
+            .arg("-r")
+            .arg("-i")
+            .arg(aws_config.ssh_private_key.as_ref())
+            .arg("-o")
+            .arg("StrictHostKeyChecking=no")
+            .args(ssh_options(aws_config, control_dir(aws_config).as_deref()))
+            .arg(local_path)
+            .arg(format!("{user}@{addr}:{remote_path}")),
+    )
+    .await?;
+    if !exit_status.success() {
+        anyhow::bail!(
+            "Failed to copy {} to the head node at address {addr}",
+            local_path.display()
+        );
+    };
+    Ok(())
+}
+
+/// Syncs the given local directory into the given directory on the head node
+/// with rsync, so that only the files which changed are sent; with `delete`,
+/// the files which are not in the local directory are removed from the remote
+/// one.
+pub async fn sync_to_head_node(
+    cluster_name: &str,
+    aws_config: &AwsConfig,
+    local_dir: &Path,
+    remote_dir: &str,
+    delete: bool,
+) -> anyhow::Result<()> {
+    assert_uses_ssh(aws_config)?;
+    let user = aws_config.ssh_user.as_ref();
+    let addr = get_head_node_ip(cluster_name, aws_config).await?;
+    let mut command = Command::new("rsync");
+    command.arg("-az").arg("-e").arg(rsync_remote_shell(
+        aws_config,
+        control_dir(aws_config).as_deref(),
+    ));
+    if delete {
+        command.arg("--delete");
+    };
+    // The trailing slash makes rsync sync the directory's contents, rather
+    // than the directory itself, into the remote directory.
+    let exit_status = logging::run(
+        command
+            .arg(format!("{}/", local_dir.display()))
+            .arg(format!("{user}@{addr}:{remote_dir}")),
+    )
+    .await?;
+    if !exit_status.success() {
+        anyhow::bail!(
+            "Failed to sync {} to the head node at address {addr}",
+            local_dir.display()
+        );
+    };
+    Ok(())
 }
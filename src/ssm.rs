@@ -0,0 +1,116 @@
+use std::{process::Stdio, time::Duration};
+
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::{Child, Command},
+    time::timeout,
+};
+
+/// The line which the Session Manager plugin prints once a port-forwarding
+/// session is ready for connections.
+const READY_LINE: &str = "Waiting for connections...";
+
+/// Starting a session includes a round trip to the SSM API, so it is given
+/// longer than an ssh port-forward.
+const READY_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The parameters of the `AWS-StartPortForwardingSession` document which
+/// forward the given local port to the given port on the instance.
+pub fn port_forwarding_parameters(local_port: u16, remote_port: u16) -> String {
+    serde_json::json!({
+        "portNumber": [remote_port.to_string()],
+        "localPortNumber": [local_port.to_string()],
+    })
+    .to_string()
+}
+
+fn start_session_command(instance_id: &str, region: &str) -> Command {
+    let mut command = Command::new("aws");
+    command
+        .args(["ssm", "start-session", "--target", instance_id])
+        .args(["--region", region])
+        .kill_on_drop(true);
+    command
+}
+
+fn spawn(command: &mut Command) -> anyhow::Result<Child> {
+    command.spawn().map_err(|error| {
+        anyhow::anyhow!("Failed to run the AWS CLI (which SSM sessions need, along with its Session Manager plugin): {error}")
+    })
+}
+
+/// Opens an interactive shell on the given instance through SSM Session
+/// Manager.
+pub async fn start_session(instance_id: &str, region: &str) -> anyhow::Result<()> {
+    let exit_status = spawn(&mut start_session_command(instance_id, region))?
+        .wait()
+        .await?;
+    if !exit_status.success() {
+        anyhow::bail!("Failed to start an SSM session on instance {instance_id}");
+    };
+    Ok(())
+}
+
+/// Forwards the given local port to the given port on the given instance
+/// through SSM Session Manager, in a session which is detached from the
+/// launcher (and so outlives it).
+pub fn tunnel(
+    instance_id: &str,
+    region: &str,
+    local_port: u16,
+    remote_port: u16,
+) -> anyhow::Result<Child> {
+    spawn(
+        start_session_command(instance_id, region)
+            .args(["--document-name", "AWS-StartPortForwardingSession"])
+            .arg("--parameters")
+            .arg(port_forwarding_parameters(local_port, remote_port))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(false),
+    )
+}
+
+/// Forwards each of the given `(local, remote)` pairs of ports to the given
+/// instance through SSM Session Manager (one session per pair), returning the
+/// sessions once all of them are ready.
+pub async fn port_forward(
+    instance_id: &str,
+    region: &str,
+    portforwards: &[(u16, u16)],
+) -> anyhow::Result<Vec<Child>> {
+    let mut children = vec![];
+    for &(local_port, remote_port) in portforwards {
+        let mut child = spawn(
+            start_session_command(instance_id, region)
+                .args(["--document-name", "AWS-StartPortForwardingSession"])
+                .arg("--parameters")
+                .arg(port_forwarding_parameters(local_port, remote_port))
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped()),
+        )?;
+        let mut lines = BufReader::new(child.stdout.take().expect("stdout must exist")).lines();
+        timeout(READY_TIMEOUT, async {
+            loop {
+                let Some(line) = lines.next_line().await? else {
+                    anyhow::bail!(
+                        "Failed to forward port {local_port} to {instance_id} through SSM"
+                    );
+                };
+                if line.trim() == READY_LINE {
+                    break Ok(());
+                };
+            }
+        })
+        .await
+        .map_err(|_| {
+            anyhow::anyhow!("Forwarding port {local_port} to {instance_id} through SSM timed out")
+        })??;
+        // The plugin reports each connection on its output, so the output is
+        // drained for as long as the session lasts.
+        tokio::spawn(async move { while let Ok(Some(_)) = lines.next_line().await {} });
+        children.push(child);
+    }
+    Ok(children)
+}
@@ -0,0 +1,481 @@
+use std::path::{Path, PathBuf};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::StrRef;
+
+/// The directory (relative to the user's home directory) in which
+/// daft-launcher keeps its local state.
+const STATE_DIR_NAME: &str = ".daft-launcher";
+
+const SUBMISSIONS_FILE_NAME: &str = "submissions.jsonl";
+
+const OUTCOMES_FILE_NAME: &str = "outcomes.jsonl";
+
+const CLUSTERS_FILE_NAME: &str = "clusters.jsonl";
+
+const ACTIONS_FILE_NAME: &str = "actions.jsonl";
+
+const TUNNELS_FILE_NAME: &str = "tunnels.jsonl";
+
+/// The directory (relative to the state directory) with a directory per
+/// region, and within it one per cluster spun up from this machine.
+const CLUSTERS_DIR_NAME: &str = "clusters";
+
+const RAY_CONFIG_FILE_NAME: &str = "ray.yaml";
+
+/// The configuration file reconstructed for a cluster which is operated on by
+/// name, without its own configuration file on this machine.
+const ADOPTED_CONFIG_FILE_NAME: &str = "adopted.daft.toml";
+
+/// The comment which heads a cached Ray configuration, followed by the hash of
+/// the configuration file which it was generated from.
+const CONFIG_HASH_COMMENT: &str = "# daft-launcher config hash: ";
+
+/// A record of a job which was submitted to a cluster by daft-launcher.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Submission {
+    pub job_name: StrRef,
+    pub submission_id: StrRef,
+    pub cluster: StrRef,
+    /// The time of submission, in seconds since the Unix epoch.
+    pub submitted_at: u64,
+}
+
+/// A record of how a job which was submitted by daft-launcher finished.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub submission_id: StrRef,
+    pub status: StrRef,
+    /// How long the job ran for, in seconds.
+    pub duration: Option<u64>,
+    /// Ray's explanation of the status (e.g., the reason for a failure).
+    pub message: Option<StrRef>,
+}
+
+/// A record of a cluster which was spun up by daft-launcher, along with the
+/// configuration file which it was spun up from.
+///
+/// A new record is appended whenever the cluster's state changes, so the most
+/// recent record of a cluster is the current one.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Cluster {
+    pub name: StrRef,
+    pub region: StrRef,
+    pub config: PathBuf,
+    #[serde(default = "default_provider")]
+    pub provider: StrRef,
+    /// The address of the head node once the cluster was spun up, if it could
+    /// be found.
+    #[serde(default)]
+    pub head_ip: Option<StrRef>,
+    /// The time at which the cluster was spun up, in seconds since the Unix
+    /// epoch (missing from older records).
+    #[serde(default)]
+    pub created_at: Option<u64>,
+    /// What was last done to the cluster from this machine (missing from older
+    /// records).
+    #[serde(default)]
+    pub state: Option<ClusterState>,
+    /// The profile of the configuration file which the cluster was spun up
+    /// with, if any.
+    #[serde(default)]
+    pub profile: Option<StrRef>,
+}
+
+/// Only provisioned clusters are spun up by daft-launcher.
+fn default_provider() -> StrRef {
+    "provisioned".into()
+}
+
+/// The states of a cluster, as last recorded by daft-launcher.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClusterState {
+    Up,
+    Down,
+    Killed,
+}
+
+impl ClusterState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Up => "up",
+            Self::Down => "down",
+            Self::Killed => "killed",
+        }
+    }
+}
+
+/// The actions on clusters which are recorded in the local audit log, once
+/// they have succeeded.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ActionKind {
+    Up,
+    Down,
+    Kill,
+    Submit,
+}
+
+impl ActionKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Up => "up",
+            Self::Down => "down",
+            Self::Kill => "kill",
+            Self::Submit => "submit",
+        }
+    }
+}
+
+/// A record of an action which was taken on a cluster by daft-launcher, as
+/// listed by `daft history`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Action {
+    pub action: ActionKind,
+    /// The local user who took the action.
+    pub user: StrRef,
+    pub cluster: StrRef,
+    /// A hash of the (effective) configuration which the action was taken
+    /// with, to tell which version of it was used.
+    pub config_hash: StrRef,
+    /// What the action was taken on within the cluster (e.g., the job which
+    /// was submitted), if anything.
+    pub detail: Option<StrRef>,
+    /// The time of the action, in seconds since the Unix epoch.
+    pub at: u64,
+}
+
+/// A record of a tunnel to a cluster which `provisioned connect --detach`
+/// opened in the background.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct DetachedTunnel {
+    pub cluster: StrRef,
+    /// The pid of the ssh process which holds the tunnel open.
+    pub pid: u32,
+    /// The forwarded ports, as `(local, remote)` pairs.
+    pub ports: Vec<(u16, u16)>,
+    /// The time at which the tunnel was opened, in seconds since the Unix
+    /// epoch.
+    pub opened_at: u64,
+    /// The command line of the ssh process (or its image name, on Windows),
+    /// to tell it apart from a later process which reuses its pid (missing
+    /// from older records).
+    #[serde(default)]
+    pub command: Option<StrRef>,
+}
+
+pub fn state_dir() -> anyhow::Result<PathBuf> {
+    let home = crate::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine the home directory"))?;
+    Ok(home.join(STATE_DIR_NAME))
+}
+
+async fn state_file_path(file_name: &str) -> anyhow::Result<PathBuf> {
+    let state_dir = state_dir()?;
+    fs::create_dir_all(&state_dir).await?;
+    Ok(state_dir.join(file_name))
+}
+
+async fn append_record(file_name: &str, record: &impl Serialize) -> anyhow::Result<()> {
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(state_file_path(file_name).await?)
+        .await?;
+    file.write_all(line.as_bytes()).await?;
+    Ok(())
+}
+
+async fn read_records<T: DeserializeOwned>(file_name: &str) -> anyhow::Result<Vec<T>> {
+    let contents = match fs::read_to_string(state_file_path(file_name).await?).await {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => return Err(error.into()),
+    };
+    Ok(parse_records(&contents))
+}
+
+/// Replaces the records in the given file with the given ones.
+async fn write_records(file_name: &str, records: &[impl Serialize]) -> anyhow::Result<()> {
+    let mut contents = String::new();
+    for record in records {
+        contents.push_str(&serde_json::to_string(record)?);
+        contents.push('\n');
+    }
+    fs::write(state_file_path(file_name).await?, contents).await?;
+    Ok(())
+}
+
+/// Parses one record per line, skipping the lines which cannot be parsed
+/// (e.g., from a partially written record).
+pub fn parse_records<T: DeserializeOwned>(contents: &str) -> Vec<T> {
+    contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// The Ray configuration which a cluster was spun up with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedRayConfig {
+    pub contents: String,
+    /// The hash of the configuration file which it was generated from, if
+    /// known.
+    pub config_hash: Option<StrRef>,
+}
+
+/// Returns where the Ray configuration of the cluster with the given name in
+/// the given region is cached; clusters are keyed by region as well as name,
+/// just as their records are.
+pub fn ray_config_path(region: &str, cluster: &str) -> anyhow::Result<PathBuf> {
+    Ok(cluster_dir(region, cluster)?.join(RAY_CONFIG_FILE_NAME))
+}
+
+/// Returns where the reconstructed configuration file of the cluster with the
+/// given name in the given region is kept.
+pub fn adopted_config_path(region: &str, cluster: &str) -> anyhow::Result<PathBuf> {
+    Ok(cluster_dir(region, cluster)?.join(ADOPTED_CONFIG_FILE_NAME))
+}
+
+fn cluster_dir(region: &str, cluster: &str) -> anyhow::Result<PathBuf> {
+    for component in [region, cluster] {
+        if component.is_empty()
+            || component == "."
+            || component == ".."
+            || component.contains(['/', '\\'])
+        {
+            anyhow::bail!("The state of {component:?} cannot be kept locally, since it is not a valid directory name");
+        };
+    }
+    Ok(state_dir()?
+        .join(CLUSTERS_DIR_NAME)
+        .join(region)
+        .join(cluster))
+}
+
+/// Heads the given Ray configuration with the hash of the configuration file
+/// which it was generated from.
+pub fn format_cached_ray_config(contents: &str, config_hash: &str) -> String {
+    format!("{CONFIG_HASH_COMMENT}{config_hash}\n{contents}")
+}
+
+/// Parses a cached Ray configuration, as formatted by
+/// [`format_cached_ray_config`].
+pub fn parse_cached_ray_config(contents: &str) -> CachedRayConfig {
+    let config_hash = contents
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix(CONFIG_HASH_COMMENT))
+        .map(|config_hash| config_hash.trim().into());
+    CachedRayConfig {
+        contents: contents.to_string(),
+        config_hash,
+    }
+}
+
+/// Caches the Ray configuration which the cluster with the given name (in the
+/// given region) is spun up with, along with the hash of the configuration
+/// file which it was generated from, and returns the path to pass to Ray.
+pub async fn cache_ray_config(
+    region: &str,
+    cluster: &str,
+    contents: &str,
+    config_hash: &str,
+) -> anyhow::Result<PathBuf> {
+    let path = ray_config_path(region, cluster)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    };
+    fs::write(&path, format_cached_ray_config(contents, config_hash)).await?;
+    Ok(path)
+}
+
+/// Reads the Ray configuration which the cluster with the given name (in the
+/// given region) was spun up with from this machine, if any.
+pub async fn cached_ray_config(
+    region: &str,
+    cluster: &str,
+) -> anyhow::Result<Option<CachedRayConfig>> {
+    match fs::read_to_string(ray_config_path(region, cluster)?).await {
+        Ok(contents) => Ok(Some(parse_cached_ray_config(&contents))),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Forgets the Ray configuration which the cluster with the given name (in the
+/// given region) was spun up with, once it is gone.
+pub async fn remove_cached_ray_config(region: &str, cluster: &str) -> anyhow::Result<()> {
+    match fs::remove_file(ray_config_path(region, cluster)?).await {
+        Ok(()) => Ok(()),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Appends the given submission to the local submission log.
+pub async fn record_submission(submission: &Submission) -> anyhow::Result<()> {
+    append_record(SUBMISSIONS_FILE_NAME, submission).await
+}
+
+/// Reads all of the recorded submissions, oldest first.
+pub async fn read_submissions() -> anyhow::Result<Vec<Submission>> {
+    read_records(SUBMISSIONS_FILE_NAME).await
+}
+
+/// Appends the given outcome to the local outcome log.
+pub async fn record_outcome(outcome: &Outcome) -> anyhow::Result<()> {
+    append_record(OUTCOMES_FILE_NAME, outcome).await
+}
+
+/// Reads all of the recorded outcomes, oldest first.
+pub async fn read_outcomes() -> anyhow::Result<Vec<Outcome>> {
+    read_records(OUTCOMES_FILE_NAME).await
+}
+
+/// Appends the given cluster to the local cluster log.
+pub async fn record_cluster(cluster: &Cluster) -> anyhow::Result<()> {
+    append_record(CLUSTERS_FILE_NAME, cluster).await
+}
+
+/// Reads all of the recorded clusters, oldest first.
+pub async fn read_clusters() -> anyhow::Result<Vec<Cluster>> {
+    read_records(CLUSTERS_FILE_NAME).await
+}
+
+/// Records the given state of the cluster with the given name and region, if
+/// it was spun up from this machine.
+pub async fn record_cluster_state(
+    name: &str,
+    region: &str,
+    state: ClusterState,
+) -> anyhow::Result<()> {
+    let clusters = read_clusters().await?;
+    let Some(cluster) = clusters
+        .iter()
+        .rev()
+        .find(|cluster| &*cluster.name == name && &*cluster.region == region)
+    else {
+        return Ok(());
+    };
+    record_cluster(&Cluster {
+        state: Some(state),
+        ..cluster.clone()
+    })
+    .await
+}
+
+/// Appends the given action to the local audit log.
+pub async fn record_action(action: &Action) -> anyhow::Result<()> {
+    append_record(ACTIONS_FILE_NAME, action).await
+}
+
+/// Reads all of the recorded actions, oldest first.
+pub async fn read_actions() -> anyhow::Result<Vec<Action>> {
+    read_records(ACTIONS_FILE_NAME).await
+}
+
+/// Returns the most recent of the given actions (on the given cluster, if one
+/// is given), at most `limit` of them, most recent first.
+pub fn recent_actions<'a>(
+    actions: &'a [Action],
+    cluster: Option<&str>,
+    limit: usize,
+) -> Vec<&'a Action> {
+    actions
+        .iter()
+        .rev()
+        .filter(|action| cluster.is_none() || cluster == Some(&*action.cluster))
+        .take(limit)
+        .collect()
+}
+
+/// Appends the given tunnel to the local list of detached tunnels.
+pub async fn record_tunnel(tunnel: &DetachedTunnel) -> anyhow::Result<()> {
+    append_record(TUNNELS_FILE_NAME, tunnel).await
+}
+
+/// Reads the local list of detached tunnels, including the ones which have
+/// since closed.
+pub async fn read_tunnels() -> anyhow::Result<Vec<DetachedTunnel>> {
+    read_records(TUNNELS_FILE_NAME).await
+}
+
+/// Replaces the local list of detached tunnels (e.g., to drop the ones which
+/// were stopped).
+pub async fn write_tunnels(tunnels: &[DetachedTunnel]) -> anyhow::Result<()> {
+    write_records(TUNNELS_FILE_NAME, tunnels).await
+}
+
+/// Returns the most recent record of each cluster (by name and region) whose
+/// configuration file still exists, most recent first.
+pub fn known_clusters(clusters: &[Cluster], exists: impl Fn(&Path) -> bool) -> Vec<&Cluster> {
+    let mut known = Vec::<&Cluster>::new();
+    for cluster in clusters.iter().rev() {
+        let seen = known
+            .iter()
+            .any(|other| other.name == cluster.name && other.region == cluster.region);
+        if !seen && exists(&cluster.config) {
+            known.push(cluster);
+        };
+    }
+    known
+}
+
+/// Finds the cluster with the given name (in the given region, if one is
+/// given) among the given known clusters, which are most recent first.
+pub fn find_cluster<'a>(
+    known: &[&'a Cluster],
+    name: &str,
+    region: Option<&str>,
+) -> Option<&'a Cluster> {
+    known
+        .iter()
+        .find(|cluster| {
+            &*cluster.name == name && (region.is_none() || region == Some(&*cluster.region))
+        })
+        .copied()
+}
+
+/// Finds the most recent submission of the job with the given name to the
+/// given cluster.
+pub fn latest_submission<'a>(
+    submissions: &'a [Submission],
+    cluster: &str,
+    job_name: &str,
+) -> Option<&'a Submission> {
+    submissions
+        .iter()
+        .rev()
+        .find(|submission| &*submission.cluster == cluster && &*submission.job_name == job_name)
+}
+
+/// Pairs each of the submissions of the given job (or of every job, if none is
+/// given) to the given cluster with its outcome, if one was recorded, oldest
+/// first.
+pub fn history<'a>(
+    submissions: &'a [Submission],
+    outcomes: &'a [Outcome],
+    cluster: &str,
+    job_name: Option<&str>,
+) -> Vec<(&'a Submission, Option<&'a Outcome>)> {
+    submissions
+        .iter()
+        .filter(|submission| {
+            &*submission.cluster == cluster
+                && (job_name.is_none() || job_name == Some(&*submission.job_name))
+        })
+        .map(|submission| {
+            let outcome = outcomes
+                .iter()
+                .rev()
+                .find(|outcome| outcome.submission_id == submission.submission_id);
+            (submission, outcome)
+        })
+        .collect()
+}
@@ -1,10 +1,20 @@
-use std::io::ErrorKind;
+use std::{io::ErrorKind, sync::OnceLock};
 
 use tempdir::TempDir;
 use tokio::fs;
 
 use super::*;
 
+/// The home directory which the tests see in place of the real one; created
+/// once per test run, under the system's temporary directory.
+static HOME_DIR: OnceLock<TempDir> = OnceLock::new();
+
+pub fn home_dir() -> &'static Path {
+    HOME_DIR
+        .get_or_init(|| TempDir::new("home").expect("The temporary home should be created"))
+        .path()
+}
+
 fn not_found_okay(result: std::io::Result<()>) -> std::io::Result<()> {
     match result {
         Ok(()) => Ok(()),
@@ -20,6 +30,32 @@ async fn get_path() -> (TempDir, PathBuf) {
     (temp_dir, PathBuf::from(path.as_ref()))
 }
 
+/// An instance of the given type and in the given state, in the cluster with
+/// the given name; the rest of its fields are left empty, for the tests to set
+/// with struct update syntax.
+fn aws_instance(
+    id: &str,
+    name: &str,
+    node_type: NodeType,
+    state: InstanceStateName,
+) -> AwsInstance {
+    AwsInstance {
+        instance_id: id.into(),
+        regular_name: name.into(),
+        ray_name: format!("ray-{name}-{}", node_type.as_str()).into(),
+        key_pair_name: None,
+        public_ipv4_address: None,
+        private_ipv4_address: None,
+        state: Some(state),
+        node_type,
+        launch_time: None,
+        instance_type: None,
+        vcpus: None,
+        ttl: None,
+        launched_by_launcher: false,
+    }
+}
+
 /// This tests the creation of a daft-launcher configuration file.
 ///
 /// # Note
@@ -38,7 +74,16 @@ async fn test_init(#[case] provider: DaftProvider) {
         sub_command: SubCommand::Config(ConfigCommand::Init(Init {
             path: path.clone(),
             provider,
+            template: InitTemplate::Default,
+            interactive: false,
         })),
+        profile: None,
+        region: None,
+        output: OutputFormat::Text,
+        verbose: 0,
+        quiet: false,
+        log_file: None,
+        non_interactive: false,
     }
     .run()
     .await
@@ -61,14 +106,34 @@ async fn test_check(#[case] provider: DaftProvider) {
         sub_command: SubCommand::Config(ConfigCommand::Init(Init {
             path: path.clone(),
             provider,
+            template: InitTemplate::Default,
+            interactive: false,
         })),
+        profile: None,
+        region: None,
+        output: OutputFormat::Text,
+        verbose: 0,
+        quiet: false,
+        log_file: None,
+        non_interactive: false,
     }
     .run()
     .await
     .unwrap();
 
     DaftLauncher {
-        sub_command: SubCommand::Config(ConfigCommand::Check(ConfigPath { config: path })),
+        sub_command: SubCommand::Config(ConfigCommand::Check(Check {
+            strict: false,
+            fix_version: false,
+            config_path: ConfigPath { config: path },
+        })),
+        profile: None,
+        region: None,
+        output: OutputFormat::Text,
+        verbose: 0,
+        quiet: false,
+        log_file: None,
+        non_interactive: false,
     }
     .run()
     .await
@@ -85,7 +150,7 @@ async fn test_check(#[case] provider: DaftProvider) {
     "source ~/.bashrc".into(),
     r#"uv pip install boto3 pip py-spy deltalake getdaft "ray[default]==2.34""#.into(),
 ])]
-#[case("3.9".parse().unwrap(), "2.34".parse().unwrap(), vec!["requests==0.0.0".into()], vec![r#"echo "Hello, world!""#.into()], vec![
+#[case("3.9".parse().unwrap(), "2.34".parse().unwrap(), vec!["requests==0.0.0".into(), r#"pandas; python_version < "3.12""#.into()], vec![r#"echo "Hello, world!""#.into()], vec![
     "curl -LsSf https://astral.sh/uv/install.sh | sh".into(),
     "uv python install 3.9".into(),
     "uv python pin 3.9".into(),
@@ -93,7 +158,7 @@ async fn test_check(#[case] provider: DaftProvider) {
     "echo 'source $HOME/.venv/bin/activate' >> ~/.bashrc".into(),
     "source ~/.bashrc".into(),
     r#"uv pip install boto3 pip py-spy deltalake getdaft "ray[default]==2.34""#.into(),
-    r#"uv pip install "requests==0.0.0""#.into(),
+    r#"uv pip install requests==0.0.0 'pandas; python_version < "3.12"'"#.into(),
     r#"echo "Hello, world!""#.into(),
 ])]
 fn test_generate_setup_commands(
@@ -106,6 +171,7 @@ fn test_generate_setup_commands(
     let actual = generate_setup_commands(
         python_version,
         ray_version,
+        None,
         dependencies.as_slice(),
         run.as_slice(),
     );
@@ -146,19 +212,37 @@ pub fn simple_config() -> (DaftConfig, Option<TeardownBehaviour>, RayConfig) {
             requires: "=1.2.3".parse().unwrap(),
             python_version: "3.12".parse().unwrap(),
             ray_version: "2.34".parse().unwrap(),
+            daft_version: None,
+            env: BTreeMap::new(),
+            file_mounts: BTreeMap::new(),
+            file_mounts_exclude: vec![],
+            metrics: None,
+            tunnel_timeout: default_tunnel_timeout(),
             provider_config: ProviderConfig::Provisioned(AwsConfig {
-                region: test_name.clone(),
+                region: Some(test_name.clone()),
                 number_of_workers,
                 ssh_user: test_name.clone(),
                 ssh_private_key: ssh_private_key.clone(),
+                ssh_proxy_jump: None,
+                ssh_keep_alive: default_ssh_keep_alive(),
+                ssh_control_persist: default_ssh_control_persist(),
                 instance_type: test_name.clone(),
                 image_id: test_name.clone(),
                 iam_instance_profile_name: Some(test_name.clone()),
-                dependencies: vec![],
+                dependencies: Dependencies::default(),
+                pre_setup_commands: vec![],
+                setup_commands: None,
+                setup_script: None,
                 run: vec![r#"echo "Hello, world!""#.into()],
+                spot: false,
+                ttl_hours: None,
+                forwards: vec![],
+                connection: Connection::Ssh,
             }),
         },
         jobs: HashMap::default(),
+        notifications: None,
+        sql: SqlConfig::default(),
     };
     let node_config = RayNodeConfig {
         key_name: "testkey".into(),
@@ -167,6 +251,14 @@ pub fn simple_config() -> (DaftConfig, Option<TeardownBehaviour>, RayConfig) {
         iam_instance_profile: Some(IamInstanceProfile {
             name: test_name.clone(),
         }),
+        instance_market_options: None,
+        tag_specifications: vec![TagSpecification {
+            resource_type: "instance".into(),
+            tags: vec![RayTag {
+                key: LAUNCHER_TAG.into(),
+                value: env!("CARGO_PKG_VERSION").into(),
+            }],
+        }],
     };
 
     let ray_config = RayConfig {
@@ -201,6 +293,8 @@ pub fn simple_config() -> (DaftConfig, Option<TeardownBehaviour>, RayConfig) {
         ]
         .into_iter()
         .collect(),
+        file_mounts: BTreeMap::new(),
+        rsync_exclude: vec![],
         setup_commands: vec![
             "curl -LsSf https://astral.sh/uv/install.sh | sh".into(),
             "uv python install 3.12".into(),
@@ -211,7 +305,3911 @@ pub fn simple_config() -> (DaftConfig, Option<TeardownBehaviour>, RayConfig) {
             r#"uv pip install boto3 pip py-spy deltalake getdaft "ray[default]==2.34""#.into(),
             r#"echo "Hello, world!""#.into(),
         ],
+        head_setup_commands: vec![],
+        head_start_ray_commands: vec![],
     };
 
     (daft_config, None, ray_config)
 }
+
+#[rstest::rstest]
+#[case(Duration::from_secs(0), "0s")]
+#[case(Duration::from_secs(59), "59s")]
+#[case(Duration::from_secs(61), "1m 1s")]
+#[case(Duration::from_secs(3600), "1h 0m 0s")]
+#[case(Duration::from_millis(3_723_999), "1h 2m 3s")]
+fn test_format_duration(#[case] duration: Duration, #[case] expected: &str) {
+    assert_eq!(format_duration(duration), expected);
+}
+
+fn ray_job(submission_id: &str, status: RayJobStatus, daft_job_name: Option<&str>) -> RayJob {
+    RayJob {
+        submission_id: Some(submission_id.into()),
+        job_id: None,
+        status,
+        entrypoint: "python main.py".into(),
+        start_time: None,
+        end_time: None,
+        metadata: daft_job_name.map(|name| {
+            [(DAFT_JOB_NAME_METADATA_KEY.into(), name.into())]
+                .into_iter()
+                .collect()
+        }),
+        message: None,
+        runtime_env: None,
+    }
+}
+
+#[test]
+fn test_format_submit_all_summary() {
+    let ray_jobs = [
+        ray_job("raysubmit_2", RayJobStatus::Failed, Some("train")),
+        ray_job("raysubmit_1", RayJobStatus::Succeeded, Some("etl")),
+    ];
+    let failures = vec![(
+        StrRef::from("report"),
+        anyhow::anyhow!("The git source could not be cloned"),
+    )];
+    assert_eq!(
+        format_submit_all_summary(&ray_jobs, &failures),
+        "Submitted 2 of the 3 jobs: etl, train\nFailed to submit report: The git source could not be cloned"
+    );
+    assert_eq!(
+        format_submit_all_summary(&ray_jobs, &vec![]),
+        "Submitted 2 of the 2 jobs: etl, train"
+    );
+    assert_eq!(
+        format_submit_all_summary(&[], &failures),
+        "Submitted none of the jobs\nFailed to submit report: The git source could not be cloned"
+    );
+}
+
+#[test]
+fn test_match_submissions() {
+    let submissions = [
+        (StrRef::from("etl"), StrRef::from("raysubmit_1")),
+        (StrRef::from("train"), StrRef::from("raysubmit_2")),
+    ];
+    let listed = vec![
+        ray_job("raysubmit_1", RayJobStatus::Running, Some("etl")),
+        ray_job("raysubmit_3", RayJobStatus::Running, Some("other")),
+    ];
+    let (ray_jobs, missing) = match_submissions(&submissions, listed);
+    assert_eq!(
+        ray_jobs,
+        vec![ray_job("raysubmit_1", RayJobStatus::Running, Some("etl"))]
+    );
+    assert_eq!(
+        missing
+            .iter()
+            .map(|(job_name, error)| format!("{job_name}: {error}"))
+            .collect::<Vec<_>>(),
+        ["train: Submission raysubmit_2 is no longer listed on the cluster"]
+    );
+}
+
+fn submission(job_name: &str, submission_id: &str, cluster: &str) -> state::Submission {
+    state::Submission {
+        job_name: job_name.into(),
+        submission_id: submission_id.into(),
+        cluster: cluster.into(),
+        submitted_at: 0,
+    }
+}
+
+#[rstest::rstest]
+#[case("example-job", vec!["raysubmit_2", "raysubmit_3"])]
+#[case("raysubmit_1", vec!["raysubmit_1"])]
+#[case("other-job", vec!["raysubmit_6"])]
+#[case("unknown-job", vec!["unknown-job"])]
+fn test_resolve_jobs_to_stop(#[case] job: &str, #[case] expected: Vec<&str>) {
+    let ray_jobs = vec![
+        ray_job("raysubmit_1", RayJobStatus::Succeeded, Some("example-job")),
+        ray_job("raysubmit_2", RayJobStatus::Running, Some("example-job")),
+        ray_job("raysubmit_3", RayJobStatus::Pending, Some("example-job")),
+        ray_job("raysubmit_4", RayJobStatus::Running, None),
+    ];
+    let submissions = vec![
+        submission("other-job", "raysubmit_5", "test"),
+        submission("other-job", "raysubmit_6", "test"),
+        submission("other-job", "raysubmit_7", "other-cluster"),
+    ];
+    assert_eq!(
+        resolve_jobs_to_stop(&ray_jobs, &submissions, "test", job),
+        expected
+    );
+}
+
+#[test]
+fn test_parse_submissions() {
+    let expected = vec![
+        submission("example-job", "raysubmit_1", "test"),
+        submission("example-job", "raysubmit_2", "test"),
+    ];
+    let mut contents = expected
+        .iter()
+        .map(|submission| serde_json::to_string(submission).unwrap())
+        .collect::<Vec<_>>()
+        .join("\n");
+    // A partially written record should be skipped.
+    contents.push_str("\n{\"job_name\": \"exam");
+    let actual = state::parse_records::<state::Submission>(&contents);
+    assert_eq!(actual, expected);
+    assert_eq!(
+        state::latest_submission(&actual, "test", "example-job"),
+        expected.last()
+    );
+}
+
+fn parse_byoc_config_with_jobs(jobs: &str) -> DaftConfig {
+    try_parse_byoc_config_with_jobs(jobs).unwrap()
+}
+
+fn try_parse_byoc_config_with_jobs(jobs: &str) -> Result<DaftConfig, toml::de::Error> {
+    let contents = format!(
+        r#"
+[setup]
+name = "test"
+requires = "={version}"
+python-version = "3.12"
+ray-version = "2.34"
+
+[setup.byoc]
+
+{jobs}
+"#,
+        version = env!("CARGO_PKG_VERSION"),
+    );
+    toml::from_str(&contents)
+}
+
+#[rstest::rstest]
+#[case(
+    r#"
+[[job]]
+name = "example-job"
+command = "python main.py"
+working-dir = "."
+"#,
+    "{}"
+)]
+#[case(
+    r#"
+[[job]]
+name = "example-job"
+command = "python main.py"
+working-dir = "."
+env = { B = "2", A = "1" }
+"#,
+    r#"{"env_vars":{"A":"1","B":"2"}}"#
+)]
+#[case(
+    r#"
+[[job]]
+name = "example-job"
+command = "python main.py"
+working-dir = "."
+dependencies = ["requests==2.32.3", "numpy"]
+"#,
+    r#"{"pip":["requests==2.32.3","numpy"]}"#
+)]
+fn test_job_runtime_env(#[case] jobs: &str, #[case] expected: &str) {
+    let daft_config = parse_byoc_config_with_jobs(jobs);
+    let runtime_env = daft_config.jobs["example-job"].runtime_env(&BTreeMap::new());
+    assert_eq!(serde_json::to_string(&runtime_env).unwrap(), expected);
+}
+
+#[rstest::rstest]
+#[case(&["python", "main.py"], "python main.py")]
+#[case(&["python", "sql.py", "SELECT * FROM t"], "python sql.py 'SELECT * FROM t'")]
+#[case(&["echo", "it's"], r#"echo 'it'"'"'s'"#)]
+#[case(&["echo", ""], "echo ''")]
+fn test_entrypoint(#[case] command_segments: &[&str], #[case] expected: &str) {
+    assert_eq!(ray_jobs::entrypoint(command_segments), expected);
+}
+
+#[rstest::rstest]
+#[case("0 2 * * *", true)]
+#[case("*/15 0-6 1,15 * MON-FRI", true)]
+#[case("@daily", true)]
+#[case("0 2 * *", false)]
+#[case("0 2 * * * *", false)]
+#[case("0 2 * * ; rm -rf /", false)]
+fn test_validate_cron_expression(#[case] cron: &str, #[case] valid: bool) {
+    assert_eq!(schedule::validate_cron_expression(cron).is_ok(), valid);
+}
+
+#[test]
+fn test_update_crontab() {
+    let config = Path::new("/home/user/.daft.toml");
+    let exe = Path::new("/usr/local/bin/daft");
+    let marker = schedule::marker(config, "example-job");
+    let entry =
+        schedule::generate_crontab_entry("0 2 * * *", exe, config, "example-job", None, None);
+    assert_eq!(
+        entry,
+        "0 2 * * * /usr/local/bin/daft job submit example-job /home/user/.daft.toml # daft-launcher:/home/user/.daft.toml:example-job",
+    );
+    assert_eq!(
+        schedule::generate_crontab_entry(
+            "0 2 * * *",
+            exe,
+            config,
+            "example-job",
+            Some("prod"),
+            Some("eu-west-1"),
+        ),
+        "0 2 * * * /usr/local/bin/daft --profile prod --region eu-west-1 job submit example-job /home/user/.daft.toml # daft-launcher:/home/user/.daft.toml:example-job",
+    );
+    let percent_config = Path::new("/home/user/100%/.daft.toml");
+    assert_eq!(
+        schedule::generate_crontab_entry("@daily", exe, percent_config, "example-job", None, None),
+        r"@daily /usr/local/bin/daft job submit example-job '/home/user/100\%/.daft.toml' # daft-launcher:/home/user/100\%/.daft.toml:example-job",
+    );
+
+    let existing = "0 0 * * * backup.sh\n";
+    let installed = schedule::update_crontab(existing, &marker, Some(&entry));
+    assert_eq!(installed, format!("0 0 * * * backup.sh\n{entry}\n"));
+
+    let replaced = schedule::update_crontab(&installed, &marker, Some(&entry));
+    assert_eq!(replaced, installed);
+
+    let removed = schedule::update_crontab(&installed, &marker, None);
+    assert_eq!(removed, existing);
+}
+
+#[rstest::rstest]
+#[case(&["daft", "job", "submit", "example-job"], &[])]
+#[case(&["daft", "job", "submit", "example-job", "--", "--date", "2024-01-01"], &["--date", "2024-01-01"])]
+#[case(&["daft", "job", "submit", "example-job", "my.toml", "--", "-v"], &["-v"])]
+fn test_submit_args_passthrough(#[case] argv: &[&str], #[case] expected: &[&str]) {
+    let DaftLauncher {
+        sub_command: SubCommand::Job(JobCommand::Submit(Submit { args, .. })),
+        ..
+    } = DaftLauncher::try_parse_from(argv).unwrap()
+    else {
+        panic!("Expected a `job submit` command");
+    };
+    let args = args.iter().map(AsRef::as_ref).collect::<Vec<&str>>();
+    assert_eq!(args, expected);
+}
+
+#[test]
+fn test_submit_watch_conflicts_with_dry_run() {
+    assert!(
+        DaftLauncher::try_parse_from(["daft", "job", "submit", "example-job", "--watch"]).is_ok()
+    );
+    assert!(DaftLauncher::try_parse_from([
+        "daft",
+        "job",
+        "submit",
+        "example-job",
+        "--watch",
+        "--dry-run"
+    ])
+    .is_err());
+}
+
+#[test]
+fn test_run_requires_command() {
+    assert!(DaftLauncher::try_parse_from(["daft", "job", "run"]).is_err());
+
+    let DaftLauncher {
+        sub_command: SubCommand::Job(JobCommand::Run(run)),
+        ..
+    } = DaftLauncher::try_parse_from(["daft", "job", "run", "--", "python", "train.py"]).unwrap()
+    else {
+        panic!("Expected a `job run` command");
+    };
+    assert_eq!(run.working_dir, PathBuf::from("."));
+    assert_eq!(run.command, vec![StrRef::from("python"), "train.py".into()]);
+}
+
+#[rstest::rstest]
+#[case("data/train.parquet", false, &["*.parquet"], true)]
+#[case("data", true, &["data/"], true)]
+#[case("data", false, &["data/"], false)]
+#[case("src/data", true, &["/data"], false)]
+#[case("src/data", true, &["src/data"], true)]
+#[case("src/main.py", false, &[".git/", "*.parquet"], false)]
+fn test_is_excluded(
+    #[case] relative_path: &str,
+    #[case] is_dir: bool,
+    #[case] excludes: &[&str],
+    #[case] expected: bool,
+) {
+    let excludes = excludes
+        .iter()
+        .map(|&exclude| exclude.into())
+        .collect::<Vec<StrRef>>();
+    assert_eq!(
+        working_dir::is_excluded(Path::new(relative_path), is_dir, &excludes),
+        expected
+    );
+}
+
+#[test]
+fn test_largest_paths() {
+    let entry = |relative_path: &str, size| working_dir::Entry {
+        relative_path: relative_path.into(),
+        is_dir: size == 0,
+        size,
+    };
+    let entries = vec![
+        entry("data", 0),
+        entry("data/a.parquet", 300),
+        entry("data/b.parquet", 200),
+        entry("model.bin", 400),
+        entry("main.py", 10),
+    ];
+    assert_eq!(
+        working_dir::largest_paths(&entries),
+        vec![
+            (PathBuf::from("data"), 500),
+            (PathBuf::from("model.bin"), 400),
+            (PathBuf::from("main.py"), 10),
+        ],
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_walk_skips_symlink_loops() {
+    let temp_dir = TempDir::new("test_walk_skips_symlink_loops").unwrap();
+    let dir = temp_dir.path();
+    std::fs::create_dir_all(dir.join("a")).unwrap();
+    std::fs::create_dir_all(dir.join("b")).unwrap();
+    std::fs::write(dir.join("a/main.py"), "print(1)").unwrap();
+    std::os::unix::fs::symlink(dir, dir.join("a/root")).unwrap();
+    std::os::unix::fs::symlink(dir.join("b"), dir.join("a/to_b")).unwrap();
+    std::os::unix::fs::symlink(dir.join("a"), dir.join("b/to_a")).unwrap();
+
+    let paths = working_dir::walk(dir, &[])
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.relative_path.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    // Each symlink is followed until it leads back into a directory on the way
+    // to it.
+    assert_eq!(
+        paths,
+        ["a", "b", "b/to_a", "b/to_a/main.py", "a/main.py", "a/to_b",]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_walk_skips_dangling_symlinks() {
+    let temp_dir = TempDir::new("test_walk_skips_dangling_symlinks").unwrap();
+    let dir = temp_dir.path();
+    std::fs::write(dir.join("main.py"), "print(1)").unwrap();
+    std::os::unix::fs::symlink(dir.join("missing"), dir.join("dangling")).unwrap();
+
+    let paths = working_dir::walk(dir, &[])
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.relative_path.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(paths, ["main.py"]);
+}
+
+#[rstest::rstest]
+#[case("python train.py", &[], Some(&["python", "train.py"][..]))]
+#[case("python train.py --lr {lr}", &[("lr", "0.01")], Some(&["python", "train.py", "--lr", "0.01"][..]))]
+#[case("python train.py --lr {lr} --min-lr {lr}", &[("lr", "0.01")], Some(&["python", "train.py", "--lr", "0.01", "--min-lr", "0.01"][..]))]
+#[case("python train.py --lr {lr}", &[("lr", "0.01"), ("lr", "0.1")], Some(&["python", "train.py", "--lr", "0.1"][..]))]
+#[case("python train.py --name {name}", &[("name", "my 'first' run")], Some(&["python", "train.py", "--name", "my 'first' run"][..]))]
+#[case("python train.py '--lr={lr}'", &[("lr", "0.01")], Some(&["python", "train.py", "--lr=0.01"][..]))]
+#[case(r#"python -c "print(f'{x}')""#, &[], Some(&["python", "-c", "print(f'{x}')"][..]))]
+#[case("python train.py --lr {lr}", &[], None)]
+#[case("python train.py --out s3://bucket/{lr}/model", &[("lr", "0.01")], None)]
+#[case("python train.py", &[("lr", "0.01")], None)]
+fn test_command_segments(
+    #[case] command: &str,
+    #[case] variables: &[(&str, &str)],
+    #[case] expected: Option<&[&str]>,
+) {
+    let daft_job = DaftJob {
+        command: command.into(),
+        source: JobSource::WorkingDir(Arc::from(Path::new("."))),
+        env: BTreeMap::default(),
+        dependencies: vec![],
+        excludes: vec![],
+        working_dir_size_limit: None,
+        upload_to: None,
+        outputs: vec![],
+        outputs_uri: None,
+        entrypoint_resources: EntrypointResources::default(),
+        depends_on: vec![],
+    };
+    let variables = variables
+        .iter()
+        .map(|&(key, value)| (key.into(), value.into()))
+        .collect::<Vec<(StrRef, StrRef)>>();
+    assert_eq!(
+        daft_job.command_segments(&variables).ok(),
+        expected.map(|expected| expected.iter().map(ToString::to_string).collect())
+    );
+}
+
+#[rstest::rstest]
+#[case("python main.py", Some(&["python", "main.py"][..]))]
+#[case("python  main.py  --verbose", Some(&["python", "main.py", "--verbose"][..]))]
+#[case(r#"python main.py --name "my run""#, Some(&["python", "main.py", "--name", "my run"][..]))]
+#[case(r#"python main.py --name 'my "run"'"#, Some(&["python", "main.py", "--name", r#"my "run""#][..]))]
+#[case(r"python main.py --name my\ run", Some(&["python", "main.py", "--name", "my run"][..]))]
+#[case(r#"python main.py --name """#, Some(&["python", "main.py", "--name", ""][..]))]
+#[case(r#"python main.py --name "my run"#, None)]
+fn test_split_command(#[case] command: &str, #[case] expected: Option<&[&str]>) {
+    let segments = split_command(command).ok();
+    assert_eq!(
+        segments
+            .as_ref()
+            .map(|segments| segments.iter().map(String::as_str).collect::<Vec<_>>())
+            .as_deref(),
+        expected,
+    );
+}
+
+#[rstest::rstest]
+#[case(
+    r#"
+[[job]]
+name = "train"
+command = "python train.py"
+working-dir = "."
+depends-on = ["prepare-data", "download-model"]
+
+[[job]]
+name = "prepare-data"
+command = "python prepare.py"
+working-dir = "."
+depends-on = ["download-data"]
+
+[[job]]
+name = "download-data"
+command = "python download.py"
+working-dir = "."
+
+[[job]]
+name = "download-model"
+command = "python download.py --model"
+working-dir = "."
+"#,
+    vec!["download-data", "download-model", "prepare-data", "train"]
+)]
+fn test_topological_order(#[case] jobs: &str, #[case] expected: Vec<&str>) {
+    let daft_config = parse_byoc_config_with_jobs(jobs);
+    let order = topological_order(&daft_config.jobs).unwrap();
+    assert_eq!(
+        order.iter().map(AsRef::as_ref).collect::<Vec<&str>>(),
+        expected
+    );
+
+    let selected = with_dependencies(&daft_config.jobs, &["prepare-data".into()]);
+    let mut selected = selected.iter().map(AsRef::as_ref).collect::<Vec<&str>>();
+    selected.sort();
+    assert_eq!(selected, vec!["download-data", "prepare-data"]);
+}
+
+#[rstest::rstest]
+#[case(
+    r#"
+[[job]]
+name = "a"
+command = "python a.py"
+working-dir = "."
+depends-on = ["b"]
+
+[[job]]
+name = "b"
+command = "python b.py"
+working-dir = "."
+depends-on = ["a"]
+"#
+)]
+#[case(
+    r#"
+[[job]]
+name = "a"
+command = "python a.py"
+working-dir = "."
+depends-on = ["missing"]
+"#
+)]
+fn test_invalid_job_dependencies(#[case] jobs: &str) {
+    let contents = format!(
+        r#"
+[setup]
+name = "test"
+requires = "={version}"
+python-version = "3.12"
+ray-version = "2.34"
+
+[setup.byoc]
+
+{jobs}
+"#,
+        version = env!("CARGO_PKG_VERSION"),
+    );
+    assert!(toml::from_str::<DaftConfig>(&contents).is_err());
+}
+
+#[rstest::rstest]
+#[case("", None)]
+#[case(
+    r#"
+[notifications]
+webhook-url = "https://hooks.slack.com/services/example"
+"#,
+    Some(NotificationsConfig {
+        webhook_url: Some("https://hooks.slack.com/services/example".into()),
+        desktop: false,
+        terminal_title: false,
+        events: default_notification_events(),
+    }),
+)]
+#[case(
+    r#"
+[notifications]
+webhook-url = "https://hooks.slack.com/services/example"
+events = ["job-failed"]
+"#,
+    Some(NotificationsConfig {
+        webhook_url: Some("https://hooks.slack.com/services/example".into()),
+        desktop: false,
+        terminal_title: false,
+        events: vec![NotificationEvent::JobFailed],
+    }),
+)]
+#[case(
+    r#"
+[notifications]
+desktop = true
+terminal-title = true
+"#,
+    Some(NotificationsConfig {
+        webhook_url: None,
+        desktop: true,
+        terminal_title: true,
+        events: default_notification_events(),
+    }),
+)]
+fn test_notifications_config(
+    #[case] notifications: &str,
+    #[case] expected: Option<NotificationsConfig>,
+) {
+    let daft_config = parse_byoc_config_with_jobs(notifications);
+    assert_eq!(daft_config.notifications, expected);
+}
+
+#[rstest::rstest]
+#[case(
+    r#"
+[[job]]
+name = "example-job"
+command = "python main.py"
+working-dir = "."
+"#,
+    Some(JobSource::WorkingDir(Arc::from(Path::new("."))))
+)]
+#[case(
+    r#"
+[[job]]
+name = "example-job"
+command = "python main.py"
+source = { git = "https://github.com/org/repo", rev = "main" }
+"#,
+    Some(JobSource::Git(GitSource {
+        git: "https://github.com/org/repo".into(),
+        rev: "main".into(),
+    })),
+)]
+#[case(
+    r#"
+[[job]]
+name = "example-job"
+command = "python main.py"
+working-dir = "."
+source = { git = "https://github.com/org/repo", rev = "main" }
+"#,
+    None
+)]
+#[case(
+    r#"
+[[job]]
+name = "example-job"
+command = "python main.py"
+"#,
+    None
+)]
+fn test_job_source(#[case] jobs: &str, #[case] expected: Option<JobSource>) {
+    let source = try_parse_byoc_config_with_jobs(jobs)
+        .ok()
+        .map(|daft_config| daft_config.jobs["example-job"].source.clone());
+    assert_eq!(source, expected);
+}
+
+#[tokio::test]
+async fn test_dry_run_skips_git_checkout() {
+    let daft_config = parse_byoc_config_with_jobs(
+        r#"
+[[job]]
+name = "example-job"
+command = "python main.py"
+source = { git = "https://invalid.example.com/org/repo", rev = "main" }
+"#,
+    );
+    let working_dir = daft_config.jobs["example-job"]
+        .prepare_working_dir(true)
+        .await
+        .unwrap();
+    assert_eq!(&*working_dir.path, Path::new(""));
+}
+
+#[rstest::rstest]
+#[case("s3://bucket", Some(("bucket", "")))]
+#[case("s3://bucket/", Some(("bucket", "")))]
+#[case("s3://bucket/daft/packages/", Some(("bucket", "daft/packages")))]
+#[case("s3://bucket//daft/./packages", Some(("bucket", "daft/packages")))]
+#[case("s3://bucket/daft/../packages", None)]
+#[case("s3:///packages", None)]
+#[case("gs://bucket/packages", None)]
+fn test_parse_s3_uri(#[case] uri: &str, #[case] expected: Option<(&str, &str)>) {
+    assert_eq!(
+        s3::parse_s3_uri(uri).ok(),
+        expected.map(|(bucket, prefix)| (bucket, prefix.to_string()))
+    );
+}
+
+#[rstest::rstest]
+#[case("", Some("{}"))]
+#[case(
+    r#"
+entrypoint-num-cpus = 2
+entrypoint-num-gpus = 0.5
+entrypoint-resources = { large-memory = 1 }
+"#,
+    Some(r#"{"entrypoint_num_cpus":2.0,"entrypoint_num_gpus":0.5,"entrypoint_resources":{"large-memory":1.0}}"#),
+)]
+#[case("entrypoint-num-cpus = -1", None)]
+#[case("entrypoint-resources = { large-memory = -1 }", None)]
+fn test_entrypoint_resources(#[case] resources: &str, #[case] expected: Option<&str>) {
+    let jobs = format!(
+        r#"
+[[job]]
+name = "example-job"
+command = "python main.py"
+working-dir = "."
+{resources}
+"#
+    );
+    let resources = try_parse_byoc_config_with_jobs(&jobs)
+        .ok()
+        .map(|daft_config| {
+            serde_json::to_string(&daft_config.jobs["example-job"].entrypoint_resources).unwrap()
+        });
+    assert_eq!(resources.as_deref(), expected);
+}
+
+#[test]
+fn test_history() {
+    let submissions = vec![
+        submission("example-job", "raysubmit_1", "test"),
+        submission("other-job", "raysubmit_2", "test"),
+        submission("example-job", "raysubmit_3", "other-cluster"),
+        submission("example-job", "raysubmit_4", "test"),
+    ];
+    let outcomes = vec![state::Outcome {
+        submission_id: "raysubmit_1".into(),
+        status: "FAILED".into(),
+        duration: Some(42),
+        message: Some("Job entrypoint command failed with exit code 1".into()),
+    }];
+
+    let history = state::history(&submissions, &outcomes, "test", Some("example-job"));
+    let history = history
+        .iter()
+        .map(|(submission, outcome)| (&*submission.submission_id, outcome.is_some()))
+        .collect::<Vec<_>>();
+    assert_eq!(history, vec![("raysubmit_1", true), ("raysubmit_4", false)]);
+
+    assert_eq!(
+        state::history(&submissions, &outcomes, "test", None).len(),
+        3
+    );
+}
+
+#[rstest::rstest]
+#[case("results/part-0.parquet", &["results/*.parquet"], true)]
+#[case("results/part-0.parquet", &["./results/*.parquet"], true)]
+#[case("results/nested/part-0.parquet", &["results/**/*.parquet"], true)]
+#[case("results/part-0.csv", &["results/*.parquet"], false)]
+#[case("results/part-0.csv", &["results/*.parquet", "*/*.csv"], true)]
+fn test_outputs_matches_any(
+    #[case] relative_path: &str,
+    #[case] patterns: &[&str],
+    #[case] expected: bool,
+) {
+    let patterns = patterns
+        .iter()
+        .map(|&pattern| pattern.into())
+        .collect::<Vec<StrRef>>();
+    assert_eq!(outputs::matches_any(relative_path, &patterns), expected);
+}
+
+#[rstest::rstest]
+#[case("results/part-0.parquet", Ok(Some("results/part-0.parquet")))]
+#[case("/results//./part-0.parquet", Ok(Some("results/part-0.parquet")))]
+#[case("results/", Ok(None))]
+#[case("/", Ok(None))]
+#[case("", Ok(None))]
+#[case("../escaped.parquet", Err(()))]
+#[case("results/../../escaped.parquet", Err(()))]
+fn test_outputs_normalize_key(#[case] key: &str, #[case] expected: Result<Option<&str>, ()>) {
+    assert_eq!(
+        outputs::normalize_key(key).map_err(|_| ()),
+        expected.map(|path| path.map(String::from))
+    );
+}
+
+#[rstest::rstest]
+#[case("results/*.parquet", true)]
+#[case("./results/**/*.csv", true)]
+#[case("/var/log/*.log", false)]
+#[case("../*.csv", false)]
+#[case("results/../../*.csv", false)]
+fn test_outputs_check_relative(#[case] path: &str, #[case] expected: bool) {
+    assert_eq!(outputs::check_relative(path).is_ok(), expected);
+}
+
+#[rstest::rstest]
+#[case(
+    "gcs://_ray_pkg_abc123.zip",
+    Some("/tmp/ray/session_latest/runtime_resources/working_dir_files/_ray_pkg_abc123")
+)]
+#[case("s3://bucket/_ray_pkg_abc123.zip", None)]
+fn test_head_node_working_dir(#[case] working_dir_uri: &str, #[case] expected: Option<&str>) {
+    assert_eq!(
+        outputs::head_node_working_dir(working_dir_uri)
+            .ok()
+            .as_deref(),
+        expected
+    );
+}
+
+#[rstest::rstest]
+#[case(
+    ray_job("raysubmit_1", RayJobStatus::Running, Some("example-job")),
+    r#"{"event":"running","job_name":"example-job","submission_id":"raysubmit_1"}"#
+)]
+#[case(
+    RayJob {
+        start_time: Some(1_000),
+        end_time: Some(43_000),
+        message: Some("Job entrypoint command failed with exit code 1".into()),
+        ..ray_job("raysubmit_1", RayJobStatus::Failed, None)
+    },
+    r#"{"event":"failed","submission_id":"raysubmit_1","duration":42,"reason":"Job entrypoint command failed with exit code 1"}"#
+)]
+#[case(
+    RayJob {
+        message: Some("Job finished successfully.".into()),
+        ..ray_job("raysubmit_1", RayJobStatus::Succeeded, Some("example-job"))
+    },
+    r#"{"event":"succeeded","job_name":"example-job","submission_id":"raysubmit_1"}"#
+)]
+fn test_job_event(#[case] ray_job: RayJob, #[case] expected: &str) {
+    let event = events::JobEvent::from_ray_job(&ray_job, Duration::from_secs(100));
+    assert_eq!(serde_json::to_string(&event).unwrap(), expected);
+}
+
+#[rstest::rstest]
+#[case("SELECT 1;", Some("SELECT 1"))]
+#[case(
+    "SELECT *\nFROM t\nWHERE x = 1;  ",
+    Some("SELECT *\nFROM t\nWHERE x = 1")
+)]
+#[case("SELECT *\nFROM t", None)]
+#[case(";", Some(""))]
+fn test_complete_statement(#[case] buffer: &str, #[case] expected: Option<&str>) {
+    assert_eq!(sql::complete_statement(buffer), expected);
+}
+
+#[test]
+fn test_sql_interactive_args() {
+    assert!(DaftLauncher::try_parse_from(["daft", "job", "sql"]).is_err());
+    assert!(DaftLauncher::try_parse_from(["daft", "job", "sql", "--interactive"]).is_ok());
+    assert!(
+        DaftLauncher::try_parse_from(["daft", "job", "sql", "--interactive", "--dry-run"]).is_err()
+    );
+}
+
+#[rstest::rstest]
+#[case("SELECT * FROM t WHERE date = :date", &[("date", "2024-06-01")], Some("SELECT * FROM t WHERE date = '2024-06-01'"))]
+#[case("SELECT * FROM t WHERE n > :n AND ok = :ok", &[("n", "5"), ("ok", "true")], Some("SELECT * FROM t WHERE n > 5 AND ok = true"))]
+#[case("SELECT * FROM t WHERE name = :name", &[("name", "x' OR '1'='1")], Some("SELECT * FROM t WHERE name = 'x'' OR ''1''=''1'"))]
+#[case("SELECT ':date', \":date\", x::int FROM t", &[], Some("SELECT ':date', \":date\", x::int FROM t"))]
+#[case("SELECT * FROM t WHERE a = :a OR b = :a", &[("a", "1"), ("a", "2")], Some("SELECT * FROM t WHERE a = 2 OR b = 2"))]
+#[case("SELECT * FROM t WHERE date = :date", &[], None)]
+fn test_bind_parameters(
+    #[case] sql: &str,
+    #[case] params: &[(&str, &str)],
+    #[case] expected: Option<&str>,
+) {
+    let params = params
+        .iter()
+        .map(|&(key, value)| (key.into(), value.into()))
+        .collect::<Vec<(StrRef, StrRef)>>();
+    assert_eq!(sql::bind_parameters(sql, &params).ok().as_deref(), expected);
+}
+
+#[rstest::rstest]
+#[case(&["daft", "job", "sql", "SELECT 1"], &["python", "sql.py", "--", "SELECT 1"])]
+#[case(&["daft", "job", "sql", "SELECT 1", "--limit", "20"], &["python", "sql.py", "--limit", "20", "--", "SELECT 1"])]
+#[case(
+    &["daft", "job", "sql", "SELECT 1", "--limit", "20", "--max-column-width", "40"],
+    &["python", "sql.py", "--limit", "20", "--max-column-width", "40", "--", "SELECT 1"],
+)]
+#[case(&["daft", "job", "sql", "SELECT 1", "--explain"], &["python", "sql.py", "--explain", "--", "SELECT 1"])]
+fn test_sql_preview_command_segments(#[case] argv: &[&str], #[case] expected: &[&str]) {
+    let DaftLauncher {
+        sub_command: SubCommand::Job(JobCommand::Sql(Sql { sql, preview, .. })),
+        ..
+    } = DaftLauncher::try_parse_from(argv).unwrap()
+    else {
+        panic!("Expected a `job sql` command");
+    };
+    assert_eq!(preview.command_segments(&[], &sql.unwrap()), expected);
+}
+
+#[test]
+fn test_sql_explain_conflicts_with_limit() {
+    assert!(DaftLauncher::try_parse_from([
+        "daft",
+        "job",
+        "sql",
+        "SELECT 1",
+        "--explain",
+        "--limit",
+        "5"
+    ])
+    .is_err());
+}
+
+#[test]
+fn test_sql_setup() {
+    let daft_config = parse_byoc_config_with_jobs(
+        r#"
+[sql]
+setup = ["CREATE TABLE t AS SELECT * FROM read_parquet('s3://bucket/t/*.parquet')"]
+"#,
+    );
+    let preview = SqlPreview {
+        limit: None,
+        max_column_width: None,
+        explain: false,
+    };
+    assert_eq!(
+        preview.command_segments(&daft_config.sql.setup, "SELECT * FROM t"),
+        [
+            "python",
+            "sql.py",
+            "--setup",
+            "CREATE TABLE t AS SELECT * FROM read_parquet('s3://bucket/t/*.parquet')",
+            "--",
+            "SELECT * FROM t",
+        ]
+    );
+}
+
+/// A stand-in for the `daft` module, which records what sql.py does with it,
+/// and fails queries of tables which were not registered.
+const FAKE_DAFT: &str = r#"
+import re
+
+tables = {}
+
+class DataFrame:
+    def __init__(self, statement):
+        self.statement = statement
+
+    def collect(self):
+        print(f"collect {self.statement}")
+        return self
+
+    def show(self, **kwargs):
+        print(f"show {self.statement}")
+
+def sql(statement):
+    for table in re.findall(r"FROM (\w+)\b(?!\()", statement):
+        if table not in tables:
+            raise ValueError(f"no table {table}")
+    return DataFrame(statement)
+
+def create_temp_table(name, df):
+    print(f"create {name}")
+    tables[name] = df
+
+class context:
+    @staticmethod
+    def set_runner_ray():
+        pass
+"#;
+
+#[tokio::test]
+async fn test_sql_script_runs_setup() {
+    let dir = TempDir::new("test_sql_script_runs_setup").unwrap();
+    fs::create_dir(dir.path().join("daft")).await.unwrap();
+    fs::write(dir.path().join("daft").join("__init__.py"), FAKE_DAFT)
+        .await
+        .unwrap();
+    fs::write(dir.path().join("sql.py"), asset!("sql.py"))
+        .await
+        .unwrap();
+    let preview = SqlPreview {
+        limit: None,
+        max_column_width: None,
+        explain: false,
+    };
+    let run = |setup: &[StrRef]| {
+        let command_segments = preview.command_segments(setup, "SELECT count(*) FROM trips");
+        std::process::Command::new("python3")
+            .args(&command_segments[1..])
+            .current_dir(dir.path())
+            .output()
+            .unwrap()
+    };
+
+    let output =
+        run(&["CREATE TABLE trips AS SELECT * FROM read_parquet('s3://bucket/trips')".into()]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "collect SELECT * FROM read_parquet('s3://bucket/trips')\n\
+         create trips\n\
+         show SELECT count(*) FROM trips\n"
+    );
+
+    let output = run(&[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no table trips"));
+}
+
+#[test]
+fn test_expand_and_check_path() {
+    let home = crate::home_dir().unwrap();
+    let expanded = expand_and_check_path(Arc::from(Path::new("~/.ssh/daft-key"))).unwrap();
+    assert_eq!(&*expanded, home.join(".ssh").join("daft-key").as_path());
+    let expanded = expand_and_check_path(Arc::from(Path::new("keys/daft-key"))).unwrap();
+    assert_eq!(&*expanded, Path::new("keys/daft-key"));
+}
+
+#[test]
+fn test_format_connect_env() {
+    assert_eq!(
+        format_connect_env(1234),
+        "export RAY_ADDRESS=ray://localhost:10001\nexport DAFT_RUNNER=ray\nexport DAFT_LAUNCHER_TUNNEL_PID=1234\n"
+    );
+}
+
+#[tokio::test]
+async fn test_config_extends() {
+    let temp_dir = TempDir::new("test_config_extends").unwrap();
+    let base_path = temp_dir.path().join("base.daft.toml");
+    let local_path = temp_dir.path().join("local.daft.toml");
+    fs::write(
+        &base_path,
+        format!(
+            r#"
+[setup]
+name = "shared"
+requires = "={version}"
+python-version = "3.12"
+ray-version = "2.34"
+
+[setup.byoc]
+namespace = "team"
+
+[[job]]
+name = "base-job"
+command = "python base.py"
+working-dir = "."
+"#,
+            version = env!("CARGO_PKG_VERSION"),
+        ),
+    )
+    .await
+    .unwrap();
+    fs::write(
+        &local_path,
+        r#"
+extends = "base.daft.toml"
+
+[setup.byoc]
+namespace = "mine"
+
+[[job]]
+name = "my-job"
+command = "python mine.py"
+working-dir = "."
+"#,
+    )
+    .await
+    .unwrap();
+
+    let daft_config = read_daft_config(&local_path, None).await.unwrap();
+    assert_eq!(&*daft_config.setup.name, "shared");
+    let ProviderConfig::Byoc(k8s_config) = &daft_config.setup.provider_config else {
+        panic!("Expected a BYOC configuration");
+    };
+    assert_eq!(&*k8s_config.namespace, "mine");
+    assert_eq!(
+        daft_config
+            .jobs
+            .keys()
+            .map(AsRef::as_ref)
+            .collect::<Vec<&str>>(),
+        ["my-job"]
+    );
+
+    fs::write(&base_path, r#"extends = "local.daft.toml""#)
+        .await
+        .unwrap();
+    assert!(read_daft_config(&local_path, None).await.is_err());
+}
+
+#[tokio::test]
+#[rstest::rstest]
+#[case(None, "default", Some(4))]
+#[case(Some("dev"), "default", Some(1))]
+#[case(Some("prod"), "prod", Some(16))]
+#[case(Some("staging"), "default", None)]
+async fn test_config_profiles(
+    #[case] profile: Option<&str>,
+    #[case] expected_instance_type: &str,
+    #[case] expected_number_of_workers: Option<usize>,
+) {
+    let temp_dir = TempDir::new("test_config_profiles").unwrap();
+    let path = temp_dir.path().join(".daft.toml");
+    fs::write(
+        &path,
+        format!(
+            r#"
+[setup]
+name = "test"
+requires = "={version}"
+python-version = "3.12"
+ray-version = "2.34"
+
+[setup.provisioned]
+region = "us-west-2"
+number-of-workers = 4
+ssh-user = "ubuntu"
+ssh-private-key = "testkey.pem"
+instance-type = "default"
+image-id = "ami-04dd23e62ed049936"
+
+[profile.dev.setup.provisioned]
+number-of-workers = 1
+
+[profile.prod.setup.provisioned]
+number-of-workers = 16
+instance-type = "prod"
+"#,
+            version = env!("CARGO_PKG_VERSION"),
+        ),
+    )
+    .await
+    .unwrap();
+
+    let daft_config = read_daft_config(&path, profile).await;
+    let Some(expected_number_of_workers) = expected_number_of_workers else {
+        assert!(daft_config.is_err());
+        return;
+    };
+    let ProviderConfig::Provisioned(aws_config) = daft_config.unwrap().setup.provider_config else {
+        panic!("Expected a provisioned configuration");
+    };
+    assert_eq!(&*aws_config.instance_type, expected_instance_type);
+    assert_eq!(aws_config.number_of_workers, expected_number_of_workers);
+}
+
+#[test]
+fn test_profile_is_global() {
+    let launcher =
+        DaftLauncher::try_parse_from(["daft", "job", "list", "--profile", "prod"]).unwrap();
+    assert_eq!(launcher.profile.as_deref(), Some("prod"));
+}
+
+#[tokio::test]
+#[rstest::rstest]
+#[case(
+    ".daft.yaml",
+    r#"
+setup:
+  name: test
+  requires: "={version}"
+  python-version: "3.12"
+  ray-version: "2.34"
+  byoc: {}
+job:
+  - name: example-job
+    command: python main.py
+    working-dir: "."
+"#
+)]
+#[case(
+    ".daft.json",
+    r#"{
+  "setup": {
+    "name": "test",
+    "requires": "={version}",
+    "python-version": "3.12",
+    "ray-version": "2.34",
+    "byoc": {}
+  },
+  "job": [
+    { "name": "example-job", "command": "python main.py", "working-dir": "." }
+  ]
+}"#
+)]
+async fn test_config_formats(#[case] file_name: &str, #[case] contents: &str) {
+    let temp_dir = TempDir::new("test_config_formats").unwrap();
+    let path = temp_dir.path().join(file_name);
+    let contents = contents.replace("{version}", env!("CARGO_PKG_VERSION"));
+    fs::write(&path, contents).await.unwrap();
+
+    let expected = parse_byoc_config_with_jobs(
+        r#"
+[[job]]
+name = "example-job"
+command = "python main.py"
+working-dir = "."
+"#,
+    );
+    assert_eq!(read_daft_config(&path, None).await.unwrap(), expected);
+}
+
+#[test]
+fn test_migrate() {
+    let old = r#"
+[setup]
+name = "my-daft-cluster"
+version = "0.1.0"
+provider = "provisioned"
+dependencies = ["requests"] # Optional additional Python packages to install
+
+[setup.provisioned]
+region = "us-west-2"
+number-of-workers = 4
+ssh-user = "ubuntu"
+ssh-private-key = "~/.ssh/daft-key"
+
+[run]
+pre-setup-commands = ["echo pre"]
+post-setup-commands = ["echo post"]
+
+[[job]]
+name = "example-job"
+command = "python my_script.py"
+working-dir = "."
+"#;
+    let (migrated, notes) = migrate::migrate(old, "3.12", "2.34").unwrap();
+    assert!(notes.is_empty());
+    assert!(migrated.contains("# Optional additional Python packages to install"));
+
+    let daft_config = toml::from_str::<DaftConfig>(&migrated).unwrap();
+    let ProviderConfig::Provisioned(aws_config) = &daft_config.setup.provider_config else {
+        panic!("Expected a provisioned configuration");
+    };
+    assert_eq!(
+        aws_config.dependencies,
+        Dependencies::List(vec!["requests".into()])
+    );
+    assert_eq!(aws_config.pre_setup_commands, [StrRef::from("echo pre")]);
+    assert_eq!(aws_config.run, [StrRef::from("echo post")]);
+    assert_eq!(daft_config.jobs.len(), 1);
+
+    let (remigrated, _) = migrate::migrate(&migrated, "3.12", "2.34").unwrap();
+    assert_eq!(remigrated, migrated);
+}
+
+#[test]
+fn test_show_config_round_trips() {
+    let daft_config = parse_byoc_config_with_jobs(
+        r#"
+[[job]]
+name = "train"
+command = "python train.py"
+working-dir = "."
+entrypoint-num-gpus = 1
+depends-on = ["prepare"]
+
+[[job]]
+name = "prepare"
+command = "python prepare.py"
+source = { git = "https://github.com/org/repo", rev = "main" }
+outputs = ["data/*.parquet"]
+
+[sql]
+setup = ["CREATE TABLE t AS SELECT 1"]
+"#,
+    );
+
+    let shown = toml::to_string(&daft_config).unwrap();
+    assert_eq!(toml::from_str::<DaftConfig>(&shown).unwrap(), daft_config);
+
+    let shown = serde_json::to_value(&daft_config).unwrap();
+    assert_eq!(shown["setup"]["byoc"]["namespace"], "default");
+    assert_eq!(shown["job"][0]["name"], "prepare");
+    assert_eq!(shown["job"][1]["working-dir-size-limit"], 100);
+}
+
+#[rstest::rstest]
+#[case("", Some(working_dir::DEFAULT_SIZE_LIMIT_MIB))]
+#[case(r#"upload-to = "s3://bucket/packages""#, None)]
+#[case(
+    "working-dir-size-limit = 1024\nupload-to = \"s3://bucket/packages\"",
+    Some(1024)
+)]
+fn test_working_dir_size_limit(#[case] settings: &str, #[case] expected: Option<u64>) {
+    let daft_config = parse_byoc_config_with_jobs(&format!(
+        r#"
+[[job]]
+name = "train"
+command = "python train.py"
+working-dir = "."
+{settings}
+"#
+    ));
+    assert_eq!(daft_config.jobs["train"].working_dir_size_limit, expected);
+}
+
+const EDITABLE_CONFIG: &str = r#"[setup]
+name = "my-daft-cluster"
+python-version = "3.12"
+
+# AWS-specific configuration
+[setup.provisioned]
+number-of-workers = 4 # Optional
+
+[[job]]
+name = "example-job"
+command = "python my_script.py"
+"#;
+
+#[rstest::rstest]
+#[case("setup.name", Some("my-daft-cluster"))]
+#[case("setup.provisioned.number-of-workers", Some("4"))]
+#[case("job.0.command", Some("python my_script.py"))]
+#[case("setup.provisioned.region", None)]
+#[case("setup..name", None)]
+fn test_config_get(#[case] path: &str, #[case] expected: Option<&str>) {
+    assert_eq!(edit::get(EDITABLE_CONFIG, path).ok().as_deref(), expected);
+}
+
+#[rstest::rstest]
+#[case(
+    "setup.provisioned.number-of-workers",
+    "8",
+    Some("number-of-workers = 8 # Optional\n")
+)]
+#[case("setup.python-version", "3.11", Some("python-version = \"3.11\"\n"))]
+#[case(
+    "setup.provisioned.region",
+    "us-east-1",
+    Some("region = \"us-east-1\"\n")
+)]
+#[case("job.0.env", "{ DEBUG = \"1\" }", Some("env = { DEBUG = \"1\" }\n"))]
+#[case("setup.provisioned", "8", None)]
+#[case("setup.name.first", "8", None)]
+fn test_config_set(#[case] path: &str, #[case] value: &str, #[case] expected: Option<&str>) {
+    let Some(expected) = expected else {
+        assert!(edit::set(EDITABLE_CONFIG, path, value).is_err());
+        return;
+    };
+    let edited = edit::set(EDITABLE_CONFIG, path, value).unwrap();
+    assert!(edited.contains(expected));
+    assert!(edited.contains("# AWS-specific configuration"));
+}
+
+#[rstest::rstest]
+#[case(
+    "ssm:/team/daft/ssh-user",
+    Some(secrets::SecretReference::Ssm("/team/daft/ssh-user"))
+)]
+#[case(
+    "secretsmanager:daft/webhook",
+    Some(secrets::SecretReference::SecretsManager("daft/webhook"))
+)]
+#[case("ssm:", None)]
+#[case("vault:daft", None)]
+#[case("/team/daft/ssh-user", None)]
+fn test_secret_reference(
+    #[case] reference: &str,
+    #[case] expected: Option<secrets::SecretReference<'_>>,
+) {
+    assert_eq!(secrets::SecretReference::parse(reference).ok(), expected);
+}
+
+#[test]
+fn test_substitute_secrets() {
+    let mut table = toml::from_str::<toml::Table>(
+        r#"
+[setup.provisioned]
+ssh-user = { secret = "ssm:/team/daft/ssh-user" }
+region = "us-west-2"
+
+[[job]]
+name = "example-job"
+env = { TOKEN = { secret = "secretsmanager:daft/token" }, DEBUG = "1" }
+"#,
+    )
+    .unwrap();
+    let mut references = secrets::references(&table);
+    references.sort();
+    assert_eq!(
+        references,
+        ["secretsmanager:daft/token", "ssm:/team/daft/ssh-user"]
+    );
+
+    let resolved = [
+        ("ssm:/team/daft/ssh-user", "ubuntu"),
+        ("secretsmanager:daft/token", "hunter2"),
+    ]
+    .into_iter()
+    .map(|(reference, value)| (reference.to_string(), value.to_string()))
+    .collect();
+    secrets::substitute_all(&mut table, &resolved);
+    assert_eq!(
+        table["setup"]["provisioned"]["ssh-user"].as_str(),
+        Some("ubuntu")
+    );
+    assert_eq!(table["job"][0]["env"]["TOKEN"].as_str(), Some("hunter2"));
+    assert_eq!(table["job"][0]["env"]["DEBUG"].as_str(), Some("1"));
+}
+
+#[test]
+fn test_redact_secrets() {
+    let mut table = toml::from_str::<toml::Table>(
+        r#"
+[setup.provisioned]
+ssh-user = { secret = "ssm:/team/daft/ssh-user" }
+region = "us-west-2"
+"#,
+    )
+    .unwrap();
+    secrets::redact_all(&mut table);
+    assert_eq!(
+        table["setup"]["provisioned"]["ssh-user"].as_str(),
+        Some("<secret ssm:/team/daft/ssh-user>")
+    );
+    assert_eq!(
+        table["setup"]["provisioned"]["region"].as_str(),
+        Some("us-west-2")
+    );
+}
+
+#[rstest::rstest]
+#[case("ssh-rsa AAAAB3NzaC1yc2E daft-key", Some("AAAAB3NzaC1yc2E"))]
+#[case("ssh-ed25519 AAAAC3NzaC1lZDI1NTE5\n", Some("AAAAC3NzaC1lZDI1NTE5"))]
+#[case("", None)]
+fn test_public_key_material(#[case] public_key: &str, #[case] expected: Option<&str>) {
+    assert_eq!(cloud_check::public_key_material(public_key), expected);
+}
+
+#[tokio::test]
+#[rstest::rstest]
+#[case(None, 4)]
+#[case(Some("prod"), 16)]
+async fn test_user_defaults(
+    #[case] profile: Option<&str>,
+    #[case] expected_number_of_workers: usize,
+) {
+    let temp_dir = TempDir::new("test_user_defaults").unwrap();
+    let path = temp_dir.path().join(".daft.toml");
+    fs::write(
+        &path,
+        format!(
+            r#"
+[setup]
+name = "test"
+requires = "={version}"
+python-version = "3.12"
+ray-version = "2.34"
+
+[setup.provisioned]
+ssh-user = "ec2-user"
+
+[profile.dev.setup.provisioned]
+number-of-workers = 1
+
+[profile.prod.setup.provisioned]
+number-of-workers = 16
+"#,
+            version = env!("CARGO_PKG_VERSION"),
+        ),
+    )
+    .await
+    .unwrap();
+    let defaults = toml::from_str::<toml::Table>(
+        r#"
+default-profile = "staging"
+
+[setup.provisioned]
+region = "eu-west-1"
+ssh-user = "ubuntu"
+ssh-private-key = "~/.ssh/daft-key.pem"
+
+[setup.byoc]
+namespace = "team"
+"#,
+    )
+    .unwrap();
+
+    let daft_config =
+        config::read_with_defaults::<DaftConfig>(&path, profile, Some(defaults), Secrets::Resolve)
+            .await
+            .unwrap();
+    let ProviderConfig::Provisioned(aws_config) = daft_config.setup.provider_config else {
+        panic!("Expected a provisioned configuration");
+    };
+    assert_eq!(aws_config.region.as_deref(), Some("eu-west-1"));
+    assert_eq!(&*aws_config.ssh_user, "ec2-user");
+    assert_eq!(aws_config.number_of_workers, expected_number_of_workers);
+}
+
+#[tokio::test]
+async fn test_user_default_profile() {
+    let temp_dir = TempDir::new("test_user_default_profile").unwrap();
+    let path = temp_dir.path().join(".daft.toml");
+    fs::write(
+        &path,
+        format!(
+            r#"
+[setup]
+name = "test"
+requires = "={version}"
+python-version = "3.12"
+ray-version = "2.34"
+
+[setup.byoc]
+
+[profile.dev.setup.byoc]
+namespace = "dev"
+"#,
+            version = env!("CARGO_PKG_VERSION"),
+        ),
+    )
+    .await
+    .unwrap();
+    let defaults = toml::from_str::<toml::Table>(r#"default-profile = "dev""#).unwrap();
+
+    let daft_config =
+        config::read_with_defaults::<DaftConfig>(&path, None, Some(defaults), Secrets::Resolve)
+            .await
+            .unwrap();
+    let ProviderConfig::Byoc(k8s_config) = daft_config.setup.provider_config else {
+        panic!("Expected a BYOC configuration");
+    };
+    assert_eq!(&*k8s_config.namespace, "dev");
+}
+
+#[test]
+fn test_aws_overrides() {
+    let DaftLauncher {
+        sub_command: SubCommand::Provisioned(ProvisionedCommand::Up(Up { overrides, .. })),
+        ..
+    } = DaftLauncher::try_parse_from([
+        "daft",
+        "provisioned",
+        "up",
+        "--workers",
+        "8",
+        "--instance-type",
+        "m7i.4xlarge",
+    ])
+    .unwrap()
+    else {
+        panic!("Expected a `provisioned up` command");
+    };
+
+    let (daft_config, _, _) = simple_config();
+    let ProviderConfig::Provisioned(mut aws_config) = daft_config.setup.provider_config else {
+        panic!("Expected a provisioned configuration");
+    };
+    overrides.apply(&mut aws_config);
+    assert_eq!(aws_config.number_of_workers, 8);
+    assert_eq!(&*aws_config.instance_type, "m7i.4xlarge");
+    assert_eq!(aws_config.region.as_deref(), Some("test"));
+}
+
+#[rstest::rstest]
+#[case(InitTemplate::Gpu, "g5.2xlarge")]
+#[case(InitTemplate::Spot, "m7i.2xlarge")]
+#[case(InitTemplate::BigMemory, "r7i.4xlarge")]
+#[case(InitTemplate::Arm, "m7g.2xlarge")]
+fn test_init_template_overrides(#[case] template: InitTemplate, #[case] instance_type: &str) {
+    let contents = template.overrides().iter().fold(
+        asset!("template-provisioned.toml").to_string(),
+        |contents, (key, value)| edit::set(&contents, key, value).unwrap(),
+    );
+    assert_eq!(
+        edit::get(&contents, "setup.provisioned.instance-type").unwrap(),
+        instance_type
+    );
+    assert_eq!(
+        edit::get(&contents, "setup.provisioned.spot").unwrap(),
+        (template == InitTemplate::Spot).to_string()
+    );
+}
+
+#[test]
+fn test_convert_spot() {
+    let (mut daft_config, teardown_behaviour, _) = simple_config();
+    let ProviderConfig::Provisioned(aws_config) = &mut daft_config.setup.provider_config else {
+        panic!("Expected a provisioned configuration");
+    };
+    aws_config.spot = true;
+
+    let ray_config = convert(&daft_config, teardown_behaviour).unwrap();
+    let market_type = |node_type: &str| {
+        ray_config.available_node_types[node_type]
+            .node_config
+            .instance_market_options
+            .as_ref()
+            .map(|options| options.market_type.to_string())
+    };
+    assert_eq!(market_type("ray.head.default"), None);
+    assert_eq!(market_type("ray.worker.default").as_deref(), Some("spot"));
+}
+
+#[rstest::rstest]
+#[case("2", Some("staging"))]
+#[case("prod", Some("prod"))]
+#[case("0", None)]
+#[case("4", None)]
+#[case("dev", None)]
+fn test_parse_selection(#[case] answer: &str, #[case] expected: Option<&str>) {
+    let options = [
+        "prod".to_string(),
+        "staging".to_string(),
+        "local".to_string(),
+    ];
+    assert_eq!(wizard::parse_selection(answer, &options), expected);
+}
+
+#[test]
+fn test_byoc_context() {
+    let daft_config: DaftConfig = toml::from_str(&format!(
+        r#"
+[setup]
+name = "test"
+requires = "={version}"
+python-version = "3.12"
+ray-version = "2.34"
+
+[setup.byoc]
+context = "staging"
+"#,
+        version = env!("CARGO_PKG_VERSION"),
+    ))
+    .unwrap();
+    let ProviderConfig::Byoc(k8s_config) = daft_config.setup.provider_config else {
+        panic!("Expected a BYOC configuration");
+    };
+    let command = k8s_config.kubectl();
+    let args = command
+        .as_std()
+        .get_args()
+        .map(|arg| arg.to_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(args, ["--context", "staging", "-n", "default"]);
+}
+
+#[rstest::rstest]
+#[case("instance-type", "instance-type", 0)]
+#[case("instance-typ", "instance-type", 1)]
+#[case("numbr-of-wrokers", "number-of-workers", 3)]
+#[case("", "region", 6)]
+fn test_levenshtein(#[case] a: &str, #[case] b: &str, #[case] expected: usize) {
+    assert_eq!(config::levenshtein(a, b), expected);
+    assert_eq!(config::levenshtein(b, a), expected);
+}
+
+#[tokio::test]
+#[rstest::rstest]
+#[case(None, "did you mean `instance-type`?")]
+#[case(Some("dev"), "did you mean `instance-type`? (at line 12, column 1)")]
+async fn test_unknown_key_suggestion(#[case] profile: Option<&str>, #[case] expected: &str) {
+    let temp_dir = TempDir::new("test_unknown_key_suggestion").unwrap();
+    let path = temp_dir.path().join(".daft.toml");
+    fs::write(
+        &path,
+        format!(
+            r#"
+[setup]
+name = "test"
+requires = "={version}"
+python-version = "3.12"
+ray-version = "2.34"
+
+[setup.provisioned]
+region = "us-west-2"
+number-of-workers = 4
+ssh-user = "ubuntu"
+instance-typ = "m7i.2xlarge"
+
+[profile.dev.setup.provisioned]
+number-of-workers = 1
+"#,
+            version = env!("CARGO_PKG_VERSION"),
+        ),
+    )
+    .await
+    .unwrap();
+
+    let error = config::read_with_defaults::<DaftConfig>(&path, profile, None, Secrets::Resolve)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains(expected), "{error}");
+}
+
+#[test]
+fn test_import_ray_config() {
+    let ray_yaml = r#"
+cluster_name: analytics
+max_workers: 2
+provider:
+  type: aws
+  region: eu-west-1
+  availability_zone: eu-west-1a
+auth:
+  ssh_user: ec2-user
+  ssh_private_key: ~/.ssh/analytics.pem
+available_node_types:
+  ray.head.default:
+    max_workers: 0
+    node_config:
+      InstanceType: m7i.xlarge
+      ImageId: ami-0123456789abcdef0
+      IamInstanceProfile:
+        Name: analytics-profile
+  ray.worker.default:
+    max_workers: 6
+    node_config:
+      InstanceType: m7i.4xlarge
+      ImageId: ami-0123456789abcdef0
+setup_commands:
+  - pip install requests
+file_mounts:
+  /etc/ssl/certs/internal.pem: ./certs/internal.pem
+rsync_exclude:
+  - "**/.git"
+docker: {}
+"#;
+    let template = asset!("template-provisioned.toml")
+        .replace("<requires>", concat!("=", env!("CARGO_PKG_VERSION")))
+        .replace("<python-version>", "3.12")
+        .replace("<ray-version>", "2.34");
+    let (contents, warnings) = ray_import::import(ray_yaml, &template).unwrap();
+
+    let daft_config = toml::from_str::<DaftConfig>(&contents).unwrap();
+    assert_eq!(&*daft_config.setup.name, "analytics");
+    assert!(daft_config.jobs.is_empty());
+    let ProviderConfig::Provisioned(aws_config) = daft_config.setup.provider_config else {
+        panic!("Expected a provisioned configuration");
+    };
+    assert_eq!(aws_config.region.as_deref(), Some("eu-west-1"));
+    assert_eq!(aws_config.number_of_workers, 6);
+    assert_eq!(&*aws_config.ssh_user, "ec2-user");
+    assert_eq!(&*aws_config.instance_type, "m7i.4xlarge");
+    assert_eq!(&*aws_config.image_id, "ami-0123456789abcdef0");
+    assert_eq!(
+        aws_config.iam_instance_profile_name.as_deref(),
+        Some("analytics-profile")
+    );
+    assert_eq!(aws_config.run, [StrRef::from("pip install requests")]);
+    assert_eq!(
+        daft_config.setup.file_mounts,
+        BTreeMap::from([(
+            StrRef::from("/etc/ssl/certs/internal.pem"),
+            PathRef::from(Path::new("./certs/internal.pem")),
+        )])
+    );
+    assert_eq!(
+        daft_config.setup.file_mounts_exclude,
+        [StrRef::from("**/.git")]
+    );
+
+    for skipped in ["`docker`", "`provider.availability_zone`", "m7i.4xlarge"] {
+        assert!(
+            warnings.iter().any(|warning| warning.contains(skipped)),
+            "{warnings:?}"
+        );
+    }
+}
+
+#[test]
+fn test_import_ray_config_requires_aws() {
+    let ray_yaml = "cluster_name: analytics\nprovider:\n  type: gcp\n";
+    assert!(ray_import::import(ray_yaml, asset!("template-provisioned.toml")).is_err());
+}
+
+#[test]
+fn test_adopt() {
+    let instance = |node_type, state| AwsInstance {
+        key_pair_name: Some("legacy-key".into()),
+        public_ipv4_address: Some(Ipv4Addr::new(1, 2, 3, 4)),
+        instance_type: Some("m7i.2xlarge".into()),
+        ..aws_instance("i-0", "legacy", node_type, state)
+    };
+    let instances = [
+        instance(NodeType::Head, InstanceStateName::Running),
+        instance(NodeType::Worker, InstanceStateName::Running),
+        instance(NodeType::Worker, InstanceStateName::Pending),
+        instance(NodeType::Worker, InstanceStateName::Terminated),
+    ];
+    let instances = instances.iter().collect::<Vec<_>>();
+    let template = asset!("template-provisioned.toml")
+        .replace("<requires>", concat!("=", env!("CARGO_PKG_VERSION")))
+        .replace("<python-version>", "3.12")
+        .replace("<ray-version>", "2.34");
+
+    let (contents, warnings) =
+        adopt::adopt(&template, "legacy", "eu-west-1", &instances, None, None).unwrap();
+    let daft_config = toml::from_str::<DaftConfig>(&contents).unwrap();
+    assert_eq!(&*daft_config.setup.name, "legacy");
+    assert!(daft_config.jobs.is_empty());
+    let ProviderConfig::Provisioned(aws_config) = daft_config.setup.provider_config else {
+        panic!("Expected a provisioned configuration");
+    };
+    assert_eq!(aws_config.region.as_deref(), Some("eu-west-1"));
+    assert_eq!(aws_config.number_of_workers, 2);
+    assert_eq!(&*aws_config.instance_type, "m7i.2xlarge");
+    assert_eq!(aws_config.iam_instance_profile_name, None);
+    assert!(aws_config.ssh_private_key.ends_with(".ssh/legacy-key"));
+    assert!(warnings
+        .iter()
+        .any(|warning| warning.contains("`--ssh-user`")));
+
+    let (contents, _) = adopt::adopt(
+        &template,
+        "legacy",
+        "eu-west-1",
+        &instances,
+        Some("ec2-user"),
+        Some(Path::new("keys/legacy.pem")),
+    )
+    .unwrap();
+    assert!(contents.contains(r#"ssh-user = "ec2-user""#));
+    assert!(contents.contains(r#"ssh-private-key = "keys/legacy.pem""#));
+
+    let stopped = [instance(NodeType::Head, InstanceStateName::Stopped)];
+    let stopped = stopped.iter().collect::<Vec<_>>();
+    assert!(adopt::adopt(&template, "legacy", "eu-west-1", &stopped, None, None).is_err());
+}
+
+#[rstest::rstest]
+#[case("ray[default]==2.34.0", Some(("ray", "2.34.0")))]
+#[case("pandas == 2.2", Some(("pandas", "2.2")))]
+#[case("pandas>=2.2", None)]
+#[case("pandas", None)]
+fn test_pinned_version(#[case] dependency: &str, #[case] expected: Option<(&str, &str)>) {
+    assert_eq!(lint::pinned_version(dependency), expected);
+}
+
+#[test]
+fn test_lint() {
+    let (mut daft_config, _, _) = simple_config();
+    assert_eq!(lint::lint(&daft_config), Vec::<String>::new());
+
+    let ProviderConfig::Provisioned(aws_config) = &mut daft_config.setup.provider_config else {
+        panic!("Expected a provisioned configuration");
+    };
+    aws_config.iam_instance_profile_name = None;
+    aws_config.instance_type = "t3.micro".into();
+    aws_config.number_of_workers = 16;
+    aws_config.dependencies =
+        Dependencies::List(vec!["ray[default]==2.30.0".into(), "ray==2.34.1".into()]);
+    aws_config.run = vec!["ray start --head --dashboard-host=0.0.0.0".into()];
+
+    let warnings = lint::lint(&daft_config);
+    assert_eq!(warnings.len(), 5, "{warnings:?}");
+    for expected in [
+        "iam-instance-profile-name",
+        "t3.micro",
+        "dashboard",
+        "pins ray==2.30.0, but the cluster runs Ray 2.34",
+        "only one of them can be installed",
+    ] {
+        assert!(
+            warnings.iter().any(|warning| warning.contains(expected)),
+            "{warnings:?}"
+        );
+    }
+}
+
+#[test]
+fn test_lint_byoc_file_mounts() {
+    let (mut daft_config, _, _) = simple_config();
+    daft_config.setup.provider_config = ProviderConfig::Byoc(K8sConfig {
+        namespace: "default".into(),
+        context: None,
+    });
+    assert_eq!(lint::lint(&daft_config), Vec::<String>::new());
+    daft_config.setup.file_mounts =
+        BTreeMap::from([("~/data".into(), Arc::from(Path::new("data")))]);
+    assert_eq!(
+        lint::lint(&daft_config),
+        [lint::BYOC_FILE_MOUNTS_WARNING.to_string()]
+    );
+}
+
+#[tokio::test]
+#[rstest::rstest]
+#[case(None, Some("analytics-dev"))]
+#[case(Some("prod"), Some("analytics-prod"))]
+#[case(Some("broken"), None)]
+async fn test_config_vars(#[case] profile: Option<&str>, #[case] expected_name: Option<&str>) {
+    let temp_dir = TempDir::new("test_config_vars").unwrap();
+    let path = temp_dir.path().join(".daft.toml");
+    fs::write(
+        &path,
+        r#"
+[vars]
+env = "dev"
+workers = 4
+
+[setup]
+name = "analytics-{{env}}"
+requires = "<requires>"
+python-version = "3.12"
+ray-version = "2.34"
+
+[setup.byoc]
+namespace = "{{ env }}"
+
+[[job]]
+name = "example-job"
+command = "python main.py --workers {{workers}}"
+working-dir = "."
+
+[profile.prod.vars]
+env = "prod"
+
+[profile.broken.setup]
+name = "{{missing}}"
+"#
+        .replace("<requires>", concat!("=", env!("CARGO_PKG_VERSION"))),
+    )
+    .await
+    .unwrap();
+
+    let daft_config =
+        config::read_with_defaults::<DaftConfig>(&path, profile, None, Secrets::Resolve).await;
+    let Some(expected_name) = expected_name else {
+        assert!(daft_config.unwrap_err().to_string().contains("missing"));
+        return;
+    };
+    let daft_config = daft_config.unwrap();
+    assert_eq!(&*daft_config.setup.name, expected_name);
+    let ProviderConfig::Byoc(k8s_config) = &daft_config.setup.provider_config else {
+        panic!("Expected a BYOC configuration");
+    };
+    assert_eq!(
+        Some(&*k8s_config.namespace),
+        expected_name.strip_prefix("analytics-")
+    );
+    assert_eq!(
+        &*daft_config.jobs["example-job"].command,
+        "python main.py --workers 4"
+    );
+}
+
+#[test]
+fn test_config_diff() {
+    let (old_config, _, _) = simple_config();
+    let mut new_config = old_config.clone();
+    let ProviderConfig::Provisioned(aws_config) = &mut new_config.setup.provider_config else {
+        panic!("Expected a provisioned configuration");
+    };
+    aws_config.number_of_workers = 8;
+    aws_config.iam_instance_profile_name = None;
+    aws_config.dependencies = Dependencies::List(vec!["pandas".into()]);
+
+    let changes = diff::diff(
+        &toml::Value::try_from(&old_config).unwrap(),
+        &toml::Value::try_from(&new_config).unwrap(),
+    )
+    .iter()
+    .map(ToString::to_string)
+    .collect::<Vec<_>>();
+    assert_eq!(
+        changes,
+        [
+            r#"~ setup.provisioned.dependencies = [] -> ["pandas"]"#,
+            r#"- setup.provisioned.iam-instance-profile-name = "test""#,
+            "~ setup.provisioned.number-of-workers = 4 -> 8",
+        ]
+    );
+}
+
+#[test]
+fn test_export_is_deterministic() {
+    let (daft_config, teardown_behaviour, _) = simple_config();
+    let exports = (0..8)
+        .map(|_| {
+            serde_yaml::to_string(&convert(&daft_config, teardown_behaviour).unwrap()).unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert!(exports.iter().all(|export| *export == exports[0]));
+    let head = exports[0].find("ray.head.default").unwrap();
+    let worker = exports[0].find("ray.worker.default").unwrap();
+    assert!(head < worker);
+}
+
+#[test]
+fn test_custom_setup_commands() {
+    let (mut daft_config, teardown_behaviour, _) = simple_config();
+    let ProviderConfig::Provisioned(aws_config) = &mut daft_config.setup.provider_config else {
+        panic!("Expected a provisioned configuration");
+    };
+    aws_config.pre_setup_commands = vec!["sudo apt-get update".into()];
+    let ray_config = convert(&daft_config, teardown_behaviour).unwrap();
+    assert_eq!(&*ray_config.setup_commands[0], "sudo apt-get update");
+    assert_eq!(
+        &*ray_config.setup_commands[1],
+        "curl -LsSf https://astral.sh/uv/install.sh | sh"
+    );
+
+    let ProviderConfig::Provisioned(aws_config) = &mut daft_config.setup.provider_config else {
+        panic!("Expected a provisioned configuration");
+    };
+    aws_config.setup_commands = Some(vec!["pip install getdaft ray[default]".into()]);
+    let ray_config = convert(&daft_config, teardown_behaviour).unwrap();
+    assert_eq!(
+        ray_config.setup_commands,
+        [
+            StrRef::from("sudo apt-get update"),
+            StrRef::from("pip install getdaft ray[default]"),
+            StrRef::from(r#"echo "Hello, world!""#),
+        ]
+    );
+
+    let ProviderConfig::Provisioned(aws_config) = &mut daft_config.setup.provider_config else {
+        panic!("Expected a provisioned configuration");
+    };
+    aws_config.dependencies = Dependencies::List(vec!["pandas".into()]);
+    assert!(convert(&daft_config, teardown_behaviour).is_err());
+}
+
+#[rstest::rstest]
+#[case(
+    None,
+    r#"uv pip install boto3 pip py-spy deltalake getdaft "ray[default]==2.34""#
+)]
+#[case(
+    Some("0.4.1"),
+    r#"uv pip install boto3 pip py-spy deltalake "getdaft==0.4.1" "ray[default]==2.34""#
+)]
+fn test_daft_version(#[case] daft_version: Option<&str>, #[case] expected: &str) {
+    let commands = generate_setup_commands(
+        "3.12".parse().unwrap(),
+        "2.34".parse().unwrap(),
+        daft_version.map(|daft_version| daft_version.parse().unwrap()),
+        &[],
+        &[],
+    );
+    assert!(commands.contains(&StrRef::from(expected)), "{commands:?}");
+}
+
+#[rstest::rstest]
+#[case("0.4.1", true)]
+#[case("0.2", false)]
+fn test_parse_daft_version(#[case] daft_version: &str, #[case] valid: bool) {
+    let contents = format!(
+        r#"
+name = "test"
+requires = "={version}"
+python-version = "3.12"
+ray-version = "2.34"
+daft-version = "{daft_version}"
+
+[byoc]
+"#,
+        version = env!("CARGO_PKG_VERSION"),
+    );
+    assert_eq!(toml::from_str::<DaftSetup>(&contents).is_ok(), valid);
+}
+
+#[test]
+fn test_daft_ray_specifiers() {
+    let requires_dist = [
+        "pyarrow>=7.0.0".to_string(),
+        r#"ray[client,data]>=2.0.0; platform_system != "Windows" and extra == "ray""#.to_string(),
+        r#"ray[client,data]<2.38.0,>=2.0.0; platform_system == "Windows" and extra == "ray""#
+            .to_string(),
+        "raydp; extra == \"raydp\"".to_string(),
+        "ray (>=2.10)".to_string(),
+    ];
+    assert_eq!(
+        compat::specifiers_of(&requires_dist, "ray"),
+        [">=2.0.0", ">=2.10"]
+    );
+}
+
+#[rstest::rstest]
+#[case("2.34", ">=2.0.0", Some(true))]
+#[case("2.34", ">=2.0.0,<2.34", Some(false))]
+#[case("2.34", "<2.38.0, >=2.10", Some(true))]
+#[case("2.34", "==2.34.0", Some(true))]
+#[case("2.34", "==2.*", Some(true))]
+#[case("2.34", "!=2.34.*", Some(false))]
+#[case("2.34", "~=2.30", Some(true))]
+#[case("2.34", "~=2.30.1", Some(false))]
+#[case("2.34", ">2.34.0", Some(false))]
+#[case("2.34", "===2.34", None)]
+fn test_daft_ray_compatibility(
+    #[case] ray_version: &str,
+    #[case] specifiers: &str,
+    #[case] expected: Option<bool>,
+) {
+    assert_eq!(
+        compat::satisfies(&ray_version.parse().unwrap(), specifiers),
+        expected
+    );
+}
+
+#[test]
+fn test_parse_requirements() {
+    let requirements = r#"
+# The pinned requirements of the project
+requests==2.32.3
+pandas>=2.2  # for the reports
+
+ray[default]==2.34.0
+"#;
+    assert_eq!(
+        parse_requirements(requirements).unwrap(),
+        [
+            StrRef::from("requests==2.32.3"),
+            StrRef::from("pandas>=2.2"),
+            StrRef::from("ray[default]==2.34.0"),
+        ]
+    );
+    assert!(parse_requirements("-r other.txt").is_err());
+}
+
+#[tokio::test]
+async fn test_dependencies_file_is_relative_to_config() {
+    let temp_dir = TempDir::new("test_dependencies_file_is_relative_to_config").unwrap();
+    let path = temp_dir.path().join(".daft.toml");
+    fs::write(
+        &path,
+        format!(
+            r#"
+[setup]
+name = "test"
+requires = "={version}"
+python-version = "3.12"
+ray-version = "2.34"
+
+[setup.provisioned]
+region = "us-west-2"
+ssh-user = "ubuntu"
+ssh-private-key = "testkey.pem"
+dependencies = {{ file = "requirements.txt" }}
+"#,
+            version = env!("CARGO_PKG_VERSION"),
+        ),
+    )
+    .await
+    .unwrap();
+    fs::write(
+        temp_dir.path().join("requirements.txt"),
+        "requests==2.32.3\n",
+    )
+    .await
+    .unwrap();
+
+    let daft_config = read_daft_config(&path, None).await.unwrap();
+    let ProviderConfig::Provisioned(aws_config) = &daft_config.setup.provider_config else {
+        panic!("Expected a provisioned configuration");
+    };
+    assert_eq!(
+        aws_config.dependencies,
+        Dependencies::File {
+            file: Arc::from(temp_dir.path().join("requirements.txt"))
+        }
+    );
+    assert_eq!(
+        aws_config.dependencies.resolve().unwrap(),
+        [StrRef::from("requests==2.32.3")]
+    );
+}
+
+#[tokio::test]
+async fn test_dependencies_file() {
+    let temp_dir = TempDir::new("test_dependencies_file").unwrap();
+    let path = temp_dir.path().join("pyproject.toml");
+    fs::write(
+        &path,
+        r#"
+[project]
+name = "analytics"
+dependencies = ["requests==2.32.3", "pandas>=2.2"]
+"#,
+    )
+    .await
+    .unwrap();
+
+    let dependencies = Dependencies::File {
+        file: Arc::from(path),
+    };
+    assert_eq!(
+        dependencies.resolve().unwrap(),
+        [
+            StrRef::from("requests==2.32.3"),
+            StrRef::from("pandas>=2.2")
+        ]
+    );
+    let missing = Dependencies::File {
+        file: Arc::from(temp_dir.path().join("requirements.txt")),
+    };
+    assert!(missing.resolve().is_err());
+
+    let parsed = toml::from_str::<BTreeMap<String, Dependencies>>(
+        r#"dependencies = { file = "requirements.txt" }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        parsed["dependencies"],
+        Dependencies::File {
+            file: Arc::from(Path::new("requirements.txt"))
+        }
+    );
+}
+
+#[rstest::rstest]
+#[case("3.8", false)]
+#[case("3.9", true)]
+#[case("3.10", true)]
+#[case("3.12.4", true)]
+#[case("3.13", false)]
+fn test_parse_python_version(#[case] python_version: &str, #[case] valid: bool) {
+    let contents = format!(
+        r#"
+name = "test"
+requires = "={version}"
+python-version = "{python_version}"
+ray-version = "2.34"
+
+[byoc]
+"#,
+        version = env!("CARGO_PKG_VERSION"),
+    );
+    let setup = toml::from_str::<DaftSetup>(&contents);
+    assert_eq!(setup.is_ok(), valid);
+    if let Ok(setup) = setup {
+        let commands =
+            generate_setup_commands(setup.python_version, setup.ray_version, None, &[], &[]);
+        assert!(commands.contains(&format!("uv python install {python_version}").into()));
+    };
+}
+
+#[test]
+fn test_cluster_env() {
+    let (mut daft_config, teardown_behaviour, _) = simple_config();
+    daft_config.setup.env = BTreeMap::from([
+        ("AWS_DEFAULT_REGION".into(), "us-west-2".into()),
+        ("HTTPS_PROXY".into(), "http://proxy.internal:3128".into()),
+    ]);
+    let ray_config = convert(&daft_config, teardown_behaviour).unwrap();
+    assert_eq!(
+        ray_config.setup_commands[..2],
+        [
+            StrRef::from("echo 'export AWS_DEFAULT_REGION=us-west-2' >> ~/.bashrc"),
+            StrRef::from("echo 'export HTTPS_PROXY=http://proxy.internal:3128' >> ~/.bashrc"),
+        ]
+    );
+
+    daft_config.setup.env = BTreeMap::from([("NOT-VALID".into(), "value".into())]);
+    assert!(convert(&daft_config, teardown_behaviour).is_err());
+
+    let daft_config = parse_byoc_config_with_jobs(
+        r#"
+[[job]]
+name = "example-job"
+command = "python main.py"
+working-dir = "."
+env = { DAFT_PROGRESS_BAR = "0" }
+"#,
+    );
+    let cluster_env = BTreeMap::from([
+        ("AWS_DEFAULT_REGION".into(), "us-west-2".into()),
+        ("DAFT_PROGRESS_BAR".into(), "1".into()),
+    ]);
+    let runtime_env = daft_config.jobs["example-job"].runtime_env(&cluster_env);
+    assert_eq!(
+        runtime_env.env_vars,
+        BTreeMap::from([
+            ("AWS_DEFAULT_REGION".into(), "us-west-2".into()),
+            ("DAFT_PROGRESS_BAR".into(), "0".into()),
+        ])
+    );
+}
+
+#[test]
+fn test_setup_script() {
+    let (mut daft_config, teardown_behaviour, _) = simple_config();
+    let ProviderConfig::Provisioned(aws_config) = &mut daft_config.setup.provider_config else {
+        panic!("Expected a provisioned configuration");
+    };
+    aws_config.pre_setup_commands = vec!["sudo apt-get update".into()];
+    aws_config.setup_script = Some(Arc::from(Path::new("bootstrap.sh")));
+
+    let ray_config = convert(&daft_config, teardown_behaviour).unwrap();
+    assert_eq!(
+        ray_config.file_mounts,
+        BTreeMap::from([(
+            StrRef::from("~/.daft-launcher/setup-script.sh"),
+            PathRef::from(Path::new("bootstrap.sh")),
+        )])
+    );
+    assert_eq!(
+        ray_config.setup_commands[..2],
+        [
+            StrRef::from("sudo apt-get update"),
+            StrRef::from("bash ~/.daft-launcher/setup-script.sh"),
+        ]
+    );
+    assert!(serde_yaml::to_string(&ray_config)
+        .unwrap()
+        .contains("file_mounts:"));
+}
+
+#[test]
+fn test_file_mounts() {
+    let (mut daft_config, teardown_behaviour, _) = simple_config();
+    daft_config.setup.file_mounts = BTreeMap::from([
+        (
+            "/etc/ssl/certs/internal.pem".into(),
+            PathRef::from(Path::new("certs/internal.pem")),
+        ),
+        ("~/data".into(), PathRef::from(Path::new("data"))),
+    ]);
+    daft_config.setup.file_mounts_exclude = vec!["*.tmp".into()];
+
+    let ray_config = convert(&daft_config, teardown_behaviour).unwrap();
+    assert_eq!(ray_config.file_mounts, daft_config.setup.file_mounts);
+    assert_eq!(ray_config.rsync_exclude, [StrRef::from("*.tmp")]);
+
+    for remote_path in ["relative/path", "~/.daft-launcher/setup-script.sh"] {
+        daft_config.setup.file_mounts =
+            BTreeMap::from([(remote_path.into(), PathRef::from(Path::new("data")))]);
+        assert!(convert(&daft_config, teardown_behaviour).is_err());
+    }
+}
+
+#[rstest::rstest]
+#[case(".daft.toml", None, true)]
+#[case("-", None, false)]
+#[case(
+    "s3://team-configs/team.daft.toml",
+    Some("s3://team-configs/team.daft.toml"),
+    false
+)]
+#[case(
+    "https://example.com/team.daft.toml",
+    Some("https://example.com/team.daft.toml"),
+    false
+)]
+fn test_config_location(
+    #[case] path: &str,
+    #[case] remote_url: Option<&str>,
+    #[case] is_local: bool,
+) {
+    assert_eq!(config::remote_url(Path::new(path)), remote_url);
+    assert_eq!(config::is_local(Path::new(path)), is_local);
+}
+
+#[tokio::test]
+async fn test_version_mismatch_suggests_fix() {
+    let temp_dir = TempDir::new("test_version_mismatch_suggests_fix").unwrap();
+    let path = temp_dir.path().join("team.daft.toml");
+    fs::write(
+        &path,
+        r#"
+[setup]
+name = "my-cluster"
+requires = "=0.0.1"
+python-version = "3.12"
+ray-version = "2.34"
+
+[setup.byoc]
+"#,
+    )
+    .await
+    .unwrap();
+
+    let error = read_daft_config(&path, None).await.unwrap_err().to_string();
+    assert!(
+        error.contains(&format!(
+            "`daft config check --fix-version {}`",
+            path.display()
+        )),
+        "{error}"
+    );
+}
+
+#[tokio::test]
+async fn test_job_name_completion() {
+    let temp_dir = TempDir::new("test_job_name_completion").unwrap();
+    let nested_dir = temp_dir.path().join("src").join("pipelines");
+    fs::create_dir_all(&nested_dir).await.unwrap();
+    fs::write(
+        temp_dir.path().join(".daft.toml"),
+        r#"
+[[job]]
+name = "ingest"
+
+[[job]]
+name = "report"
+"#,
+    )
+    .await
+    .unwrap();
+
+    let path = completion::nearest_config(&nested_dir).unwrap();
+    assert_eq!(path, temp_dir.path().join(".daft.toml"));
+    let contents = fs::read_to_string(path).await.unwrap();
+    assert_eq!(completion::job_names(&contents), ["ingest", "report"]);
+    assert!(completion::job_names("not = [valid").is_empty());
+}
+
+#[test]
+fn test_output_json() {
+    let DaftLauncher { output, .. } =
+        DaftLauncher::try_parse_from(["daft", "provisioned", "list", "--output", "json"]).unwrap();
+    assert_eq!(output, OutputFormat::Json);
+
+    let instances = [
+        AwsInstance {
+            public_ipv4_address: Some(Ipv4Addr::new(203, 0, 113, 7)),
+            ..aws_instance(
+                "i-0123",
+                "my-cluster",
+                NodeType::Head,
+                InstanceStateName::Running,
+            )
+        },
+        aws_instance(
+            "i-4567",
+            "my-cluster",
+            NodeType::Worker,
+            InstanceStateName::Stopped,
+        ),
+    ];
+    let instances = filter_instances(&instances, Some("my-"), true, false).unwrap();
+    assert_eq!(instances.len(), 1);
+    assert_eq!(
+        serde_json::to_string(&InstanceRecord::from(instances[0])).unwrap(),
+        r#"{"name":"my-cluster","instance_id":"i-0123","node_type":"head","state":"running","public_ipv4_address":"203.0.113.7","launch_time":null}"#
+    );
+}
+
+#[test]
+fn test_output_csv() {
+    let DaftLauncher {
+        sub_command,
+        output,
+        ..
+    } = DaftLauncher::try_parse_from([
+        "daft",
+        "provisioned",
+        "list",
+        "--output",
+        "csv",
+        "--tag",
+        "team=data",
+        "--tag",
+        "env=prod",
+    ])
+    .unwrap();
+    assert_eq!(output, OutputFormat::Csv);
+    let SubCommand::Provisioned(ProvisionedCommand::List(List { tags, .. })) = sub_command else {
+        panic!("Expected `provisioned list`, but got {sub_command:?}");
+    };
+    let filters = tag_filters(&tags);
+    assert_eq!(
+        filters
+            .iter()
+            .map(|filter| (filter.name().unwrap(), filter.values()))
+            .collect::<Vec<_>>(),
+        [
+            ("tag:team", ["data".to_string()].as_slice()),
+            ("tag:env", ["prod".to_string()].as_slice()),
+        ]
+    );
+    assert!(tag_filters(&[]).is_empty());
+
+    let instance = AwsInstance {
+        public_ipv4_address: Some(Ipv4Addr::new(203, 0, 113, 7)),
+        ..aws_instance(
+            "i-0123",
+            "my, cluster",
+            NodeType::Head,
+            InstanceStateName::Running,
+        )
+    };
+    assert_eq!(
+        format_csv(&[InstanceRecord::from(&instance)]).unwrap(),
+        "name,instance_id,node_type,state,public_ipv4_address,launch_time\n\"my, cluster\",i-0123,head,running,203.0.113.7,\n"
+    );
+}
+
+/// The header row which the csv crate derives from the fields of the given
+/// record.
+fn serialized_csv_header<T: Serialize>(record: &T) -> String {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.serialize(record).unwrap();
+    let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+    csv.lines().next().unwrap().to_string()
+}
+
+#[test]
+fn test_csv_headers() {
+    fn assert_header<T: CsvRecord>(record: T) {
+        assert_eq!(T::HEADER.join(","), serialized_csv_header(&record));
+        assert_eq!(
+            format_csv::<T>(&[]).unwrap(),
+            format!("{}\n", T::HEADER.join(","))
+        );
+    }
+
+    let instance = aws_instance(
+        "i-0123",
+        "my-cluster",
+        NodeType::Head,
+        InstanceStateName::Running,
+    );
+    assert_header(InstanceRecord::from(&instance));
+    assert_header(ClusterSummaryRecord {
+        name: "my-cluster",
+        head_state: Some("running"),
+        head_ipv4_address: None,
+        workers: String::new(),
+        vcpus: 4,
+    });
+    assert_header(ClusterRecord {
+        provider: "provisioned",
+        name: "my-cluster".into(),
+        location: "us-west-2".into(),
+        status: "running".into(),
+        workers: 0,
+    });
+    assert_header(state::Cluster {
+        name: "my-cluster".into(),
+        region: "us-west-2".into(),
+        config: PathBuf::from(".daft.toml"),
+        provider: "provisioned".into(),
+        head_ip: None,
+        created_at: None,
+        state: None,
+        profile: None,
+    });
+    assert_header(state::Action {
+        action: state::ActionKind::Up,
+        user: "me".into(),
+        cluster: "my-cluster".into(),
+        config_hash: "abc".into(),
+        detail: None,
+        at: 0,
+    });
+    assert_header(cost::DailyCost {
+        date: "2024-01-01".into(),
+        cluster: "my-cluster".into(),
+        usd: 1.5,
+    });
+    assert_header(ssh::NodeOutput {
+        address: Ipv4Addr::new(10, 0, 0, 7),
+        is_head: true,
+        exit_code: Some(0),
+        stdout: "".into(),
+        stderr: "".into(),
+    });
+}
+
+#[rstest::rstest]
+#[case(OutputFormat::Text, Some(TextOrJson::Text))]
+#[case(OutputFormat::Json, Some(TextOrJson::Json))]
+#[case(OutputFormat::Csv, None)]
+fn test_text_or_json(#[case] output: OutputFormat, #[case] expected: Option<TextOrJson>) {
+    let result = output.text_or_json("doctor");
+    match expected {
+        Some(expected) => assert_eq!(result.unwrap(), expected),
+        None => assert_eq!(
+            result.unwrap_err().to_string(),
+            "`--output csv` is not available for `doctor`"
+        ),
+    }
+}
+
+#[test]
+fn test_instance_address() {
+    let mut instance = AwsInstance {
+        public_ipv4_address: Some(Ipv4Addr::new(203, 0, 113, 7)),
+        private_ipv4_address: Some(Ipv4Addr::new(10, 0, 0, 7)),
+        ..aws_instance(
+            "i-0123",
+            "my-cluster",
+            NodeType::Head,
+            InstanceStateName::Running,
+        )
+    };
+    assert_eq!(instance.address(), Some(Ipv4Addr::new(203, 0, 113, 7)));
+    instance.public_ipv4_address = None;
+    assert_eq!(instance.address(), Some(Ipv4Addr::new(10, 0, 0, 7)));
+    instance.private_ipv4_address = None;
+    assert_eq!(instance.address(), None);
+}
+
+#[rstest::rstest]
+#[case(0, false, logging::Level::Info)]
+#[case(2, false, logging::Level::Debug)]
+#[case(0, true, logging::Level::Warn)]
+fn test_verbosity(#[case] verbose: u8, #[case] quiet: bool, #[case] expected: logging::Level) {
+    assert_eq!(logging::verbosity(verbose, quiet), expected);
+}
+
+#[test]
+fn test_log_record() {
+    assert_eq!(
+        logging::format_record(
+            Duration::from_millis(1_700_000_000_042),
+            logging::Level::Debug,
+            "Running \"ray\" \"up\""
+        ),
+        r#"ts=1700000000.042 level=debug msg="Running \"ray\" \"up\"""#
+    );
+    assert!(DaftLauncher::try_parse_from(["daft", "provisioned", "list", "-q", "-v"]).is_err());
+}
+
+#[test]
+fn test_ensure_interactive() {
+    assert!(wizard::check_interactive(
+        true,
+        "Changing the requirement needs confirmation",
+        "pass it explicitly"
+    )
+    .is_ok());
+    let error = wizard::check_interactive(
+        false,
+        "Changing the requirement needs confirmation",
+        "pass it explicitly",
+    )
+    .unwrap_err()
+    .to_string();
+    assert!(error.starts_with("Changing the requirement needs confirmation, but the launcher is running non-interactively"));
+    assert!(error.ends_with("; pass it explicitly"));
+}
+
+#[test]
+fn test_format_instances_to_kill() {
+    let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let instances = [
+        AwsInstance {
+            launch_time: Some(now - Duration::from_secs(2 * 3600 + 5 * 60)),
+            ..aws_instance(
+                "i-0123",
+                "my-cluster",
+                NodeType::Head,
+                InstanceStateName::Running,
+            )
+        },
+        aws_instance(
+            "i-4567",
+            "my-cluster",
+            NodeType::Worker,
+            InstanceStateName::Pending,
+        ),
+    ];
+    assert_eq!(
+        format_instances_to_kill(&instances.iter().collect::<Vec<_>>(), now),
+        "  my-cluster i-0123 (head, up 2h 5m 0s)\n  my-cluster i-4567 (worker, up n/a)"
+    );
+    assert!(DaftLauncher::try_parse_from(["daft", "provisioned", "kill", "--yes"]).is_ok());
+}
+
+#[rstest::rstest]
+#[case("90m", Ok(Duration::from_secs(90 * 60)))]
+#[case("24h", Ok(Duration::from_secs(24 * 3600)))]
+#[case("7d", Ok(Duration::from_secs(7 * 24 * 3600)))]
+#[case("2w", Ok(Duration::from_secs(14 * 24 * 3600)))]
+#[case("0h", Err(()))]
+#[case("24", Err(()))]
+#[case("h", Err(()))]
+#[case("24ч", Err(()))]
+#[case("-1h", Err(()))]
+#[case("4294967296h", Err(()))]
+fn test_parse_age(#[case] raw: &str, #[case] expected: Result<Duration, ()>) {
+    assert_eq!(parse_age(raw).map_err(|_| ()), expected);
+}
+
+#[test]
+fn test_stale_clusters() {
+    let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let hours = |hours: u64| Duration::from_secs(hours * 3600);
+    let instance =
+        |id: &str, name: &str, node_type, state, up: u64, ttl: Option<u64>| AwsInstance {
+            launch_time: Some(now - hours(up)),
+            ttl: ttl.map(hours),
+            launched_by_launcher: true,
+            ..aws_instance(id, name, node_type, state)
+        };
+    let instances = [
+        instance(
+            "i-0",
+            "old",
+            NodeType::Head,
+            InstanceStateName::Running,
+            30,
+            None,
+        ),
+        instance(
+            "i-1",
+            "old",
+            NodeType::Worker,
+            InstanceStateName::Running,
+            30,
+            None,
+        ),
+        instance(
+            "i-2",
+            "old",
+            NodeType::Worker,
+            InstanceStateName::Terminated,
+            30,
+            None,
+        ),
+        instance(
+            "i-3",
+            "expired",
+            NodeType::Head,
+            InstanceStateName::Running,
+            5,
+            Some(4),
+        ),
+        instance(
+            "i-4",
+            "fresh",
+            NodeType::Head,
+            InstanceStateName::Running,
+            5,
+            Some(12),
+        ),
+        instance(
+            "i-5",
+            "gone",
+            NodeType::Head,
+            InstanceStateName::Terminated,
+            50,
+            None,
+        ),
+        AwsInstance {
+            launched_by_launcher: false,
+            ..instance(
+                "i-6",
+                "foreign",
+                NodeType::Head,
+                InstanceStateName::Running,
+                50,
+                None,
+            )
+        },
+    ];
+    let instances = instances.iter().collect::<Vec<_>>();
+
+    let stale = stale_clusters(&instances, None, now);
+    assert_eq!(stale.len(), 1);
+    assert_eq!(&*stale[0].name, "expired");
+    assert_eq!(stale[0].reason, "past its TTL of 4h 0m 0s");
+
+    let stale = stale_clusters(&instances, Some(hours(24)), now);
+    let names = stale
+        .iter()
+        .map(|cluster| &*cluster.name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["old", "expired"]);
+    assert_eq!(stale[0].reason, "older than 24h 0m 0s");
+    assert_eq!(stale[0].instances, [instances[0], instances[1]]);
+}
+
+#[test]
+fn test_ttl_tag() {
+    let (mut daft_config, _, _) = simple_config();
+    let ProviderConfig::Provisioned(aws_config) = &mut daft_config.setup.provider_config else {
+        unreachable!()
+    };
+    aws_config.ttl_hours = Some(24);
+    let ray_config = serde_yaml::to_string(&convert(&daft_config, None).unwrap()).unwrap();
+    assert!(ray_config.contains("TagSpecifications:"));
+    assert!(ray_config.contains("Key: daft-launcher-ttl-hours"));
+    assert!(ray_config.contains("Value: '24'"));
+    assert!(ray_config.contains("Key: daft-launcher-version"));
+}
+
+#[test]
+fn test_lock_conflict() {
+    assert_eq!(
+        lock::parameter_name("my-cluster"),
+        "/daft-launcher/locks/my-cluster"
+    );
+    let holder = lock::Holder {
+        user: "alice".into(),
+        command: "up".into(),
+        since: 1_700_000_000,
+    };
+    assert_eq!(
+        lock::describe_conflict("my-cluster", &holder),
+        "The cluster my-cluster is locked by alice since 2023-11-14 22:13 UTC (running `up`); wait for that to finish, or pass `--force` if it was interrupted"
+    );
+    assert_eq!(
+        serde_json::from_str::<lock::Holder>(&serde_json::to_string(&holder).unwrap()).unwrap(),
+        holder
+    );
+    assert!(DaftLauncher::try_parse_from(["daft", "down", "--force"]).is_ok());
+}
+
+#[rstest::rstest]
+#[case(Some("AccessDeniedException"), true)]
+#[case(Some("UnauthorizedOperation"), true)]
+#[case(Some("ThrottlingException"), false)]
+#[case(None, false)]
+fn test_lock_is_permission_error(#[case] code: Option<&str>, #[case] expected: bool) {
+    assert_eq!(lock::is_permission_error(code), expected);
+}
+
+#[test]
+fn test_cached_ray_config() {
+    let (daft_config, _, _) = simple_config();
+    let ray_config = serde_yaml::to_string(&convert(&daft_config, None).unwrap()).unwrap();
+    let hash = config_hash(&daft_config);
+    let cached =
+        state::parse_cached_ray_config(&state::format_cached_ray_config(&ray_config, &hash));
+    assert_eq!(cached.config_hash, Some(hash));
+    assert_eq!(
+        state::parse_cached_ray_config(&ray_config).config_hash,
+        None
+    );
+
+    let ProviderConfig::Provisioned(aws_config) = &daft_config.setup.provider_config else {
+        panic!("Expected a provisioned configuration");
+    };
+    let region = aws_config.region();
+    let teardown = teardown_ray_config(&cached.contents, TeardownBehaviour::Kill, region).unwrap();
+    let expected =
+        serde_yaml::to_string(&convert(&daft_config, Some(TeardownBehaviour::Kill)).unwrap())
+            .unwrap();
+    assert_eq!(
+        serde_yaml::from_str::<serde_yaml::Value>(&teardown).unwrap(),
+        serde_yaml::from_str::<serde_yaml::Value>(&expected).unwrap(),
+    );
+    assert!(teardown_ray_config(&cached.contents, TeardownBehaviour::Kill, "ap-south-1").is_err());
+    assert!(teardown_ray_config("cluster_name: test", TeardownBehaviour::Down, region).is_err());
+
+    let path = state::ray_config_path("us-west-2", "my-cluster").unwrap();
+    assert!(path.ends_with("clusters/us-west-2/my-cluster/ray.yaml"));
+    assert!(state::ray_config_path("us-west-2", "../my-cluster").is_err());
+    assert!(state::ray_config_path("us-west-2", "a/b").is_err());
+    assert!(state::ray_config_path("", "my-cluster").is_err());
+    let path = state::adopted_config_path("us-west-2", "my-cluster").unwrap();
+    assert!(path.ends_with("clusters/us-west-2/my-cluster/adopted.daft.toml"));
+    assert!(state::adopted_config_path("us-west-2", "..").is_err());
+}
+
+#[rstest::rstest]
+#[case(None, doctor::Status::Pass)]
+#[case(Some(3600), doctor::Status::Pass)]
+#[case(Some(60), doctor::Status::Warn)]
+#[case(Some(-60), doctor::Status::Fail)]
+fn test_describe_expiry(#[case] remaining: Option<i64>, #[case] expected: doctor::Status) {
+    let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let expiry = remaining.map(|remaining| {
+        if remaining >= 0 {
+            now + Duration::from_secs(remaining.unsigned_abs())
+        } else {
+            now - Duration::from_secs(remaining.unsigned_abs())
+        }
+    });
+    assert_eq!(doctor::describe_expiry(expiry, now).0, expected);
+}
+
+#[rstest::rstest]
+#[case("https://203.0.113.7:6443", Some("203.0.113.7:6443"))]
+#[case("https://kube.example.com", Some("kube.example.com:443"))]
+#[case("http://localhost/api", Some("localhost:80"))]
+#[case("kube.example.com", None)]
+fn test_endpoint_address(#[case] url: &str, #[case] expected: Option<&str>) {
+    assert_eq!(doctor::endpoint_address(url).as_deref(), expected);
+}
+
+#[test]
+fn test_exit_code() {
+    use exit_code::{Classify, Failure};
+
+    let error = anyhow::Result::<()>::Err(anyhow::anyhow!("The job failed"))
+        .classify(Failure::Job)
+        .unwrap_err();
+    assert_eq!(exit_code::of(&error), 6);
+    assert_eq!(error.to_string(), "The job failed");
+
+    // The class closest to the cause wins.
+    let error = Err::<(), _>(error.context("Running the jobs failed"))
+        .classify(Failure::Tunnel)
+        .unwrap_err();
+    assert_eq!(exit_code::of(&error), 6);
+
+    assert_eq!(
+        exit_code::of(&anyhow::anyhow!("Something went wrong")),
+        exit_code::GENERAL
+    );
+}
+
+#[test]
+fn test_resolve_shorthand() {
+    let (daft_config, _, _) = simple_config();
+    let provisioned = daft_config.setup.provider_config;
+    let byoc = ProviderConfig::Byoc(K8sConfig {
+        namespace: "default".into(),
+        context: None,
+    });
+    let config_path = ConfigPath {
+        config: ".daft.toml".into(),
+    };
+    let up = Up {
+        overrides: AwsOverrides {
+            workers: Some(8),
+            instance_type: None,
+        },
+        show_output: false,
+        lock_args: LockArgs { force: false },
+        config_path: config_path.clone(),
+    };
+    let down = Down {
+        lock_args: LockArgs { force: false },
+        config_path: config_path.clone(),
+    };
+
+    assert_eq!(
+        resolve_shorthand(&SubCommand::Up(up.clone()), &provisioned).unwrap(),
+        SubCommand::Provisioned(ProvisionedCommand::Up(up.clone())),
+    );
+    assert_eq!(
+        resolve_shorthand(&SubCommand::Up(up), &byoc).unwrap(),
+        SubCommand::Byoc(ByocCommand::Info(config_path.clone())),
+    );
+    assert_eq!(
+        resolve_shorthand(&SubCommand::Down(down.clone()), &provisioned).unwrap(),
+        SubCommand::Provisioned(ProvisionedCommand::Down(down.clone())),
+    );
+    assert!(resolve_shorthand(&SubCommand::Down(down), &byoc).is_err());
+    assert_eq!(
+        resolve_shorthand(&SubCommand::Doctor(config_path.clone()), &byoc).unwrap(),
+        SubCommand::Doctor(config_path),
+    );
+}
+
+#[rstest::rstest]
+#[case(
+    "Acquiring an up-to-date head node",
+    Some(progress::Stage::Provisioning)
+)]
+#[case(
+    "  Launched instance i-0123456789abcdef0 [state=pending, info=pending]",
+    Some(progress::Stage::Provisioning)
+)]
+#[case(
+    "[1/7] Waiting for SSH to become available",
+    Some(progress::Stage::WaitingForSsh)
+)]
+#[case("[2/7] Processing file mounts", Some(progress::Stage::SyncingFiles))]
+#[case(
+    "[6/7] Running setup commands",
+    Some(progress::Stage::RunningSetupCommands)
+)]
+#[case("[7/7] Starting the Ray runtime", Some(progress::Stage::StartingRay))]
+#[case("Fetching the new head node", None)]
+fn test_ray_up_stage(#[case] line: &str, #[case] expected: Option<progress::Stage>) {
+    assert_eq!(progress::Stage::of(line), expected);
+}
+
+#[test]
+fn test_ray_up_progress() {
+    let progress = progress::Progress::default();
+    assert_eq!(progress.advance("Cluster: daft"), None);
+    assert_eq!(
+        progress.advance("[1/7] Waiting for SSH to become available"),
+        Some(progress::Stage::WaitingForSsh)
+    );
+    assert_eq!(progress.advance("    Running `uptime`"), None);
+    // Stages are only reported once, and never go backwards.
+    assert_eq!(
+        progress.advance("[1/7] Waiting for SSH to become available"),
+        None
+    );
+    assert_eq!(progress.advance("Launched instance i-0123"), None);
+    assert_eq!(
+        progress.advance("[6/7] Running setup commands"),
+        Some(progress::Stage::RunningSetupCommands)
+    );
+}
+
+#[test]
+fn test_manpages() {
+    let temp_dir = TempDir::new("test_manpages").unwrap();
+    let paths = manpages::write_all(&DaftLauncher::command(), temp_dir.path()).unwrap();
+    assert!(paths.contains(&temp_dir.path().join("daft.1")));
+    assert!(paths.contains(&temp_dir.path().join("daft-provisioned-up.1")));
+    assert!(paths.contains(&temp_dir.path().join("daft-job-submit.1")));
+    assert!(!paths.iter().any(|path| path.ends_with("daft-help.1")));
+
+    let page = std::fs::read_to_string(temp_dir.path().join("daft.1")).unwrap();
+    assert!(page.contains("daft job submit example"));
+}
+
+#[tokio::test]
+async fn test_region_is_optional() {
+    let temp_dir = TempDir::new("test_region_is_optional").unwrap();
+    let path = temp_dir.path().join(".daft.toml");
+    fs::write(
+        &path,
+        format!(
+            r#"
+[setup]
+name = "test"
+requires = "={version}"
+python-version = "3.12"
+ray-version = "2.34"
+
+[setup.provisioned]
+ssh-user = "ubuntu"
+ssh-private-key = "~/.ssh/daft-key.pem"
+"#,
+            version = env!("CARGO_PKG_VERSION"),
+        ),
+    )
+    .await
+    .unwrap();
+
+    let daft_config = config::read_with_defaults::<DaftConfig>(&path, None, None, Secrets::Resolve)
+        .await
+        .unwrap();
+    let ProviderConfig::Provisioned(aws_config) = daft_config.setup.provider_config else {
+        panic!("Expected a provisioned configuration");
+    };
+    // The region is detected once the configuration is read.
+    assert_eq!(aws_config.region, None);
+    assert_eq!(
+        &*resolve_region(Some(&"eu-west-1".into())).await.unwrap(),
+        "eu-west-1"
+    );
+}
+
+#[tokio::test]
+async fn test_empty_region_is_rejected() {
+    let temp_dir = TempDir::new("test_empty_region_is_rejected").unwrap();
+    let path = temp_dir.path().join(".daft.toml");
+    fs::write(
+        &path,
+        format!(
+            r#"
+[setup]
+name = "test"
+requires = "={version}"
+python-version = "3.12"
+ray-version = "2.34"
+
+[setup.provisioned]
+region = ""
+ssh-user = "ubuntu"
+ssh-private-key = "~/.ssh/daft-key.pem"
+"#,
+            version = env!("CARGO_PKG_VERSION"),
+        ),
+    )
+    .await
+    .unwrap();
+
+    let error = read_daft_config(&path, None).await.unwrap_err().to_string();
+    assert!(error.contains("must not be empty"), "{error}");
+}
+
+#[test]
+fn test_list_contexts() {
+    let daft_launcher =
+        DaftLauncher::try_parse_from(["daft", "list", "--context", "staging", "--context", "prod"])
+            .unwrap();
+    let SubCommand::List(ListClusters { contexts, .. }) = daft_launcher.sub_command else {
+        panic!("Expected the list command");
+    };
+    assert_eq!(contexts, [StrRef::from("staging"), StrRef::from("prod")]);
+}
+
+#[rstest::rstest]
+#[case(&["daft", "provisioned", "list", "--region", "eu-west-1"])]
+#[case(&["daft", "--region", "eu-west-1", "provisioned", "up", "--workers", "2"])]
+#[case(&["daft", "config", "init", "--template", "gpu", "--region", "eu-west-1"])]
+fn test_global_region(#[case] args: &[&str]) {
+    let daft_launcher = DaftLauncher::try_parse_from(args).unwrap();
+    assert_eq!(daft_launcher.region.as_deref(), Some("eu-west-1"));
+}
+
+#[test]
+fn test_known_clusters() {
+    let cluster = |name: &str, region: &str, config: &str| state::Cluster {
+        name: name.into(),
+        region: region.into(),
+        config: config.into(),
+        provider: "provisioned".into(),
+        head_ip: None,
+        created_at: None,
+        state: None,
+        profile: None,
+    };
+    let clusters = [
+        cluster("a", "us-west-2", "/old/a.toml"),
+        cluster("b", "us-west-2", "/deleted/b.toml"),
+        cluster("a", "us-west-2", "/new/a.toml"),
+        cluster("a", "eu-west-1", "/eu/a.toml"),
+    ];
+    let known = state::known_clusters(&clusters, |path| !path.starts_with("/deleted"));
+    assert_eq!(known, [&clusters[3], &clusters[2]]);
+
+    let running = HashSet::from([(StrRef::from("a"), StrRef::from("us-west-2"))]);
+    assert_eq!(
+        format_cluster_options(&known, &running),
+        [
+            "a (eu-west-1, not running) from /eu/a.toml",
+            "a (us-west-2, running) from /new/a.toml",
+        ]
+    );
+}
+
+#[test]
+fn test_find_cluster() {
+    // Records from before the clusters' states were recorded are still read.
+    let clusters = state::parse_records::<state::Cluster>(concat!(
+        r#"{"name":"a","region":"us-west-2","config":"/a.toml"}"#,
+        "\n",
+        r#"{"name":"b","region":"us-west-2","config":"/b.toml","provider":"provisioned","head_ip":"1.2.3.4","created_at":1700000000,"state":"up"}"#,
+        "\n",
+        r#"{"name":"a","region":"eu-west-1","config":"/eu/a.toml","provider":"provisioned","head_ip":null,"created_at":1700000000,"state":"killed"}"#,
+        "\n",
+    ));
+    assert_eq!(clusters.len(), 3);
+    assert_eq!(clusters[0].state, None);
+    assert_eq!(clusters[1].head_ip.as_deref(), Some("1.2.3.4"));
+    let known = state::known_clusters(&clusters, |_| true);
+
+    let found = |name, region| {
+        state::find_cluster(&known, name, region).map(|cluster| cluster.config.clone())
+    };
+    assert_eq!(found("a", None), Some(PathBuf::from("/eu/a.toml")));
+    assert_eq!(
+        found("a", Some("us-west-2")),
+        Some(PathBuf::from("/a.toml"))
+    );
+    assert_eq!(found("b", Some("eu-west-1")), None);
+    assert_eq!(found("c", None), None);
+}
+
+#[test]
+fn test_terminal_title_sequence() {
+    assert_eq!(
+        notifications::terminal_title_sequence("test: [2/5] Waiting for SSH\n"),
+        "\x1b]0;test: [2/5] Waiting for SSH \x07"
+    );
+}
+
+#[test]
+fn test_list_clusters() {
+    let instances = [
+        aws_instance("i-0", "a", NodeType::Head, InstanceStateName::Running),
+        aws_instance("i-1", "a", NodeType::Worker, InstanceStateName::Running),
+        aws_instance("i-2", "a", NodeType::Worker, InstanceStateName::Terminated),
+        aws_instance("i-3", "b", NodeType::Head, InstanceStateName::Stopped),
+    ];
+    let kuberay = r#"{"items": [{
+        "metadata": {"name": "raycluster-kuberay", "namespace": "ray"},
+        "status": {"state": "ready", "availableWorkerReplicas": 2}
+    }]}"#;
+
+    let mut clusters = aws_cluster_records(&instances, "us-west-2");
+    clusters.extend(parse_kuberay_clusters(kuberay, Some("staging")).unwrap());
+    let summary = clusters
+        .iter()
+        .map(|cluster| {
+            format!(
+                "{} {} {} {} {}",
+                cluster.provider, cluster.name, cluster.location, cluster.status, cluster.workers
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            "aws a us-west-2 running 1",
+            "aws b us-west-2 stopped 0",
+            "kubernetes raycluster-kuberay staging/ray ready 2",
+        ]
+    );
+}
+
+#[rstest::rstest]
+#[case(ListColumn::Name, ["i-1", "i-0", "i-2"])]
+#[case(ListColumn::InstanceId, ["i-0", "i-1", "i-2"])]
+#[case(ListColumn::Uptime, ["i-2", "i-0", "i-1"])]
+fn test_sort_instances(#[case] column: ListColumn, #[case] expected: [&str; 3]) {
+    let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let instance = |id: &str, name: &str, launch_time| AwsInstance {
+        launch_time,
+        ..aws_instance(id, name, NodeType::Head, InstanceStateName::Running)
+    };
+    let instances = [
+        instance("i-0", "b", Some(now - Duration::from_secs(60))),
+        instance("i-1", "a", None),
+        instance("i-2", "c", Some(now - Duration::from_secs(3600))),
+    ];
+    let mut sorted = instances.iter().collect::<Vec<_>>();
+    sort_instances(&mut sorted, column);
+    let ids = sorted
+        .iter()
+        .map(|instance| &*instance.instance_id)
+        .collect::<Vec<_>>();
+    assert_eq!(ids, expected);
+    assert_eq!(format_launch_time(now), "2023-11-14T22:13:20Z");
+}
+
+#[test]
+fn test_hourly_cost() {
+    let product = r#"{
+        "product": {"attributes": {"instanceType": "i3.2xlarge", "regionCode": "us-west-2"}},
+        "terms": {"OnDemand": {"ABC.JRTCKXETXF": {"priceDimensions": {"ABC.JRTCKXETXF.6YS6EN2CT7": {
+            "unit": "Hrs",
+            "pricePerUnit": {"USD": "0.6240000000"}
+        }}}}}
+    }"#;
+    assert_eq!(pricing::parse_price(product), Some(0.624));
+    assert_eq!(pricing::parse_price(r#"{"terms": {}}"#), None);
+
+    let instance = |id: &str, name: &str, state| AwsInstance {
+        instance_type: Some("i3.2xlarge".into()),
+        ..aws_instance(id, name, NodeType::Head, state)
+    };
+    let instances = [
+        instance("i-0", "a", InstanceStateName::Running),
+        instance("i-1", "a", InstanceStateName::Running),
+        instance("i-2", "a", InstanceStateName::Stopped),
+        instance("i-3", "b", InstanceStateName::Pending),
+    ];
+    let prices = HashMap::from([(StrRef::from("i3.2xlarge"), 0.624)]);
+    let costs = cluster_hourly_costs(&instances.iter().collect::<Vec<_>>(), &prices);
+    assert_eq!(
+        costs
+            .iter()
+            .map(|(cluster, cost)| format!("{cluster} {}", pricing::format_price(*cost)))
+            .collect::<Vec<_>>(),
+        ["a $1.248", "b $0.624"]
+    );
+}
+
+#[test]
+fn test_summarize_clusters() {
+    let instance = |name: &str, node_type, state, vcpus| AwsInstance {
+        public_ipv4_address: matches!(node_type, NodeType::Head)
+            .then(|| Ipv4Addr::new(10, 0, 0, 1)),
+        vcpus: Some(vcpus),
+        ..aws_instance("i-0", name, node_type, state)
+    };
+    let instances = [
+        instance("a", NodeType::Worker, InstanceStateName::Running, 8),
+        instance("a", NodeType::Head, InstanceStateName::Running, 4),
+        instance("b", NodeType::Head, InstanceStateName::Stopped, 4),
+        instance("a", NodeType::Worker, InstanceStateName::Pending, 8),
+        instance("a", NodeType::Worker, InstanceStateName::Running, 8),
+        instance("a", NodeType::Worker, InstanceStateName::Terminated, 8),
+    ];
+    let summaries = summarize_clusters(&instances.iter().collect::<Vec<_>>());
+    assert_eq!(
+        summaries,
+        [
+            ClusterSummary {
+                name: "a",
+                head_state: Some("running"),
+                head_ipv4_address: Some(Ipv4Addr::new(10, 0, 0, 1)),
+                workers: BTreeMap::from([("pending", 1), ("running", 2), ("terminated", 1)]),
+                vcpus: 28,
+            },
+            ClusterSummary {
+                name: "b",
+                head_state: Some("stopped"),
+                head_ipv4_address: Some(Ipv4Addr::new(10, 0, 0, 1)),
+                workers: BTreeMap::new(),
+                vcpus: 0,
+            },
+        ]
+    );
+    let table =
+        format_cluster_summaries_table(&summaries, &BTreeMap::from([("a", 1.5)])).to_string();
+    assert!(table.contains("2 running"), "{table}");
+    assert!(table.contains("$1.500"), "{table}");
+}
+
+#[test]
+fn test_monitor() {
+    let nodes = r#"{"result": true, "data": {"summary": [
+        {"ip": "10.0.0.2", "cpu": 87.5, "mem": [8589934592, 2147483648, 75.0, 6442450944],
+         "raylet": {"isHeadNode": false, "state": "ALIVE", "objectStoreUsedMemory": 1073741824, "objectStoreAvailableMemory": 3221225472}},
+        {"ip": "10.0.0.1", "cpu": 12.0, "mem": [4294967296, 4294967296, 0.0, 0],
+         "raylet": {"isHeadNode": true, "state": "ALIVE", "objectStoreUsedMemory": 0, "objectStoreAvailableMemory": 1073741824}}
+    ]}}"#;
+    let nodes = monitor::parse_nodes(nodes).unwrap();
+    assert_eq!(
+        nodes
+            .iter()
+            .map(|node| (&*node.ip, node.is_head))
+            .collect::<Vec<_>>(),
+        [("10.0.0.1", true), ("10.0.0.2", false)]
+    );
+    assert_eq!(
+        monitor::format_usage(nodes[1].memory_used, nodes[1].memory_total),
+        "6.0/8.0 GiB (75%)"
+    );
+    assert_eq!(
+        monitor::format_usage(nodes[1].object_store_used, nodes[1].object_store_total),
+        "1.0/4.0 GiB (25%)"
+    );
+    assert_eq!(monitor::format_usage(0, 0), "0.0/0.0 GiB (0%)");
+    assert!(monitor::parse_nodes(r#"{"result": false}"#).is_err());
+
+    let events = r#"{"result": true, "data": {"result": {"total": 3, "result": [
+        {"time": "2024-05-01 10:00:00", "severity": "INFO", "source_type": "AUTOSCALER", "message": "Adding 2 node(s) of type worker.\n"},
+        {"time": "2024-05-01 10:05:00", "severity": "INFO", "source_type": "GCS", "message": "Something else"},
+        {"time": "2024-05-01 10:10:00", "severity": "WARNING", "source_type": "AUTOSCALER", "message": "Removing 1 node(s) of type worker (idle)."}
+    ]}}}"#;
+    assert_eq!(
+        monitor::parse_autoscaler_events(events).unwrap(),
+        [
+            monitor::AutoscalerEvent {
+                time: "2024-05-01 10:10:00".into(),
+                severity: "WARNING".into(),
+                message: "Removing 1 node(s) of type worker (idle).".into(),
+            },
+            monitor::AutoscalerEvent {
+                time: "2024-05-01 10:00:00".into(),
+                severity: "INFO".into(),
+                message: "Adding 2 node(s) of type worker.".into(),
+            },
+        ]
+    );
+}
+
+#[rstest::rstest]
+#[case(
+    "2024-05-01 10:00:00,123\tINFO autoscaler.py:1001 -- StandardAutoscaler: Adding 2 node(s) of type ray.worker.default.",
+    Some((autoscaler_log::EventKind::NodeLaunched, Some("2024-05-01 10:00:00,123"), "StandardAutoscaler: Adding 2 node(s) of type ray.worker.default."))
+)]
+#[case(
+    "2024-05-01 10:01:00,000\tWARNING node_launcher.py:108 -- Failed to launch 2 node(s) of type ray.worker.default. (InsufficientInstanceCapacity): We currently do not have sufficient capacity.",
+    Some((autoscaler_log::EventKind::NodeFailedToLaunch, Some("2024-05-01 10:01:00,000"), "Failed to launch 2 node(s) of type ray.worker.default. (InsufficientInstanceCapacity): We currently do not have sufficient capacity."))
+)]
+#[case(
+    "Removing 1 nodes of type ray.worker.default (idle).",
+    Some((autoscaler_log::EventKind::ScaleDown, None, "Removing 1 nodes of type ray.worker.default (idle)."))
+)]
+#[case(
+    "2024-05-01 10:02:00,000\tWARNING resource_demand_scheduler.py:782 -- The autoscaler could not find a node type to satisfy the request: [{'GPU': 8}]. No available node types can fulfill resource request {'GPU': 8}.",
+    Some((autoscaler_log::EventKind::Infeasible, Some("2024-05-01 10:02:00,000"), "The autoscaler could not find a node type to satisfy the request: [{'GPU': 8}]. No available node types can fulfill resource request {'GPU': 8}."))
+)]
+#[case(
+    "2024-05-01 10:03:00,000\tINFO autoscaler.py:470 -- Resized to 4 CPUs.",
+    None
+)]
+fn test_autoscaler_log(
+    #[case] line: &str,
+    #[case] expected: Option<(autoscaler_log::EventKind, Option<&str>, &str)>,
+) {
+    let event = autoscaler_log::parse_line(line);
+    assert_eq!(
+        event
+            .as_ref()
+            .map(|event| (event.kind, event.time.as_deref(), &*event.message)),
+        expected
+    );
+}
+
+#[test]
+fn test_format_node_outputs() {
+    let outputs = [
+        ssh::NodeOutput {
+            address: "10.0.0.1".parse().unwrap(),
+            is_head: true,
+            exit_code: Some(0),
+            stdout: "Filesystem Size\n/dev/root 97G\n".into(),
+            stderr: "".into(),
+        },
+        ssh::NodeOutput {
+            address: "10.0.0.2".parse().unwrap(),
+            is_head: false,
+            exit_code: Some(127),
+            stdout: "".into(),
+            stderr: "bash: nvidia-smi: command not found".into(),
+        },
+    ];
+    assert_eq!(
+        ssh::format_node_outputs(&outputs),
+        "==> 10.0.0.1 (head) <==\nFilesystem Size\n/dev/root 97G\n==> 10.0.0.2 (worker) (exited with 127) <==\nbash: nvidia-smi: command not found\n"
+    );
+}
+
+#[test]
+fn test_rsync_remote_shell() {
+    let (daft_config, _, _) = simple_config();
+    let ProviderConfig::Provisioned(mut aws_config) = daft_config.setup.provider_config else {
+        unreachable!()
+    };
+    assert_eq!(
+        ssh::rsync_remote_shell(&aws_config, None),
+        "ssh -i testkey.pem -o StrictHostKeyChecking=no -o ServerAliveInterval=30 -o ServerAliveCountMax=3 -o ControlPath=none"
+    );
+    aws_config.ssh_private_key = Arc::from(PathBuf::from("my keys/test key.pem"));
+    aws_config.ssh_keep_alive = 0;
+    assert_eq!(
+        ssh::rsync_remote_shell(&aws_config, None),
+        "ssh -i 'my keys/test key.pem' -o StrictHostKeyChecking=no -o ControlPath=none"
+    );
+    assert_eq!(
+        ssh::rsync_remote_shell(&aws_config, Some(Path::new("/home/me/.daft-launcher/ssh"))),
+        "ssh -i 'my keys/test key.pem' -o StrictHostKeyChecking=no -o ControlMaster=auto -o 'ControlPath=/home/me/.daft-launcher/ssh/%C' -o ControlPersist=600"
+    );
+
+    aws_config.ssh_proxy_jump = Some(ProxyJump {
+        host: "ec2-user@bastion.example.com".into(),
+        ssh_private_key: Arc::from(PathBuf::from("bastion.pem")),
+    });
+    assert_eq!(
+        ssh::proxy_command(aws_config.ssh_proxy_jump.as_ref().unwrap()),
+        "ssh -i bastion.pem -o StrictHostKeyChecking=no -W %h:%p ec2-user@bastion.example.com"
+    );
+    assert_eq!(
+        ssh::rsync_remote_shell(&aws_config, None),
+        "ssh -i 'my keys/test key.pem' -o StrictHostKeyChecking=no -o 'ProxyCommand=ssh -i bastion.pem -o StrictHostKeyChecking=no -W %h:%p ec2-user@bastion.example.com' -o ControlPath=none"
+    );
+}
+
+#[test]
+fn test_node_address_through_bastion() {
+    let (daft_config, _, _) = simple_config();
+    let ProviderConfig::Provisioned(mut aws_config) = daft_config.setup.provider_config else {
+        unreachable!()
+    };
+    let public = Ipv4Addr::new(203, 0, 113, 7);
+    let private = Ipv4Addr::new(10, 0, 0, 7);
+    let worker = AwsInstance {
+        public_ipv4_address: Some(public),
+        private_ipv4_address: Some(private),
+        ..aws_instance(
+            "i-0123",
+            "my-cluster",
+            NodeType::Worker,
+            InstanceStateName::Running,
+        )
+    };
+    let private_worker = AwsInstance {
+        public_ipv4_address: None,
+        ..worker.clone()
+    };
+    assert_eq!(ssh::node_address(&worker, &aws_config), Some(public));
+    assert_eq!(
+        ssh::node_address(&private_worker, &aws_config),
+        Some(private)
+    );
+
+    aws_config.ssh_proxy_jump = Some(ProxyJump {
+        host: "ec2-user@bastion.example.com".into(),
+        ssh_private_key: Arc::from(PathBuf::from("bastion.pem")),
+    });
+    assert_eq!(ssh::node_address(&worker, &aws_config), Some(private));
+    assert_eq!(
+        ssh::node_address(&private_worker, &aws_config),
+        Some(private)
+    );
+}
+
+#[test]
+fn test_ssh_command_exits_on_forward_failure() {
+    let (daft_config, _, _) = simple_config();
+    let ProviderConfig::Provisioned(aws_config) = daft_config.setup.provider_config else {
+        unreachable!()
+    };
+    let args = |portforwards: &[(u16, u16)]| {
+        ssh::ssh_command_to(Ipv4Addr::LOCALHOST, &aws_config, portforwards)
+            .as_std()
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+    };
+    assert!(!args(&[]).contains(&"ExitOnForwardFailure=yes".to_string()));
+    let args = args(&[(8265, 8265)]);
+    let position = args
+        .iter()
+        .position(|arg| arg == "ExitOnForwardFailure=yes")
+        .unwrap();
+    assert_eq!(args[position - 1], "-o");
+    assert!(args.contains(&"8265:localhost:8265".to_string()));
+}
+
+#[rstest::rstest]
+#[case("10001", Ok((10001, 10001)))]
+#[case("3000:grafana", Ok((3000, 3000)))]
+#[case("9091:prometheus", Ok((9091, 9090)))]
+#[case("client", Ok((10001, 10001)))]
+#[case("8080:8265", Ok((8080, 8265)))]
+#[case("grafna", Err(()))]
+#[case("x:3000", Err(()))]
+#[case("70000", Err(()))]
+fn test_parse_port_forward(#[case] raw: &str, #[case] expected: Result<(u16, u16), ()>) {
+    assert_eq!(
+        parse_port_forward(raw)
+            .map(|forward| (forward.local, forward.remote))
+            .map_err(|_| ()),
+        expected
+    );
+}
+
+#[rstest::rstest]
+#[case(&["--client"], &[(10001, 10001)])]
+#[case(&["--forward", "client"], &[(10001, 10001)])]
+#[case(&["--forward", "3000:grafana", "--client"], &[(3000, 3000), (10001, 10001)])]
+#[case(&[], &[])]
+fn test_connect_client(#[case] args: &[&str], #[case] expected: &[(u16, u16)]) {
+    let DaftLauncher { sub_command, .. } =
+        DaftLauncher::try_parse_from(["daft", "provisioned", "connect"].iter().chain(args))
+            .unwrap();
+    let SubCommand::Provisioned(ProvisionedCommand::Connect(connect)) = sub_command else {
+        panic!("Expected `provisioned connect`, but got {sub_command:?}");
+    };
+    assert_eq!(
+        connect
+            .port_forwards()
+            .iter()
+            .map(|forward| (forward.local, forward.remote))
+            .collect::<Vec<_>>(),
+        expected
+    );
+}
+
+#[test]
+fn test_merge_port_forwards() {
+    assert_eq!(
+        merge_port_forwards([(9090, 9090), (3000, 3000), (10001, 10001), (3000, 8080)]),
+        [(9090, 9090), (10001, 10001), (3000, 8080)]
+    );
+}
+
+#[test]
+fn test_ssm_connection() {
+    assert_eq!(
+        serde_json::from_str::<Connection>(r#""ssm""#).unwrap(),
+        Connection::Ssm
+    );
+    assert!(serde_json::from_str::<Connection>(r#""telnet""#).is_err());
+    let (daft_config, _, _) = simple_config();
+    assert!(!toml::to_string(&daft_config)
+        .unwrap()
+        .contains("connection"));
+
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&ssm::port_forwarding_parameters(8266, 8265))
+            .unwrap(),
+        serde_json::json!({"portNumber": ["8265"], "localPortNumber": ["8266"]})
+    );
+}
+
+#[rstest::rstest]
+#[case(0, 1)]
+#[case(1, 2)]
+#[case(4, 16)]
+#[case(5, 30)]
+#[case(100, 30)]
+fn test_tunnel_backoff(#[case] attempt: u32, #[case] expected_secs: u64) {
+    assert_eq!(tunnel::backoff(attempt), Duration::from_secs(expected_secs));
+}
+
+#[tokio::test]
+async fn test_tunnel_reconnects() {
+    let reconnects = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let child = tokio::process::Command::new("true").spawn().unwrap();
+    let tunnel = tunnel::supervise(child, 8266, {
+        let reconnects = reconnects.clone();
+        move || {
+            reconnects.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async {
+                Ok::<_, anyhow::Error>(
+                    tokio::process::Command::new("sleep")
+                        .arg("60")
+                        .kill_on_drop(true)
+                        .spawn()?,
+                )
+            }
+        }
+    });
+    assert_eq!(tunnel.address(), "http://localhost:8266");
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    assert_eq!(reconnects.load(std::sync::atomic::Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_detached_tunnels() {
+    let mut child = tokio::process::Command::new("sleep")
+        .arg("60")
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+    let pid = child.id().unwrap();
+    let command = process_command(pid).await;
+    assert!(command
+        .as_deref()
+        .is_some_and(|command| command.contains("sleep")));
+    let tunnel = state::DetachedTunnel {
+        cluster: "my-cluster".into(),
+        pid,
+        ports: vec![(8265, 8265)],
+        opened_at: 1_700_000_000,
+        command,
+    };
+    assert!(is_open(&tunnel).await);
+    // A process which reused the tunnel's pid is not taken for it, nor is a
+    // tunnel whose command line was not recorded.
+    assert!(
+        !is_open(&state::DetachedTunnel {
+            command: Some("ssh -N -L 8265:localhost:8265 ubuntu@10.0.0.1".into()),
+            ..tunnel.clone()
+        })
+        .await
+    );
+    assert!(
+        !is_open(&state::DetachedTunnel {
+            command: None,
+            ..tunnel.clone()
+        })
+        .await
+    );
+    child.kill().await.unwrap();
+    assert!(!is_open(&tunnel).await);
+
+    let tunnels = [state::DetachedTunnel {
+        cluster: "my-cluster".into(),
+        pid: 4242,
+        ports: vec![(8265, 8265), (10001, 10001)],
+        opened_at: 1_700_000_000,
+        command: None,
+    }];
+    let table = format_tunnels_table(
+        &tunnels,
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000 + 3600 + 120),
+    )
+    .to_string();
+    for expected in ["my-cluster", "4242", "8265:8265, 10001:10001", "1h 2m 0s"] {
+        assert!(table.contains(expected), "{table}");
+    }
+}
+
+#[tokio::test]
+async fn test_dry_run_address_with_dashboard_port_in_use() {
+    let (daft_config, _, _) = simple_config();
+    // Either this listener or whatever already listens there holds the port.
+    let _listener = std::net::TcpListener::bind(("127.0.0.1", RAY_DASHBOARD_PORT));
+    let address = describe_dry_run_address(&daft_config).await;
+    assert!(
+        address.starts_with("a free local port, picked at submit time"),
+        "{address}"
+    );
+}
+
+#[tokio::test]
+async fn test_wait_for_dashboard() {
+    let listener = tokio::net::TcpListener::bind(("localhost", 0))
+        .await
+        .unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = tokio::io::AsyncReadExt::read(&mut stream, &mut request).await;
+            let _ = tokio::io::AsyncWriteExt::write_all(
+                &mut stream,
+                b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\n{}",
+            )
+            .await;
+        }
+    });
+
+    let mut child = tokio::process::Command::new("sleep")
+        .arg("10")
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+    tunnel::wait_for_dashboard(&mut child, port, Duration::from_secs(5))
+        .await
+        .unwrap();
+
+    let mut child = tokio::process::Command::new("sh")
+        .args(["-c", "echo 'bind: Address already in use' >&2; exit 1"])
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let error = tunnel::wait_for_dashboard(
+        &mut child,
+        free_local_port().unwrap(),
+        Duration::from_secs(5),
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        error.to_string().contains("Address already in use"),
+        "{error}"
+    );
+}
+
+#[rstest::rstest]
+#[case("7d", Ok(7))]
+#[case("2w", Ok(14))]
+#[case("30d", Ok(30))]
+#[case("0d", Err(()))]
+#[case("7", Err(()))]
+#[case("d", Err(()))]
+#[case("", Err(()))]
+#[case("7д", Err(()))]
+#[case("700000000w", Err(()))]
+fn test_cost_since(#[case] raw: &str, #[case] expected: Result<u32, ()>) {
+    assert_eq!(cost::parse_since(raw).map_err(|_| ()), expected);
+}
+
+#[test]
+fn test_cost_report() {
+    assert_eq!(
+        cost::cluster_name("ray-cluster-name$my-cluster"),
+        Some("my-cluster")
+    );
+    assert_eq!(cost::cluster_name("ray-cluster-name$"), None);
+    assert_eq!(cost::cluster_name("team$data"), None);
+    assert_eq!(
+        cost::format_date(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+        "2023-11-14"
+    );
+
+    let cost = |date: &str, cluster: &str, usd| cost::DailyCost {
+        date: date.into(),
+        cluster: cluster.into(),
+        usd,
+    };
+    let costs = [
+        cost("2024-05-01", "a", 1.25),
+        cost("2024-05-01", "b", 10.0),
+        cost("2024-05-02", "a", 2.5),
+    ];
+    assert_eq!(
+        cost::totals(&costs),
+        BTreeMap::from([("a", 3.75), ("b", 10.0)])
+    );
+    let table = format_costs_table(&costs).to_string();
+    let row = |date: &str| {
+        let line = table.lines().find(|line| line.contains(date)).unwrap();
+        line.split('│')
+            .map(str::trim)
+            .filter(|cell| !cell.is_empty())
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        row("2024-05-01"),
+        ["2024-05-01", "$1.25", "$10.00"],
+        "{table}"
+    );
+    assert_eq!(row("2024-05-02"), ["2024-05-02", "$2.50", "-"], "{table}");
+}
+
+#[test]
+fn test_action_history() {
+    let action = |action, cluster: &str, at| state::Action {
+        action,
+        user: "alice".into(),
+        cluster: cluster.into(),
+        config_hash: "0123456789ab".into(),
+        detail: None,
+        at,
+    };
+    let contents = [
+        action(state::ActionKind::Up, "dev", 1),
+        action(state::ActionKind::Up, "prod", 2),
+        action(state::ActionKind::Kill, "dev", 3),
+    ]
+    .iter()
+    .map(|action| serde_json::to_string(action).unwrap())
+    .collect::<Vec<_>>()
+    .join("\n");
+    assert!(contents.contains(r#""action":"kill""#));
+    let actions = state::parse_records::<state::Action>(&format!("{contents}\n{{\"action\":"));
+    assert_eq!(actions.len(), 3);
+
+    let recent = state::recent_actions(&actions, Some("dev"), 20);
+    assert_eq!(
+        recent.iter().map(|action| action.at).collect::<Vec<_>>(),
+        [3, 1]
+    );
+    let recent = state::recent_actions(&actions, None, 2);
+    assert_eq!(
+        recent.iter().map(|action| action.at).collect::<Vec<_>>(),
+        [3, 2]
+    );
+    let table = format_actions_table(&recent).to_string();
+    assert!(table.contains("1970-01-01T00:00:03Z"), "{table}");
+    assert!(table.contains("kill"), "{table}");
+
+    let (mut daft_config, _, _) = simple_config();
+    let hash = config_hash(&daft_config);
+    assert_eq!(hash.len(), 12);
+    assert_eq!(config_hash(&daft_config), hash);
+    daft_config.setup.name = "other".into();
+    assert_ne!(config_hash(&daft_config), hash);
+}
+
+#[test]
+fn test_metrics() {
+    let (mut daft_config, _, _) = simple_config();
+    daft_config.setup.metrics = Some(
+        toml::from_str::<MetricsConfig>(
+            r#"
+grafana-port = 3001
+"#,
+        )
+        .unwrap(),
+    );
+    let metrics = daft_config.setup.metrics.as_ref().unwrap();
+    assert_eq!(metrics.prometheus_port, 9090);
+    assert_eq!(metrics.grafana_port, 3001);
+    assert_eq!(
+        metrics::port_forwards(metrics),
+        [(9090, 9090), (3001, 3000)]
+    );
+    assert!(toml::from_str::<MetricsConfig>("grafana = true").is_err());
+
+    let ray_config = convert(&daft_config, None).unwrap();
+    assert_eq!(ray_config.head_setup_commands.len(), 1);
+    assert!(ray_config.head_setup_commands[0].contains("grafana-11.2.0.linux-"));
+    assert!(ray_config
+        .head_start_ray_commands
+        .iter()
+        .any(|command| command
+            .contains("RAY_GRAFANA_IFRAME_HOST=http://localhost:3001 ray start --head")));
+    assert!(ray_config
+        .head_start_ray_commands
+        .contains(&StrRef::from("ray metrics launch-prometheus")));
+    let yaml = serde_yaml::to_string(&ray_config).unwrap();
+    assert!(yaml.contains("head_start_ray_commands:"), "{yaml}");
+
+    daft_config.setup.metrics = None;
+    let yaml = serde_yaml::to_string(&convert(&daft_config, None).unwrap()).unwrap();
+    assert!(!yaml.contains("head_"), "{yaml}");
+}
+
+#[test]
+fn test_job_metrics() {
+    let tasks = r#"{"result": true, "data": {"result": {"total": 4, "result": [
+        {"name": "read", "func_or_class_name": "read_parquet", "state": "FINISHED", "start_time_ms": 1000, "end_time_ms": 4000},
+        {"name": "read", "func_or_class_name": "read_parquet", "state": "FINISHED", "start_time_ms": 1000, "end_time_ms": 2000},
+        {"name": "join", "func_or_class_name": "hash_join", "state": "FAILED", "start_time_ms": 5000, "end_time_ms": 10000},
+        {"name": "write", "state": "PENDING_NODE_ASSIGNMENT"}
+    ]}}}"#;
+    let tasks = job_metrics::parse_tasks(tasks).unwrap();
+    assert_eq!(tasks.len(), 4);
+    assert_eq!(
+        job_metrics::slowest_stages(&tasks),
+        [
+            job_metrics::StageTiming {
+                name: "hash_join".into(),
+                tasks: 1,
+                failed_tasks: 1,
+                total_ms: 5000,
+                max_ms: 5000,
+            },
+            job_metrics::StageTiming {
+                name: "read_parquet".into(),
+                tasks: 2,
+                failed_tasks: 0,
+                total_ms: 4000,
+                max_ms: 3000,
+            },
+            job_metrics::StageTiming {
+                name: "write".into(),
+                tasks: 1,
+                failed_tasks: 0,
+                total_ms: 0,
+                max_ms: 0,
+            },
+        ]
+    );
+    assert!(job_metrics::tasks_path("02000000").contains("filter_values=02000000"));
+
+    let nodes = r#"{"data": {"summary": [
+        {"raylet": {"storeStats": {"spilledBytesTotal": 1073741824, "restoredBytesTotal": 1048576}}},
+        {"raylet": {"storeStats": {"spilledBytesTotal": 536870912}}}
+    ]}}"#;
+    assert_eq!(
+        job_metrics::parse_spilling(nodes).unwrap(),
+        (1610612736, 1048576)
+    );
+    assert_eq!(job_metrics::format_bytes(1610612736), "1.5 GiB");
+    assert_eq!(job_metrics::format_bytes(1048576), "1.0 MiB");
+
+    let prometheus = r#"{"status": "success", "data": {"resultType": "vector", "result": [
+        {"metric": {}, "value": [1714550400, "8589934592"]}
+    ]}}"#;
+    assert_eq!(
+        job_metrics::parse_prometheus_value(prometheus),
+        Some(8589934592)
+    );
+    assert_eq!(
+        job_metrics::parse_prometheus_value(r#"{"data": {"result": []}}"#),
+        None
+    );
+}
+
+#[test]
+fn test_idle_for() {
+    let now = UNIX_EPOCH + Duration::from_secs(10 * 3600);
+    let launched = Some(UNIX_EPOCH + Duration::from_secs(3600));
+    let finished = |id: &str, end_hours: u64| RayJob {
+        end_time: Some(end_hours * 3600 * 1000),
+        ..ray_job(id, RayJobStatus::Succeeded, None)
+    };
+
+    assert_eq!(
+        idle_for(&[], launched, now),
+        Some(Duration::from_secs(9 * 3600))
+    );
+    assert_eq!(
+        idle_for(&[finished("a", 4), finished("b", 7)], launched, now),
+        Some(Duration::from_secs(3 * 3600))
+    );
+    assert_eq!(
+        idle_for(
+            &[finished("a", 4), ray_job("b", RayJobStatus::Running, None)],
+            launched,
+            now
+        ),
+        None
+    );
+    assert_eq!(idle_for(&[], None, now), None);
+    assert!(DaftLauncher::try_parse_from(["daft", "provisioned", "list", "--kill-idle"]).is_err());
+    assert!(DaftLauncher::try_parse_from([
+        "daft",
+        "provisioned",
+        "list",
+        "--idle-after",
+        "4",
+        "--kill-idle"
+    ])
+    .is_ok());
+}
+
+#[test]
+fn test_home_dir_is_not_the_real_one() {
+    assert_ne!(crate::home_dir(), dirs::home_dir());
+    assert!(state::state_dir().unwrap().starts_with(home_dir()));
+}
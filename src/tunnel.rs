@@ -0,0 +1,158 @@
+use std::{future::Future, time::Duration};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, BufReader},
+    process::Child,
+    task::JoinHandle,
+    time::Instant,
+};
+
+use crate::logging;
+
+/// How long to wait before the first attempt to reopen a tunnel which closed.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest to wait between attempts to reopen a tunnel.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How often a new port-forward is checked for readiness.
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Waits for the Ray dashboard to answer (on `/api/version`) through the given
+/// port-forward process on the given local port, failing if the process exits
+/// first or the dashboard does not answer within the given timeout.
+///
+/// This works the same for every kind of port-forward (and every version and
+/// locale of ssh and kubectl), since only the forwarded port is looked at.
+/// Once the dashboard answers, the process's stderr (if piped) is logged in the
+/// background, so that the process never blocks on writing to it.
+pub async fn wait_for_dashboard(
+    child: &mut Child,
+    port: u16,
+    ready_timeout: Duration,
+) -> anyhow::Result<()> {
+    let client = reqwest::Client::builder()
+        .timeout(READY_POLL_INTERVAL * 4)
+        .build()?;
+    let url = format!("http://localhost:{port}/api/version");
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            let mut stderr = String::new();
+            if let Some(mut pipe) = child.stderr.take() {
+                let _ = pipe.read_to_string(&mut stderr).await;
+            };
+            anyhow::bail!("the port-forward exited with {status}: {}", stderr.trim());
+        };
+        let answered = client
+            .get(&url)
+            .send()
+            .await
+            .is_ok_and(|response| response.status().is_success());
+        if answered {
+            break;
+        };
+        if start.elapsed() > ready_timeout {
+            anyhow::bail!(
+                "the Ray dashboard did not answer through the port-forward within {}s",
+                ready_timeout.as_secs()
+            );
+        };
+        tokio::time::sleep(READY_POLL_INTERVAL).await;
+    }
+    if let Some(stderr) = child.stderr.take() {
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                logging::debug(line);
+            }
+        });
+    };
+    Ok(())
+}
+
+/// A port-forward to the cluster which is reopened whenever it closes (e.g.,
+/// when the machine wakes from sleep), for as long as this is held.
+pub struct Tunnel {
+    /// The local port from which the tunnel forwards.
+    local_port: u16,
+    /// `None` for a tunnel which is held open by another process (see
+    /// [`Tunnel::shared`]).
+    supervisor: Option<JoinHandle<()>>,
+}
+
+impl Tunnel {
+    /// A tunnel which another process (e.g., `provisioned connect --detach`)
+    /// holds open, and which is left open when this is dropped.
+    pub fn shared(local_port: u16) -> Self {
+        Self {
+            local_port,
+            supervisor: None,
+        }
+    }
+
+    /// The local address (on the tunnel's local port) of the service which the
+    /// tunnel forwards to, e.g. `http://localhost:8265`.
+    pub fn address(&self) -> String {
+        format!("http://localhost:{}", self.local_port)
+    }
+}
+
+impl Drop for Tunnel {
+    fn drop(&mut self) {
+        // The port-forward process is killed along with the supervisor, which
+        // owns it.
+        if let Some(supervisor) = &self.supervisor {
+            supervisor.abort();
+        };
+    }
+}
+
+/// Returns the delay before the given (zero-based) attempt to reopen a
+/// tunnel, doubling from [`INITIAL_BACKOFF`] up to [`MAX_BACKOFF`].
+pub fn backoff(attempt: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_BACKOFF)
+}
+
+/// Watches the given port-forward process (from the given local port), calling
+/// `reconnect` to open a new one (with backoff between failed attempts)
+/// whenever it exits.
+pub fn supervise<F, Fut>(mut child: Child, local_port: u16, reconnect: F) -> Tunnel
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = anyhow::Result<Child>> + Send,
+{
+    let supervisor = tokio::spawn(async move {
+        loop {
+            let status = child.wait().await;
+            logging::warn(format!(
+                "The tunnel to the cluster closed ({}); reconnecting...",
+                match status {
+                    Ok(status) => status.to_string(),
+                    Err(error) => error.to_string(),
+                }
+            ));
+            let mut attempt = 0;
+            child = loop {
+                tokio::time::sleep(backoff(attempt)).await;
+                match reconnect().await {
+                    Ok(child) => break child,
+                    Err(error) => {
+                        attempt += 1;
+                        logging::warn(format!(
+                            "Reconnecting the tunnel to the cluster failed ({error}); retrying in {}s...",
+                            backoff(attempt).as_secs()
+                        ));
+                    }
+                }
+            };
+            logging::info("Reconnected the tunnel to the cluster");
+        }
+    });
+    Tunnel {
+        local_port,
+        supervisor: Some(supervisor),
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+use crate::{working_dir, StrRef};
+
+/// How long to wait for further changes after a change is detected, so that a
+/// burst of changes (e.g., from saving several files at once) only triggers a
+/// single resubmission.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watches a working directory for changes to any of its (non-excluded)
+/// contents.
+pub struct WorkingDirWatcher {
+    dir: PathBuf,
+    excludes: Vec<StrRef>,
+    events: mpsc::UnboundedReceiver<notify::Result<notify::Event>>,
+    _watcher: RecommendedWatcher,
+}
+
+impl WorkingDirWatcher {
+    pub fn new(dir: &Path, excludes: &[StrRef]) -> anyhow::Result<Self> {
+        let dir = dir.canonicalize()?;
+        let (sender, events) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            // The receiver is only dropped along with the watcher itself.
+            let _ = sender.send(event);
+        })?;
+        watcher.watch(&dir, RecursiveMode::Recursive)?;
+        Ok(Self {
+            dir,
+            excludes: excludes.to_vec(),
+            events,
+            _watcher: watcher,
+        })
+    }
+
+    /// Waits until a non-excluded path in the working directory changes.
+    pub async fn changed(&mut self) -> anyhow::Result<()> {
+        loop {
+            let event = self
+                .events
+                .recv()
+                .await
+                .ok_or_else(|| anyhow::anyhow!("The working directory watcher stopped"))??;
+            if event.paths.iter().any(|path| self.is_relevant(path)) {
+                break;
+            };
+        }
+
+        // Swallow the rest of the burst of changes.
+        tokio::time::sleep(DEBOUNCE).await;
+        while self.events.try_recv().is_ok() {}
+        Ok(())
+    }
+
+    fn is_relevant(&self, path: &Path) -> bool {
+        let Ok(relative_path) = path.strip_prefix(&self.dir) else {
+            return true;
+        };
+        // A path is excluded if it, or any of the directories containing it,
+        // is excluded.
+        !relative_path
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| {
+                let is_dir = ancestor != relative_path || path.is_dir();
+                working_dir::is_excluded(ancestor, is_dir, &self.excludes)
+            })
+    }
+}
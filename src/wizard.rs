@@ -0,0 +1,183 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rustyline::{error::ReadlineError, DefaultEditor};
+use tokio::process::Command;
+
+use crate::edit;
+
+/// Whether the launcher may ask the user questions on the terminal.
+static INTERACTIVE: AtomicBool = AtomicBool::new(true);
+
+/// Sets whether the launcher may ask the user questions; it may not with
+/// `--non-interactive`, or when the standard input is not a terminal (e.g., in
+/// CI).
+pub fn set_interactive(interactive: bool) {
+    INTERACTIVE.store(interactive, Ordering::Relaxed);
+}
+
+pub fn is_interactive() -> bool {
+    INTERACTIVE.load(Ordering::Relaxed)
+}
+
+/// Fails if the launcher may not ask the user questions, explaining what
+/// needed the user's input, and what to do instead.
+pub fn ensure_interactive(needs_input: &str, instead: &str) -> anyhow::Result<()> {
+    check_interactive(is_interactive(), needs_input, instead)
+}
+
+/// Fails, as [`ensure_interactive`] does, unless `interactive`.
+pub fn check_interactive(
+    interactive: bool,
+    needs_input: &str,
+    instead: &str,
+) -> anyhow::Result<()> {
+    if !interactive {
+        anyhow::bail!("{needs_input}, but the launcher is running non-interactively (with `--non-interactive`, or without a terminal); {instead}");
+    };
+    Ok(())
+}
+
+/// Asks the user questions on the terminal, offering a default answer for each.
+pub struct Wizard {
+    editor: DefaultEditor,
+}
+
+impl Wizard {
+    pub fn new() -> anyhow::Result<Self> {
+        Ok(Self {
+            editor: DefaultEditor::new()?,
+        })
+    }
+
+    /// Asks the given question, returning the default answer if the user
+    /// enters nothing.
+    pub fn ask(&mut self, question: &str, default: &str) -> anyhow::Result<String> {
+        let prompt = format!("{question} [{default}]: ");
+        let answer = match tokio::task::block_in_place(|| self.editor.readline(&prompt)) {
+            Ok(answer) => answer,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => {
+                anyhow::bail!("Aborted the configuration")
+            }
+            Err(error) => return Err(error.into()),
+        };
+        let answer = answer.trim();
+        Ok(if answer.is_empty() { default } else { answer }.to_string())
+    }
+
+    /// Asks the user to pick one of the given options, either by number or by
+    /// name.
+    pub fn select(
+        &mut self,
+        question: &str,
+        options: &[String],
+        default: &str,
+    ) -> anyhow::Result<String> {
+        for (index, option) in options.iter().enumerate() {
+            println!("  {}) {option}", index + 1);
+        }
+        loop {
+            let answer = self.ask(question, default)?;
+            match parse_selection(&answer, options) {
+                Some(option) => break Ok(option.to_string()),
+                None => println!("Please pick one of the options above"),
+            };
+        }
+    }
+
+    /// Asks a yes-or-no question.
+    pub fn confirm(&mut self, question: &str, default: bool) -> anyhow::Result<bool> {
+        loop {
+            let answer = self.ask(question, if default { "y" } else { "n" })?;
+            match parse_confirmation(&answer) {
+                Some(answer) => break Ok(answer),
+                None => println!("Please answer `y` or `n`"),
+            };
+        }
+    }
+}
+
+/// Returns the option which the given answer picks, either by (1-based) number
+/// or by name.
+pub fn parse_selection<'a>(answer: &str, options: &'a [String]) -> Option<&'a str> {
+    match answer.parse::<usize>() {
+        Ok(number) => options.get(number.checked_sub(1)?),
+        Err(_) => options.iter().find(|option| *option == answer),
+    }
+    .map(String::as_str)
+}
+
+pub fn parse_confirmation(answer: &str) -> Option<bool> {
+    match answer.to_lowercase().as_str() {
+        "y" | "yes" => Some(true),
+        "n" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Runs the given `kubectl` command, returning the non-empty lines of its
+/// output, or nothing if it fails (e.g., if there is no kubeconfig).
+async fn kubectl_lines(args: &[&str]) -> Vec<String> {
+    match Command::new("kubectl").args(args).output().await {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(ToString::to_string)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Asks for the settings of a provisioned cluster, writing the answers into
+/// the given configuration file.
+pub fn provisioned(wizard: &mut Wizard, mut contents: String) -> anyhow::Result<String> {
+    for (key, question) in [
+        ("setup.name", "Cluster name"),
+        ("setup.provisioned.region", "AWS region"),
+        ("setup.provisioned.instance-type", "Instance type"),
+        ("setup.provisioned.number-of-workers", "Number of workers"),
+        ("setup.provisioned.ssh-private-key", "SSH private key"),
+    ] {
+        let answer = wizard.ask(question, &edit::get(&contents, key)?)?;
+        contents = edit::set(&contents, key, &answer)?;
+    }
+    Ok(contents)
+}
+
+/// Asks for the settings of a BYOC cluster, offering the contexts in the
+/// user's kubeconfig as choices, and writing the answers into the given
+/// configuration file.
+pub async fn byoc(wizard: &mut Wizard, mut contents: String) -> anyhow::Result<String> {
+    let name = wizard.ask("Cluster name", &edit::get(&contents, "setup.name")?)?;
+    contents = edit::set(&contents, "setup.name", &name)?;
+
+    let contexts = kubectl_lines(&["config", "get-contexts", "-o", "name"]).await;
+    let current_context = kubectl_lines(&["config", "current-context"])
+        .await
+        .into_iter()
+        .next();
+    let context = match (contexts.as_slice(), current_context) {
+        ([], _) => {
+            println!("No kubeconfig contexts were found; the current context will be used");
+            None
+        }
+        (_, current_context) => Some(wizard.select(
+            "Kubeconfig context",
+            &contexts,
+            current_context.as_deref().unwrap_or(&contexts[0]),
+        )?),
+    };
+    if let Some(context) = context {
+        contents = edit::set(&contents, "setup.byoc.context", &context)?;
+    };
+
+    let namespace = wizard.ask("Namespace", &edit::get(&contents, "setup.byoc.namespace")?)?;
+    contents = edit::set(&contents, "setup.byoc.namespace", &namespace)?;
+
+    if !wizard.confirm("Is KubeRay installed in the cluster?", true)? {
+        println!(
+            "The launcher submits jobs to a Ray cluster which KubeRay manages; install it with:\n\n  helm repo add kuberay https://ray-project.github.io/kuberay-helm/\n  helm install kuberay-operator kuberay/kuberay-operator\n\nand then create a Ray cluster in the namespace {namespace}"
+        );
+    };
+    Ok(contents)
+}
@@ -0,0 +1,172 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use glob::Pattern;
+
+use crate::{logging, StrRef};
+
+/// The default maximum size (in MiB) of a job's working directory.
+pub const DEFAULT_SIZE_LIMIT_MIB: u64 = 100;
+
+/// The number of largest paths to report when a working directory is too
+/// large.
+const LARGEST_PATHS_TO_REPORT: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The path of the entry, relative to the working directory.
+    pub relative_path: PathBuf,
+    pub is_dir: bool,
+    /// The size of the entry in bytes (zero for directories).
+    pub size: u64,
+}
+
+/// Returns whether the given relative path is excluded by any of the given
+/// patterns.
+///
+/// Patterns follow a simplified `.gitignore` syntax: a pattern ending in `/`
+/// only matches directories, a pattern containing a `/` is matched against the
+/// full relative path, and any other pattern is matched against the file name
+/// alone.
+pub fn is_excluded(relative_path: &Path, is_dir: bool, excludes: &[StrRef]) -> bool {
+    let full_path = relative_path
+        .iter()
+        .map(|segment| segment.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    let file_name = relative_path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy())
+        .unwrap_or_default();
+    excludes.iter().any(|exclude| {
+        let (exclude, dirs_only) = match exclude.strip_suffix('/') {
+            Some(exclude) => (exclude, true),
+            None => (&**exclude, false),
+        };
+        if dirs_only && !is_dir {
+            return false;
+        };
+        let Ok(pattern) = Pattern::new(exclude.trim_start_matches('/')) else {
+            return false;
+        };
+        if exclude.contains('/') {
+            pattern.matches(&full_path)
+        } else {
+            pattern.matches(&file_name)
+        }
+    })
+}
+
+/// Recursively lists the contents of the given directory in a deterministic
+/// order, skipping anything which is excluded (along with the contents of
+/// excluded directories).
+///
+/// Symlinks are followed, except for those to a directory which contains them
+/// (which would otherwise be walked forever); dangling symlinks are skipped.
+pub fn walk(dir: &Path, excludes: &[StrRef]) -> anyhow::Result<Vec<Entry>> {
+    let mut entries = vec![];
+    // Each directory to walk, along with the real paths of the directories
+    // which lead to it (itself included).
+    let mut stack = vec![(PathBuf::new(), vec![dir.canonicalize()?])];
+    while let Some((relative_dir, ancestors)) = stack.pop() {
+        let mut file_names = std::fs::read_dir(dir.join(&relative_dir))?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        file_names.sort();
+        for file_name in file_names {
+            let relative_path = relative_dir.join(file_name);
+            let path = dir.join(&relative_path);
+            let metadata = std::fs::symlink_metadata(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let metadata = if metadata.is_symlink() {
+                match std::fs::metadata(&path) {
+                    Ok(metadata) => metadata,
+                    Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                        logging::warn(format!(
+                            "Skipping {}, since it links to a path which does not exist",
+                            path.display()
+                        ));
+                        continue;
+                    }
+                    Err(error) => {
+                        return Err(error)
+                            .with_context(|| format!("Failed to read {}", path.display()))
+                    }
+                }
+            } else {
+                metadata
+            };
+            let is_dir = metadata.is_dir();
+            if is_excluded(&relative_path, is_dir, excludes) {
+                continue;
+            };
+            if is_dir {
+                let real_path = path.canonicalize()?;
+                if ancestors.contains(&real_path) {
+                    logging::warn(format!(
+                        "Skipping {}, since it links to a directory which contains it",
+                        path.display()
+                    ));
+                    continue;
+                };
+                let mut ancestors = ancestors.clone();
+                ancestors.push(real_path);
+                stack.push((relative_path.clone(), ancestors));
+            };
+            entries.push(Entry {
+                relative_path,
+                is_dir,
+                size: if is_dir { 0 } else { metadata.len() },
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Sums the sizes of the given entries by their top-level path, returning the
+/// largest ones first.
+pub fn largest_paths(entries: &[Entry]) -> Vec<(PathBuf, u64)> {
+    let mut sizes = HashMap::<PathBuf, u64>::new();
+    for entry in entries {
+        let top_level = entry
+            .relative_path
+            .iter()
+            .next()
+            .map(PathBuf::from)
+            .unwrap_or_default();
+        *sizes.entry(top_level).or_default() += entry.size;
+    }
+    let mut sizes = sizes.into_iter().collect::<Vec<_>>();
+    sizes.sort_by(|(a_path, a_size), (b_path, b_size)| {
+        b_size.cmp(a_size).then_with(|| a_path.cmp(b_path))
+    });
+    sizes
+}
+
+fn format_size(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Asserts that the given working directory (minus its excluded paths) fits
+/// within the given size limit, reporting its largest paths if it does not.
+pub fn check_size(dir: &Path, excludes: &[StrRef], limit_mib: u64) -> anyhow::Result<()> {
+    let entries = walk(dir, excludes)?;
+    let total = entries.iter().map(|entry| entry.size).sum::<u64>();
+    if total <= limit_mib * 1024 * 1024 {
+        return Ok(());
+    };
+
+    let breakdown = largest_paths(&entries)
+        .into_iter()
+        .take(LARGEST_PATHS_TO_REPORT)
+        .map(|(path, size)| format!("  {} ({})", path.display(), format_size(size)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    anyhow::bail!(
+        "The working directory {dir:?} is {}, which exceeds the limit of {limit_mib} MiB; consider adding its largest paths to the job's `excludes` or raising its `working-dir-size-limit`:\n{breakdown}",
+        format_size(total),
+    )
+}